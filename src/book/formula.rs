@@ -0,0 +1,568 @@
+//! Finding the cell references in a formula.
+//!
+//! This is a scanner rather than a parser. It only knows enough about formula
+//! syntax to skip string literals and function names and is meant for
+//! features like tracing precedents that need the references and nothing else.
+//! [`parse_segments`] splits a formula into its references and the text
+//! between them for features that rewrite or highlight the references.
+use std::ops::Range;
+
+use super::{name_to_col, LAST_COLUMN, LAST_ROW};
+
+/// A cell or range reference in a formula like `A1`, `$B$2:C4` or
+/// `'My Sheet'!D5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// The sheet named in the reference. `None` means the formula's own sheet.
+    pub sheet: Option<String>,
+    /// The top left `(row, col)` of the reference.
+    pub start: (usize, usize),
+    /// The bottom right `(row, col)`. The same as `start` for a single cell.
+    pub end: (usize, usize),
+    /// Where the reference is in the formula in bytes.
+    pub span: Range<usize>,
+}
+
+impl Reference {
+    /// Whether the reference covers the cell at `row`, `col`.
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        row >= self.start.0 && row <= self.end.0 && col >= self.start.1 && col <= self.end.1
+    }
+}
+
+/// Find every cell and range reference in `formula` in the order they appear.
+pub fn find_references(formula: &str) -> Vec<Reference> {
+    let bytes = formula.as_bytes();
+    let mut refs = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'"' => pos = skip_string(bytes, pos),
+            b if is_word_byte(b) || b == b'$' || b == b'\'' => {
+                let starts_word = pos == 0 || !is_word_byte(bytes[pos - 1]);
+                match starts_word.then(|| scan_reference(formula, pos)).flatten() {
+                    Some(reference) => {
+                        pos = reference.span.end;
+                        refs.push(reference);
+                    }
+                    None => pos = skip_word(bytes, pos),
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+    refs
+}
+
+/// Read `text` as a single reference like `B2:D10` or `Sheet2!A1` with nothing
+/// before or after it.
+pub fn parse_reference(text: &str) -> Option<Reference> {
+    if text.is_empty() {
+        return None;
+    }
+    scan_reference(text, 0).filter(|reference| reference.span.end == text.len())
+}
+
+/// A piece of a formula. Joining the segments in order gives back the formula.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormulaSegment<'f> {
+    /// A cell or range reference.
+    Placeholder(Reference),
+    /// Everything between references: operators, functions, literals.
+    Unparsed(&'f str),
+}
+
+impl<'f> FormulaSegment<'f> {
+    /// The text of the segment in `formula`.
+    pub fn text<'a>(&'a self, formula: &'a str) -> &'a str {
+        match self {
+            FormulaSegment::Placeholder(reference) => &formula[reference.span.clone()],
+            FormulaSegment::Unparsed(text) => text,
+        }
+    }
+}
+
+/// Split `formula` into references and the unparsed text around them.
+pub fn parse_segments(formula: &str) -> Vec<FormulaSegment<'_>> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    for reference in find_references(formula) {
+        if reference.span.start > pos {
+            segments.push(FormulaSegment::Unparsed(
+                &formula[pos..reference.span.start],
+            ));
+        }
+        pos = reference.span.end;
+        segments.push(FormulaSegment::Placeholder(reference));
+    }
+    if pos < formula.len() {
+        segments.push(FormulaSegment::Unparsed(&formula[pos..]));
+    }
+    segments
+}
+
+/// What a highlighted piece of a formula is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Reference,
+    Function,
+    String,
+    Number,
+}
+
+/// Find the references, function names, strings and numbers in `formula` for
+/// highlighting. Anything else, including text the scanner doesn't
+/// understand, is left out.
+pub fn highlight_tokens(formula: &str) -> Vec<(Range<usize>, TokenKind)> {
+    let bytes = formula.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    for segment in parse_segments(formula) {
+        let end = pos + segment.text(formula).len();
+        match segment {
+            FormulaSegment::Placeholder(reference) => {
+                tokens.push((reference.span, TokenKind::Reference));
+            }
+            FormulaSegment::Unparsed(_) => scan_tokens(bytes, pos..end, &mut tokens),
+        }
+        pos = end;
+    }
+    tokens
+}
+
+/// Find the function names, strings and numbers in the part of the formula
+/// between two references.
+fn scan_tokens(bytes: &[u8], span: Range<usize>, tokens: &mut Vec<(Range<usize>, TokenKind)>) {
+    let mut pos = span.start;
+    while pos < span.end {
+        let start = pos;
+        let starts_word = pos == 0 || !is_word_byte(bytes[pos - 1]);
+        match bytes[pos] {
+            b'"' => {
+                pos = skip_string(bytes, pos).min(span.end);
+                tokens.push((start..pos, TokenKind::String));
+            }
+            b if starts_word && (b.is_ascii_digit() || b == b'.') => {
+                pos = skip_number(bytes, pos).min(span.end);
+                tokens.push((start..pos, TokenKind::Number));
+            }
+            b if starts_word && is_word_byte(b) => {
+                while pos < span.end && is_word_byte(bytes[pos]) {
+                    pos += 1;
+                }
+                if bytes.get(pos) == Some(&b'(') {
+                    tokens.push((start..pos, TokenKind::Function));
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+}
+
+/// Skip past the number starting at `pos` including an exponent like `1.5E3`.
+fn skip_number(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+        pos += 1;
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        let mut exp = pos + 1;
+        if matches!(bytes.get(exp), Some(b'+' | b'-')) {
+            exp += 1;
+        }
+        if bytes.get(exp).is_some_and(|b| b.is_ascii_digit()) {
+            pos = exp;
+            while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                pos += 1;
+            }
+        }
+    }
+    pos
+}
+
+/// Pair up the parentheses in `formula` outside of string literals. Returns
+/// the byte offsets of each `(` with its `)` and of every parenthesis without
+/// a match.
+pub fn match_parens(formula: &str) -> (Vec<(usize, usize)>, Vec<usize>) {
+    let bytes = formula.as_bytes();
+    let mut pairs = Vec::new();
+    let mut open = Vec::new();
+    let mut orphans = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'"' => {
+                pos = skip_string(bytes, pos);
+                continue;
+            }
+            b'(' => open.push(pos),
+            b')' => match open.pop() {
+                Some(start) => pairs.push((start, pos)),
+                None => orphans.push(pos),
+            },
+            _ => (),
+        }
+        pos += 1;
+    }
+    orphans.extend(open);
+    orphans.sort();
+    (pairs, orphans)
+}
+
+/// Cycle the `$` anchors of a reference like Excel's F4:
+/// `A1 → $A$1 → A$1 → $A1 → A1`. Both ends of a range follow the first cell
+/// and a sheet prefix is kept as it is.
+pub fn cycle_anchors(reference: &str) -> String {
+    let (sheet, cells) = match reference.rfind('!') {
+        Some(idx) => reference.split_at(idx + 1),
+        None => ("", reference),
+    };
+    let parts: Vec<(&str, &str)> = cells
+        .split(':')
+        .map(|cell| {
+            let cell = cell.trim_start_matches('$');
+            let digits = cell.find(|c: char| c == '$' || c.is_ascii_digit());
+            let (col, row) = cell.split_at(digits.unwrap_or(cell.len()));
+            (col, row.trim_start_matches('$'))
+        })
+        .collect();
+    let col_anchored = cells.starts_with('$');
+    let row_anchored = cells
+        .split(':')
+        .next()
+        .is_some_and(|cell| cell.trim_start_matches('$').contains('$'));
+    let (col_anchored, row_anchored) = match (col_anchored, row_anchored) {
+        (false, false) => (true, true),
+        (true, true) => (false, true),
+        (false, true) => (true, false),
+        (true, false) => (false, false),
+    };
+    let anchor = |anchored: bool| if anchored { "$" } else { "" };
+    let cells: Vec<String> = parts
+        .iter()
+        .map(|(col, row)| {
+            format!(
+                "{}{}{}{}",
+                anchor(col_anchored),
+                col,
+                anchor(row_anchored),
+                row
+            )
+        })
+        .collect();
+    format!("{}{}", sheet, cells.join(":"))
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'.'
+}
+
+/// Skip past the string literal starting at `pos`. Doubled quotes are escapes.
+fn skip_string(bytes: &[u8], mut pos: usize) -> usize {
+    pos += 1;
+    while pos < bytes.len() {
+        if bytes[pos] == b'"' {
+            if bytes.get(pos + 1) == Some(&b'"') {
+                pos += 2;
+                continue;
+            }
+            return pos + 1;
+        }
+        pos += 1;
+    }
+    pos
+}
+
+fn skip_word(bytes: &[u8], mut pos: usize) -> usize {
+    if bytes[pos] == b'\'' {
+        // An unterminated quoted sheet name. Skip the quote on its own.
+        return pos + 1;
+    }
+    pos += 1;
+    while pos < bytes.len() && (is_word_byte(bytes[pos]) || bytes[pos] == b'$') {
+        pos += 1;
+    }
+    pos
+}
+
+/// Try to read a reference starting at `start`.
+fn scan_reference(formula: &str, start: usize) -> Option<Reference> {
+    let (sheet, pos) = scan_sheet_prefix(formula, start);
+    let (first, mut pos) = scan_cell(formula.as_bytes(), pos)?;
+    let mut end = first;
+    if formula.as_bytes().get(pos) == Some(&b':') {
+        if let Some((second, after)) = scan_cell(formula.as_bytes(), pos + 1) {
+            end = second;
+            pos = after;
+        }
+    }
+    // Anything that carries on like a name or a function call isn't a
+    // reference, e.g. `LOG10(` or `A1B`.
+    match formula.as_bytes().get(pos) {
+        Some(b) if is_word_byte(*b) || *b == b'(' || *b == b'!' => return None,
+        _ => (),
+    }
+    Some(Reference {
+        sheet,
+        start: (first.0.min(end.0), first.1.min(end.1)),
+        end: (first.0.max(end.0), first.1.max(end.1)),
+        span: start..pos,
+    })
+}
+
+/// Read a `Sheet!` or `'Sheet name'!` prefix returning the sheet name and
+/// where the cell starts. Without a prefix the position is unchanged.
+fn scan_sheet_prefix(formula: &str, start: usize) -> (Option<String>, usize) {
+    let bytes = formula.as_bytes();
+    if bytes[start] == b'\'' {
+        let mut pos = start + 1;
+        let mut name = String::new();
+        while pos < bytes.len() {
+            if bytes[pos] == b'\'' {
+                if bytes.get(pos + 1) == Some(&b'\'') {
+                    name.push('\'');
+                    pos += 2;
+                    continue;
+                }
+                if bytes.get(pos + 1) == Some(&b'!') {
+                    return (Some(name), pos + 2);
+                }
+                break;
+            }
+            let c = formula[pos..]
+                .chars()
+                .next()
+                .expect("pos is a char boundary");
+            name.push(c);
+            pos += c.len_utf8();
+        }
+        return (None, start);
+    }
+    let mut pos = start;
+    while pos < bytes.len() && is_word_byte(bytes[pos]) {
+        pos += 1;
+    }
+    if pos > start && bytes.get(pos) == Some(&b'!') {
+        return (Some(formula[start..pos].to_string()), pos + 1);
+    }
+    (None, start)
+}
+
+/// Read a cell like `B12` or `$B$12` returning its `(row, col)` and the
+/// position after it.
+fn scan_cell(bytes: &[u8], mut pos: usize) -> Option<((usize, usize), usize)> {
+    if bytes.get(pos) == Some(&b'$') {
+        pos += 1;
+    }
+    let letters = pos;
+    while pos < bytes.len() && bytes[pos].is_ascii_alphabetic() {
+        pos += 1;
+    }
+    if pos == letters || pos - letters > 3 {
+        return None;
+    }
+    let col = name_to_col(std::str::from_utf8(&bytes[letters..pos]).ok()?)?;
+    if bytes.get(pos) == Some(&b'$') {
+        pos += 1;
+    }
+    let digits = pos;
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+        pos += 1;
+    }
+    let row: usize = std::str::from_utf8(&bytes[digits..pos])
+        .ok()?
+        .parse()
+        .ok()?;
+    if row < 1 || row > LAST_ROW as usize || col > LAST_COLUMN as usize {
+        return None;
+    }
+    Some(((row, col), pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cycle_anchors, find_references, highlight_tokens, match_parens, parse_reference,
+        parse_segments, FormulaSegment, TokenKind,
+    };
+
+    type Found = (Option<String>, (usize, usize), (usize, usize), String);
+
+    fn found(formula: &str) -> Vec<Found> {
+        find_references(formula)
+            .into_iter()
+            .map(|r| (r.sheet, r.start, r.end, formula[r.span].to_string()))
+            .collect()
+    }
+
+    fn r(sheet: Option<&str>, start: (usize, usize), end: (usize, usize), text: &str) -> Found {
+        (sheet.map(String::from), start, end, text.to_string())
+    }
+
+    #[test]
+    fn test_find_references() {
+        let cases = vec![
+            ("=1+2", vec![]),
+            ("=A1", vec![r(None, (1, 1), (1, 1), "A1")]),
+            (
+                "=$B$2*c3",
+                vec![
+                    r(None, (2, 2), (2, 2), "$B$2"),
+                    r(None, (3, 3), (3, 3), "c3"),
+                ],
+            ),
+            ("=SUM(A1:B3)", vec![r(None, (1, 1), (3, 2), "A1:B3")]),
+            ("=SUM(B3:A1)", vec![r(None, (1, 1), (3, 2), "B3:A1")]),
+            (
+                "=Sheet2!A1+1",
+                vec![r(Some("Sheet2"), (1, 1), (1, 1), "Sheet2!A1")],
+            ),
+            (
+                "='My Sheet'!C4:D5",
+                vec![r(Some("My Sheet"), (4, 3), (5, 4), "'My Sheet'!C4:D5")],
+            ),
+            ("=LOG10(A2)", vec![r(None, (2, 1), (2, 1), "A2")]),
+            ("=\"A1\"&B1", vec![r(None, (1, 2), (1, 2), "B1")]),
+            (
+                "=\"say \"\"A1\"\"\"&B1",
+                vec![r(None, (1, 2), (1, 2), "B1")],
+            ),
+            ("=my_A1+ABCD1+1.5E3", vec![]),
+        ];
+        for (formula, expected) in cases {
+            assert_eq!(expected, found(formula), "{}", formula);
+        }
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        let parsed = |text: &str| parse_reference(text).map(|r| (r.sheet, r.start, r.end));
+        assert_eq!(Some((None, (2, 2), (10, 4))), parsed("B2:D10"));
+        assert_eq!(Some((None, (2, 2), (10, 4))), parsed("d10:b2"));
+        assert_eq!(
+            Some((Some(String::from("My Sheet")), (1, 1), (1, 1))),
+            parsed("'My Sheet'!A1")
+        );
+        for bad in ["", "red", "A1 ", "A1:", "A0", "SUM(A1)", "Sheet2!"] {
+            assert_eq!(None, parsed(bad), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_segments() {
+        // Placeholders are written as `[text]`.
+        let cases = vec![
+            ("", ""),
+            ("=1+2", "=1+2"),
+            ("=A1", "=[A1]"),
+            ("A1", "[A1]"),
+            ("=$A$1*2", "=[$A$1]*2"),
+            ("=SUM(A1:B3)", "=SUM([A1:B3])"),
+            ("=Sheet2!A1+'My Sheet'!B2", "=[Sheet2!A1]+['My Sheet'!B2]"),
+            ("=A1&\"B2\"&C3", "=[A1]&\"B2\"&[C3]"),
+            ("=LOG10(A1)", "=LOG10([A1])"),
+        ];
+        for (formula, expected) in cases {
+            let segments = parse_segments(formula);
+            let shown: String = segments
+                .iter()
+                .map(|segment| match segment {
+                    FormulaSegment::Placeholder(r) => format!("[{}]", &formula[r.span.clone()]),
+                    FormulaSegment::Unparsed(text) => text.to_string(),
+                })
+                .collect();
+            assert_eq!(expected, shown, "{}", formula);
+            let joined: String = segments.iter().map(|s| s.text(formula)).collect();
+            assert_eq!(formula, joined);
+        }
+    }
+
+    #[test]
+    fn test_parse_segments_offsets() {
+        let formula = "=SUM(A1:B3)+Sheet2!$C$4";
+        let segments = parse_segments(formula);
+        assert_eq!(4, segments.len());
+        match &segments[1] {
+            FormulaSegment::Placeholder(r) => {
+                assert_eq!(5..10, r.span);
+                assert_eq!(((1, 1), (3, 2)), (r.start, r.end));
+            }
+            other => panic!("Expected a placeholder but got {:?}", other),
+        }
+        match &segments[3] {
+            FormulaSegment::Placeholder(r) => {
+                assert_eq!(12..formula.len(), r.span);
+                assert_eq!(Some("Sheet2".to_string()), r.sheet);
+                assert_eq!((4, 3), r.start);
+            }
+            other => panic!("Expected a placeholder but got {:?}", other),
+        }
+        assert_eq!(FormulaSegment::Unparsed(")+"), segments[2]);
+    }
+
+    #[test]
+    fn test_reference_contains() {
+        let reference = &find_references("=B2:C4")[0];
+        assert!(reference.contains(2, 2));
+        assert!(reference.contains(4, 3));
+        assert!(!reference.contains(1, 2));
+        assert!(!reference.contains(3, 4));
+    }
+
+    #[test]
+    fn test_cycle_anchors() {
+        let mut reference = String::from("A1");
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            reference = cycle_anchors(&reference);
+            seen.push(reference.clone());
+        }
+        assert_eq!(vec!["$A$1", "A$1", "$A1", "A1"], seen);
+        assert_eq!("$AB$12:$C$3", cycle_anchors("AB12:C3"));
+        assert_eq!("AB$12:C$3", cycle_anchors("$AB$12:C3"));
+        assert_eq!("Sheet2!$B$2", cycle_anchors("Sheet2!B2"));
+        assert_eq!("'My Sheet'!$C4", cycle_anchors("'My Sheet'!C$4"));
+    }
+
+    #[test]
+    fn test_match_parens() {
+        assert_eq!((vec![], vec![]), match_parens("=A1+1"));
+        assert_eq!(
+            (vec![(9, 14), (3, 17)], vec![]),
+            match_parens("=IF(A1>0,(B1+1),2)")
+        );
+        // Parentheses in strings don't count.
+        assert_eq!((vec![(4, 9)], vec![]), match_parens("=LEN(\")(\")"));
+        assert_eq!((vec![(5, 8)], vec![4]), match_parens("=SUM((A1)"));
+        assert_eq!((vec![(5, 8)], vec![3]), match_parens("=A1)+(B1)"));
+    }
+
+    #[test]
+    fn test_highlight_tokens() {
+        let shown = |formula: &str| -> Vec<(String, TokenKind)> {
+            highlight_tokens(formula)
+                .into_iter()
+                .map(|(span, kind)| (formula[span].to_string(), kind))
+                .collect()
+        };
+        let t = |text: &str, kind| (text.to_string(), kind);
+        assert_eq!(
+            vec![
+                t("SUM", TokenKind::Function),
+                t("A1:B2", TokenKind::Reference),
+                t("1.5E3", TokenKind::Number),
+                t("\"a(\"", TokenKind::String),
+                t("Sheet2!C3", TokenKind::Reference),
+            ],
+            shown("=SUM(A1:B2)*1.5E3&\"a(\"&Sheet2!C3")
+        );
+        assert_eq!(
+            vec![t("LOG10", TokenKind::Function), t("2", TokenKind::Number)],
+            shown("=LOG10(my_name2)+2")
+        );
+        // Half typed formulas still get what can be found.
+        assert_eq!(
+            vec![t("IF", TokenKind::Function), t("\"open", TokenKind::String)],
+            shown("=IF(\"open")
+        );
+    }
+}
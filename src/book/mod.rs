@@ -1,14 +1,18 @@
+pub mod formula;
 pub mod sui;
 pub use sui::ParseWarning;
 
+use std::cell::RefCell;
 use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use ironcalc::{
     base::{
+        cell::CellValue,
         expressions::types::Area,
-        types::{SheetData, Style, Worksheet},
+        types::{HorizontalAlignment, SheetData, Style, Worksheet},
         worksheet::WorksheetDimension,
         Model, UserModel,
     },
@@ -35,6 +39,25 @@ pub(crate) const COL_PIXELS: f64 = 5.0;
 // publically.
 pub(crate) const LAST_COLUMN: i32 = 16_384;
 pub(crate) const LAST_ROW: i32 = 1_048_576;
+/// The values a cell holds when its formula fails.
+pub(crate) const ERROR_VALUES: [&str; 12] = [
+    "#ERROR!", "#REF!", "#NAME?", "#VALUE!", "#DIV/0!", "#N/A", "#NUM!", "#N/IMPL!", "#SPILL!",
+    "#CALC!", "#CIRC!", "#NULL!",
+];
+/// The style paths that `Book::set_cell_style` accepts from users.
+pub(crate) const STYLE_PATHS: [&str; 11] = [
+    "font.b",
+    "font.i",
+    "font.strike",
+    "font.color",
+    "font.u",
+    "fill.bg_color",
+    "fill.fg_color",
+    "num_fmt",
+    "alignment.horizontal",
+    "alignment.vertical",
+    "alignment.wrap_text",
+];
 
 #[derive(Debug, Clone)]
 pub struct AddressRange<'book> {
@@ -43,8 +66,12 @@ pub struct AddressRange<'book> {
 }
 
 impl<'book> AddressRange<'book> {
+    /// The addresses in the range row by row. Rows always run top to bottom
+    /// and left to right whichever way the range was selected.
     pub fn as_rows(&self) -> Vec<Vec<Address>> {
-        let (row_range, col_range) = self.get_ranges();
+        let (mut row_range, mut col_range) = self.get_ranges();
+        row_range.sort();
+        col_range.sort();
         let mut rows = Vec::with_capacity(row_range.len());
         for ri in row_range.iter() {
             let mut row = Vec::with_capacity(col_range.len());
@@ -60,6 +87,8 @@ impl<'book> AddressRange<'book> {
         rows
     }
 
+    /// The addresses in the range starting at `start` and moving towards
+    /// `end`.
     pub fn as_series(&self) -> Vec<Address> {
         let (row_range, col_range) = self.get_ranges();
         let mut rows = Vec::with_capacity(row_range.len() * col_range.len());
@@ -76,31 +105,99 @@ impl<'book> AddressRange<'book> {
     }
 
     fn get_ranges(&self) -> (Vec<usize>, Vec<usize>) {
-        let row_range = if self.start.row <= self.end.row {
-            (self.start.row..=self.end.row)
-                .into_iter()
-                .collect::<Vec<usize>>()
-        } else {
-            let mut v = (self.start.row..=self.end.row)
-                .into_iter()
-                .collect::<Vec<usize>>();
-            v.reverse();
-            v
-        };
-        let col_range = if self.start.col <= self.end.col {
-            (self.start.col..=self.end.col)
-                .into_iter()
-                .collect::<Vec<usize>>()
-        } else {
-            let mut v = (self.start.col..=self.end.col)
-                .into_iter()
-                .collect::<Vec<usize>>();
-            v.reverse();
-            v
-        };
-        (row_range, col_range)
+        (
+            directional_range(self.start.row, self.end.row),
+            directional_range(self.start.col, self.end.col),
+        )
+    }
+}
+
+/// Every index between `start` and `end` inclusive in the order you get
+/// walking from `start` to `end`.
+fn directional_range(start: usize, end: usize) -> Vec<usize> {
+    let mut range: Vec<usize> = (std::cmp::min(start, end)..=std::cmp::max(start, end)).collect();
+    if start > end {
+        range.reverse();
+    }
+    range
+}
+
+/// The first value of a series filled by `Book::fill_series`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeriesStart {
+    Number(f64),
+    /// Dates step by days and are formatted as dates.
+    Date(time::Date),
+}
+
+impl SeriesStart {
+    /// Read a number like `1.5` or a date like `2024-01-31`.
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Ok(n) = text.parse::<f64>() {
+            return n.is_finite().then_some(SeriesStart::Number(n));
+        }
+        let mut parts = text.splitn(3, '-');
+        let year = parts.next()?.parse::<i32>().ok()?;
+        let month = parts.next()?.parse::<u8>().ok()?;
+        let day = parts.next()?.parse::<u8>().ok()?;
+        let month = time::Month::try_from(month).ok()?;
+        time::Date::from_calendar_date(year, month, day)
+            .ok()
+            .map(SeriesStart::Date)
+    }
+}
+
+/// Turn a date into the day number spreadsheets store dates as.
+fn date_to_serial(date: time::Date) -> i64 {
+    let epoch = time::Date::from_calendar_date(1899, time::Month::December, 30)
+        .expect("The spreadsheet epoch is a valid date");
+    (date - epoch).whole_days()
+}
+
+/// Write a number without float noise like `0.30000000000000004` or
+/// trailing zeros.
+fn format_series_number(n: f64) -> String {
+    let text = format!("{:.10}", n);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        String::from("0")
+    } else {
+        text.to_string()
     }
+}
 
+/// How `Book::change_case_range` changes text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseMode {
+    Upper,
+    Lower,
+    /// Capitalize the first letter of every word and lowercase the rest.
+    Title,
+}
+
+impl CaseMode {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            CaseMode::Upper => text.to_uppercase(),
+            CaseMode::Lower => text.to_lowercase(),
+            CaseMode::Title => {
+                let mut out = String::with_capacity(text.len());
+                let mut prev = None;
+                for c in text.chars() {
+                    // An apostrophe doesn't start a new word, e.g. `Don't`.
+                    let starts_word =
+                        !prev.map_or(false, |p: char| p.is_alphanumeric() || p == '\'');
+                    if starts_word {
+                        out.extend(c.to_uppercase());
+                    } else {
+                        out.extend(c.to_lowercase());
+                    }
+                    prev = Some(c);
+                }
+                out
+            }
+        }
+    }
 }
 
 /// A spreadsheet book with some internal state tracking.
@@ -115,6 +212,14 @@ pub struct Book {
     /// Parse warnings produced when loading a `.sui` file with invalid lines.
     /// Empty for xlsx files and for freshly created books.
     pub parse_warnings: Vec<ParseWarning>,
+    /// The result of [`Book::get_size`] for each sheet index. Every change to
+    /// the book clears it.
+    size_cache: RefCell<HashMap<u32, (usize, usize)>>,
+    /// Only recalculate when [`Book::recalculate`] is called.
+    manual_calc: bool,
+    /// A change was made in manual calculation mode so the computed values
+    /// may be out of date.
+    pub needs_recalc: bool,
 }
 
 impl Book {
@@ -127,9 +232,39 @@ impl Book {
             format: FileFormat::Sui,
             file_path: None,
             parse_warnings: Vec::new(),
+            size_cache: RefCell::new(HashMap::new()),
+            manual_calc: false,
+            needs_recalc: false,
+        }
+    }
+
+    /// Record that the book changed.
+    fn mark_changed(&mut self) {
+        self.dirty = true;
+        self.size_cache.get_mut().clear();
+        if self.manual_calc {
+            self.needs_recalc = true;
         }
     }
 
+    /// Construct a new empty book using the given locale and timezone.
+    pub fn new_with_locale(locale: &str, tz: &str) -> Result<Self> {
+        let locale: &'static str = Box::leak(locale.to_string().into_boxed_str());
+        let tz: &'static str = Box::leak(tz.to_string().into_boxed_str());
+        let mut book = Book::new(
+            UserModel::new_empty("default_name", locale, tz, "en").map_err(|e| anyhow!(e))?,
+        );
+        book.update_cell(
+            &Address {
+                sheet: 0,
+                row: 1,
+                col: 1,
+            },
+            "",
+        )?;
+        Ok(book)
+    }
+
     pub fn from_model(model: Model<'static>) -> Self {
         let um: UserModel<'static> = UserModel::from_model(model);
         Self::new(um)
@@ -250,6 +385,18 @@ impl Book {
         Ok(())
     }
 
+    pub fn csv_for_range<W>(&self, range: &AddressRange, sink: W) -> Result<()>
+    where
+        W: std::io::Write,
+    {
+        let rows = self.get_rows_for_range(range)?;
+        let mut writer = csv::Writer::from_writer(sink);
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        Ok(())
+    }
+
     /// Construct a payload of (html, csv_text) for a sheet.
     pub fn sheeet_to_clipboard_content(&self, sheet: u32) -> Result<(String, String), anyhow::Error> {
         let rows = self.get_export_rows_for_sheet(sheet)?;
@@ -318,8 +465,54 @@ impl Book {
 
     /// Evaluate the spreadsheet calculating formulas and style changes.
     /// This can be an expensive operation.
+    ///
+    /// In manual calculation mode this only marks the values as out of date.
     pub fn evaluate(&mut self) {
+        if self.manual_calc {
+            self.needs_recalc = true;
+        } else {
+            self.recalculate();
+        }
+    }
+
+    /// Evaluate the spreadsheet even in manual calculation mode.
+    pub fn recalculate(&mut self) {
         self.model.evaluate();
+        self.needs_recalc = false;
+    }
+
+    /// Turn manual calculation mode on or off. Turning it off catches up on
+    /// any pending recalculation.
+    pub fn set_manual_calc(&mut self, manual: bool) {
+        if manual == self.manual_calc {
+            return;
+        }
+        self.manual_calc = manual;
+        if manual {
+            self.model.pause_evaluation();
+        } else {
+            self.model.resume_evaluation();
+            if self.needs_recalc {
+                self.recalculate();
+            }
+        }
+    }
+
+    /// What `formula` evaluates to when entered in the current cell.
+    ///
+    /// The formula is evaluated in a copy of the workbook so the book itself
+    /// is left alone. Like [`Book::evaluate`] this can be expensive.
+    pub fn preview_formula(&self, formula: &str) -> Result<String> {
+        let bytes = self.model.get_model().to_bytes();
+        let mut model = Model::from_bytes(&bytes, "en").map_err(|e| anyhow!(e))?;
+        let Address { sheet, row, col } = self.location;
+        model
+            .set_user_input(sheet, row as i32, col as i32, formula.to_string())
+            .map_err(|e| anyhow!(e))?;
+        model.evaluate();
+        model
+            .get_formatted_cell_value(sheet, row as i32, col as i32)
+            .map_err(|e| anyhow!(e))
     }
 
     /// Construct a new book from a path.
@@ -341,9 +534,135 @@ impl Book {
         Ok(())
     }
 
+    /// Save every sheet in the book to a csv file named after the sheet in `dir`.
+    /// The directory is created if it does not exist. Sheets without any content
+    /// are skipped.
+    ///
+    /// Returns the paths that were written and the names of the skipped sheets.
+    pub fn save_all_sheets_to_csv<P: AsRef<Path>>(
+        &self,
+        dir: P,
+    ) -> Result<(Vec<PathBuf>, Vec<String>)> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("Unable to create directory {}: {}", dir.display(), e))?;
+        let mut used_names = std::collections::BTreeSet::new();
+        let mut written = Vec::new();
+        let mut skipped = Vec::new();
+        for (idx, (name, _id)) in self.get_all_sheets_identifiers().into_iter().enumerate() {
+            let rows = self.get_export_rows_for_sheet(idx as u32)?;
+            if rows.iter().all(|row| row.iter().all(|cell| cell.is_empty())) {
+                skipped.push(name);
+                continue;
+            }
+            let base = sanitize_file_name(&name);
+            let mut file_name = base.clone();
+            let mut suffix = 1;
+            while used_names.contains(&file_name) {
+                file_name = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+            used_names.insert(file_name.clone());
+            let path = dir.join(format!("{}.csv", file_name));
+            self.save_sheet_to_csv(idx as u32, &path.to_string_lossy())?;
+            written.push(path);
+        }
+        Ok((written, skipped))
+    }
+
+    /// Save an address range in the book to a csv file
+    pub fn save_range_to_csv(&self, range: &AddressRange, path: &str) -> Result<()> {
+        let file_path = std::path::Path::new(path);
+        let file = std::fs::File::create(file_path)?;
+        let writer = std::io::BufWriter::new(file);
+        self.csv_for_range(range, writer)?;
+        Ok(())
+    }
+
+    /// Render a sheet in the book as a GitHub-flavored Markdown table.
+    /// The first row of data is used as the table header.
+    pub fn markdown_for_sheet(&self, sheet: u32) -> Result<String> {
+        let worksheet = self
+            .model
+            .get_model()
+            .workbook
+            .worksheet(sheet)
+            .map_err(|e| anyhow!(e))?;
+        let mut max_row = 1;
+        let mut max_col = 1;
+        for (r, cols) in worksheet.sheet_data.iter() {
+            max_row = max(*r, max_row);
+            for (c, _) in cols.iter() {
+                max_col = max(*c, max_col);
+            }
+        }
+        let start = Address {
+            sheet,
+            row: 1,
+            col: 1,
+        };
+        let end = Address {
+            sheet,
+            row: max_row as usize,
+            col: max_col as usize,
+        };
+        self.markdown_for_range(&AddressRange {
+            start: &start,
+            end: &end,
+        })
+    }
+
+    /// Render an address range as a GitHub-flavored Markdown table.
+    /// The first row of the range is used as the table header.
+    pub fn markdown_for_range(&self, range: &AddressRange) -> Result<String> {
+        let rows = self.get_rows_for_range(range)?;
+        let aligns = self.get_markdown_alignments(range);
+        Ok(rows_to_markdown(&rows, &aligns))
+    }
+
+    /// Save a sheet in the book to a markdown file
+    pub fn save_sheet_to_markdown(&self, sheet: u32, path: &str) -> Result<()> {
+        let text = self.markdown_for_sheet(sheet)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Save an address range in the book to a markdown file
+    pub fn save_range_to_markdown(&self, range: &AddressRange, path: &str) -> Result<()> {
+        let text = self.markdown_for_range(range)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Compute the markdown separator marker for each column in the range
+    /// from the first cell in that column with an explicit horizontal alignment.
+    fn get_markdown_alignments(&self, range: &AddressRange) -> Vec<&'static str> {
+        let rows = range.as_rows();
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        (0..width)
+            .map(|ci| {
+                let align = rows
+                    .iter()
+                    .filter_map(|row| self.get_cell_style(&row[ci]))
+                    .filter_map(|style| style.alignment)
+                    .map(|alignment| alignment.horizontal)
+                    .find(|h| {
+                        matches!(
+                            h,
+                            HorizontalAlignment::Left
+                                | HorizontalAlignment::Center
+                                | HorizontalAlignment::Right
+                        )
+                    });
+                markdown_align_marker(align.as_ref())
+            })
+            .collect()
+    }
+
     /// Save book to an xlsx file.
     pub fn save_to_xlsx(&mut self, path: &str) -> Result<()> {
-        // TODO(zaphar): Currently overwrites. Should we prompt in this case?
+        // NOTE(zaphar): This overwrites unconditionally. The ui is responsible
+        // for confirming before it clobbers a different existing file.
         let file_path = std::path::Path::new(path);
         let file = std::fs::File::create(file_path)?;
         let writer = std::io::BufWriter::new(file);
@@ -358,7 +677,8 @@ impl Book {
         self.model
             .get_worksheets_properties()
             .iter()
-            .map(|sheet| (sheet.name.to_owned(), sheet.sheet_id))
+            .enumerate()
+            .map(|(idx, sheet)| (sheet.name.to_owned(), idx as u32))
             .collect()
     }
 
@@ -371,7 +691,7 @@ impl Book {
         self.model
             .rename_sheet(idx, sheet_name)
             .map_err(|e| anyhow!(e))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -384,7 +704,7 @@ impl Book {
         self.model
             .set_selected_sheet(self.location.sheet)
             .map_err(|e| anyhow!(e))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -394,16 +714,47 @@ impl Book {
     }
 
     /// Move to a specific sheet location in the current sheet
-    pub fn move_to(&mut self, Address { sheet: _, row, col }: &Address) -> Result<()> {
-        // FIXME(zaphar): Check that this is safe first.
+    ///
+    /// Returns an error if the address is outside of the sheet or names a
+    /// sheet that doesn't exist.
+    pub fn move_to(&mut self, Address { sheet, row, col }: &Address) -> Result<()> {
+        if *sheet as usize >= self.model.get_model().workbook.worksheets.len() {
+            return Err(anyhow!("Invalid sheet index: {}", sheet));
+        }
+        if *row < 1 || *row > LAST_ROW as usize {
+            return Err(anyhow!("Row {} is out of range", row));
+        }
+        if *col < 1 || *col > LAST_COLUMN as usize {
+            return Err(anyhow!("Column {} is out of range", col));
+        }
         self.location.row = *row;
         self.location.col = *col;
-        self.dirty = true;
         Ok(())
     }
 
     /// Extend a cell to the rest of the range.
+    ///
+    /// When the first cells of every column, or of the row for a single row
+    /// range, start a pattern like `1, 2` or `Jan, Feb` the pattern is
+    /// continued instead. See [`Book::extend_patterns`].
+    ///
+    /// Both ends of the range must be on the same sheet. That sheet doesn't
+    /// need to be the current one.
     pub fn extend_to(&mut self, from: &Address, to: &Address) -> Result<()> {
+        if from.sheet != to.sheet {
+            return Err(anyhow!(
+                "Can't extend {} on sheet {} to {} on sheet {}",
+                from.to_range_part(),
+                from.sheet,
+                to.to_range_part(),
+                to.sheet
+            ));
+        }
+        if self.extend_patterns(from, to)? {
+            self.evaluate();
+            self.mark_changed();
+            return Ok(());
+        }
         for cell in (AddressRange {
             start: from,
             end: to,
@@ -416,7 +767,7 @@ impl Book {
                 .model
                 .get_model()
                 .extend_to(
-                    self.location.sheet,
+                    from.sheet,
                     from.row as i32,
                     from.col as i32,
                     cell.row as i32,
@@ -425,7 +776,7 @@ impl Book {
                 .map_err(|e| anyhow!(e))?;
             self.model
                 .set_user_input(
-                    self.location.sheet,
+                    cell.sheet,
                     cell.row as i32,
                     cell.col as i32,
                     &contents,
@@ -433,22 +784,277 @@ impl Book {
                 .map_err(|e| anyhow!(e))?;
         }
         self.evaluate();
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
+    /// Remove the rows of a range that repeat an earlier row, comparing the
+    /// rendered values. Rows that are completely empty are never duplicates.
+    ///
+    /// The duplicates are cleared or, with `shift_up`, the rows that are kept
+    /// move up to close the gaps and the end of the range is cleared.
+    /// Formulas that move keep their relative references pointing the same
+    /// way. Returns how many rows were removed.
+    pub fn dedupe_range(&mut self, range: &AddressRange, shift_up: bool) -> Result<usize> {
+        let rows = range.as_rows();
+        let mut seen = HashSet::new();
+        let mut kept = Vec::with_capacity(rows.len());
+        let mut duplicates = Vec::new();
+        for row in rows.iter() {
+            let values = row
+                .iter()
+                .map(|cell| self.get_cell_addr_rendered(cell))
+                .collect::<Result<Vec<String>>>()?;
+            if values.iter().all(|v| v.is_empty()) || seen.insert(values) {
+                kept.push(row);
+            } else {
+                duplicates.push(row);
+            }
+        }
+        if duplicates.is_empty() {
+            return Ok(0);
+        }
+        if shift_up {
+            let model = self.model.get_model();
+            let mut moved = Vec::new();
+            for (target, row) in rows.iter().zip(kept.iter()) {
+                for (to, from) in target.iter().zip(row.iter()) {
+                    let contents = model
+                        .extend_to(
+                            from.sheet,
+                            from.row as i32,
+                            from.col as i32,
+                            to.row as i32,
+                            to.col as i32,
+                        )
+                        .map_err(|e| anyhow!(e))?;
+                    moved.push((to.clone(), contents));
+                }
+            }
+            let (first, last) = (&rows[0][0], &rows[rows.len() - 1][rows[0].len() - 1]);
+            self.clear_cell_range(first.clone(), last.clone())?;
+            for (to, contents) in moved {
+                if !contents.is_empty() {
+                    self.update_cell(&to, contents)?;
+                }
+            }
+        } else {
+            for row in duplicates.iter() {
+                self.clear_cell_range(row[0].clone(), row[row.len() - 1].clone())?;
+            }
+        }
+        self.mark_changed();
+        self.evaluate();
+        Ok(duplicates.len())
+    }
+
+    /// Rewrite every text cell in a range with `transform`. Text that ends
+    /// up empty clears the cell. Returns how many cells changed.
+    fn map_text_range<F>(&mut self, range: &AddressRange, transform: F) -> Result<usize>
+    where
+        F: Fn(&str) -> String,
+    {
+        let mut changed = 0;
+        for cell in range.as_series() {
+            let Some(text) = self.get_cell_addr_text(&cell)? else {
+                continue;
+            };
+            let new_text = transform(&text);
+            if new_text == text {
+                continue;
+            }
+            if new_text.is_empty() {
+                self.clear_cell_contents(cell)?;
+            } else {
+                self.update_cell(&cell, new_text)?;
+            }
+            changed += 1;
+        }
+        if changed > 0 {
+            self.evaluate();
+        }
+        Ok(changed)
+    }
+
+    /// Strip the whitespace around the text cells of a range. With
+    /// `collapse_inner` runs of whitespace inside the text become a single
+    /// space as well. Returns how many cells changed.
+    pub fn trim_range(&mut self, range: &AddressRange, collapse_inner: bool) -> Result<usize> {
+        self.map_text_range(range, |text| {
+            if collapse_inner {
+                text.split_whitespace().collect::<Vec<&str>>().join(" ")
+            } else {
+                text.trim().to_string()
+            }
+        })
+    }
+
+    /// Add `delta` to a cell holding a plain number like `-12.50`. Numbers
+    /// keep as many decimal places as they had. Returns false and leaves the
+    /// cell alone if it holds anything else, formulas and text like `nan` or
+    /// `1e3` included.
+    pub fn add_to_number(&mut self, cell: &Address, delta: f64) -> Result<bool> {
+        let contents = self.get_cell_addr_contents(cell)?;
+        let text = contents.trim();
+        let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !digits(whole) || !digits(fraction) {
+            return Ok(false);
+        }
+        let Ok(number) = text.parse::<f64>() else {
+            return Ok(false);
+        };
+        let decimals = fraction.len();
+        self.update_cell(cell, format!("{:.*}", decimals, number + delta))?;
+        Ok(true)
+    }
+
+    /// Change the case of the text cells of a range. Returns how many cells
+    /// changed.
+    pub fn change_case_range(&mut self, range: &AddressRange, mode: CaseMode) -> Result<usize> {
+        self.map_text_range(range, |text| mode.apply(text))
+    }
+
+    /// Split the text in the first column of a range on `delimiter` and
+    /// write the pieces into the columns to the right. Those columns are
+    /// overwritten unless `insert` is set, in which case enough new columns
+    /// are inserted first. Delimiters inside double quotes don't split.
+    ///
+    /// Returns the most pieces any cell was split into. Cells without the
+    /// delimiter are left alone.
+    pub fn split_column(
+        &mut self,
+        range: &AddressRange,
+        delimiter: &str,
+        insert: bool,
+    ) -> Result<usize> {
+        let col = std::cmp::min(range.start.col, range.end.col);
+        let mut splits = Vec::new();
+        for row in range.as_rows() {
+            let cell = Address {
+                col,
+                ..row[0].clone()
+            };
+            let Some(text) = self.get_cell_addr_text(&cell)? else {
+                continue;
+            };
+            let pieces: Vec<String> = split_outside_quotes(&text, delimiter)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            if pieces.len() > 1 {
+                splits.push((cell, pieces));
+            }
+        }
+        let most = splits.iter().map(|(_, p)| p.len()).max().unwrap_or(1);
+        if splits.is_empty() {
+            return Ok(most);
+        }
+        if insert {
+            self.insert_columns(col + 1, most - 1)?;
+        }
+        for (cell, pieces) in splits {
+            for (offset, piece) in pieces.iter().enumerate() {
+                let target = Address {
+                    col: cell.col + offset,
+                    ..cell.clone()
+                };
+                if piece.is_empty() {
+                    self.clear_cell_contents(target)?;
+                } else {
+                    self.update_cell(&target, piece)?;
+                }
+            }
+        }
+        self.evaluate();
+        Ok(most)
+    }
+
+    /// Fill a range with a series counting up from `start` by `step`. Each
+    /// column gets the series going down, unless the range is a single row
+    /// in which case the series goes across. Dates step by days and the
+    /// range is given a date format. Returns how many cells were filled.
+    pub fn fill_series(
+        &mut self,
+        range: &AddressRange,
+        start: SeriesStart,
+        step: f64,
+    ) -> Result<usize> {
+        let rows = range.as_rows();
+        let across = rows.len() == 1;
+        let mut filled = 0;
+        for (ri, row) in rows.iter().enumerate() {
+            for (ci, cell) in row.iter().enumerate() {
+                let idx = (if across { ci } else { ri }) as f64;
+                let value = match start {
+                    SeriesStart::Number(n) => format_series_number(n + idx * step),
+                    SeriesStart::Date(date) => {
+                        format_series_number(date_to_serial(date) as f64 + idx * step)
+                    }
+                };
+                self.update_cell(cell, value)?;
+                filled += 1;
+            }
+        }
+        if let SeriesStart::Date(_) = start {
+            let (first, last) = (&rows[0][0], &rows[rows.len() - 1][rows[0].len() - 1]);
+            let area = calculate_area(first.sheet, first, last);
+            self.set_cell_style(&[("num_fmt", "yyyy-mm-dd")], &area)?;
+        }
+        self.evaluate();
+        Ok(filled)
+    }
+
+    /// Continue the pattern at the start of each line of a range over the
+    /// rest of the line. The pattern ends at the first empty cell and every
+    /// cell after it is overwritten, like a fill. The lines run from `from`
+    /// towards `to` down each column, or across when the range is a single
+    /// row. A pattern is two or more numbers stepping evenly, or month or
+    /// weekday names.
+    ///
+    /// Nothing changes and false is returned unless every line has a
+    /// pattern.
+    fn extend_patterns(&mut self, from: &Address, to: &Address) -> Result<bool> {
+        let mut updates = Vec::new();
+        for line in extend_lines(from, to) {
+            let mut source = Vec::new();
+            for cell in line.iter() {
+                let contents = self.get_cell_addr_contents(cell)?;
+                if contents.is_empty() {
+                    break;
+                }
+                let number = if contents.starts_with('=') {
+                    None
+                } else {
+                    self.get_cell_addr_number(cell)
+                };
+                source.push((contents, number));
+            }
+            let targets = &line[source.len()..];
+            match continue_pattern(&source, targets.len()) {
+                Some(values) => updates.extend(targets.iter().cloned().zip(values)),
+                None => return Ok(false),
+            }
+        }
+        for (cell, value) in updates {
+            self.update_cell(&cell, value)?;
+        }
+        Ok(true)
+    }
+
     pub fn clear_current_cell(&mut self) -> Result<()> {
-        self.dirty = true;
+        self.mark_changed();
         self.clear_cell_contents(self.location.clone())
     }
 
     pub fn clear_current_cell_all(&mut self) -> Result<()> {
-        self.dirty = true;
+        self.mark_changed();
         self.clear_cell_all(self.location.clone())
     }
 
     pub fn clear_cell_contents(&mut self, Address { sheet, row, col }: Address) -> Result<()> {
-        self.dirty = true;
+        self.mark_changed();
         Ok(self
             .model
             .range_clear_contents(&Area {
@@ -466,12 +1072,12 @@ impl Book {
         self.model
             .range_clear_contents(&area)
             .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
     pub fn clear_cell_all(&mut self, Address { sheet, row, col }: Address) -> Result<()> {
-        self.dirty = true;
+        self.mark_changed();
         Ok(self
             .model
             .range_clear_all(&Area {
@@ -489,7 +1095,7 @@ impl Book {
         self.model
             .range_clear_all(&area)
             .map_err(|s| anyhow!("Unable to clear cell contents {}", s))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -530,7 +1136,7 @@ impl Book {
                 .update_range_style(area, path, val)
                 .map_err(|s| anyhow!("Unable to format cell {}", s))?;
         }
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -575,7 +1181,7 @@ impl Book {
     ) -> Result<()> {
         let area = self.get_col_range(sheet, col_idx);
         self.set_cell_style(style, &area)?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -600,7 +1206,7 @@ impl Book {
     ) -> Result<()> {
         let area = self.get_row_range(sheet, row_idx);
         self.set_cell_style(style, &area)?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -620,6 +1226,175 @@ impl Book {
             .map_err(|s| anyhow!("Unable to format cell {}", s))?)
     }
 
+    /// Get a cells contents if it holds plain text. Formulas, numbers and
+    /// empty cells give `None`.
+    pub fn get_cell_addr_text(&self, addr: &Address) -> Result<Option<String>> {
+        let contents = self.get_cell_addr_contents(addr)?;
+        if contents.starts_with('=') {
+            return Ok(None);
+        }
+        match self.model.get_model().get_cell_value_by_index(
+            addr.sheet,
+            addr.row as i32,
+            addr.col as i32,
+        ) {
+            Ok(CellValue::String(_)) => Ok(Some(contents)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Get a cells computed value if it is a number.
+    pub fn get_cell_addr_number(&self, Address { sheet, row, col }: &Address) -> Option<f64> {
+        match self
+            .model
+            .get_model()
+            .get_cell_value_by_index(*sheet, *row as i32, *col as i32)
+        {
+            Ok(CellValue::Number(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Whether a cells computed value is an error like `#DIV/0!`.
+    pub fn is_cell_addr_error(&self, Address { sheet, row, col }: &Address) -> bool {
+        match self
+            .model
+            .get_model()
+            .get_cell_value_by_index(*sheet, *row as i32, *col as i32)
+        {
+            Ok(CellValue::String(s)) => ERROR_VALUES.contains(&s.as_str()),
+            _ => false,
+        }
+    }
+
+    /// The last row of the current sheet with contents in column `col`. An
+    /// empty column falls back to the last used row of the sheet.
+    pub fn get_last_row_in_column(&self, col: usize) -> Result<usize> {
+        let sheet = self.location.sheet;
+        let mut last = None;
+        for (ri, cols) in self.get_sheet_data()?.iter() {
+            let row = *ri as usize;
+            if cols.contains_key(&(col as i32))
+                && last.map_or(true, |last| row > last)
+                && !self
+                    .get_cell_addr_contents(&Address { sheet, row, col })?
+                    .is_empty()
+            {
+                last = Some(row);
+            }
+        }
+        match last {
+            Some(row) => Ok(row),
+            None => Ok(self.get_dimensions()?.max_row as usize),
+        }
+    }
+
+    /// The cells of the current sheet whose value is an error, row by row.
+    pub fn get_error_cells(&self) -> Result<Vec<Address>> {
+        let sheet = self.location.sheet;
+        let mut cells = Vec::new();
+        for (ri, cols) in self.get_sheet_data()?.iter() {
+            for (ci, _) in cols.iter() {
+                let addr = Address {
+                    sheet,
+                    row: *ri as usize,
+                    col: *ci as usize,
+                };
+                if self.is_cell_addr_error(&addr) {
+                    cells.push(addr);
+                }
+            }
+        }
+        cells.sort_by_key(|addr| (addr.row, addr.col));
+        Ok(cells)
+    }
+
+    /// The cells and ranges the formula at `addr` refers to as `(start, end)`
+    /// pairs. References to sheets that don't exist are left out.
+    pub fn get_precedents(&self, addr: &Address) -> Result<Vec<(Address, Address)>> {
+        let contents = self.get_cell_addr_contents(addr)?;
+        if !contents.starts_with('=') {
+            return Ok(Vec::new());
+        }
+        let mut ranges = Vec::new();
+        for reference in formula::find_references(&contents) {
+            let sheet = match &reference.sheet {
+                Some(name) => match self.sheet_index_by_name(name) {
+                    Some(idx) => idx,
+                    None => continue,
+                },
+                None => addr.sheet,
+            };
+            let (row, col) = reference.start;
+            let start = Address { sheet, row, col };
+            let (row, col) = reference.end;
+            ranges.push((start, Address { sheet, row, col }));
+        }
+        Ok(ranges)
+    }
+
+    /// The cells in any sheet with a formula referring to `addr`.
+    pub fn get_dependents(&self, addr: &Address) -> Result<Vec<Address>> {
+        let mut cells = Vec::new();
+        let worksheets = &self.model.get_model().workbook.worksheets;
+        for (sheet, worksheet) in worksheets.iter().enumerate() {
+            let sheet = sheet as u32;
+            let mut found = Vec::new();
+            for (ri, cols) in worksheet.sheet_data.iter() {
+                for (ci, _) in cols.iter() {
+                    let cell = Address {
+                        sheet,
+                        row: *ri as usize,
+                        col: *ci as usize,
+                    };
+                    let contents = self.get_cell_addr_contents(&cell)?;
+                    if !contents.starts_with('=') {
+                        continue;
+                    }
+                    let refers = formula::find_references(&contents).iter().any(|r| {
+                        let target = match &r.sheet {
+                            Some(name) => self.sheet_index_by_name(name),
+                            None => Some(sheet),
+                        };
+                        target == Some(addr.sheet) && r.contains(addr.row, addr.col)
+                    });
+                    if refers {
+                        found.push(cell);
+                    }
+                }
+            }
+            found.sort_by_key(|cell| (cell.row, cell.col));
+            cells.extend(found);
+        }
+        Ok(cells)
+    }
+
+    /// The index of the sheet called `name` ignoring case like formulas do.
+    fn sheet_index_by_name(&self, name: &str) -> Option<u32> {
+        self.model
+            .get_model()
+            .workbook
+            .worksheets
+            .iter()
+            .position(|sheet| sheet.name.eq_ignore_ascii_case(name))
+            .map(|idx| idx as u32)
+    }
+
+    /// The top left and bottom right cells of `reference`. A reference without
+    /// a sheet is on the current sheet.
+    pub fn reference_range(&self, reference: &formula::Reference) -> Result<(Address, Address)> {
+        let sheet = match &reference.sheet {
+            Some(name) => self
+                .sheet_index_by_name(name)
+                .ok_or_else(|| anyhow!("No sheet named {}", name))?,
+            None => self.location.sheet,
+        };
+        let (row, col) = reference.start;
+        let start = Address { sheet, row, col };
+        let (row, col) = reference.end;
+        Ok((start, Address { sheet, row, col }))
+    }
+
     /// Get a cells actual content as a string.
     pub fn get_current_cell_contents(&self) -> Result<String> {
         Ok(self
@@ -635,7 +1410,7 @@ impl Book {
     /// Update the current cell in a book.
     /// This update won't be reflected until you call `Book::evaluate`.
     pub fn edit_current_cell<S: AsRef<str>>(&mut self, value: S) -> Result<()> {
-        self.dirty = true;
+        self.mark_changed();
         self.update_cell(&self.location.clone(), value)?;
         Ok(())
     }
@@ -652,7 +1427,7 @@ impl Book {
                 value.as_ref(),
             )
             .map_err(|e| anyhow!("Invalid cell contents: {}", e))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -670,7 +1445,7 @@ impl Book {
                 col: self.location.col,
             })?;
         }
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -688,7 +1463,7 @@ impl Book {
                 col: self.location.col + count,
             })?;
         }
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
@@ -714,6 +1489,27 @@ impl Book {
             / COL_PIXELS) as usize)
     }
 
+    /// Get the sizes of `count` columns starting at `start_idx` on a sheet.
+    ///
+    /// This looks the sheet up once so it is cheaper than calling
+    /// [`Book::get_column_size_for_sheet`] for each column.
+    pub fn get_col_sizes(&self, sheet: u32, start_idx: usize, count: usize) -> Result<Vec<usize>> {
+        let worksheet = self
+            .model
+            .get_model()
+            .workbook
+            .worksheet(sheet)
+            .map_err(|e| anyhow!("Invalid Worksheet: {}", e))?;
+        (start_idx..start_idx + count)
+            .map(|idx| {
+                Ok((worksheet
+                    .get_column_width(idx as i32)
+                    .map_err(|e| anyhow!("Error getting column width: {:?}", e))?
+                    / COL_PIXELS) as usize)
+            })
+            .collect()
+    }
+
     pub fn set_col_size(&mut self, col: usize, width: usize) -> Result<()> {
         self.set_column_size_for_sheet(self.location.sheet, col, width)
     }
@@ -727,12 +1523,15 @@ impl Book {
         self.model
             .set_columns_width(sheet, col as i32, col as i32, width as f64 * COL_PIXELS)
             .map_err(|e| anyhow!("Error setting column width: {:?}", e))?;
-        self.dirty = true;
+        self.mark_changed();
         Ok(())
     }
 
     // Get the size of the current sheet as a `(row_count, column_count)`
     pub fn get_size(&self) -> Result<(usize, usize)> {
+        if let Some(size) = self.size_cache.borrow().get(&self.location.sheet) {
+            return Ok(*size);
+        }
         let sheet = &self.get_sheet()?.sheet_data;
         let mut row_count = 0 as i32;
         let mut col_count = 0 as i32;
@@ -742,7 +1541,25 @@ impl Book {
                 col_count = max(*ci, col_count);
             }
         }
-        Ok((row_count as usize, col_count as usize))
+        let size = (row_count as usize, col_count as usize);
+        self.size_cache
+            .borrow_mut()
+            .insert(self.location.sheet, size);
+        Ok(size)
+    }
+
+    /// Make the sheet at position `idx` the current sheet.
+    ///
+    /// [`Address::sheet`] is always the position of the sheet in the workbook
+    /// and every way of selecting a sheet goes through here so the model's
+    /// selected sheet stays in step with our location.
+    pub fn set_current_sheet_index(&mut self, idx: u32) -> Result<()> {
+        if idx as usize >= self.model.get_model().workbook.worksheets.len() {
+            return Err(anyhow!("Invalid sheet index: {}", idx));
+        }
+        self.model.set_selected_sheet(idx).map_err(|e| anyhow!(e))?;
+        self.location.sheet = idx;
+        Ok(())
     }
 
     /// Select a sheet by name.
@@ -756,8 +1573,7 @@ impl Book {
             .enumerate()
             .find(|(_idx, sheet)| sheet.name == name)
         {
-            self.location.sheet = idx as u32;
-            return true;
+            return self.set_current_sheet_index(idx as u32).is_ok();
         }
         false
     }
@@ -773,10 +1589,8 @@ impl Book {
         if next == len {
             next = 0;
         }
-        self.model
-            .set_selected_sheet(next)
+        self.set_current_sheet_index(next)
             .expect("Unexpected error selecting sheet");
-        self.location.sheet = next;
     }
 
     pub fn select_prev_sheet(&mut self) {
@@ -786,13 +1600,12 @@ impl Book {
         } else {
             self.location.sheet - 1
         };
-        self.model
-            .set_selected_sheet(next)
+        self.set_current_sheet_index(next)
             .expect("Unexpected error selecting sheet");
-        self.location.sheet = next;
     }
 
-    /// Select a sheet by id.
+    /// Select a sheet by its ironcalc sheet id. The id stays the same when
+    /// sheets move around but it is not the index used in [`Address`].
     pub fn select_sheet_by_id(&mut self, id: u32) -> bool {
         if let Some((idx, _sheet)) = self
             .model
@@ -803,11 +1616,7 @@ impl Book {
             .enumerate()
             .find(|(_idx, sheet)| sheet.sheet_id == id)
         {
-            self.model
-                .set_selected_sheet(idx as u32)
-                .expect("Unexpected error selecting sheet");
-            self.location.sheet = idx as u32;
-            return true;
+            return self.set_current_sheet_index(idx as u32).is_ok();
         }
         false
     }
@@ -845,6 +1654,58 @@ impl Book {
     }
 }
 
+/// The name of a 1-based column index: 1 is `A`, 26 is `Z`, 27 is `AA` and so
+/// on. Column 0 has no name and yields an empty string.
+pub fn col_to_name(mut col: usize) -> String {
+    let mut bytes = Vec::new();
+    while col > 0 {
+        bytes.push(b'A' + ((col - 1) % 26) as u8);
+        col = (col - 1) / 26;
+    }
+    bytes.reverse();
+    String::from_utf8(bytes).expect("Column names are ascii")
+}
+
+/// The 1-based column index for a column name like `AB`. Letters are case
+/// insensitive. Returns `None` for anything that isn't a column name.
+pub fn name_to_col(name: &str) -> Option<usize> {
+    if name.is_empty() {
+        return None;
+    }
+    let mut col: usize = 0;
+    for c in name.chars() {
+        if !c.is_ascii_alphabetic() {
+            return None;
+        }
+        col = col
+            .checked_mul(26)?
+            .checked_add((c.to_ascii_uppercase() as u8 - b'A') as usize + 1)?;
+    }
+    Some(col)
+}
+
+/// Parse an A1 style cell reference like `B12` or `$AA$3` into an [`Address`]
+/// on the first sheet. Column letters are case insensitive.
+pub fn parse_cell_ref(input: &str) -> Result<Address> {
+    let invalid = || anyhow!("Invalid cell reference: {}", input);
+    let reference = input.trim();
+    let reference = reference.strip_prefix('$').unwrap_or(reference);
+    let split = reference
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(reference.len());
+    let (letters, digits) = reference.split_at(split);
+    let digits = digits.strip_prefix('$').unwrap_or(digits);
+    if letters.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let col = name_to_col(letters).ok_or_else(invalid)?;
+    let row: usize = digits.parse().map_err(|_| invalid())?;
+    if row < 1 || row > LAST_ROW as usize || col > LAST_COLUMN as usize {
+        return Err(anyhow!("Cell reference out of range: {}", input));
+    }
+    Ok(Address::new(row, col))
+}
+
 pub fn rows_to_clipboard_content(rows: &Vec<Vec<String>>) -> std::result::Result<(String, String), anyhow::Error> {
     use htmf::prelude::*;
     let table = table([]);
@@ -864,6 +1725,208 @@ pub fn rows_to_clipboard_content(rows: &Vec<Vec<String>>) -> std::result::Result
     Ok((table.with(table_rows).to_html(), String::from_utf8_lossy(&csv_content).to_string()))
 }
 
+/// Construct a GitHub-flavored Markdown table from rows of cell text.
+/// The first row becomes the header and `aligns` supplies the separator
+/// marker for each column. Pipes and newlines in cells are escaped.
+pub fn rows_to_markdown(rows: &[Vec<String>], aligns: &[&str]) -> String {
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    if width == 0 {
+        return String::new();
+    }
+    let format_row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let escape_row = |row: &Vec<String>| {
+        (0..width)
+            .map(|ci| {
+                row.get(ci)
+                    .map(|cell| cell.replace('|', "\\|").replace('\n', "<br>"))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<String>>()
+    };
+    let mut out = String::new();
+    let mut rows = rows.iter();
+    if let Some(header) = rows.next() {
+        out.push_str(&format_row(escape_row(header)));
+    }
+    out.push_str(&format_row(
+        (0..width)
+            .map(|ci| aligns.get(ci).copied().unwrap_or("---").to_string())
+            .collect(),
+    ));
+    for row in rows {
+        out.push_str(&format_row(escape_row(row)));
+    }
+    out
+}
+
+/// Replace any characters that are unsafe in a file name with `_`.
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.is_empty() || sanitized.chars().all(|c| c == '.') {
+        String::from("sheet")
+    } else {
+        sanitized
+    }
+}
+
+fn markdown_align_marker(align: Option<&HorizontalAlignment>) -> &'static str {
+    match align {
+        Some(HorizontalAlignment::Left) => ":---",
+        Some(HorizontalAlignment::Center) => ":---:",
+        Some(HorizontalAlignment::Right) => "---:",
+        _ => "---",
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// The lines `Book::extend_patterns` fills. Each column of the range going
+/// from `from`'s row towards `to`'s, or just the row if there is only one.
+fn extend_lines(from: &Address, to: &Address) -> Vec<Vec<Address>> {
+    let rows = directional_range(from.row, to.row);
+    let cols = directional_range(from.col, to.col);
+    let sheet = from.sheet;
+    if rows.len() == 1 {
+        let row = from.row;
+        return vec![cols
+            .iter()
+            .map(|&col| Address { sheet, row, col })
+            .collect()];
+    }
+    cols.iter()
+        .map(|&col| {
+            rows.iter()
+                .map(|&row| Address { sheet, row, col })
+                .collect()
+        })
+        .collect()
+}
+
+/// The next `count` values after `source`, which holds the contents of the
+/// first cells of a line and their value if they are a number.
+fn continue_pattern(source: &[(String, Option<f64>)], count: usize) -> Option<Vec<String>> {
+    let numbers = source
+        .iter()
+        .map(|(_, n)| *n)
+        .collect::<Option<Vec<f64>>>()
+        .filter(|numbers| numbers.len() >= 2);
+    if let Some(numbers) = numbers {
+        let last = numbers[numbers.len() - 1];
+        let step = last - numbers[numbers.len() - 2];
+        return Some(
+            (1..=count)
+                .map(|i| format_series_number(last + i as f64 * step))
+                .collect(),
+        );
+    }
+    continue_names(source, &MONTH_NAMES, count)
+        .or_else(|| continue_names(source, &WEEKDAY_NAMES, count))
+}
+
+/// Carry on a cycle of names like months. The names come out abbreviated
+/// and in upper or lower case if the last name in `source` was.
+fn continue_names(
+    source: &[(String, Option<f64>)],
+    names: &[&str],
+    count: usize,
+) -> Option<Vec<String>> {
+    let idxs = source
+        .iter()
+        .map(|(text, _)| name_index(text, names))
+        .collect::<Option<Vec<usize>>>()?;
+    let last = *idxs.last()?;
+    let step = match idxs.len() {
+        1 => 1,
+        n => (idxs[n - 1] + names.len() - idxs[n - 2]) % names.len(),
+    };
+    let sample = source[source.len() - 1].0.trim();
+    let short = sample.chars().count() == 3;
+    let upper = sample.to_uppercase() == sample && sample.to_lowercase() != sample;
+    let lower = sample.to_lowercase() == sample;
+    Some(
+        (1..=count)
+            .map(|i| {
+                let name = names[(last + i * step) % names.len()];
+                let name = if short { &name[..3] } else { name };
+                if upper {
+                    name.to_uppercase()
+                } else if lower {
+                    name.to_lowercase()
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Where `text` is in `names` matching either the whole name or its first
+/// three letters and ignoring case.
+fn name_index(text: &str, names: &[&str]) -> Option<usize> {
+    let text = text.trim().to_lowercase();
+    names.iter().position(|name| {
+        let name = name.to_lowercase();
+        text == name || text == name[..3]
+    })
+}
+
+/// Split `text` on `delimiter` except where the delimiter is between double
+/// quotes.
+fn split_outside_quotes<'t>(text: &'t str, delimiter: &str) -> Vec<&'t str> {
+    if delimiter.is_empty() {
+        return vec![text];
+    }
+    let mut pieces = Vec::new();
+    let mut in_quotes = false;
+    let mut piece_start = 0;
+    let mut pos = 0;
+    while pos < text.len() {
+        if text[pos..].starts_with('"') {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && text[pos..].starts_with(delimiter) {
+            pieces.push(&text[piece_start..pos]);
+            pos += delimiter.len();
+            piece_start = pos;
+            continue;
+        }
+        pos += text[pos..].chars().next().map_or(1, |c| c.len_utf8());
+    }
+    pieces.push(&text[piece_start..]);
+    pieces
+}
+
 fn calculate_area(sheet: u32, start: &Address, end: &Address) -> Area {
     let area = Area {
         sheet,
@@ -877,17 +1940,6 @@ fn calculate_area(sheet: u32, start: &Address, end: &Address) -> Area {
 
 impl Default for Book {
     fn default() -> Self {
-        let mut book =
-            Book::new(UserModel::new_empty("default_name", "en", "America/New_York", "en").unwrap());
-        book.update_cell(
-            &Address {
-                sheet: 0,
-                row: 1,
-                col: 1,
-            },
-            "",
-        )
-        .unwrap();
-        book
+        Book::new_with_locale("en", "America/New_York").unwrap()
     }
 }
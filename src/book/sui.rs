@@ -88,7 +88,7 @@
 use crate::ui::Address;
 use ironcalc::base::expressions::types::Area;
 use ironcalc::base::types::{HorizontalAlignment, Style, VerticalAlignment};
-use super::{LAST_COLUMN, LAST_ROW};
+use super::{col_to_name, name_to_col, LAST_COLUMN, LAST_ROW, STYLE_PATHS};
 use ironcalc::base::UserModel;
 
 use super::Book;
@@ -274,7 +274,7 @@ pub fn serialize_sui(book: &Book) -> String {
                 if !is_default_style(&style) {
                     let props = serialize_style_props(&style);
                     if !props.is_empty() {
-                        let cell_ref = format!("{}{row}", col_index_to_letters(*col as usize));
+                        let cell_ref = format!("{}{row}", col_to_name(*col as usize));
                         out.push_str(&format!("style {cell_ref} {}\n", props.join(" ")));
                     }
                 }
@@ -299,7 +299,7 @@ pub fn serialize_sui(book: &Book) -> String {
                         continue;
                     }
                     let cell_ref =
-                        format!("{}{row}", col_index_to_letters(col as usize));
+                        format!("{}{row}", col_to_name(col as usize));
                     let value_str = serialize_value(&content);
                     out.push_str(&format!("{cell_ref} = {value_str}\n"));
                 }
@@ -352,7 +352,7 @@ fn parse_cellref(s: &str) -> Option<(usize, usize)> {
     if col_len == 0 || col_len == s.len() {
         return None;
     }
-    let col = col_letters_to_index(&s[..col_len]);
+    let col = name_to_col(&s[..col_len])?;
     let row: usize = s[col_len..].parse().ok()?;
     if row == 0 {
         return None;
@@ -360,21 +360,6 @@ fn parse_cellref(s: &str) -> Option<(usize, usize)> {
     Some((row, col))
 }
 
-fn col_letters_to_index(s: &str) -> usize {
-    s.bytes()
-        .fold(0usize, |acc, b| acc * 26 + (b - b'A' + 1) as usize)
-}
-
-fn col_index_to_letters(mut col: usize) -> String {
-    let mut bytes = Vec::new();
-    while col > 0 {
-        bytes.push(b'A' + ((col - 1) % 26) as u8);
-        col = (col - 1) / 26;
-    }
-    bytes.reverse();
-    String::from_utf8(bytes).unwrap()
-}
-
 fn parse_value(s: &str) -> Option<String> {
     if s.starts_with('"') {
         let inner = s.strip_prefix('"')?.strip_suffix('"')?;
@@ -414,7 +399,7 @@ fn unescape_string(s: &str) -> String {
     result
 }
 
-fn parse_quoted_string(s: &str) -> Option<String> {
+pub(crate) fn parse_quoted_string(s: &str) -> Option<String> {
     let s = s.trim();
     let inner = s.strip_prefix('"')?.strip_suffix('"')?;
     Some(unescape_string(inner))
@@ -613,22 +598,8 @@ fn apply_style_props_area(
     line_num: usize,
     warnings: &mut Vec<ParseWarning>,
 ) {
-    const KNOWN_KEYS: &[&str] = &[
-        "font.b",
-        "font.i",
-        "font.strike",
-        "font.color",
-        "font.u",
-        "fill.bg_color",
-        "fill.fg_color",
-        "num_fmt",
-        "alignment.horizontal",
-        "alignment.vertical",
-        "alignment.wrap_text",
-    ];
-
     for (key, val) in props {
-        if !KNOWN_KEYS.contains(&key.as_str()) {
+        if !STYLE_PATHS.contains(&key.as_str()) {
             warnings.push(ParseWarning {
                 line: line_num,
                 message: format!("unknown style key: {key}"),
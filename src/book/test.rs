@@ -1,8 +1,10 @@
-use ironcalc::base::worksheet::WorksheetDimension;
+use ironcalc::base::{expressions::types::Area, worksheet::WorksheetDimension};
 
 use crate::ui::Address;
 
-use super::{Book, FileFormat};
+use super::{
+    col_to_name, name_to_col, parse_cell_ref, AddressRange, Book, CaseMode, FileFormat, SeriesStart,
+};
 
 #[test]
 fn test_book_default() {
@@ -317,6 +319,134 @@ fn test_range_to_clipboard_content() {
     assert_eq!(csv, expected_csv);
 }
 
+#[test]
+fn test_range_to_csv() {
+    let mut book = Book::default();
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "A1")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 2, col: 2 }, "B2")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 3, col: 3 }, "C3, with comma")
+        .expect("failed to edit cell");
+    book.evaluate();
+
+    let start = Address { sheet: 0, row: 2, col: 2 };
+    let end = Address { sheet: 0, row: 3, col: 3 };
+    let mut out = Vec::new();
+    book.csv_for_range(&super::AddressRange { start: &start, end: &end }, &mut out)
+        .expect("Failed to write csv");
+    assert_eq!(
+        String::from_utf8(out).expect("Invalid utf8"),
+        "B2,\n,\"C3, with comma\"\n"
+    );
+}
+
+#[test]
+fn test_save_all_sheets_to_csv() {
+    let dir = tmp_path("export_all");
+    std::fs::remove_dir_all(&dir).ok();
+    let mut book = Book::default();
+    book.set_sheet_name(0, "Q1&Q2").expect("failed to rename sheet");
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "first")
+        .expect("failed to edit cell");
+    book.new_sheet(Some("Q1 Q2")).expect("failed to create sheet");
+    book.update_cell(&Address { sheet: 1, row: 1, col: 1 }, "second")
+        .expect("failed to edit cell");
+    book.new_sheet(Some("Empty")).expect("failed to create sheet");
+    book.evaluate();
+
+    let (written, skipped) = book
+        .save_all_sheets_to_csv(&dir)
+        .expect("Failed to export all sheets");
+    assert_eq!(
+        written,
+        vec![dir.join("Q1_Q2.csv"), dir.join("Q1_Q2_1.csv")]
+    );
+    assert_eq!(skipped, vec!["Empty".to_string()]);
+    let first = std::fs::read_to_string(&written[0]).expect("Failed to read csv");
+    assert!(first.contains("first"));
+    let second = std::fs::read_to_string(&written[1]).expect("Failed to read csv");
+    assert!(second.contains("second"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_save_all_sheets_to_csv_numbers_collisions_from_one() {
+    let dir = tmp_path("export_all_collisions");
+    std::fs::remove_dir_all(&dir).ok();
+    let mut book = Book::default();
+    book.set_sheet_name(0, "Notes").expect("failed to rename sheet");
+    for (sheet, name) in [(1, "Q1&Q2"), (2, "Q1 Q2"), (3, "Q1?Q2")] {
+        book.new_sheet(Some(name)).expect("failed to create sheet");
+        book.update_cell(&Address { sheet, row: 1, col: 1 }, name)
+            .expect("failed to edit cell");
+    }
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "notes")
+        .expect("failed to edit cell");
+    book.evaluate();
+
+    let (written, _) = book
+        .save_all_sheets_to_csv(&dir)
+        .expect("Failed to export all sheets");
+    assert_eq!(
+        written,
+        vec![
+            dir.join("Notes.csv"),
+            dir.join("Q1_Q2.csv"),
+            dir.join("Q1_Q2_1.csv"),
+            dir.join("Q1_Q2_2.csv"),
+        ]
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_sheet_to_markdown() {
+    let mut book = Book::default();
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "Name")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 1, col: 2 }, "Qty")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 2, col: 1 }, "a|b")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 2, col: 2 }, "2")
+        .expect("failed to edit cell");
+    book.set_cell_style(
+        &[("alignment.horizontal", "right")],
+        &Area {
+            sheet: 0,
+            row: 1,
+            column: 2,
+            width: 1,
+            height: 2,
+        },
+    )
+    .expect("failed to set style");
+    book.evaluate();
+
+    let md = book.markdown_for_sheet(0).expect("Failed to get markdown");
+    assert_eq!(md, "| Name | Qty |\n| --- | ---: |\n| a\\|b | 2 |\n");
+}
+
+#[test]
+fn test_range_to_markdown() {
+    let mut book = Book::default();
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "A1")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 2, col: 2 }, "B2")
+        .expect("failed to edit cell");
+    book.update_cell(&Address { sheet: 0, row: 3, col: 2 }, "B3")
+        .expect("failed to edit cell");
+    book.evaluate();
+
+    let start = Address { sheet: 0, row: 2, col: 2 };
+    let end = Address { sheet: 0, row: 3, col: 2 };
+    let md = book
+        .markdown_for_range(&super::AddressRange { start: &start, end: &end })
+        .expect("Failed to get markdown");
+    assert_eq!(md, "| B2 |\n| --- |\n| B3 |\n");
+}
+
 // -------------------------------------------------------------------------
 // Phase 2: Book I/O Integration and Format Detection (REQ-005..REQ-008)
 // -------------------------------------------------------------------------
@@ -515,3 +645,663 @@ fn test_cross_format_conversion_xlsx_to_sui() {
     std::fs::remove_file(&path_xlsx).ok();
     std::fs::remove_file(&path_sui).ok();
 }
+
+#[test]
+fn test_parse_cell_ref() {
+    assert_eq!(Address::new(1, 1), parse_cell_ref("A1").unwrap());
+    assert_eq!(Address::new(12, 2), parse_cell_ref("b12").unwrap());
+    assert_eq!(Address::new(3, 27), parse_cell_ref("$AA$3").unwrap());
+    assert_eq!(Address::new(1, 16_384), parse_cell_ref("XFD1").unwrap());
+    for bad in ["", "A", "12", "A0", "1A", "A1B", "A-1", "XFE1", "A1048577"] {
+        assert!(parse_cell_ref(bad).is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_col_names() {
+    for (col, name) in [
+        (1, "A"),
+        (26, "Z"),
+        (27, "AA"),
+        (28, "AB"),
+        (52, "AZ"),
+        (53, "BA"),
+        (702, "ZZ"),
+        (703, "AAA"),
+    ] {
+        assert_eq!(name, col_to_name(col));
+        assert_eq!(Some(col), name_to_col(name));
+    }
+    assert_eq!(Some(28), name_to_col("ab"));
+    assert_eq!(None, name_to_col(""));
+    assert_eq!(None, name_to_col("A1"));
+    assert_eq!("AB3", Address::new(3, 28).to_range_part());
+}
+
+#[test]
+fn test_address_range_selected_backwards() {
+    let rows_of = |start: Address, end: Address| {
+        super::AddressRange {
+            start: &start,
+            end: &end,
+        }
+        .as_rows()
+        .iter()
+        .map(|row| row.iter().map(|a| (a.row, a.col)).collect::<Vec<_>>())
+        .collect::<Vec<_>>()
+    };
+    let series_of = |start: Address, end: Address| {
+        super::AddressRange {
+            start: &start,
+            end: &end,
+        }
+        .as_series()
+        .iter()
+        .map(|a| (a.row, a.col))
+        .collect::<Vec<_>>()
+    };
+    let expected_rows = vec![vec![(1, 1), (1, 2)], vec![(2, 1), (2, 2)]];
+    // Upward
+    assert_eq!(
+        vec![vec![(1, 1)], vec![(2, 1)], vec![(3, 1)]],
+        rows_of(Address::new(3, 1), Address::new(1, 1))
+    );
+    assert_eq!(
+        vec![(3, 1), (2, 1), (1, 1)],
+        series_of(Address::new(3, 1), Address::new(1, 1))
+    );
+    // Leftward
+    assert_eq!(
+        vec![vec![(1, 1), (1, 2), (1, 3)]],
+        rows_of(Address::new(1, 3), Address::new(1, 1))
+    );
+    assert_eq!(
+        vec![(1, 3), (1, 2), (1, 1)],
+        series_of(Address::new(1, 3), Address::new(1, 1))
+    );
+    // Both
+    assert_eq!(
+        expected_rows,
+        rows_of(Address::new(2, 2), Address::new(1, 1))
+    );
+    assert_eq!(
+        vec![(2, 2), (2, 1), (1, 2), (1, 1)],
+        series_of(Address::new(2, 2), Address::new(1, 1))
+    );
+}
+
+#[test]
+fn test_extend_to_upward() {
+    let mut book = Book::default();
+    book.update_cell(&Address::new(3, 1), "=B3+1")
+        .expect("failed to edit cell");
+    book.extend_to(&Address::new(3, 1), &Address::new(1, 1))
+        .expect("failed to extend");
+    assert_eq!(
+        "=B1+1",
+        book.get_cell_addr_contents(&Address::new(1, 1))
+            .expect("failed to get contents")
+    );
+}
+
+#[test]
+fn test_move_to_validates_address() {
+    let mut book = Book::default();
+    book.move_to(&Address::new(2, 3)).expect("Failed to move");
+    for bad in [
+        Address::new(0, 1),
+        Address::new(1, 0),
+        Address::new(super::LAST_ROW as usize + 1, 1),
+        Address::new(1, super::LAST_COLUMN as usize + 1),
+        Address {
+            sheet: 1,
+            row: 1,
+            col: 1,
+        },
+    ] {
+        assert!(book.move_to(&bad).is_err(), "{:?} should be refused", bad);
+    }
+    assert_eq!(Address::new(2, 3), book.location);
+    book.move_to(&Address::new(super::LAST_ROW as usize, 1))
+        .expect("Failed to move to the last row");
+}
+
+#[test]
+fn test_select_sheet_by_name_edits_that_sheet() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Two")).expect("failed to add sheet");
+    book.new_sheet(Some("Three")).expect("failed to add sheet");
+    assert_eq!(
+        vec![
+            ("Sheet1".to_string(), 0),
+            ("Two".to_string(), 1),
+            ("Three".to_string(), 2)
+        ],
+        book.get_all_sheets_identifiers()
+    );
+    assert!(book.select_sheet_by_name("Two"));
+    assert_eq!(1, book.location.sheet);
+    assert_eq!(1, book.model.get_selected_sheet());
+    book.edit_current_cell("middle")
+        .expect("failed to edit cell");
+    assert_eq!("Two", book.get_sheet_name().expect("failed to get name"));
+    for (sheet, expected) in [(0, ""), (1, "middle"), (2, "")] {
+        assert_eq!(
+            expected,
+            book.get_cell_addr_contents(&Address {
+                sheet,
+                row: 1,
+                col: 1
+            })
+            .expect("failed to get contents")
+        );
+    }
+    assert!(!book.select_sheet_by_name("Four"));
+    assert!(book.set_current_sheet_index(3).is_err());
+    assert_eq!(1, book.location.sheet);
+}
+
+#[test]
+fn test_extend_to_uses_range_sheet() {
+    let mut book = Book::default();
+    book.new_sheet(Some("Sheet2")).expect("failed to add sheet");
+    assert_eq!(0, book.location.sheet);
+    let on_sheet2 = |row| Address {
+        sheet: 1,
+        row,
+        col: 1,
+    };
+    book.update_cell(&on_sheet2(1), "=B1+1")
+        .expect("failed to edit cell");
+    book.extend_to(&on_sheet2(1), &on_sheet2(3))
+        .expect("failed to extend");
+    assert_eq!(
+        "=B3+1",
+        book.get_cell_addr_contents(&on_sheet2(3))
+            .expect("failed to get contents")
+    );
+    assert_eq!(
+        "",
+        book.get_cell_addr_contents(&Address::new(3, 1))
+            .expect("failed to get contents")
+    );
+    assert!(book.extend_to(&on_sheet2(1), &Address::new(3, 1)).is_err());
+}
+
+#[test]
+fn test_get_size_cached_between_changes() {
+    let mut book = Book::default();
+    for row in 1..=200 {
+        book.update_cell(&Address::new(row, row % 7 + 1), row.to_string())
+            .expect("failed to edit cell");
+    }
+    assert_eq!((200, 7), book.get_size().expect("Failed to get size"));
+    book.update_cell(&Address::new(500, 20), "last")
+        .expect("failed to edit cell");
+    for _ in 0..10_000 {
+        assert_eq!((500, 20), book.get_size().expect("Failed to get size"));
+    }
+    book.insert_rows(1, 1).expect("Failed to insert rows");
+    assert_eq!((501, 20), book.get_size().expect("Failed to get size"));
+    book.new_sheet(Some("Empty")).expect("failed to add sheet");
+    book.select_next_sheet();
+    assert_eq!((0, 0), book.get_size().expect("Failed to get size"));
+    book.select_prev_sheet();
+    assert_eq!((501, 20), book.get_size().expect("Failed to get size"));
+}
+
+#[test]
+fn test_get_col_sizes_matches_get_col_size() {
+    let mut book = Book::default();
+    book.set_col_size(2, 30).expect("Failed to set column size");
+    book.set_col_size(4, 3).expect("Failed to set column size");
+    let sizes = book.get_col_sizes(0, 1, 5).expect("Failed to get sizes");
+    let expected = (1..=5)
+        .map(|idx| book.get_col_size(idx).expect("Failed to get size"))
+        .collect::<Vec<_>>();
+    assert_eq!(expected, sizes);
+    assert_eq!((30, 3), (sizes[1], sizes[3]));
+    assert!(book.get_col_sizes(5, 1, 5).is_err());
+}
+
+fn dedupe_fixture() -> Book {
+    let mut book = Book::default();
+    for (row, a, b) in [
+        (1, "a", "1"),
+        (2, "b", "2"),
+        (3, "a", "1"),
+        (6, "c", "=LEN(A6)"),
+        (7, "b", "2"),
+    ] {
+        book.update_cell(&Address::new(row, 1), a)
+            .expect("failed to edit cell");
+        book.update_cell(&Address::new(row, 2), b)
+            .expect("failed to edit cell");
+    }
+    book.evaluate();
+    book
+}
+
+fn dedupe(book: &mut Book, end: Address, shift_up: bool) -> usize {
+    let start = Address::new(1, 1);
+    let range = AddressRange {
+        start: &start,
+        end: &end,
+    };
+    book.dedupe_range(&range, shift_up)
+        .expect("failed to dedupe")
+}
+
+fn column_contents(book: &Book, col: usize) -> Vec<String> {
+    (1..=7)
+        .map(|row| {
+            book.get_cell_addr_contents(&Address::new(row, col))
+                .expect("failed to get contents")
+        })
+        .collect()
+}
+
+#[test]
+fn test_dedupe_range_clears_duplicates() {
+    let mut book = dedupe_fixture();
+    let removed = dedupe(&mut book, Address::new(7, 2), false);
+    // The two empty rows don't count as duplicates of each other.
+    assert_eq!(2, removed);
+    assert_eq!(
+        vec!["a", "b", "", "", "", "c", ""],
+        column_contents(&book, 1)
+    );
+    assert_eq!(
+        vec!["1", "2", "", "", "", "=LEN(A6)", ""],
+        column_contents(&book, 2)
+    );
+}
+
+#[test]
+fn test_dedupe_range_shifts_rows_up() {
+    let mut book = dedupe_fixture();
+    let removed = dedupe(&mut book, Address::new(7, 2), true);
+    assert_eq!(2, removed);
+    assert_eq!(
+        vec!["a", "b", "", "", "c", "", ""],
+        column_contents(&book, 1)
+    );
+    // The formula moved with its row and still points at its own row.
+    assert_eq!(
+        vec!["1", "2", "", "", "=LEN(A5)", "", ""],
+        column_contents(&book, 2)
+    );
+    assert_eq!(
+        "1",
+        book.get_cell_addr_rendered(&Address::new(5, 2))
+            .expect("failed to render")
+    );
+}
+
+#[test]
+fn test_dedupe_range_compares_selected_columns_only() {
+    let mut book = dedupe_fixture();
+    // Only column A is compared so rows 2 and 7 match and 1 and 3 match.
+    book.update_cell(&Address::new(7, 2), "other")
+        .expect("failed to edit cell");
+    let removed = dedupe(&mut book, Address::new(7, 1), false);
+    assert_eq!(2, removed);
+    assert_eq!("other", column_contents(&book, 2)[6]);
+}
+
+#[test]
+fn test_trim_range() {
+    let mut book = Book::default();
+    let cells = [
+        "\u{a0}apple\u{a0}",
+        "\tpear  ",
+        "fig",
+        "=\"  spaced  \"",
+        "12",
+        "big   red\tapple ",
+    ];
+    for (row, value) in cells.iter().enumerate() {
+        book.update_cell(&Address::new(row + 1, 1), value)
+            .expect("failed to edit cell");
+    }
+    book.evaluate();
+    let (start, end) = (Address::new(1, 1), Address::new(cells.len(), 1));
+    let range = AddressRange {
+        start: &start,
+        end: &end,
+    };
+    let changed = book.trim_range(&range, false).expect("failed to trim");
+    assert_eq!(3, changed);
+    assert_eq!(
+        vec![
+            "apple",
+            "pear",
+            "fig",
+            "=\"  spaced  \"",
+            "12",
+            "big   red\tapple"
+        ],
+        column_contents(&book, 1)[..cells.len()].to_vec()
+    );
+
+    // Collapsing also squeezes the whitespace inside the text.
+    let changed = book.trim_range(&range, true).expect("failed to trim");
+    assert_eq!(1, changed);
+    assert_eq!(
+        "big red apple",
+        book.get_cell_addr_contents(&Address::new(6, 1))
+            .expect("failed to get contents")
+    );
+    // The formula is left alone even though its value has spaces.
+    assert_eq!(
+        "  spaced  ",
+        book.get_cell_addr_rendered(&Address::new(4, 1))
+            .expect("failed to render")
+    );
+}
+
+#[test]
+fn test_case_mode_apply() {
+    let cases = [
+        (CaseMode::Upper, "straße émile", "STRASSE ÉMILE"),
+        (CaseMode::Lower, "ÉMILE Zola", "émile zola"),
+        (CaseMode::Title, "mARY o'NEIL-smith", "Mary O'neil-Smith"),
+        (CaseMode::Title, "don't  stop", "Don't  Stop"),
+    ];
+    for (mode, text, expected) in cases {
+        assert_eq!(expected, mode.apply(text), "{:?} {}", mode, text);
+    }
+}
+
+#[test]
+fn test_change_case_range() {
+    let mut book = Book::default();
+    let cells = ["alice smith", "12", "", "=\"bob\"", "Carol"];
+    for (row, value) in cells.iter().enumerate() {
+        book.update_cell(&Address::new(row + 1, 1), value)
+            .expect("failed to edit cell");
+    }
+    book.evaluate();
+    let (start, end) = (Address::new(1, 1), Address::new(5, 1));
+    let range = AddressRange {
+        start: &start,
+        end: &end,
+    };
+    let changed = book
+        .change_case_range(&range, CaseMode::Upper)
+        .expect("failed to change case");
+    assert_eq!(2, changed);
+    assert_eq!(
+        vec!["ALICE SMITH", "12", "", "=\"bob\"", "CAROL"],
+        column_contents(&book, 1)[..5].to_vec()
+    );
+    let changed = book
+        .change_case_range(&range, CaseMode::Lower)
+        .expect("failed to change case");
+    assert_eq!(2, changed);
+    let changed = book
+        .change_case_range(&range, CaseMode::Lower)
+        .expect("failed to change case");
+    assert_eq!(0, changed);
+    let changed = book
+        .change_case_range(&range, CaseMode::Title)
+        .expect("failed to change case");
+    assert_eq!(2, changed);
+    assert_eq!(
+        vec!["Alice Smith", "12", "", "=\"bob\"", "Carol"],
+        column_contents(&book, 1)[..5].to_vec()
+    );
+}
+
+#[test]
+fn test_split_column() {
+    let mut book = Book::default();
+    let cells = ["a,b,c", "\"Smith, J\",42", "plain", "x,,y"];
+    for (row, value) in cells.iter().enumerate() {
+        book.update_cell(&Address::new(row + 1, 1), value)
+            .expect("failed to edit cell");
+    }
+    book.update_cell(&Address::new(3, 2), "kept")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(1, 4), "overwritten?")
+        .expect("failed to edit cell");
+    book.evaluate();
+    let (start, end) = (Address::new(1, 1), Address::new(4, 1));
+    let range = AddressRange {
+        start: &start,
+        end: &end,
+    };
+    let most = book
+        .split_column(&range, ",", false)
+        .expect("failed to split");
+    assert_eq!(3, most);
+    let row = |book: &Book, row: usize| {
+        (1..=4)
+            .map(|col| {
+                book.get_cell_addr_contents(&Address::new(row, col))
+                    .expect("failed to get contents")
+            })
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(vec!["a", "b", "c", "overwritten?"], row(&book, 1));
+    assert_eq!(vec!["\"Smith, J\"", "42", "", ""], row(&book, 2));
+    // Cells without the delimiter are left alone.
+    assert_eq!(vec!["plain", "kept", "", ""], row(&book, 3));
+    assert_eq!(vec!["x", "", "y", ""], row(&book, 4));
+}
+
+#[test]
+fn test_split_column_inserting_columns() {
+    let mut book = Book::default();
+    book.update_cell(&Address::new(1, 1), "a\tb\tc")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(1, 2), "next")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(2, 1), "=\"d\"")
+        .expect("failed to edit cell");
+    book.evaluate();
+    let (start, end) = (Address::new(1, 1), Address::new(2, 1));
+    let range = AddressRange {
+        start: &start,
+        end: &end,
+    };
+    let most = book
+        .split_column(&range, "\t", true)
+        .expect("failed to split");
+    assert_eq!(3, most);
+    let contents: Vec<String> = (1..=4)
+        .map(|col| {
+            book.get_cell_addr_contents(&Address::new(1, col))
+                .expect("failed to get contents")
+        })
+        .collect();
+    assert_eq!(vec!["a", "b", "c", "next"], contents);
+    // Formulas are never split.
+    assert_eq!(
+        "=\"d\"",
+        book.get_cell_addr_contents(&Address::new(2, 1))
+            .expect("failed to get contents")
+    );
+    assert_eq!(
+        1,
+        book.split_column(&range, ";", true)
+            .expect("failed to split")
+    );
+}
+
+#[test]
+fn test_series_start_parse() {
+    assert_eq!(Some(SeriesStart::Number(1.0)), SeriesStart::parse("1"));
+    assert_eq!(Some(SeriesStart::Number(-0.5)), SeriesStart::parse("-0.5"));
+    assert_eq!(
+        Some(SeriesStart::Date(
+            time::Date::from_calendar_date(2024, time::Month::February, 29).unwrap()
+        )),
+        SeriesStart::parse("2024-02-29")
+    );
+    assert_eq!(None, SeriesStart::parse("2023-02-29"));
+    assert_eq!(None, SeriesStart::parse("monday"));
+    assert_eq!(None, SeriesStart::parse("NaN"));
+}
+
+fn fill(book: &mut Book, end: Address, start: SeriesStart, step: f64) -> Vec<Vec<String>> {
+    let first = Address::new(1, 1);
+    let range = AddressRange {
+        start: &first,
+        end: &end,
+    };
+    book.fill_series(&range, start, step)
+        .expect("failed to fill");
+    range
+        .as_rows()
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| book.get_cell_addr_rendered(cell).expect("failed to render"))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_add_to_number_only_changes_plain_numbers() {
+    let mut book = Book::default();
+    let cell = Address::new(1, 1);
+    let mut add_one = |text: &str| {
+        book.update_cell(&cell, text)
+            .expect("Failed to update cell");
+        let changed = book.add_to_number(&cell, 1.0).expect("Failed to add");
+        (changed, book.get_cell_addr_contents(&cell).unwrap())
+    };
+    assert_eq!((true, "-0.25".to_string()), add_one("-1.25"));
+    assert_eq!((true, "8".to_string()), add_one("+7"));
+    for text in ["nan", "Infinity", "inf", "1e", "-", ".5x"] {
+        assert_eq!((false, text.to_string()), add_one(text));
+    }
+    // Exponents are text unless the workbook already read them as a number.
+    let (changed, contents) = add_one("1.5e3");
+    assert!(
+        (!changed && contents == "1.5e3") || (changed && contents == "1501"),
+        "{}",
+        contents
+    );
+}
+
+#[test]
+fn test_fill_series_numbers() {
+    let mut book = Book::default();
+    // Every column counts down.
+    assert_eq!(
+        vec![vec!["1", "1"], vec!["2", "2"], vec!["3", "3"]],
+        fill(&mut book, Address::new(3, 2), SeriesStart::Number(1.0), 1.0)
+    );
+    // A single row counts across.
+    assert_eq!(
+        vec![vec!["10", "8", "6", "4"]],
+        fill(
+            &mut book,
+            Address::new(1, 4),
+            SeriesStart::Number(10.0),
+            -2.0
+        )
+    );
+    let mut book = Book::default();
+    assert_eq!(
+        vec![vec!["0.1"], vec!["0.3"], vec!["0.5"], vec!["0.7"]],
+        fill(&mut book, Address::new(4, 1), SeriesStart::Number(0.1), 0.2)
+    );
+    assert_eq!(
+        "0.7",
+        book.get_cell_addr_contents(&Address::new(4, 1))
+            .expect("failed to get contents")
+    );
+}
+
+#[test]
+fn test_fill_series_dates() {
+    let mut book = Book::default();
+    let start = SeriesStart::parse("2024-01-01").expect("failed to parse date");
+    assert_eq!(
+        vec![vec!["2024-01-01"], vec!["2024-01-08"], vec!["2024-01-15"]],
+        fill(&mut book, Address::new(3, 1), start, 7.0)
+    );
+    // Dates are stored as day numbers.
+    assert_eq!(
+        "45292",
+        book.get_cell_addr_contents(&Address::new(1, 1))
+            .expect("failed to get contents")
+    );
+}
+
+#[test]
+fn test_extend_to_continues_numbers() {
+    let mut book = Book::default();
+    for (row, value) in [(1, "1"), (2, "3")] {
+        book.update_cell(&Address::new(row, 1), value)
+            .expect("failed to edit cell");
+    }
+    for (row, value) in [(1, "10"), (2, "9.5")] {
+        book.update_cell(&Address::new(row, 2), value)
+            .expect("failed to edit cell");
+    }
+    book.evaluate();
+    book.extend_to(&Address::new(1, 1), &Address::new(5, 2))
+        .expect("failed to extend");
+    assert_eq!(
+        vec!["1", "3", "5", "7", "9"],
+        column_contents(&book, 1)[..5].to_vec()
+    );
+    assert_eq!(
+        vec!["10", "9.5", "9", "8.5", "8"],
+        column_contents(&book, 2)[..5].to_vec()
+    );
+}
+
+#[test]
+fn test_extend_to_continues_names() {
+    let mut book = Book::default();
+    book.update_cell(&Address::new(1, 1), "Nov")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(1, 2), "Dec")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(3, 1), "FRIDAY")
+        .expect("failed to edit cell");
+    book.evaluate();
+    // A single row carries on across.
+    book.extend_to(&Address::new(1, 1), &Address::new(1, 4))
+        .expect("failed to extend");
+    let row: Vec<String> = (1..=4)
+        .map(|col| {
+            book.get_cell_addr_contents(&Address::new(1, col))
+                .expect("failed to get contents")
+        })
+        .collect();
+    assert_eq!(vec!["Nov", "Dec", "Jan", "Feb"], row);
+    // A single name is enough and the case is kept.
+    book.extend_to(&Address::new(3, 1), &Address::new(5, 1))
+        .expect("failed to extend");
+    assert_eq!(
+        vec!["FRIDAY", "SATURDAY", "SUNDAY"],
+        column_contents(&book, 1)[2..5].to_vec()
+    );
+}
+
+#[test]
+fn test_extend_to_copies_without_a_pattern() {
+    let mut book = Book::default();
+    // A single number is copied rather than counted.
+    book.update_cell(&Address::new(1, 1), "7")
+        .expect("failed to edit cell");
+    book.update_cell(&Address::new(1, 2), "=A1*2")
+        .expect("failed to edit cell");
+    book.evaluate();
+    book.extend_to(&Address::new(1, 1), &Address::new(3, 1))
+        .expect("failed to extend");
+    assert_eq!(vec!["7", "7", "7"], column_contents(&book, 1)[..3].to_vec());
+    book.extend_to(&Address::new(1, 2), &Address::new(3, 2))
+        .expect("failed to extend");
+    assert_eq!(
+        vec!["=A1*2", "=A2*2", "=A3*2"],
+        column_contents(&book, 2)[..3].to_vec()
+    );
+}
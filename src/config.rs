@@ -0,0 +1,555 @@
+//! # Config File
+//!
+//! sheetui reads user defaults from `~/.config/sheetsui/config.toml` (or
+//! `$XDG_CONFIG_HOME/sheetsui/config.toml`). The file uses a small subset of
+//! TOML:
+//!
+//! ```toml
+//! # Comments start with a hash.
+//! locale = "de"
+//! timezone = "Europe/Berlin"
+//! backup = true
+//! autosave = 300
+//! tick = 250
+//! theme = "light"
+//! cell-fg = "white"
+//! cell-bg = "#1e1e2e"
+//! stripes = false
+//! gridlines = true
+//! title = false
+//! calc = "manual"
+//! enter-moves = true
+//!
+//! [keys]
+//! navigate."ctrl-d" = "move_down"
+//! visual.v = "cancel"
+//! ```
+//!
+//! `locale` and `timezone` are used when the matching command line flags are
+//! not given. Entries in the `[keys]` section rebind keys in navigate or visual
+//! mode. Every other key is a setting that can also be changed at runtime
+//! with the `set` command.
+//!
+//! Problems in the file never stop sheetui from starting. Each bad line is
+//! reported in [`Config::warnings`] and otherwise ignored.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::ui::color::parse_color;
+use crate::ui::keymap::BindingOverride;
+use crate::ui::render::theme::{Theme, THEME_NAMES};
+
+/// User configurable settings. These can be set in the config file or changed
+/// at runtime with the `set` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Copy the existing file to `<path>.bak` before overwriting it on save.
+    pub backup: bool,
+    /// Save a modified book with a file path every this many seconds. Zero
+    /// turns autosave off.
+    pub autosave: u64,
+    /// Milliseconds to wait for input before updating the interface anyway.
+    pub tick: u64,
+    /// Name of the color theme. `auto` picks `terminal` on a light
+    /// background and `dark` otherwise.
+    pub theme: String,
+    /// The `#rrggbb` text and background colors of cells without colors of
+    /// their own. `None` leaves them to the theme.
+    pub cell_fg: Option<String>,
+    pub cell_bg: Option<String>,
+    /// Alternate the column header colors.
+    pub stripes: bool,
+    /// Draw lines between the columns of the sheet.
+    pub gridlines: bool,
+    /// Set the terminal window title to the workbook name.
+    pub title: bool,
+    /// When formulas are recalculated.
+    pub calc: CalcMode,
+    /// Move down after committing a cell edit with `Enter`.
+    pub enter_moves: bool,
+}
+
+/// When formulas are recalculated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CalcMode {
+    /// After every change.
+    #[default]
+    Auto,
+    /// Only when asked to with `F9` or `:calc`.
+    Manual,
+}
+
+impl CalcMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalcMode::Auto => "auto",
+            CalcMode::Manual => "manual",
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backup: false,
+            autosave: 0,
+            tick: 250,
+            theme: String::from("auto"),
+            cell_fg: None,
+            cell_bg: None,
+            stripes: true,
+            gridlines: false,
+            title: true,
+            calc: CalcMode::Auto,
+            enter_moves: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Set a named option from its string value.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "backup" => self.backup = parse_switch(value)?,
+            "stripes" => self.stripes = parse_switch(value)?,
+            "gridlines" => self.gridlines = parse_switch(value)?,
+            "title" => self.title = parse_switch(value)?,
+            "enter-moves" => self.enter_moves = parse_switch(value)?,
+            "autosave" => {
+                self.autosave = value
+                    .parse()
+                    .map_err(|_| anyhow!("Expected a number of seconds but got {}", value))?
+            }
+            "tick" => {
+                self.tick = match value.parse() {
+                    Ok(ms) if ms > 0 => ms,
+                    _ => {
+                        return Err(anyhow!(
+                            "Expected a positive number of milliseconds but got {}",
+                            value
+                        ))
+                    }
+                }
+            }
+            "calc" => {
+                self.calc = match value {
+                    "auto" => CalcMode::Auto,
+                    "manual" => CalcMode::Manual,
+                    _ => return Err(anyhow!("Expected auto or manual but got {}", value)),
+                }
+            }
+            "theme" => {
+                if value != "auto" && Theme::by_name(value).is_none() {
+                    return Err(anyhow!(
+                        "Unknown theme {}; expected auto or one of {}",
+                        value,
+                        THEME_NAMES.join(", ")
+                    ));
+                }
+                self.theme = value.to_string();
+            }
+            "cell-fg" => self.cell_fg = parse_default_color(value)?,
+            "cell-bg" => self.cell_bg = parse_default_color(value)?,
+            _ => return Err(anyhow!("Unknown option {}", name)),
+        }
+        Ok(())
+    }
+
+    /// The current value of every setting by name.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("backup", switch_name(self.backup)),
+            ("autosave", self.autosave.to_string()),
+            ("tick", self.tick.to_string()),
+            ("theme", self.theme.clone()),
+            ("cell-fg", default_color_name(&self.cell_fg)),
+            ("cell-bg", default_color_name(&self.cell_bg)),
+            ("stripes", switch_name(self.stripes)),
+            ("gridlines", switch_name(self.gridlines)),
+            ("title", switch_name(self.title)),
+            ("calc", self.calc.name().to_string()),
+            ("enter-moves", switch_name(self.enter_moves)),
+        ]
+    }
+}
+
+fn switch_name(value: bool) -> String {
+    String::from(if value { "on" } else { "off" })
+}
+
+fn default_color_name(color: &Option<String>) -> String {
+    color.clone().unwrap_or_else(|| String::from("default"))
+}
+
+/// A color or `default` for the theme's color.
+fn parse_default_color(value: &str) -> Result<Option<String>> {
+    if value == "default" {
+        return Ok(None);
+    }
+    parse_color(value)
+        .map(Some)
+        .map_err(|_| anyhow!("Expected a color or default but got {}", value))
+}
+
+fn parse_switch(value: &str) -> Result<bool> {
+    match value {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => Err(anyhow!("Expected on or off but got {}", value)),
+    }
+}
+
+/// The contents of the config file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub settings: Settings,
+    /// Keybinding overrides from the `[keys]` section.
+    pub keys: Vec<BindingOverride>,
+    /// Problems found while loading the config, ready to show the user.
+    pub warnings: Vec<String>,
+}
+
+/// A single `key = value` line. Keys under a `[section]` header are prefixed
+/// with the section's segments.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    line: usize,
+    key: Vec<String>,
+    value: String,
+}
+
+/// The default location of the config file if we can determine one.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("sheetsui").join("config.toml"))
+}
+
+/// Load the config from `path`.
+///
+/// A missing file is only reported when `required` is true. Otherwise the
+/// defaults are returned. Any problems are collected in [`Config::warnings`].
+pub fn load(path: &Path, required: bool) -> Config {
+    if !required && !path.exists() {
+        return Config::default();
+    }
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let mut config = parse_config(&text);
+            for warning in config.warnings.iter_mut() {
+                *warning = format!("{} {}", path.display(), warning);
+            }
+            config
+        }
+        Err(e) => Config {
+            warnings: vec![format!("Failed to read {}: {}", path.display(), e)],
+            ..Default::default()
+        },
+    }
+}
+
+/// Parse config file text. Problems are reported in [`Config::warnings`] as
+/// `line <n>: <message>`.
+pub fn parse_config(text: &str) -> Config {
+    let (entries, warnings) = parse_entries(text);
+    let mut config = Config {
+        warnings,
+        ..Default::default()
+    };
+    for entry in entries {
+        let key: Vec<&str> = entry.key.iter().map(|k| k.as_str()).collect();
+        let result = match key.as_slice() {
+            ["locale"] => {
+                config.locale = Some(entry.value);
+                Ok(())
+            }
+            ["timezone"] => {
+                config.timezone = Some(entry.value);
+                Ok(())
+            }
+            [name] => config.settings.set(name, &entry.value),
+            ["keys", mode, key] => {
+                config.keys.push(BindingOverride {
+                    line: entry.line,
+                    mode: mode.to_string(),
+                    key: key.to_string(),
+                    action: entry.value.clone(),
+                });
+                Ok(())
+            }
+            _ => Err(anyhow!("Unknown option {}", entry.key.join("."))),
+        };
+        if let Err(e) = result {
+            config.warnings.push(warning(entry.line, e));
+        }
+    }
+    config
+}
+
+fn warning(line: usize, message: impl std::fmt::Display) -> String {
+    format!("line {}: {}", line, message)
+}
+
+fn parse_entries(text: &str) -> (Vec<Entry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    let mut section: Vec<String> = Vec::new();
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let result = if let Some(header) = trimmed.strip_prefix('[') {
+            parse_section(header).map(|s| section = s)
+        } else {
+            parse_key_value(trimmed).map(|(key, value)| {
+                let mut full_key = section.clone();
+                full_key.extend(key);
+                entries.push(Entry {
+                    line,
+                    key: full_key,
+                    value,
+                });
+            })
+        };
+        if let Err(message) = result {
+            warnings.push(warning(line, message));
+        }
+    }
+    (entries, warnings)
+}
+
+fn parse_section(header: &str) -> Result<Vec<String>, String> {
+    let header = strip_comment(header);
+    let name = header
+        .strip_suffix(']')
+        .ok_or_else(|| String::from("Section header is missing a closing `]`"))?;
+    parse_key(name)
+}
+
+fn parse_key_value(line: &str) -> Result<(Vec<String>, String), String> {
+    let eq = find_unquoted(line, '=').ok_or_else(|| String::from("Expected `key = value`"))?;
+    let key = parse_key(&line[..eq])?;
+    let value = parse_value(line[eq + 1..].trim())?;
+    Ok((key, value))
+}
+
+/// Split a possibly dotted and quoted key like `navigate."ctrl-d"` into its
+/// segments.
+fn parse_key(key: &str) -> Result<Vec<String>, String> {
+    let mut segments = Vec::new();
+    let mut rest = key.trim();
+    loop {
+        let (segment, remaining) = if rest.starts_with('"') {
+            let (s, len) = parse_quoted(rest)?;
+            (s, rest[len..].trim_start())
+        } else {
+            let end = rest.find('.').unwrap_or(rest.len());
+            (rest[..end].trim().to_string(), &rest[end..])
+        };
+        if segment.is_empty() {
+            return Err(format!("Invalid key `{}`", key.trim()));
+        }
+        segments.push(segment);
+        match remaining.strip_prefix('.') {
+            Some(r) => rest = r.trim_start(),
+            None if remaining.trim().is_empty() => break,
+            None => return Err(format!("Invalid key `{}`", key.trim())),
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if value.starts_with('"') {
+        let (s, len) = parse_quoted(value)?;
+        let rest = strip_comment(&value[len..]);
+        if !rest.is_empty() {
+            return Err(format!("Unexpected text after value: `{}`", rest));
+        }
+        return Ok(s);
+    }
+    let value = strip_comment(value);
+    if value.is_empty() {
+        return Err(String::from("Missing value"));
+    }
+    Ok(value.to_string())
+}
+
+/// Parse a double quoted string at the start of `input` returning the
+/// unescaped contents and the number of bytes consumed.
+fn parse_quoted(input: &str) -> Result<(String, usize), String> {
+    let mut out = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((out, idx + 1)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => out.push('\n'),
+                Some((_, 't')) => out.push('\t'),
+                Some((_, c)) => out.push(c),
+                None => break,
+            },
+            c => out.push(c),
+        }
+    }
+    Err(String::from("Unterminated string"))
+}
+
+fn strip_comment(input: &str) -> &str {
+    match find_unquoted(input, '#') {
+        Some(idx) => input[..idx].trim(),
+        None => input.trim(),
+    }
+}
+
+fn find_unquoted(input: &str, needle: char) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (idx, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == needle && !in_quotes => return Some(idx),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config(
+            "# my defaults\nlocale = \"de\"\ntimezone = \"Europe/Berlin\" # home\nbackup = true\nautosave = 300\n",
+        );
+        assert!(
+            config.warnings.is_empty(),
+            "unexpected warnings {:?}",
+            config.warnings
+        );
+        assert_eq!(Some("de".to_string()), config.locale);
+        assert_eq!(Some("Europe/Berlin".to_string()), config.timezone);
+        assert!(config.settings.backup);
+        assert_eq!(300, config.settings.autosave);
+        assert_eq!(250, config.settings.tick);
+    }
+
+    #[test]
+    fn test_settings_tick() {
+        let mut settings = Settings::default();
+        settings.set("tick", "100").expect("Failed to set tick");
+        assert_eq!(100, settings.tick);
+        assert!(settings.set("tick", "0").is_err());
+        assert!(settings.set("tick", "soon").is_err());
+        assert_eq!(100, settings.tick);
+    }
+
+    #[test]
+    fn test_settings_theme() {
+        let mut settings = Settings::default();
+        assert_eq!("auto", settings.theme);
+        settings.set("theme", "light").expect("Failed to set theme");
+        assert_eq!("light", settings.theme);
+        let err = settings
+            .set("theme", "solarized")
+            .expect_err("Unknown theme should fail");
+        assert!(err.to_string().contains("dark, light"), "{}", err);
+        assert_eq!("light", settings.theme);
+        settings.set("theme", "auto").expect("Failed to set theme");
+        assert_eq!("auto", settings.theme);
+    }
+
+    #[test]
+    fn test_settings_cell_colors() {
+        let mut settings = Settings::default();
+        settings
+            .set("cell-fg", "red")
+            .expect("Failed to set cell-fg");
+        settings
+            .set("cell-bg", "#1e1e2e")
+            .expect("Failed to set cell-bg");
+        assert_eq!(Some("#800000".to_string()), settings.cell_fg);
+        assert_eq!(Some("#1e1e2e".to_string()), settings.cell_bg);
+        assert!(settings.set("cell-bg", "plaid").is_err());
+        settings
+            .set("cell-fg", "default")
+            .expect("Failed to reset cell-fg");
+        assert_eq!(None, settings.cell_fg);
+    }
+
+    #[test]
+    fn test_parse_config_warnings() {
+        let config = parse_config(
+            "locale = \"fr\"\nbogus = 1\nbackup = maybe\nnot a setting\nname = \"open\n",
+        );
+        assert_eq!(Some("fr".to_string()), config.locale);
+        assert!(!config.settings.backup);
+        assert_eq!(4, config.warnings.len());
+        for (line, warning) in [2, 3, 4, 5].iter().zip(&config.warnings) {
+            assert!(
+                warning.starts_with(&format!("line {}: ", line)),
+                "{}",
+                warning
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_dotted_and_quoted_keys() {
+        let (entries, warnings) =
+            parse_entries("[keys]\nnavigate.\"ctrl-d\" = \"page_down\"\n\"a=b\" = \"#x\"\n");
+        assert!(warnings.is_empty(), "unexpected warnings {:?}", warnings);
+        assert_eq!(
+            vec!["keys", "navigate", "ctrl-d"],
+            entries[0]
+                .key
+                .iter()
+                .map(|k| k.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!("page_down", entries[0].value);
+        assert_eq!(vec!["keys".to_string(), "a=b".to_string()], entries[1].key);
+        assert_eq!("#x", entries[1].value);
+    }
+
+    #[test]
+    fn test_parse_config_keys() {
+        let config =
+            parse_config("[keys]\nnavigate.\"ctrl-d\" = \"move_down\"\nvisual.v = \"cancel\"\n");
+        assert!(
+            config.warnings.is_empty(),
+            "unexpected warnings {:?}",
+            config.warnings
+        );
+        assert_eq!(2, config.keys.len());
+        assert_eq!(
+            BindingOverride {
+                line: 2,
+                mode: "navigate".to_string(),
+                key: "ctrl-d".to_string(),
+                action: "move_down".to_string(),
+            },
+            config.keys[0]
+        );
+        assert_eq!("visual", config.keys[1].mode);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = std::env::temp_dir().join("sheetui_missing_config.toml");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(Config::default(), load(&path, false));
+        assert_eq!(1, load(&path, true).warnings.len());
+    }
+}
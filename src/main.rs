@@ -1,4 +1,4 @@
-use std::{path::PathBuf, process::ExitCode};
+use std::{path::PathBuf, process::ExitCode, time::Duration};
 
 use clap::Parser;
 use crossterm::event;
@@ -9,6 +9,8 @@ use std::io::Write;
 use ui::Workspace;
 
 mod book;
+mod config;
+mod replay;
 mod ui;
 
 #[derive(Parser, Debug)]
@@ -16,52 +18,191 @@ mod ui;
 pub struct Args {
     #[arg()]
     workbook: Option<PathBuf>,
-    #[arg(default_value_t=String::from("en"), short, long)]
-    locale_name: String,
-    #[arg(default_value_t=String::from("America/New_York"), short, long)]
-    timezone_name: String,
+    /// Locale to use. Overrides the config file. [default: en]
+    #[arg(short, long)]
+    locale_name: Option<String>,
+    /// Timezone to use. Overrides the config file. [default: America/New_York]
+    #[arg(short, long)]
+    timezone_name: Option<String>,
+    /// Sheet to open at, by name or 1-based index.
+    #[arg(long)]
+    sheet: Option<String>,
+    /// Cell to open at, e.g. B12.
+    #[arg(long)]
+    cell: Option<String>,
+    /// Open the workbook without allowing changes.
+    #[arg(long)]
+    readonly: bool,
     #[arg(long)]
     log_input: Option<PathBuf>,
+    /// Replay input recorded with --log-input and exit when it runs out.
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Switch to live input once the replayed input runs out.
+    #[arg(long, requires = "replay")]
+    replay_then_interactive: bool,
+    /// Run the commands in this file, one per line, without starting the
+    /// interface.
+    #[arg(long)]
+    batch: Option<PathBuf>,
+    /// Path to a config file. [default: ~/.config/sheetsui/config.toml]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Don't switch to the terminal's default colors on a light background.
+    #[arg(long)]
+    no_default_colors: bool,
 }
 
-type ReadFn = Box<dyn FnMut() -> anyhow::Result<event::Event>>;
+/// The result of waiting for input.
+enum Input {
+    Event(event::Event),
+    /// No input arrived before the tick interval passed.
+    Tick,
+    /// There is no more input.
+    End,
+}
 
-fn run(terminal: &mut ratatui::DefaultTerminal, args: Args) -> anyhow::Result<ExitCode> {
+/// Waits up to the given interval for the next input event.
+type ReadFn = Box<dyn FnMut(Duration) -> anyhow::Result<Input>>;
+
+fn poll_input(timeout: Duration) -> anyhow::Result<Input> {
+    if event::poll(timeout)? {
+        Ok(Input::Event(event::read()?))
+    } else {
+        Ok(Input::Tick)
+    }
+}
+
+fn load_config(args: &Args) -> config::Config {
+    match &args.config {
+        Some(path) => config::load(path, true),
+        None => config::default_path()
+            .map(|path| config::load(&path, false))
+            .unwrap_or_default(),
+    }
+}
+
+fn load_workspace<'ws>(args: &Args, config: &config::Config) -> anyhow::Result<Workspace<'ws>> {
+    // Command line flags win over the config file.
+    let locale = args
+        .locale_name
+        .clone()
+        .or_else(|| config.locale.clone())
+        .unwrap_or_else(|| String::from("en"));
+    let tz = args
+        .timezone_name
+        .clone()
+        .or_else(|| config.timezone.clone())
+        .unwrap_or_else(|| String::from("America/New_York"));
     let mut ws = if let Some(workbook_path) = &args.workbook {
-        Workspace::load(workbook_path, &args.locale_name, &args.timezone_name)?
+        Workspace::load(workbook_path, &locale, &tz)?
     } else {
-        Workspace::new_empty(&args.locale_name, &args.timezone_name)?
+        Workspace::new_empty(&locale, &tz)?
     };
-    let mut read_func: ReadFn = if let Some(log_path) = args.log_input {
-        {
-            let log_file = std::fs::File::create(log_path)?;
-            Box::new(move || {
-                let evt = event::read()?;
-                to_writer(&log_file, &evt)?;
-                writeln!(&log_file, "")?;
-                Ok(evt)
-            })
-        }
-    } else {
-        Box::new(|| {
-            let evt = event::read()?;
-            Ok(evt)
+    ws.set_read_only(args.readonly);
+    ws.jump_to(args.sheet.as_deref(), args.cell.as_deref())?;
+    Ok(ws)
+}
+
+fn run(terminal: &mut ratatui::DefaultTerminal, args: Args) -> anyhow::Result<ExitCode> {
+    let config = load_config(&args);
+    let mut ws = load_workspace(&args, &config)?;
+    ws.apply_config(&config);
+    ws.set_light_background(!args.no_default_colors && ui::render::theme::light_background());
+    let mut read_func: ReadFn = if let Some(replay_path) = &args.replay {
+        let mut events = replay::load_events(replay_path)?.into_iter();
+        let interactive = args.replay_then_interactive;
+        Box::new(move |timeout| match events.next() {
+            Some(evt) => Ok(Input::Event(evt)),
+            None if interactive => poll_input(timeout),
+            None => Ok(Input::End),
         })
+    } else {
+        Box::new(poll_input)
     };
+    if let Some(log_path) = args.log_input {
+        let log_file = std::fs::File::create(log_path)?;
+        let mut inner = read_func;
+        read_func = Box::new(move |timeout| {
+            let input = inner(timeout)?;
+            // Ticks are not input so they are left out of the log.
+            if let Input::Event(evt) = &input {
+                to_writer(&log_file, evt)?;
+                writeln!(&log_file, "")?;
+            }
+            Ok(input)
+        });
+    }
+    let mut title = None;
     loop {
         terminal.draw(|frame| ui::render::draw(frame, &mut ws))?;
-        if let Some(code) = ws.handle_input(read_func()?)? {
-            return Ok(code);
+        let next_title = ws.terminal_title();
+        if next_title != title {
+            if let Some(text) = &next_title {
+                crossterm::execute!(std::io::stdout(), crossterm::terminal::SetTitle(text))?;
+            }
+            title = next_title;
         }
+        match read_func(ws.tick_interval())? {
+            Input::Event(evt) => {
+                if let Some(code) = ws.handle_input(evt)? {
+                    return Ok(code);
+                }
+            }
+            Input::Tick => ws.on_tick()?,
+            Input::End => return Ok(ExitCode::SUCCESS),
+        }
+    }
+}
+
+/// Run a batch script stopping at the first command that fails.
+fn run_batch(script: &PathBuf, args: Args) -> anyhow::Result<ExitCode> {
+    let config = load_config(&args);
+    for warning in &config.warnings {
+        eprintln!("{}", warning);
     }
+    let mut ws = load_workspace(&args, &config)?;
+    ws.apply_config(&config);
+    let text = std::fs::read_to_string(script)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", script.display(), e))?;
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match ws.run_batch_command(line) {
+            Ok(Some(code)) => return Ok(code),
+            Ok(None) => (),
+            Err(e) => {
+                eprintln!("{} line {}: {}", script.display(), idx + 1, e);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
 fn main() -> anyhow::Result<ExitCode> {
     let args = Args::parse();
+    if let Some(script) = args.batch.clone() {
+        // Batch mode never touches the terminal.
+        return run_batch(&script, args);
+    }
 
     let mut terminal = ratatui::init();
+    crossterm::execute!(
+        std::io::stdout(),
+        event::EnableMouseCapture,
+        event::EnableBracketedPaste
+    )?;
     terminal.clear()?;
     let app_result = run(&mut terminal, args);
+    crossterm::execute!(
+        std::io::stdout(),
+        event::DisableBracketedPaste,
+        event::DisableMouseCapture
+    )
+    .ok();
     ratatui::restore();
     app_result
 }
@@ -0,0 +1,50 @@
+//! Replay of input recorded with `--log-input`.
+//!
+//! The log holds one JSON serialized crossterm event per line. Replaying a log
+//! against the same workbook reproduces the session that recorded it.
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::Event;
+
+/// Load the events recorded in the log at `path` in the order they happened.
+pub fn load_events(path: &Path) -> Result<Vec<Event>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    parse_events(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// Parse the text of an input log. Blank lines are skipped and a malformed
+/// line is an error naming its line number.
+pub fn parse_events(text: &str) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let evt = serde_json::from_str(line)
+            .map_err(|e| anyhow!("line {}: Invalid input event: {}", idx + 1, e))?;
+        events.push(evt);
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_events_reports_line() {
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        let line = serde_json::to_string(&key).expect("Failed to serialize event");
+        assert_eq!(
+            vec![key.clone(), key],
+            parse_events(&format!("{line}\n\n{line}\n")).unwrap()
+        );
+        let err =
+            parse_events(&format!("{line}\n\nnot json\n")).expect_err("Malformed log should fail");
+        assert!(err.to_string().starts_with("line 3:"), "{}", err);
+    }
+}
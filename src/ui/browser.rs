@@ -0,0 +1,60 @@
+//! Opening links in the user's browser.
+//!
+//! Links go through the [`Browser`] trait so tests can check what would have
+//! been opened without starting a real browser.
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+
+/// Something that can open a url.
+pub trait Browser {
+    fn open(&mut self, url: &str) -> Result<()>;
+}
+
+/// The platform's own opener: `open` on macOS, the url protocol handler on
+/// Windows and `xdg-open` everywhere else.
+#[derive(Debug, Default)]
+pub struct SystemBrowser;
+
+impl Browser for SystemBrowser {
+    fn open(&mut self, url: &str) -> Result<()> {
+        let mut command = if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else if cfg!(target_os = "windows") {
+            // `cmd /C start` would take the `&` in a query string as the
+            // end of the command.
+            let mut command = Command::new("rundll32");
+            command.arg("url.dll,FileProtocolHandler");
+            command
+        } else {
+            Command::new("xdg-open")
+        };
+        // The opener's output would draw over the interface.
+        let mut child = command
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Some openers only return once the browser closes so don't hold up
+        // the interface waiting on them.
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// The url a link from a markdown popup points at. Links are kept with their
+/// decoration, e.g. `(https://example.com)` for an inline link. Reference
+/// style and relative links aren't something a browser can open and give
+/// `None`.
+pub fn link_url(link: &str) -> Option<&str> {
+    let url = link
+        .strip_prefix('(')
+        .and_then(|link| link.strip_suffix(')'))
+        .unwrap_or(link)
+        .trim();
+    let is_url = url.split_once("://").is_some_and(|(scheme, rest)| {
+        !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric()) && !rest.is_empty()
+    });
+    (is_url || url.starts_with("mailto:")).then_some(url)
+}
@@ -0,0 +1,34 @@
+//! Reading the system clipboard.
+//!
+//! Pasting goes through the [`SystemClipboard`] trait so tests can stand in
+//! for the real clipboard, which isn't available on every machine the tests
+//! run on.
+use anyhow::{anyhow, Result};
+
+/// A source of pasted text.
+pub trait SystemClipboard {
+    /// The clipboard contents as plain text.
+    fn get_text(&mut self) -> Result<String>;
+
+    /// The clipboard contents as html if there is any.
+    fn get_html(&mut self) -> Result<String>;
+}
+
+/// The real system clipboard.
+#[derive(Debug, Default)]
+pub struct Arboard;
+
+impl SystemClipboard for Arboard {
+    fn get_text(&mut self) -> Result<String> {
+        arboard::Clipboard::new()?
+            .get_text()
+            .map_err(|e| anyhow!(e))
+    }
+
+    fn get_html(&mut self) -> Result<String> {
+        arboard::Clipboard::new()?
+            .get()
+            .html()
+            .map_err(|e| anyhow!(e))
+    }
+}
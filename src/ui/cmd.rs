@@ -1,28 +1,504 @@
 //! Command mode command parsers.
 use slice_utils::{Measured, Peekable, Seekable, Span, StrCursor};
 
+use super::color::parse_color;
+use crate::book::{
+    formula::{self, Reference},
+    name_to_col,
+    sui::parse_quoted_string,
+    CaseMode, SeriesStart, LAST_COLUMN, LAST_ROW, STYLE_PATHS,
+};
+
 /// A parsed command entered in during command mode.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum Cmd<'a> {
     Write(Option<&'a str>),
+    WriteQuit(Option<&'a str>),
     InsertRows(usize),
     InsertColumns(usize),
-    ColorRows(Option<usize>, String),
-    ColorColumns(Option<usize>, String),
-    ColorCell(String),
+    ColorRows(Option<Extent>, String),
+    ColorColumns(Option<Extent>, String),
+    ColorCell(Option<Reference>, String),
+    Style(&'a str, String),
+    Dedupe(bool),
+    Trim(bool),
+    Case(CaseMode),
+    SplitColumn(String, bool),
+    Fill(Option<SeriesStart>, Option<f64>),
     RenameSheet(Option<usize>, &'a str),
     NewSheet(Option<&'a str>),
     SelectSheet(&'a str),
-    Edit(&'a str),
+    Goto(&'a str),
+    Edit(&'a str, bool),
+    View(&'a str),
+    NextBuffer,
+    PrevBuffer,
+    Buffers,
     Help(Option<&'a str>),
     ExportCsv(&'a str),
+    ExportMd(&'a str),
+    ExportAll(&'a str),
     SystemPaste,
+    Registers,
+    Marks,
+    Keys,
+    Errors,
+    NextError,
+    PrevError,
+    Precedents,
+    Dependents,
+    Calc,
+    Set(&'a str, &'a str),
+    ShowSettings,
     Quit,
+    ForceQuit,
+}
+
+/// Which rows or columns `color-rows` and `color-columns` color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extent {
+    /// This many going down or right from the cursor.
+    Count(usize),
+    /// From the first index to the last counting from 1.
+    Between(usize, usize),
+}
+
+impl<'a> Cmd<'a> {
+    /// Whether running this command changes the book. These are refused in
+    /// read-only mode.
+    pub fn mutates(&self) -> bool {
+        match self {
+            Cmd::Write(_)
+            | Cmd::WriteQuit(_)
+            | Cmd::InsertRows(_)
+            | Cmd::InsertColumns(_)
+            | Cmd::ColorRows(_, _)
+            | Cmd::ColorColumns(_, _)
+            | Cmd::ColorCell(_, _)
+            | Cmd::Style(_, _)
+            | Cmd::Dedupe(_)
+            | Cmd::Trim(_)
+            | Cmd::Case(_)
+            | Cmd::SplitColumn(_, _)
+            | Cmd::Fill(_, _)
+            | Cmd::RenameSheet(_, _)
+            | Cmd::NewSheet(_)
+            | Cmd::SystemPaste => true,
+            Cmd::SelectSheet(_)
+            | Cmd::Goto(_)
+            | Cmd::Edit(_, _)
+            | Cmd::View(_)
+            | Cmd::NextBuffer
+            | Cmd::PrevBuffer
+            | Cmd::Buffers
+            | Cmd::Help(_)
+            | Cmd::ExportCsv(_)
+            | Cmd::ExportMd(_)
+            | Cmd::ExportAll(_)
+            | Cmd::Registers
+            | Cmd::Marks
+            | Cmd::Keys
+            | Cmd::Errors
+            | Cmd::NextError
+            | Cmd::PrevError
+            | Cmd::Precedents
+            | Cmd::Dependents
+            | Cmd::Calc
+            | Cmd::Set(_, _)
+            | Cmd::ShowSettings
+            | Cmd::Quit
+            | Cmd::ForceQuit => false,
+        }
+    }
+}
+
+/// A command as listed for suggestions and help.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// Other spellings of the command, like `w` for `write`.
+    pub aliases: &'static [&'static str],
+    /// The arguments after the name, e.g. `[count] <color>`.
+    pub usage: &'static str,
+    /// What the command does in one line.
+    pub summary: &'static str,
+    /// Anything more to say about it. May be empty.
+    pub description: &'static str,
+    pub examples: &'static [&'static str],
+}
+
+/// Every command [`parse`] understands.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "write",
+        aliases: &["w"],
+        usage: "[path]",
+        summary: "Save the workbook.",
+        description: "Saves to `path` and remembers it for later saves when given, otherwise to the file being edited. `.xlsx` paths save in Excel format and everything else as `.sui`. Asks before overwriting a file that changed on disk or a different existing file.",
+        examples: &["write", "w report.xlsx"],
+    },
+    CommandInfo {
+        name: "wq",
+        aliases: &["x"],
+        usage: "[path]",
+        summary: "Save the workbook and quit.",
+        description: "Saves like `write` and then quits. If the save fails you stay in the application and the error is shown.",
+        examples: &["wq", "x report.sui"],
+    },
+    CommandInfo {
+        name: "insert-rows",
+        aliases: &["ir"],
+        usage: "[count]",
+        summary: "Insert rows at the current row.",
+        description: "Inserts `count` rows, or one without a count.",
+        examples: &["insert-rows", "ir 3"],
+    },
+    CommandInfo {
+        name: "insert-cols",
+        aliases: &["ic"],
+        usage: "[count]",
+        summary: "Insert columns at the current column.",
+        description: "Inserts `count` columns, or one without a count.",
+        examples: &["insert-cols", "ic 2"],
+    },
+    CommandInfo {
+        name: "color-rows",
+        aliases: &[],
+        usage: "[N-M | +count] <color>",
+        summary: "Color the background of rows.",
+        description: "Colors rows `N` to `M`, or `count` rows going down from the current row. A bare number is a count too. In range select mode it colors the selected rows. Colors can be names like `red`, `#rrggbb`, `rgb(r, g, b)`, `hsl(h, s%, l%)` or a 256 color palette index like `33`.",
+        examples: &["color-rows red", "color-rows +3 #ffcc00", "color-rows 5-8 red"],
+    },
+    CommandInfo {
+        name: "color-columns",
+        aliases: &[],
+        usage: "[B-D | N-M | +count] <color>",
+        summary: "Color the background of columns.",
+        description: "Colors columns `B` to `D`, by letter or number, or `count` columns going right from the current column. A bare number is a count too. In range select mode it colors the selected columns.",
+        examples: &[
+            "color-columns blue",
+            "color-columns +2 rgb(0, 128, 0)",
+            "color-columns B-D red",
+        ],
+    },
+    CommandInfo {
+        name: "color-cell",
+        aliases: &["cc"],
+        usage: "[range] <color>",
+        summary: "Color the background of the selected cells.",
+        description: "Colors `range` when given, otherwise the current cell or the selected range. The range may name a sheet like `Sheet2!A1`.",
+        examples: &["color-cell yellow", "color-cell B2:D10 red"],
+    },
+    CommandInfo {
+        name: "style",
+        aliases: &[],
+        usage: "<path> <value>",
+        summary: "Set a style property of the selected cells.",
+        description: "Sets the style at `path` on the current cell or the selected range. The paths are `font.b`, `font.i`, `font.u`, `font.strike`, `font.color`, `fill.bg_color`, `fill.fg_color`, `num_fmt`, `alignment.horizontal`, `alignment.vertical` and `alignment.wrap_text`. Values with spaces go in double quotes.",
+        examples: &[
+            "style font.b true",
+            "style font.color red",
+            "style num_fmt \"0.00%\"",
+            "style alignment.horizontal center",
+        ],
+    },
+    CommandInfo {
+        name: "dedupe",
+        aliases: &[],
+        usage: "[shift]",
+        summary: "Remove repeated rows from the selection.",
+        description: "Clears rows of the selected range that repeat an earlier row, comparing displayed values. With `shift` the remaining rows move up to fill the gaps. Only works from range select mode.",
+        examples: &["dedupe", "dedupe shift"],
+    },
+    CommandInfo {
+        name: "trim",
+        aliases: &[],
+        usage: "",
+        summary: "Trim whitespace around text.",
+        description: "Trims the text in the selected range or the current cell. `trim!` also squeezes runs of whitespace down to one space. Formulas and numbers are left alone.",
+        examples: &["trim", "trim!"],
+    },
+    CommandInfo {
+        name: "case",
+        aliases: &[],
+        usage: "upper|lower|title",
+        summary: "Change the case of text.",
+        description: "Changes the case of the text in the selected range or the current cell. `title` capitalizes the first letter of each word.",
+        examples: &["case upper", "case title"],
+    },
+    CommandInfo {
+        name: "split-col",
+        aliases: &[],
+        usage: "[delimiter]",
+        summary: "Split a column into the columns to its right.",
+        description: "Splits the text in the current column on the delimiter, which defaults to `,`. `\\t` splits on tabs and `\\s` or `\" \"` on spaces. `split-col!` inserts enough new columns first instead of overwriting.",
+        examples: &["split-col", "split-col! ;", "split-col \" - \""],
+    },
+    CommandInfo {
+        name: "fill",
+        aliases: &[],
+        usage: "[start] [step <step>]",
+        summary: "Fill the selection with a series.",
+        description: "Fills the selected range counting from `start` by `step`. A date start steps by days. Without a start the series carries on from the first cell and the step defaults to 1.",
+        examples: &["fill 1 step 1", "fill 2024-01-01 step 7d"],
+    },
+    CommandInfo {
+        name: "rename-sheet",
+        aliases: &[],
+        usage: "[idx] <new-name>",
+        summary: "Rename a sheet.",
+        description: "Renames the sheet at `idx`, or the current sheet without one.",
+        examples: &["rename-sheet Summary", "rename-sheet 2 Data"],
+    },
+    CommandInfo {
+        name: "new-sheet",
+        aliases: &[],
+        usage: "[name]",
+        summary: "Create a new sheet.",
+        description: "Creates a sheet called `name`, or with a default name without one.",
+        examples: &["new-sheet", "new-sheet Totals"],
+    },
+    CommandInfo {
+        name: "select-sheet",
+        aliases: &[],
+        usage: "<name>",
+        summary: "Switch to a sheet by name.",
+        description: "",
+        examples: &["select-sheet Totals"],
+    },
+    CommandInfo {
+        name: "goto",
+        aliases: &[],
+        usage: "<cell>",
+        summary: "Move to a cell.",
+        description: "The cell is on the current sheet unless it names one like `Sheet2!B12`.",
+        examples: &["goto B12", "goto Sheet2!A1"],
+    },
+    CommandInfo {
+        name: "edit",
+        aliases: &["e"],
+        usage: "<path>",
+        summary: "Open a workbook.",
+        description: "Opens the file in a new buffer or switches to it if it is already open. Running `edit` on the current file reloads it from disk unless it has unsaved changes. `edit!` reloads it anyway and throws the changes away.",
+        examples: &["edit budget.xlsx", "edit! budget.xlsx"],
+    },
+    CommandInfo {
+        name: "view",
+        aliases: &[],
+        usage: "<path>",
+        summary: "Open a workbook read-only.",
+        description: "Edits, pastes, style changes and saves are refused while navigation, copy and export still work.",
+        examples: &["view budget.xlsx"],
+    },
+    CommandInfo {
+        name: "bnext",
+        aliases: &["bn"],
+        usage: "",
+        summary: "Switch to the next buffer.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "bprev",
+        aliases: &["bp"],
+        usage: "",
+        summary: "Switch to the previous buffer.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "buffers",
+        aliases: &["ls"],
+        usage: "",
+        summary: "List the open buffers.",
+        description: "Modified buffers are marked with `[+]`.",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "help",
+        aliases: &["?"],
+        usage: "[topic]",
+        summary: "Show help.",
+        description: "The topics are `navigate`, `edit`, `visual`, `command`, `commands` for a list of every command, or the name of a command.",
+        examples: &["help", "help commands", "help write"],
+    },
+    CommandInfo {
+        name: "export-csv",
+        aliases: &[],
+        usage: "<path>",
+        summary: "Export the current sheet as csv.",
+        description: "In range select mode only the selected range is exported.",
+        examples: &["export-csv out.csv"],
+    },
+    CommandInfo {
+        name: "export-md",
+        aliases: &[],
+        usage: "<path>",
+        summary: "Export the current sheet as a Markdown table.",
+        description: "The first row becomes the header. In range select mode only the selected range is exported.",
+        examples: &["export-md table.md"],
+    },
+    CommandInfo {
+        name: "export-all",
+        aliases: &[],
+        usage: "<dir>",
+        summary: "Export every sheet as csv.",
+        description: "Writes one file per sheet into `dir` named after the sheet, creating the directory if needed. Empty sheets are skipped.",
+        examples: &["export-all out"],
+    },
+    CommandInfo {
+        name: "system-paste",
+        aliases: &[],
+        usage: "",
+        summary: "Paste from the system clipboard.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "registers",
+        aliases: &[],
+        usage: "",
+        summary: "Show the copy registers.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "marks",
+        aliases: &[],
+        usage: "",
+        summary: "List the marks and where they point.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "keys",
+        aliases: &[],
+        usage: "",
+        summary: "Show every keybinding.",
+        description: "Lists the keys of each mode including any rebound in the config file. `Alt-k` does the same.",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "errors",
+        aliases: &[],
+        usage: "",
+        summary: "List the error cells on the current sheet.",
+        description: "Type an entry's number to jump to it.",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "next-error",
+        aliases: &[],
+        usage: "",
+        summary: "Move to the next error cell.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "prev-error",
+        aliases: &[],
+        usage: "",
+        summary: "Move to the previous error cell.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "precedents",
+        aliases: &[],
+        usage: "",
+        summary: "List what the current cell's formula refers to.",
+        description: "Type an entry's number to jump to it.",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "dependents",
+        aliases: &[],
+        usage: "",
+        summary: "List the formulas that refer to the current cell.",
+        description: "Type an entry's number to jump to it.",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "calc",
+        aliases: &[],
+        usage: "",
+        summary: "Recalculate every formula now.",
+        description: "",
+        examples: &[],
+    },
+    CommandInfo {
+        name: "set",
+        aliases: &[],
+        usage: "[<option> <value>]",
+        summary: "Change a setting.",
+        description: "Without arguments shows the current settings. See `help command` for the options.",
+        examples: &["set", "set theme light", "set autosave 300"],
+    },
+    CommandInfo {
+        name: "quit",
+        aliases: &["q"],
+        usage: "",
+        summary: "Quit.",
+        description: "Asks whether to save buffers with unsaved changes first. `quit!` quits immediately and discards them.",
+        examples: &["quit", "q!"],
+    },
+];
+
+/// The command called `name` by its full name or an alias.
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS
+        .iter()
+        .find(|info| info.name == name || info.aliases.contains(&name))
+}
+
+/// The command name or alias closest to `name` if it is close enough to be a
+/// likely typo of it.
+pub fn suggest_command(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    COMMANDS
+        .iter()
+        .flat_map(|info| std::iter::once(&info.name).chain(info.aliases.iter()))
+        .map(|candidate| (edit_distance(&name, candidate), *candidate))
+        // Short names still get a suggestion for a single typo.
+        .filter(|(distance, candidate)| *distance <= 2 && *distance < candidate.len())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The message for command text that didn't parse as any command.
+pub fn unrecognized(cmd_text: &str) -> String {
+    let name = cmd_text.split_whitespace().next().unwrap_or_default();
+    match suggest_command(name) {
+        Some(suggestion) => format!(
+            "Unrecognized command {}. Did you mean {}?",
+            name, suggestion
+        ),
+        None => format!("Unrecognized command {}", cmd_text),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
 }
 
 /// Parse command text into a `Cmd`.
 pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'static str> {
     let cursor = StrCursor::new(input);
+    // try consume write-quit before write since `w` is a prefix of `wq`.
+    if let Some(cmd) = try_consume_write_quit(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume write command.
     if let Some(cmd) = try_consume_write(cursor.clone())? {
         return Ok(Some(cmd));
@@ -33,6 +509,9 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_select_sheet(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_goto(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume insert-row command.
     if let Some(cmd) = try_consume_insert_row(cursor.clone())? {
         return Ok(Some(cmd));
@@ -45,10 +524,32 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_export_csv(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_export_md(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_export_all(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    // try consume errors before edit since `e` is a prefix of `errors`.
+    if let Some(cmd) = try_consume_errors(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume edit command.
     if let Some(cmd) = try_consume_edit(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_view(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_next_buffer(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_prev_buffer(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_buffers(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     // try consume help command.
     if let Some(cmd) = try_consume_help(cursor.clone())? {
         return Ok(Some(cmd));
@@ -69,9 +570,54 @@ pub fn parse<'cmd, 'i: 'cmd>(input: &'i str) -> Result<Option<Cmd<'cmd>>, &'stat
     if let Some(cmd) = try_consume_color_cell(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_style(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     if let Some(cmd) = try_consume_system_paste(cursor.clone())? {
         return Ok(Some(cmd));
     }
+    if let Some(cmd) = try_consume_registers(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_marks(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_keys(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_dedupe(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_trim(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_case(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_split_column(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_fill(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_next_error(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_prev_error(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_precedents(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_dependents(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_calc(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
+    if let Some(cmd) = try_consume_set(cursor.clone())? {
+        return Ok(Some(cmd));
+    }
     Ok(None)
 }
 
@@ -118,6 +664,40 @@ fn try_consume_write<'cmd, 'i: 'cmd>(
     })));
 }
 
+fn try_consume_write_quit<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "x";
+    const LONG: &'static str = "wq";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+        // Write-quit never prompts so a trailing `!` is accepted as a no-op.
+        if compare(input.clone(), "!") {
+            input.seek(1);
+        }
+        if input.remaining() > 0 && !is_ws(&mut input) {
+            return Err("Invalid command: Did you mean to type `wq <path>`?");
+        }
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+        if compare(input.clone(), "!") {
+            input.seek(1);
+        }
+        if input.remaining() > 0 && !is_ws(&mut input) {
+            return Ok(None);
+        }
+    } else {
+        return Ok(None);
+    }
+    let arg = input.span(0..).trim();
+    return Ok(Some(Cmd::WriteQuit(if arg.is_empty() {
+        None
+    } else {
+        Some(arg)
+    })));
+}
+
 fn try_consume_export_csv<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -135,6 +715,40 @@ fn try_consume_export_csv<'cmd, 'i: 'cmd>(
     return Ok(Some(Cmd::ExportCsv(arg)));
 }
 
+fn try_consume_export_md<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "export-md";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() == 0 || !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `export-md <path>`?");
+    }
+    let arg = input.span(0..).trim();
+    return Ok(Some(Cmd::ExportMd(arg)));
+}
+
+fn try_consume_export_all<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "export-all";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() == 0 || !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `export-all <dir>`?");
+    }
+    let arg = input.span(0..).trim();
+    return Ok(Some(Cmd::ExportAll(arg)));
+}
+
 fn try_consume_new_sheet<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -176,6 +790,26 @@ fn try_consume_select_sheet<'cmd, 'i: 'cmd>(
     return Ok(Some(Cmd::SelectSheet(arg)));
 }
 
+fn try_consume_goto<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "goto";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `goto <cell>`?");
+    }
+    let arg = input.span(0..).trim();
+    if arg.is_empty() {
+        return Err("Invalid command: Did you forget the cell? `goto <cell>`?");
+    }
+    return Ok(Some(Cmd::Goto(arg)));
+}
+
 fn try_consume_color_cell<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -191,8 +825,53 @@ fn try_consume_color_cell<'cmd, 'i: 'cmd>(
     if input.remaining() > 0 && !is_ws(&mut input) {
         return Err("Invalid command: Did you mean to type `color-cell <color>`?");
     }
-    let arg = parse_color(input.span(0..).trim())?;
-    return Ok(Some(Cmd::ColorCell(arg)));
+    let arg = input.span(0..).trim();
+    // A leading range is told apart from the color by parsing as one.
+    if let Some((first, rest)) = arg.split_once(char::is_whitespace) {
+        if let Some(range) = formula::parse_reference(first) {
+            return Ok(Some(Cmd::ColorCell(Some(range), parse_color(rest.trim())?)));
+        }
+        if parse_color(arg).is_err() {
+            return Err("Invalid command: Expected a range like `B2:D10` before the color");
+        }
+    }
+    return Ok(Some(Cmd::ColorCell(None, parse_color(arg)?)));
+}
+
+fn try_consume_style<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "style";
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `style <path> <value>`?");
+    }
+    let arg = input.span(0..).trim();
+    let Some((path, value)) = arg.split_once(char::is_whitespace) else {
+        return Err("Invalid command: Expected a style path and a value like `style font.b true`");
+    };
+    let Some(path) = STYLE_PATHS.iter().copied().find(|p| *p == path) else {
+        return Err("Invalid command: Unknown style path. `help style` lists them");
+    };
+    let value = value.trim();
+    let value = if value.starts_with('"') {
+        parse_quoted_string(value).ok_or("Invalid command: Missing the closing quote")?
+    } else if value.contains(char::is_whitespace) {
+        return Err("Invalid command: Quote values with spaces like `style num_fmt \"0 kg\"`");
+    } else {
+        value.to_string()
+    };
+    // Colors take the same names and syntax as `color-cell`.
+    let value = if path.ends_with("color") {
+        parse_color(&value)?
+    } else {
+        value
+    };
+    return Ok(Some(Cmd::Style(path, value)));
 }
 
 fn try_consume_insert_row<'cmd, 'i: 'cmd>(
@@ -264,15 +943,99 @@ fn try_consume_edit<'cmd, 'i: 'cmd>(
     } else {
         return Ok(None);
     };
+    let force = compare(input.clone(), "!");
+    if force {
+        input.seek(1);
+    }
     if input.remaining() > 0 && !is_ws(&mut input) {
         return Err("Invalid command: Did you mean to type `edit <arg>`?");
     }
     let arg = input.span(0..).trim();
-    return Ok(Some(Cmd::Edit(if arg.is_empty() {
-        return Err("You must pass in a path to edit");
+    return Ok(Some(Cmd::Edit(
+        if arg.is_empty() {
+            return Err("You must pass in a path to edit");
+        } else {
+            arg
+        },
+        force,
+    )));
+}
+
+fn try_consume_view<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "view";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
     } else {
-        arg
-    })));
+        return Ok(None);
+    };
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `view <arg>`?");
+    }
+    let arg = input.span(0..).trim();
+    if arg.is_empty() {
+        return Err("You must pass in a path to view");
+    }
+    return Ok(Some(Cmd::View(arg)));
+}
+
+fn try_consume_next_buffer<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "bn";
+    const LONG: &'static str = "bnext";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: bnext does not take an argument");
+    }
+    return Ok(Some(Cmd::NextBuffer));
+}
+
+fn try_consume_prev_buffer<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "bp";
+    const LONG: &'static str = "bprev";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: bprev does not take an argument");
+    }
+    return Ok(Some(Cmd::PrevBuffer));
+}
+
+fn try_consume_buffers<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const SHORT: &'static str = "ls";
+    const LONG: &'static str = "buffers";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else if compare(input.clone(), SHORT) {
+        input.seek(SHORT.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: buffers does not take an argument");
+    }
+    return Ok(Some(Cmd::Buffers));
 }
 
 fn try_consume_help<'cmd, 'i: 'cmd>(
@@ -314,6 +1077,13 @@ fn try_consume_quit<'cmd, 'i: 'cmd>(
     } else {
         return Ok(None);
     }
+    if compare(input.clone(), "!") {
+        input.seek(1);
+        if input.remaining() > 0 {
+            return Err("Invalid command: Quit does not take an argument");
+        }
+        return Ok(Some(Cmd::ForceQuit));
+    }
     if input.remaining() > 0 {
         return Err("Invalid command: Quit does not take an argument");
     }
@@ -336,6 +1106,310 @@ fn try_consume_system_paste<'cmd, 'i: 'cmd>(
     return Ok(Some(Cmd::SystemPaste));
 }
 
+fn try_consume_registers<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "registers";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: registers does not take an argument");
+    }
+    return Ok(Some(Cmd::Registers));
+}
+
+fn try_consume_marks<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "marks";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: marks does not take an argument");
+    }
+    return Ok(Some(Cmd::Marks));
+}
+
+fn try_consume_keys<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "keys";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: keys does not take an argument");
+    }
+    return Ok(Some(Cmd::Keys));
+}
+
+fn try_consume_dedupe<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "dedupe";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `dedupe [shift]`?");
+    }
+    return match input.span(0..).trim() {
+        "" => Ok(Some(Cmd::Dedupe(false))),
+        "shift" => Ok(Some(Cmd::Dedupe(true))),
+        _ => Err("dedupe only takes `shift` as an argument"),
+    };
+}
+
+fn try_consume_trim<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "trim";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    let collapse = compare(input.clone(), "!");
+    if collapse {
+        input.seek(1);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: trim does not take an argument");
+    }
+    return Ok(Some(Cmd::Trim(collapse)));
+}
+
+fn try_consume_case<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "case";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `case upper|lower|title`?");
+    }
+    return match input.span(0..).trim() {
+        "upper" => Ok(Some(Cmd::Case(CaseMode::Upper))),
+        "lower" => Ok(Some(Cmd::Case(CaseMode::Lower))),
+        "title" => Ok(Some(Cmd::Case(CaseMode::Title))),
+        _ => Err("case takes one of `upper`, `lower` or `title`"),
+    };
+}
+
+fn try_consume_split_column<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "split-col";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    let insert = compare(input.clone(), "!");
+    if insert {
+        input.seek(1);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `split-col [delimiter]`?");
+    }
+    let delimiter = match input.span(0..).trim() {
+        "" => ",".to_string(),
+        "\\t" => "\t".to_string(),
+        "\\s" => " ".to_string(),
+        // Quotes keep a delimiter like a space that would be trimmed away.
+        quoted if quoted.len() >= 2 && quoted.starts_with('"') && quoted.ends_with('"') => {
+            match &quoted[1..quoted.len() - 1] {
+                "" => return Err("Invalid command: The delimiter can't be empty"),
+                delimiter => delimiter.to_string(),
+            }
+        }
+        delimiter => delimiter.to_string(),
+    };
+    return Ok(Some(Cmd::SplitColumn(delimiter, insert)));
+}
+
+fn try_consume_fill<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "fill";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Err("Invalid command: Did you mean to type `fill [start] [step <step>]`?");
+    }
+    let args: Vec<&str> = input.span(0..).split_whitespace().collect();
+    let (start, step) = match args.as_slice() {
+        [] => (None, None),
+        ["step", step] => (None, Some(*step)),
+        [start] => (Some(*start), None),
+        [start, "step", step] => (Some(*start), Some(*step)),
+        _ => return Err("Invalid command: Did you mean to type `fill [start] [step <step>]`?"),
+    };
+    let start = match start {
+        Some(start) => Some(
+            SeriesStart::parse(start)
+                .ok_or("The fill start must be a number or a date like 2024-01-31")?,
+        ),
+        None => None,
+    };
+    let step = match step {
+        // Dates step by days so allow writing that out.
+        Some(step) => Some(
+            step.strip_suffix('d')
+                .unwrap_or(step)
+                .parse::<f64>()
+                .map_err(|_| "The fill step must be a number like 2 or 7d")?,
+        ),
+        None => None,
+    };
+    return Ok(Some(Cmd::Fill(start, step)));
+}
+
+fn try_consume_errors<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "errors";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: errors does not take an argument");
+    }
+    return Ok(Some(Cmd::Errors));
+}
+
+fn try_consume_next_error<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "next-error";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: next-error does not take an argument");
+    }
+    return Ok(Some(Cmd::NextError));
+}
+
+fn try_consume_prev_error<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "prev-error";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: prev-error does not take an argument");
+    }
+    return Ok(Some(Cmd::PrevError));
+}
+
+fn try_consume_precedents<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "precedents";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: precedents does not take an argument");
+    }
+    return Ok(Some(Cmd::Precedents));
+}
+
+fn try_consume_dependents<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "dependents";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: dependents does not take an argument");
+    }
+    return Ok(Some(Cmd::Dependents));
+}
+
+fn try_consume_calc<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "calc";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 {
+        return Err("Invalid command: calc does not take an argument");
+    }
+    return Ok(Some(Cmd::Calc));
+}
+
+fn try_consume_set<'cmd, 'i: 'cmd>(
+    mut input: StrCursor<'i>,
+) -> Result<Option<Cmd<'cmd>>, &'static str> {
+    const LONG: &'static str = "set";
+
+    if compare(input.clone(), LONG) {
+        input.seek(LONG.len());
+    } else {
+        return Ok(None);
+    }
+    if input.remaining() > 0 && !is_ws(&mut input) {
+        return Ok(None);
+    }
+    let args = input.span(0..).trim();
+    if args.is_empty() {
+        return Ok(Some(Cmd::ShowSettings));
+    }
+    match args.split_once(char::is_whitespace) {
+        Some((name, value)) if !value.trim().is_empty() => {
+            Ok(Some(Cmd::Set(name, value.trim())))
+        }
+        _ => Err("Invalid command: Did you mean to type `set <option> <value>`?"),
+    }
+}
+
 fn try_consume_rename_sheet<'cmd, 'i: 'cmd>(
     mut input: StrCursor<'i>,
 ) -> Result<Option<Cmd<'cmd>>, &'static str> {
@@ -366,11 +1440,14 @@ fn try_consume_color_rows<'cmd, 'i: 'cmd>(
         return Ok(None);
     }
     if input.remaining() > 0 && !is_ws(&mut input) {
-        return Err("Invalid command: Did you mean to type `color-rows [count] <color>`?");
+        return Err("Invalid command: Did you mean to type `color-rows [N-M | +count] <color>`?");
     }
-    let (idx, rest) = try_consume_usize(input.clone());
-    let arg = parse_color(rest.span(0..).trim())?;
-    return Ok(Some(Cmd::ColorRows(idx, arg)));
+    let (extent, arg) = parse_extent_and_color(
+        input.span(0..).trim(),
+        false,
+        "Invalid command: Use `color-rows N-M <color>` for rows N to M or `color-rows +count <color>` for rows from the cursor",
+    )?;
+    return Ok(Some(Cmd::ColorRows(extent, arg)));
 }
 
 fn try_consume_color_columns<'cmd, 'i: 'cmd>(
@@ -383,52 +1460,69 @@ fn try_consume_color_columns<'cmd, 'i: 'cmd>(
         return Ok(None);
     }
     if input.remaining() > 0 && !is_ws(&mut input) {
-        return Err("Invalid command: Did you mean to type `color-columns [count] <color>`?");
+        return Err("Invalid command: Did you mean to type `color-columns [B-D | N-M | +count] <color>`?");
     }
-    let (idx, rest) = try_consume_usize(input.clone());
-    let arg = parse_color(rest.span(0..).trim())?;
-    return Ok(Some(Cmd::ColorColumns(idx, arg)));
-}
-
-pub(crate) fn parse_color(color: &str) -> Result<String, &'static str> {
-    use colorsys::{Ansi256, Rgb};
-    if color.is_empty() {
-        return Err("Invalid command: `color-columns` requires a color argument");
-    }
-    let parsed = match color.to_lowercase().as_str() {
-        "black" => Ansi256::new(0).as_rgb().to_hex_string(),
-        "red" => Ansi256::new(1).as_rgb().to_hex_string(),
-        "green" => Ansi256::new(2).as_rgb().to_hex_string(),
-        "yellow" => Ansi256::new(3).as_rgb().to_hex_string(),
-        "blue" => Ansi256::new(4).as_rgb().to_hex_string(),
-        "magenta" => Ansi256::new(5).as_rgb().to_hex_string(),
-        "cyan" => Ansi256::new(6).as_rgb().to_hex_string(),
-        "gray" | "grey" => Ansi256::new(7).as_rgb().to_hex_string(),
-        "darkgrey" | "darkgray" => Ansi256::new(8).as_rgb().to_hex_string(),
-        "lightred" => Ansi256::new(9).as_rgb().to_hex_string(),
-        "lightgreen" => Ansi256::new(10).as_rgb().to_hex_string(),
-        "lightyellow" => Ansi256::new(11).as_rgb().to_hex_string(),
-        "lightblue" => Ansi256::new(12).as_rgb().to_hex_string(),
-        "lightmagenta" => Ansi256::new(13).as_rgb().to_hex_string(),
-        "lightcyan" => Ansi256::new(14).as_rgb().to_hex_string(),
-        "white" => Ansi256::new(15).as_rgb().to_hex_string(),
-        candidate => {
-            if candidate.starts_with("#") {
-                candidate.to_string()
-            } else if candidate.starts_with("rgb(") {
-                if let Ok(rgb) = <Rgb as std::str::FromStr>::from_str(candidate) {
-                    // Note that the colorsys rgb model clamps the f64 values to no more
-                    // than 255.0 so the below casts are safe.
-                    rgb.to_hex_string()
-                } else {
-                    return Err("Invalid color");
-                }
-            } else {
-                return Err("Invalid color");
-            }
+    let (extent, arg) = parse_extent_and_color(
+        input.span(0..).trim(),
+        true,
+        "Invalid command: Use `color-columns B-D <color>` for columns B to D or `color-columns +count <color>` for columns from the cursor",
+    )?;
+    return Ok(Some(Cmd::ColorColumns(extent, arg)));
+}
+
+/// Split the arguments of `color-rows` or `color-columns` into the optional
+/// extent and the color. `invalid` is the error for an extent that doesn't
+/// parse.
+fn parse_extent_and_color(
+    arg: &str,
+    columns: bool,
+    invalid: &'static str,
+) -> Result<(Option<Extent>, String), &'static str> {
+    if let Some((first, rest)) = arg.split_once(char::is_whitespace) {
+        if let Some(extent) = parse_extent(first, columns) {
+            return Ok((Some(extent), parse_color(rest.trim())?));
+        }
+        if parse_color(arg).is_err() {
+            return Err(invalid);
         }
+    }
+    Ok((None, parse_color(arg)?))
+}
+
+/// Parse `+3` or `3` as a count and `5-8` as rows or columns 5 to 8. With
+/// `columns` the indices may also be column names like `B-D` or just `B`.
+fn parse_extent(text: &str, columns: bool) -> Option<Extent> {
+    let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    let is_name = |text: &str| columns && name_to_col(text).is_some();
+    let last = if columns { LAST_COLUMN } else { LAST_ROW } as usize;
+    let index = |text: &str| {
+        let idx = if is_name(text) {
+            name_to_col(text)?
+        } else if is_number(text) {
+            text.parse().ok()?
+        } else {
+            return None;
+        };
+        (1..=last).contains(&idx).then_some(idx)
     };
-    Ok(parsed)
+    if let Some((first, last)) = text.split_once('-') {
+        let (first, last) = (index(first)?, index(last)?);
+        return Some(Extent::Between(first.min(last), first.max(last)));
+    }
+    if is_name(text) {
+        let idx = index(text)?;
+        return Some(Extent::Between(idx, idx));
+    }
+    let count = text.strip_prefix('+').unwrap_or(text);
+    if !is_number(count) {
+        return None;
+    }
+    // No count can reach past the last row or column.
+    count
+        .parse()
+        .ok()
+        .filter(|count| *count <= last)
+        .map(Extent::Count)
 }
 
 fn try_consume_usize<'cmd, 'i: 'cmd>(mut input: StrCursor<'i>) -> (Option<usize>, StrCursor<'i>) {
@@ -0,0 +1,63 @@
+//! Parsing colors written as names, `#rrggbb`, `rgb()`, `hsl()` or palette
+//! indices.
+use colorsys::{Ansi256, Hsl, Rgb};
+
+/// Parse a color name like `red` or any of the forms [`parse_rgb`] takes into
+/// a `#rrggbb` hex string.
+pub(crate) fn parse_color(color: &str) -> Result<String, &'static str> {
+    if color.is_empty() {
+        return Err("Invalid command: Missing a color");
+    }
+    let parsed = match color.to_lowercase().as_str() {
+        "black" => Ansi256::new(0).as_rgb().to_hex_string(),
+        "red" => Ansi256::new(1).as_rgb().to_hex_string(),
+        "green" => Ansi256::new(2).as_rgb().to_hex_string(),
+        "yellow" => Ansi256::new(3).as_rgb().to_hex_string(),
+        "blue" => Ansi256::new(4).as_rgb().to_hex_string(),
+        "magenta" => Ansi256::new(5).as_rgb().to_hex_string(),
+        "cyan" => Ansi256::new(6).as_rgb().to_hex_string(),
+        "gray" | "grey" => Ansi256::new(7).as_rgb().to_hex_string(),
+        "darkgrey" | "darkgray" => Ansi256::new(8).as_rgb().to_hex_string(),
+        "lightred" => Ansi256::new(9).as_rgb().to_hex_string(),
+        "lightgreen" => Ansi256::new(10).as_rgb().to_hex_string(),
+        "lightyellow" => Ansi256::new(11).as_rgb().to_hex_string(),
+        "lightblue" => Ansi256::new(12).as_rgb().to_hex_string(),
+        "lightmagenta" => Ansi256::new(13).as_rgb().to_hex_string(),
+        "lightcyan" => Ansi256::new(14).as_rgb().to_hex_string(),
+        "white" => Ansi256::new(15).as_rgb().to_hex_string(),
+        candidate => parse_rgb(candidate).ok_or("Invalid color")?.to_hex_string(),
+    };
+    Ok(parsed)
+}
+
+/// Parse the colors that aren't names: `#rrggbb`, `rgb(r, g, b)`,
+/// `hsl(h, s%, l%)` and indices into the 256 color palette like `33`.
+pub(crate) fn parse_rgb(color: &str) -> Option<Rgb> {
+    let color = color.trim();
+    if color.starts_with('#') {
+        Rgb::from_hex_str(color).ok()
+    } else if color.starts_with("rgb(") {
+        // Note that the colorsys rgb model clamps the values to no more than
+        // 255.0.
+        <Rgb as std::str::FromStr>::from_str(color).ok()
+    } else if let Some(args) = color.strip_prefix("hsl(") {
+        let args: Vec<&str> = args.strip_suffix(')')?.split(',').map(str::trim).collect();
+        let [hue, saturation, lightness] = args[..] else {
+            return None;
+        };
+        let percent = |text: &str| -> Option<f64> {
+            let value: f64 = text.strip_suffix('%').unwrap_or(text).parse().ok()?;
+            (0.0..=100.0).contains(&value).then_some(value)
+        };
+        let hue: f64 = hue.parse().ok()?;
+        if !(0.0..=360.0).contains(&hue) {
+            return None;
+        }
+        let hsl = Hsl::new(hue, percent(saturation)?, percent(lightness)?, None);
+        Some(Rgb::from(&hsl))
+    } else if !color.is_empty() && color.chars().all(|c| c.is_ascii_digit()) {
+        color.parse().ok().map(|idx| Ansi256::new(idx).as_rgb())
+    } else {
+        None
+    }
+}
@@ -1,11 +1,58 @@
+use crate::ui::cmd::{find_command, CommandInfo, COMMANDS};
 use crate::ui::render::markdown::Markdown;
 
 pub fn to_widget(topic: &str) -> Markdown {
     match topic {
+        "" => Markdown::from_str(include_str!("../../../docs/intro.md")),
         "navigate" => Markdown::from_str(include_str!("../../../docs/navigation.md")),
         "edit" => Markdown::from_str(include_str!("../../../docs/edit.md")),
         "command" => Markdown::from_str(include_str!("../../../docs/command.md")),
         "visual" => Markdown::from_str(include_str!("../../../docs/visual.md")),
-        _ => Markdown::from_str(include_str!("../../../docs/intro.md")),
+        topic => match find_command(topic) {
+            Some(info) => Markdown::from_str(&command_page(info)),
+            None => Markdown::from_str(&command_index()),
+        },
+    }
+}
+
+/// A help page for one command.
+fn command_page(info: &CommandInfo) -> String {
+    let mut page = format!(
+        "# {}\n\n`{}`\n\n{}\n\n",
+        info.name,
+        signature(info),
+        info.summary
+    );
+    if !info.description.is_empty() {
+        page.push_str(&format!("{}\n\n", info.description));
+    }
+    if !info.aliases.is_empty() {
+        let aliases: Vec<String> = info.aliases.iter().map(|a| format!("`{}`", a)).collect();
+        page.push_str(&format!("## Aliases\n\n{}\n\n", aliases.join(", ")));
+    }
+    if !info.examples.is_empty() {
+        page.push_str("## Examples\n\n");
+        for example in info.examples {
+            page.push_str(&format!("* `{}`\n", example));
+        }
+    }
+    page
+}
+
+/// A list of every command with its summary.
+fn command_index() -> String {
+    let mut page =
+        String::from("# Commands\n\nType `help <command>` for more about a command.\n\n");
+    for info in COMMANDS {
+        page.push_str(&format!("* `{}` {}\n", signature(info), info.summary));
+    }
+    page
+}
+
+fn signature(info: &CommandInfo) -> String {
+    if info.usage.is_empty() {
+        info.name.to_string()
+    } else {
+        format!("{} {}", info.name, info.usage)
     }
 }
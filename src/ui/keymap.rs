@@ -0,0 +1,701 @@
+//! Keybindings for the modal interface.
+//!
+//! Navigate and range select mode dispatch keys through a [`Keymap`] that maps
+//! a key and its modifiers, or a sequence of two keys like `g t`, to a named
+//! [`Action`]. The default keymap reproduces the built in bindings and the
+//! `[keys]` section of the config file can override them with entries like
+//! `navigate."ctrl-d" = "move_down"` or `navigate."g b" = "next_buffer"`.
+//! [`Keymap::cheat_sheet`] lists the current bindings for the `keys` command
+//! so the displayed keys always match what the handlers do.
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::Modality;
+
+/// A named action that a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Cancel,
+    Help,
+    Command,
+    Edit,
+    Substitute,
+    Save,
+    Quit,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    GPrefix,
+    NextSheet,
+    PrevSheet,
+    NextBuffer,
+    PrevBuffer,
+    RangeSelect,
+    Visual,
+    Select,
+    Copy,
+    CopyFormatted,
+    Paste,
+    RegisterPrefix,
+    ClearCell,
+    ClearCellAll,
+    ClearRow,
+    ClearRowAll,
+    ClearColumn,
+    CopyRow,
+    CopyColumn,
+    ToggleBold,
+    ToggleItalic,
+    GrowColumn,
+    ShrinkColumn,
+    InsertRowBelow,
+    InsertRowAbove,
+    Extend,
+    Peek,
+    Recalc,
+    NextError,
+    PrevError,
+    RecordMacro,
+    PlayMacro,
+    RepeatChange,
+    JumpBack,
+    JumpForward,
+    SetMark,
+    JumpToMark,
+    WriteQuit,
+    ForceQuit,
+    LastRow,
+    SelectRows,
+    SelectColumns,
+    SwapAnchor,
+    SwapAnchorColumn,
+    CopyWithHeader,
+    Increment,
+    Decrement,
+    StartFormula,
+    Keys,
+}
+
+const ACTION_NAMES: [(Action, &'static str); 60] = [
+    (Action::Cancel, "cancel"),
+    (Action::Help, "help"),
+    (Action::Command, "command"),
+    (Action::Edit, "edit"),
+    (Action::Substitute, "substitute"),
+    (Action::Save, "save"),
+    (Action::Quit, "quit"),
+    (Action::MoveUp, "move_up"),
+    (Action::MoveDown, "move_down"),
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveRight, "move_right"),
+    (Action::GPrefix, "g_prefix"),
+    (Action::NextSheet, "next_sheet"),
+    (Action::PrevSheet, "prev_sheet"),
+    (Action::NextBuffer, "next_buffer"),
+    (Action::PrevBuffer, "prev_buffer"),
+    (Action::RangeSelect, "range_select"),
+    (Action::Visual, "visual"),
+    (Action::Select, "select"),
+    (Action::Copy, "copy"),
+    (Action::CopyFormatted, "copy_formatted"),
+    (Action::Paste, "paste"),
+    (Action::RegisterPrefix, "register_prefix"),
+    (Action::ClearCell, "clear_cell"),
+    (Action::ClearCellAll, "clear_cell_all"),
+    (Action::ClearRow, "clear_row"),
+    (Action::ClearRowAll, "clear_row_all"),
+    (Action::ClearColumn, "clear_column"),
+    (Action::CopyRow, "copy_row"),
+    (Action::CopyColumn, "copy_column"),
+    (Action::ToggleBold, "toggle_bold"),
+    (Action::ToggleItalic, "toggle_italic"),
+    (Action::GrowColumn, "grow_column"),
+    (Action::ShrinkColumn, "shrink_column"),
+    (Action::InsertRowBelow, "insert_row_below"),
+    (Action::InsertRowAbove, "insert_row_above"),
+    (Action::Extend, "extend"),
+    (Action::Peek, "peek"),
+    (Action::Recalc, "recalc"),
+    (Action::NextError, "next_error"),
+    (Action::PrevError, "prev_error"),
+    (Action::RecordMacro, "record_macro"),
+    (Action::PlayMacro, "play_macro"),
+    (Action::RepeatChange, "repeat_change"),
+    (Action::JumpBack, "jump_back"),
+    (Action::JumpForward, "jump_forward"),
+    (Action::SetMark, "set_mark"),
+    (Action::JumpToMark, "jump_to_mark"),
+    (Action::WriteQuit, "write_quit"),
+    (Action::ForceQuit, "force_quit"),
+    (Action::LastRow, "last_row"),
+    (Action::SelectRows, "select_rows"),
+    (Action::SelectColumns, "select_columns"),
+    (Action::SwapAnchor, "swap_anchor"),
+    (Action::SwapAnchorColumn, "swap_anchor_column"),
+    (Action::CopyWithHeader, "copy_with_header"),
+    (Action::Increment, "increment"),
+    (Action::Decrement, "decrement"),
+    (Action::StartFormula, "start_formula"),
+    (Action::Keys, "keys"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(action, _)| *action)
+    }
+
+    /// Whether the action changes the book. These are refused in read-only
+    /// mode.
+    pub fn mutates(&self) -> bool {
+        matches!(
+            self,
+            Action::Edit
+                | Action::Substitute
+                | Action::Save
+                | Action::Paste
+                | Action::ClearCell
+                | Action::ClearCellAll
+                | Action::ClearRow
+                | Action::ClearRowAll
+                | Action::ClearColumn
+                | Action::ToggleBold
+                | Action::ToggleItalic
+                | Action::GrowColumn
+                | Action::ShrinkColumn
+                | Action::InsertRowBelow
+                | Action::InsertRowAbove
+                | Action::Extend
+                | Action::RepeatChange
+                | Action::Increment
+                | Action::Decrement
+                | Action::StartFormula
+        )
+    }
+
+    /// What the action does for the keybinding cheat sheet.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Cancel => "Cancel a count or a pending key",
+            Action::Help => "Show help for the current mode",
+            Action::Command => "Enter command mode",
+            Action::Edit => "Edit the cell",
+            Action::Substitute => "Clear the cell and edit it",
+            Action::Save => "Save the workbook",
+            Action::Quit => "Quit",
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::GPrefix => "Start a `g` command. `gg` goes to the top",
+            Action::NextSheet => "Next sheet",
+            Action::PrevSheet => "Previous sheet",
+            Action::NextBuffer => "Next buffer",
+            Action::PrevBuffer => "Previous buffer",
+            Action::RangeSelect => "Select a range",
+            Action::Visual => "Select a range from the cursor",
+            Action::Select => "Mark a corner of the selection",
+            Action::Copy => "Copy",
+            Action::CopyFormatted => "Copy the displayed values",
+            Action::Paste => "Paste",
+            Action::RegisterPrefix => "Pick the register for the next copy or paste",
+            Action::ClearCell => "Clear the contents",
+            Action::ClearCellAll => "Clear the contents and formatting",
+            Action::ClearRow => "Clear the row",
+            Action::ClearRowAll => "Clear the row and its formatting",
+            Action::ClearColumn => "Clear the column",
+            Action::CopyRow => "Copy the row",
+            Action::CopyColumn => "Copy the column",
+            Action::ToggleBold => "Toggle bold",
+            Action::ToggleItalic => "Toggle italic",
+            Action::GrowColumn => "Widen the column",
+            Action::ShrinkColumn => "Narrow the column",
+            Action::InsertRowBelow => "Insert a row below and edit it",
+            Action::InsertRowAbove => "Insert a row above and edit it",
+            Action::Extend => "Extend the first row or column over the selection",
+            Action::Peek => "Show the whole cell in a popup",
+            Action::Recalc => "Recalculate every formula",
+            Action::NextError => "Next error cell",
+            Action::PrevError => "Previous error cell",
+            Action::RecordMacro => "Record a macro into a register or stop recording",
+            Action::PlayMacro => "Play the macro in a register",
+            Action::RepeatChange => "Repeat the last change",
+            Action::JumpBack => "Jump back",
+            Action::JumpForward => "Jump forward",
+            Action::SetMark => "Set a mark",
+            Action::JumpToMark => "Jump to a mark",
+            Action::WriteQuit => "Save and quit",
+            Action::ForceQuit => "Quit without saving",
+            Action::LastRow => "Go to the last row in the column",
+            Action::SelectRows => "Select whole rows",
+            Action::SelectColumns => "Select whole columns",
+            Action::SwapAnchor => "Swap the corners of the selection",
+            Action::SwapAnchorColumn => "Swap the columns of the selection",
+            Action::CopyWithHeader => "Copy with the header row",
+            Action::Increment => "Add the count to numbers",
+            Action::Decrement => "Subtract the count from numbers",
+            Action::StartFormula => "Start a formula in the cell",
+            Action::Keys => "Show the keybindings",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        ACTION_NAMES
+            .iter()
+            .find(|(action, _)| action == self)
+            .map(|(_, name)| *name)
+            .expect("Every action has a name")
+    }
+}
+
+/// A keybinding override read from the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingOverride {
+    /// 1-based line number in the config file.
+    pub line: usize,
+    pub mode: String,
+    pub key: String,
+    pub action: String,
+}
+
+/// A key and its modifiers.
+pub type Key = (KeyCode, KeyModifiers);
+
+/// Maps keys in a modality to actions.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Most bindings are a single key. A two key sequence leaves its first key
+    /// doing whatever it does alone, so `g` still starts `gg`.
+    bindings: HashMap<(Modality, Vec<Key>), Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        let none = KeyModifiers::NONE;
+        let ctrl = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+        let shift = KeyModifiers::SHIFT;
+        let c = KeyCode::Char;
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        for (code, mods, action) in [
+            (KeyCode::Esc, none, Cancel),
+            (c('h'), alt, Help),
+            (c(':'), none, Command),
+            (c('e'), none, Edit),
+            (c('i'), none, Edit),
+            (c('s'), none, Substitute),
+            (c('s'), ctrl, Save),
+            (c('q'), none, RecordMacro),
+            (c('k'), none, MoveUp),
+            (KeyCode::Up, none, MoveUp),
+            (KeyCode::Enter, shift, MoveUp),
+            (c('j'), none, MoveDown),
+            (KeyCode::Down, none, MoveDown),
+            (KeyCode::Enter, none, MoveDown),
+            (c('h'), none, MoveLeft),
+            (KeyCode::Left, none, MoveLeft),
+            (KeyCode::Tab, shift, MoveLeft),
+            (c('l'), none, MoveRight),
+            (KeyCode::Right, none, MoveRight),
+            (KeyCode::Tab, none, MoveRight),
+            (c('g'), none, GPrefix),
+            (c('n'), ctrl, NextSheet),
+            (c('p'), ctrl, PrevSheet),
+            (c('r'), ctrl, RangeSelect),
+            (c('v'), none, Visual),
+            (c('c'), ctrl, Copy),
+            (c('y'), none, Copy),
+            (c('C'), ctrl, CopyFormatted),
+            (c('Y'), none, CopyFormatted),
+            (c('p'), none, Paste),
+            (c('v'), ctrl, Paste),
+            (c('"'), none, RegisterPrefix),
+            (c('d'), none, ClearCell),
+            (c('D'), none, ClearCellAll),
+            (c('B'), none, ToggleBold),
+            (c('I'), none, ToggleItalic),
+            (c('l'), ctrl, GrowColumn),
+            (c('h'), ctrl, ShrinkColumn),
+            (c('o'), none, InsertRowBelow),
+            (c('O'), none, InsertRowAbove),
+            (c('K'), none, Peek),
+            (KeyCode::F(9), none, Recalc),
+            (c('@'), none, PlayMacro),
+            (c('.'), none, RepeatChange),
+            (c('o'), ctrl, JumpBack),
+            (c('O'), ctrl, JumpForward),
+            (c('m'), none, SetMark),
+            (c('`'), none, JumpToMark),
+            (c('\''), none, JumpToMark),
+            (c('G'), none, LastRow),
+            (c('V'), none, SelectRows),
+            (c('a'), ctrl, Increment),
+            (c('x'), ctrl, Decrement),
+            (c('+'), none, Increment),
+            (c('-'), none, Decrement),
+            (c('='), none, StartFormula),
+            (c('k'), alt, Keys),
+        ] {
+            keymap.bind(Modality::Navigate, code, mods, action);
+        }
+        for (keys, action) in [
+            ([c('g'), c('t')], NextBuffer),
+            ([c('g'), c('T')], PrevBuffer),
+            ([c(']'), c('e')], NextError),
+            ([c('['), c('e')], PrevError),
+            ([c('d'), c('d')], ClearRow),
+            ([c('d'), c('D')], ClearRowAll),
+            ([c('d'), c('c')], ClearColumn),
+            ([c('y'), c('y')], CopyRow),
+            ([c('y'), c('c')], CopyColumn),
+            ([c('Z'), c('Z')], WriteQuit),
+            ([c('Z'), c('Q')], ForceQuit),
+        ] {
+            keymap.bind_sequence(Modality::Navigate, &keys.map(|code| (code, none)), action);
+        }
+        for (code, mods, action) in [
+            (KeyCode::Esc, none, Cancel),
+            (c('h'), alt, Help),
+            (c(':'), none, Command),
+            (c('k'), none, MoveUp),
+            (c('j'), none, MoveDown),
+            (c('h'), none, MoveLeft),
+            (c('l'), none, MoveRight),
+            (c(' '), none, Select),
+            (KeyCode::Enter, none, Select),
+            (c('n'), ctrl, NextSheet),
+            (c('p'), ctrl, PrevSheet),
+            // NOTE(zaphar): Range select copies the formatted values by
+            // default which is the opposite of navigate mode.
+            (c('c'), ctrl, CopyFormatted),
+            (c('y'), none, CopyFormatted),
+            (c('C'), ctrl, Copy),
+            (c('Y'), none, Copy),
+            (c('"'), none, RegisterPrefix),
+            (c('d'), none, ClearCell),
+            (c('D'), none, ClearCellAll),
+            (c('x'), none, Extend),
+            (c('V'), none, SelectRows),
+            (c('C'), none, SelectColumns),
+            (c('|'), none, SelectColumns),
+            (c('o'), none, SwapAnchor),
+            (c('O'), none, SwapAnchorColumn),
+            (c('H'), none, CopyWithHeader),
+            (c('a'), ctrl, Increment),
+            (c('x'), ctrl, Decrement),
+            (c('+'), none, Increment),
+            (c('-'), none, Decrement),
+            (c('k'), alt, Keys),
+        ] {
+            keymap.bind(Modality::RangeSelect, code, mods, action);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn bind(&mut self, modality: Modality, code: KeyCode, mods: KeyModifiers, action: Action) {
+        self.bind_sequence(modality, &[(code, mods)], action);
+    }
+
+    /// Bind a sequence of keys pressed one after the other.
+    pub fn bind_sequence(&mut self, modality: Modality, keys: &[Key], action: Action) {
+        self.bindings
+            .insert((modality, normalize_keys(keys)), action);
+    }
+
+    /// Look up the action bound to a key event in a modality.
+    pub fn lookup(&self, modality: &Modality, key: &KeyEvent) -> Option<Action> {
+        self.lookup_sequence(modality, &[(key.code, key.modifiers)])
+    }
+
+    /// Look up the action bound to a sequence of keys in a modality.
+    pub fn lookup_sequence(&self, modality: &Modality, keys: &[Key]) -> Option<Action> {
+        self.bindings
+            .get(&(modality.clone(), normalize_keys(keys)))
+            .copied()
+    }
+
+    /// Whether a sequence in `modality` starts with `key`.
+    pub fn starts_sequence(&self, modality: &Modality, key: &KeyEvent) -> bool {
+        let first = normalize(key.code, key.modifiers);
+        self.bindings
+            .keys()
+            .any(|(m, keys)| m == modality && keys.len() > 1 && keys[0] == first)
+    }
+
+    /// Apply overrides from the config file returning a warning for each one
+    /// that could not be understood.
+    pub fn apply_overrides(&mut self, overrides: &[BindingOverride]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for binding in overrides {
+            let Some(modality) = modality_from_name(&binding.mode) else {
+                warnings.push(format!(
+                    "line {}: Unknown keybinding mode {}",
+                    binding.line, binding.mode
+                ));
+                continue;
+            };
+            let Some(keys) = parse_keys(&binding.key) else {
+                warnings.push(format!(
+                    "line {}: Unknown key {}",
+                    binding.line, binding.key
+                ));
+                continue;
+            };
+            let Some(action) = Action::from_name(&binding.action) else {
+                warnings.push(format!(
+                    "line {}: Unknown action {}",
+                    binding.line, binding.action
+                ));
+                continue;
+            };
+            self.bind_sequence(modality, &keys, action);
+        }
+        warnings
+    }
+
+    /// The key sequences bound to each action in `modality` in the order of
+    /// [`ACTION_NAMES`]. Single keys come before sequences and plain keys
+    /// before keys with modifiers.
+    pub fn bindings(&self, modality: &Modality) -> Vec<(Action, Vec<Vec<Key>>)> {
+        ACTION_NAMES
+            .iter()
+            .filter_map(|(action, _)| {
+                let mut sequences: Vec<Vec<Key>> = self
+                    .bindings
+                    .iter()
+                    .filter(|((m, _), a)| m == modality && *a == action)
+                    .map(|((_, keys), _)| keys.clone())
+                    .collect();
+                sequences.sort_by_key(|keys| {
+                    let mods: Vec<u8> = keys.iter().map(|(_, mods)| mods.bits()).collect();
+                    (keys.len(), mods, sequence_name(keys))
+                });
+                (!sequences.is_empty()).then_some((*action, sequences))
+            })
+            .collect()
+    }
+
+    /// A Markdown cheat sheet of every key grouped by mode.
+    pub fn cheat_sheet(&self) -> String {
+        let mut lines = vec!["# Keys".to_string(), String::new()];
+        for (modality, title) in CHEAT_SHEET_SECTIONS {
+            lines.push(format!("## {}", title));
+            lines.push(String::new());
+            for (action, sequences) in self.bindings(&modality) {
+                let keys: Vec<String> = sequences
+                    .iter()
+                    .map(|keys| code_span(&sequence_name(keys)))
+                    .collect();
+                lines.push(format!("* {} {}", keys.join(", "), action.description()));
+            }
+            for (_, keys) in FIXED_KEYS.iter().filter(|(m, _)| *m == modality) {
+                for (keys, description) in keys.iter() {
+                    lines.push(format!("* {} {}", code_span(keys), description));
+                }
+            }
+            lines.push(String::new());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Keys handled outside the keymap, listed in the cheat sheet after the
+/// keymap's bindings. Keys are written like the config file's `[keys]`
+/// section with spaces between the keys of a sequence and `<digit>` standing
+/// in for any digit.
+pub const FIXED_KEYS: &[(Modality, &[(&str, &str)])] = &[
+    (Modality::Navigate, &[("<digit>", "Count for the next key")]),
+    (
+        Modality::RangeSelect,
+        &[("<digit>", "Count for the next key")],
+    ),
+    (
+        Modality::CellEdit,
+        &[
+            ("enter", "Save the cell"),
+            ("esc", "Cancel the edit"),
+            ("tab", "Save and move right, or complete a function"),
+            ("backtab", "Save and move left"),
+            ("ctrl-space", "Complete a function name"),
+            ("f4", "Cycle the `$` anchors of a reference"),
+            ("ctrl-r", "Select a range"),
+            ("ctrl-p", "Insert the selected range"),
+            ("alt-h", "Show help for the current mode"),
+            ("alt-k", "Show the keybindings"),
+        ],
+    ),
+    (
+        Modality::Command,
+        &[
+            ("enter", "Run the command"),
+            ("esc", "Cancel"),
+            ("ctrl-c", "Cancel"),
+            ("up", "Previous command in the history"),
+            ("down", "Next command in the history"),
+            ("ctrl-a", "Go to the start of the line"),
+            ("ctrl-e", "Go to the end of the line"),
+            ("ctrl-u", "Delete to the start of the line"),
+            ("ctrl-k", "Delete to the end of the line"),
+            ("ctrl-w", "Delete the word before the cursor"),
+            ("ctrl-v", "Paste the system clipboard"),
+            ("alt-h", "Show help for the current mode"),
+            ("alt-k", "Show the keybindings"),
+        ],
+    ),
+];
+
+/// The sections of the cheat sheet in order.
+const CHEAT_SHEET_SECTIONS: [(Modality, &str); 4] = [
+    (Modality::Navigate, "Navigate"),
+    (Modality::RangeSelect, "Visual"),
+    (Modality::CellEdit, "Edit"),
+    (Modality::Command, "Command"),
+];
+
+/// Wrap `text` in backticks for Markdown, using two when it has one itself.
+fn code_span(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+/// The name of a key as [`parse_key`] reads it, e.g. `ctrl-d` or `pagedown`.
+pub fn key_name(code: KeyCode, mods: KeyModifiers) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in [
+        (KeyModifiers::CONTROL, "ctrl-"),
+        (KeyModifiers::ALT, "alt-"),
+        (KeyModifiers::SHIFT, "shift-"),
+    ] {
+        if mods.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match code {
+        KeyCode::Char(' ') => name.push_str("space"),
+        KeyCode::Char(c) => name.push(c),
+        KeyCode::Enter => name.push_str("enter"),
+        KeyCode::Esc => name.push_str("esc"),
+        KeyCode::Tab => name.push_str("tab"),
+        KeyCode::BackTab => name.push_str("backtab"),
+        KeyCode::Backspace => name.push_str("backspace"),
+        KeyCode::Delete => name.push_str("delete"),
+        KeyCode::Up => name.push_str("up"),
+        KeyCode::Down => name.push_str("down"),
+        KeyCode::Left => name.push_str("left"),
+        KeyCode::Right => name.push_str("right"),
+        KeyCode::Home => name.push_str("home"),
+        KeyCode::End => name.push_str("end"),
+        KeyCode::PageUp => name.push_str("pageup"),
+        KeyCode::PageDown => name.push_str("pagedown"),
+        KeyCode::F(n) => name.push_str(&format!("f{}", n)),
+        other => name.push_str(&format!("{:?}", other).to_lowercase()),
+    }
+    name
+}
+
+/// The name of a key sequence with spaces between the keys, e.g. `g t`.
+pub fn sequence_name(keys: &[Key]) -> String {
+    let names: Vec<String> = keys
+        .iter()
+        .map(|(code, mods)| key_name(*code, *mods))
+        .collect();
+    names.join(" ")
+}
+
+fn modality_from_name(name: &str) -> Option<Modality> {
+    match name {
+        "navigate" => Some(Modality::Navigate),
+        "visual" => Some(Modality::RangeSelect),
+        _ => None,
+    }
+}
+
+/// Characters carry their own case so shift is dropped from them. This keeps
+/// `Y` working whether or not the terminal also reports the shift modifier.
+fn normalize(code: KeyCode, mods: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(_) => (code, mods.difference(KeyModifiers::SHIFT)),
+        _ => (code, mods),
+    }
+}
+
+fn normalize_keys(keys: &[Key]) -> Vec<Key> {
+    keys.iter()
+        .map(|(code, mods)| normalize(*code, *mods))
+        .collect()
+}
+
+/// Parse a single key or a sequence of two keys separated by a space like
+/// `g t`.
+pub fn parse_keys(keys: &str) -> Option<Vec<Key>> {
+    let keys: Vec<Key> = keys
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Option<_>>()?;
+    (1..=2).contains(&keys.len()).then_some(keys)
+}
+
+/// Parse a key description like `ctrl-d`, `shift-enter`, `Y` or `pagedown`.
+pub fn parse_key(key: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut mods = KeyModifiers::NONE;
+    let mut rest = key;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        if rest.chars().count() > 1 && lower.starts_with("ctrl-") {
+            mods |= KeyModifiers::CONTROL;
+            rest = &rest[5..];
+        } else if rest.chars().count() > 1 && lower.starts_with("alt-") {
+            mods |= KeyModifiers::ALT;
+            rest = &rest[4..];
+        } else if rest.chars().count() > 1 && lower.starts_with("shift-") {
+            mods |= KeyModifiers::SHIFT;
+            rest = &rest[6..];
+        } else {
+            break;
+        }
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match rest.to_ascii_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => {
+                let n: u8 = name.strip_prefix('f')?.parse().ok()?;
+                KeyCode::F(n)
+            }
+        },
+    };
+    let code = match code {
+        KeyCode::Char(c) if mods.contains(KeyModifiers::SHIFT) => {
+            KeyCode::Char(c.to_ascii_uppercase())
+        }
+        code => code,
+    };
+    Some((code, mods))
+}
@@ -1,33 +1,74 @@
 //! Ui rendering logic
-use std::{path::PathBuf, process::ExitCode, str::FromStr};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
 
-use crate::book::{self, AddressRange, Book};
+use crate::book::{self, AddressRange, Book, SeriesStart};
+use crate::config::{CalcMode, Config, Settings};
 
 use anyhow::{anyhow, Result};
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ironcalc::base::expressions::types::Area;
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Flex, Layout},
+    layout::{Constraint, Flex, Layout, Rect},
     style::{Modifier, Style},
+    text::{Line, Text},
     widgets::Block,
 };
 use tui_prompts::{State, Status, TextPrompt, TextState};
 use ratatui_textarea::{CursorMove, TextArea};
 
+mod browser;
+mod clipboard;
 mod cmd;
+pub mod color;
 mod help;
+pub mod keymap;
 pub mod render;
 #[cfg(test)]
 mod test;
 
-use cmd::Cmd;
-use render::{markdown::Markdown, viewport::ViewportState};
+use browser::Browser;
+use clipboard::SystemClipboard;
+use cmd::{Cmd, Extent};
+use keymap::{Action, Key, Keymap};
+use render::{
+    completion::{self, CompletionState},
+    markdown::Markdown,
+    tab_titles,
+    theme::Theme,
+    viewport::ViewportState,
+};
 
 /// Default file name used when creating a new empty workbook.
 const DEFAULT_WORKBOOK_NAME: &str = "Untitled.sui";
 
-#[derive(Default, Debug, PartialEq, Clone)]
+/// Maximum number of commands kept in the command history.
+const COMMAND_HISTORY_LIMIT: usize = 200;
+
+/// How long a status message stays visible if no key is pressed.
+const STATUS_MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of rows a single notch of the mouse wheel scrolls.
+const WHEEL_SCROLL_ROWS: u16 = 3;
+
+/// The widest the popup showing a cell's contents gets.
+const PEEK_MAX_WIDTH: u16 = 80;
+
+/// The most locations the jump list remembers.
+const JUMP_LIST_LIMIT: usize = 100;
+
+/// How deeply macros may replay other macros before playback is stopped.
+const MACRO_DEPTH_LIMIT: usize = 20;
+
+#[derive(Default, Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Modality {
     #[default]
     Navigate,
@@ -38,56 +79,187 @@ pub enum Modality {
     Quit,
 }
 
+/// What a range selection covers between its start and end.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Just the cells between the corners.
+    #[default]
+    Cells,
+    /// The whole rows, up to this last used column.
+    Rows(usize),
+    /// The whole columns, down to this last used row.
+    Columns(usize),
+}
+
 #[derive(Debug, Default)]
 pub struct RangeSelection {
     pub original_location: Option<Address>,
     pub start: Option<Address>,
     pub end: Option<Address>,
+    pub mode: SelectionMode,
 }
 
 impl RangeSelection {
     pub fn get_range(&self) -> Option<(Address, Address)> {
         if let (Some(start), Some(end)) = (&self.start, &self.end) {
-            return Some((
-                Address {
-                    sheet: start.sheet,
-                    row: std::cmp::min(start.row, end.row),
-                    col: std::cmp::min(start.col, end.col),
-                },
-                Address {
-                    sheet: end.sheet,
-                    row: std::cmp::max(start.row, end.row),
-                    col: std::cmp::max(start.col, end.col),
-                },
-            ));
+            let mut start_addr = Address {
+                sheet: start.sheet,
+                row: std::cmp::min(start.row, end.row),
+                col: std::cmp::min(start.col, end.col),
+            };
+            let mut end_addr = Address {
+                sheet: end.sheet,
+                row: std::cmp::max(start.row, end.row),
+                col: std::cmp::max(start.col, end.col),
+            };
+            match self.mode {
+                SelectionMode::Cells => (),
+                SelectionMode::Rows(last_col) => {
+                    start_addr.col = 1;
+                    end_addr.col = std::cmp::max(end_addr.col, last_col);
+                }
+                SelectionMode::Columns(last_row) => {
+                    start_addr.row = 1;
+                    end_addr.row = std::cmp::max(end_addr.row, last_row);
+                }
+            }
+            return Some((start_addr, end_addr));
         }
         None
     }
 
+    /// Whether the cell at `row`, `col` should be drawn as selected. Whole
+    /// row and column selections reach past the used part of the sheet.
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        let Some((start, end)) = self.get_range() else {
+            return false;
+        };
+        let in_rows = row >= start.row && row <= end.row;
+        let in_cols = col >= start.col && col <= end.col;
+        match self.mode {
+            SelectionMode::Cells => in_rows && in_cols,
+            SelectionMode::Rows(_) => in_rows,
+            SelectionMode::Columns(_) => in_cols,
+        }
+    }
+
     pub fn reset_range_selection(&mut self) {
         self.start = None;
         self.end = None;
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ClipboardContents {
     Cell(String),
     Range(Vec<Vec<String>>),
 }
 
+/// A change to the book that `.` can repeat at another cell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LastChange {
+    ToggleBold,
+    ToggleItalic,
+    ClearCell,
+    ClearCellAll,
+    /// Cleared whole rows or columns with `dd`, `dD` or `dc`.
+    ClearLines {
+        columns: bool,
+        count: usize,
+        all: bool,
+    },
+    /// Pasted these rows at the cursor.
+    Paste(Vec<Vec<String>>),
+    /// Committed this text in the cell editor.
+    EditCell(String),
+    /// Added this to the number in the cell.
+    Increment(f64),
+}
+
+/// An action waiting on the user to answer a prompt dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingAction {
+    /// The file was modified on disk after we loaded it.
+    FileChanged,
+    /// Saving to `path` would overwrite a different existing file.
+    Overwrite { path: PathBuf, quit: bool },
+}
+
 #[derive(Debug)]
 pub struct AppState<'ws> {
     pub modality_stack: Vec<Modality>,
     pub viewport_state: ViewportState,
     pub command_state: TextState<'ws>,
+    pub command_history: Vec<String>,
+    command_history_idx: Option<usize>,
+    /// The command being typed when browsing the history started. Moving
+    /// newer than the latest entry brings it back.
+    command_draft: String,
     pub numeric_prefix: Vec<char>,
     pub char_queue: Vec<char>,
+    /// The last key when it could start a sequence like `g t`, with the
+    /// register that was picked before it.
+    pending_key: Option<(Key, Option<char>)>,
     pub range_select: RangeSelection,
     pub dialog_scroll: u16,
+    /// The furthest the dialog could scroll when it was last drawn. There is
+    /// no limit until it has been drawn.
+    dialog_max_scroll: u16,
+    /// How many rows of the dialog's content were last shown at once.
+    dialog_rows: u16,
+    pub status_message: Option<(String, Instant)>,
+    pub settings: Settings,
+    pub pending_action: Option<PendingAction>,
     dirty: bool,
     popup: Option<Markdown>,
+    /// Title of the popup when it isn't the help.
+    popup_title: Option<String>,
+    /// Size the popup to its content up to this many columns.
+    popup_max_width: Option<u16>,
+    /// The cells the numbered entries of the popup jump to.
+    popup_targets: Vec<Address>,
+    /// The unnamed register used by plain copy and paste.
     clipboard: Option<ClipboardContents>,
+    /// Named registers selected with the `"<name>` prefix.
+    registers: HashMap<char, ClipboardContents>,
+    /// The register selected for the next copy or paste.
+    register: Option<char>,
+    /// The register and keys of the macro being recorded.
+    recording: Option<(char, Vec<Event>)>,
+    /// Recorded macros by register.
+    macros: HashMap<char, Vec<Event>>,
+    /// The macro replayed last for `@@`.
+    last_macro: Option<char>,
+    /// How many macro replays are running inside each other.
+    replay_depth: usize,
+    /// Set when a replay fails so every running replay stops.
+    replay_aborted: bool,
+    /// The change `.` repeats.
+    last_change: Option<LastChange>,
+    /// Locations saved with `m<letter>`.
+    marks: HashMap<char, Address>,
+    /// Where long moves started from, oldest first.
+    jumps: Vec<Address>,
+    /// The position in `jumps` that `Ctrl-o` goes back from. It is
+    /// `jumps.len()` unless we are walking the list.
+    jump_idx: usize,
+    /// Where the sheet tabs were last rendered.
+    tabs_area: Rect,
+    /// Where the cell editor was last rendered.
+    edit_area: Rect,
+    /// The function names offered while editing a formula.
+    completion: Option<CompletionState>,
+    /// Set after typing an operator in a formula. Holds the cell picked with
+    /// the arrow keys and how many characters its reference takes up before
+    /// the editor's cursor, zero until an arrow key is pressed.
+    point: Option<(Address, usize)>,
+    /// The formula being edited and what it evaluates to.
+    formula_preview: Option<(String, String)>,
+    /// The cell a left click landed on while the button is held.
+    mouse_anchor: Option<Address>,
+    /// The terminal has a light background so the `auto` theme leaves the
+    /// colors to the terminal.
+    light_background: bool,
 }
 
 impl<'ws> Default for AppState<'ws> {
@@ -96,13 +268,43 @@ impl<'ws> Default for AppState<'ws> {
             modality_stack: vec![Modality::default()],
             viewport_state: Default::default(),
             command_state: Default::default(),
+            command_history: Vec::new(),
+            command_history_idx: None,
+            command_draft: String::new(),
             numeric_prefix: Default::default(),
             char_queue: Default::default(),
+            pending_key: None,
             range_select: Default::default(),
             dialog_scroll: 0,
+            dialog_max_scroll: u16::MAX,
+            dialog_rows: 1,
+            status_message: None,
+            settings: Default::default(),
+            pending_action: None,
             dirty: false,
             popup: Default::default(),
+            popup_title: None,
+            popup_max_width: None,
+            popup_targets: Vec::new(),
             clipboard: Default::default(),
+            registers: Default::default(),
+            register: None,
+            recording: None,
+            macros: HashMap::new(),
+            last_macro: None,
+            replay_depth: 0,
+            replay_aborted: false,
+            last_change: None,
+            marks: HashMap::new(),
+            jumps: Vec::new(),
+            jump_idx: 0,
+            tabs_area: Rect::default(),
+            edit_area: Rect::default(),
+            completion: None,
+            point: None,
+            formula_preview: None,
+            mouse_anchor: None,
+            light_background: false,
         }
     }
 }
@@ -135,6 +337,32 @@ impl<'ws> AppState<'ws> {
     pub fn reset_n_prefix(&mut self) {
         self.numeric_prefix.clear();
     }
+
+    pub fn set_status<S: Into<String>>(&mut self, msg: S) {
+        self.status_message = Some((msg.into(), Instant::now()));
+    }
+
+    /// The current status message if it hasn't expired yet.
+    pub fn status(&self) -> Option<&str> {
+        match &self.status_message {
+            Some((msg, at)) if at.elapsed() < STATUS_MESSAGE_TIMEOUT => Some(msg.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Record an executed command in the history skipping empty commands and
+    /// consecutive duplicates.
+    pub fn push_command_history(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() || self.command_history.last().map(|c| c == cmd).unwrap_or(false) {
+            return;
+        }
+        self.command_history.push(cmd.to_owned());
+        if self.command_history.len() > COMMAND_HISTORY_LIMIT {
+            let overflow = self.command_history.len() - COMMAND_HISTORY_LIMIT;
+            self.command_history.drain(0..overflow);
+        }
+    }
 }
 
 // TODO(jwall): Should we just be using `Area` for this?.
@@ -152,16 +380,7 @@ impl Address {
     }
 
     pub fn to_range_part(&self) -> String {
-        let count = if self.col == 26 {
-            1
-        } else {
-            (self.col / 26) + 1
-        };
-        format!(
-            "{}{}",
-            render::viewport::COLNAMES[(self.col - 1) % 26].repeat(count),
-            self.row
-        )
+        format!("{}{}", book::col_to_name(self.col), self.row)
     }
 }
 
@@ -171,17 +390,63 @@ impl Default for Address {
     }
 }
 
+/// A workbook open in the workspace but not currently active.
+struct Buffer {
+    name: PathBuf,
+    book: Book,
+    disk_stamp: Option<(SystemTime, u64)>,
+    viewport_state: ViewportState,
+    read_only: bool,
+}
+
 /// A workspace defining our UI state.
 pub struct Workspace<'ws> {
     name: PathBuf,
     book: Book,
     pub(crate) state: AppState<'ws>,
     text_area: TextArea<'ws>,
+    /// Modification time and size of the file when we last loaded or saved it.
+    disk_stamp: Option<(SystemTime, u64)>,
+    locale: String,
+    tz: String,
+    last_autosave: Instant,
+    keymap: Keymap,
+    /// Refuse any change to the book when set.
+    read_only: bool,
+    /// Every open workbook. The active one lives in the fields above so its
+    /// slot is always `None`.
+    buffers: Vec<Option<Buffer>>,
+    active: usize,
+    /// Where `Ctrl-v` and `p` read the system clipboard from.
+    system_clipboard: Box<dyn SystemClipboard>,
+    /// Opens the links typed by number in a popup.
+    browser: Box<dyn Browser>,
 }
 
-fn parse_csv_rows(text: &str) -> Result<Vec<Vec<String>>, anyhow::Error> {
+/// Parse clipboard text into rows of cells.
+///
+/// Spreadsheet applications put tab separated data on the clipboard so if the
+/// first line contains a tab we parse it as TSV. Otherwise we fall back to
+/// CSV. A single line with neither delimiter is treated as a single cell.
+fn rows_from_text(text: &str) -> Result<Vec<Vec<String>>, anyhow::Error> {
+    let first_line = text.lines().next().unwrap_or("");
+    let delimiter = if first_line.contains('\t') {
+        b'\t'
+    } else if first_line.contains(',') || text.trim_end_matches(['\r', '\n']).contains('\n') {
+        b','
+    } else {
+        let cell = text.trim_end_matches(['\r', '\n']);
+        if cell.is_empty() {
+            return Ok(Vec::new());
+        }
+        return Ok(vec![vec![cell.to_string()]]);
+    };
+    // NOTE(zaphar): The csv reader treats the first row as a header by default
+    // and errors on ragged rows. Neither is what we want for a paste.
     let reader = csv::ReaderBuilder::new()
         .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
         .from_reader(text.as_bytes());
     let mut rows = Vec::new();
     for rec in reader.into_byte_records() {
@@ -200,6 +465,121 @@ fn parse_csv_rows(text: &str) -> Result<Vec<Vec<String>>, anyhow::Error> {
     Ok(rows)
 }
 
+/// Parse the rows out of the first html `<table>` in clipboard content.
+///
+/// Returns `None` if there is no table with at least one row. Cell text has
+/// html whitespace collapsed and entities decoded. `<br>` becomes a newline.
+fn rows_from_html(html: &str) -> Option<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row: Option<Vec<String>> = None;
+    let mut cell: Option<String> = None;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|i| &rest[i + 3..]).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with('<') {
+            let end = rest.find('>')?;
+            let tag = rest[1..end].trim();
+            rest = &rest[end + 1..];
+            let name = tag
+                .split(|c: char| c.is_whitespace() || (c == '/' && !tag.starts_with('/')))
+                .next()
+                .unwrap_or("")
+                .to_ascii_lowercase();
+            match name.as_str() {
+                "tr" => {
+                    if let Some(r) = row.take() {
+                        rows.push(r);
+                    }
+                    row = Some(Vec::new());
+                }
+                "/tr" => {
+                    if let Some(r) = row.take() {
+                        rows.push(r);
+                    }
+                }
+                "td" | "th" => {
+                    cell = Some(String::new());
+                }
+                "/td" | "/th" => {
+                    if let (Some(r), Some(c)) = (row.as_mut(), cell.take()) {
+                        r.push(decode_html_text(&c));
+                    }
+                }
+                "br" => {
+                    // NOTE(zaphar): Use an entity so the newline survives
+                    // whitespace collapsing in decode_html_text.
+                    if let Some(c) = cell.as_mut() {
+                        c.push_str("&#10;");
+                    }
+                }
+                "/table" => break,
+                _ => {
+                    // We don't care about any other tags.
+                }
+            }
+            continue;
+        }
+        let end = rest.find('<').unwrap_or(rest.len());
+        if let Some(c) = cell.as_mut() {
+            c.push_str(&rest[..end]);
+        }
+        rest = &rest[end..];
+    }
+    if let Some(r) = row.take() {
+        rows.push(r);
+    }
+    if rows.is_empty() {
+        None
+    } else {
+        Some(rows)
+    }
+}
+
+/// Collapse html whitespace and decode the entities we expect to see in
+/// clipboard content.
+fn decode_html_text(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let mut out = String::with_capacity(collapsed.len());
+    let mut rest = collapsed.as_str();
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => {
+                    if let Some(hex) = entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                    } else if let Some(dec) = entity.strip_prefix('#') {
+                        dec.parse().ok().and_then(char::from_u32)
+                    } else {
+                        None
+                    }
+                }
+            };
+            c.map(|c| (c, end))
+        });
+        if let Some((c, end)) = decoded {
+            out.push(c);
+            rest = &rest[end + 1..];
+        } else {
+            out.push('&');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
 impl<'ws> Workspace<'ws> {
     /// Constructs a new Workspace from an `Book` with a path for the name.
     pub fn new(book: Book, name: PathBuf) -> Self {
@@ -208,33 +588,273 @@ impl<'ws> Workspace<'ws> {
             name,
             state: AppState::default(),
             text_area: reset_text_area("".to_owned()),
+            disk_stamp: None,
+            locale: String::from("en"),
+            tz: String::from("America/New_York"),
+            last_autosave: Instant::now(),
+            keymap: Keymap::default(),
+            read_only: false,
+            buffers: vec![None],
+            active: 0,
+            system_clipboard: Box::new(clipboard::Arboard),
+            browser: Box::new(browser::SystemBrowser),
         };
         ws.handle_movement_change();
         ws
     }
 
-    pub fn new_empty(_locale: &str, _tz: &str) -> Result<Self> {
-        let mut book = Book::default();
+    pub fn new_empty(locale: &str, tz: &str) -> Result<Self> {
+        let mut book = Book::new_with_locale(locale, tz)?;
         book.dirty = false;
-        Ok(Self::new(book, PathBuf::from_str(DEFAULT_WORKBOOK_NAME).unwrap()))
+        let mut ws = Self::new(book, PathBuf::from_str(DEFAULT_WORKBOOK_NAME).unwrap());
+        ws.set_locale(locale, tz);
+        Ok(ws)
     }
 
     /// Loads a workspace from a path.
     pub fn load(path: &PathBuf, locale: &str, tz: &str) -> Result<Self> {
         let book = load_book(path, locale, tz)?;
-        Ok(Workspace::new(book, path.clone()))
+        let mut ws = Workspace::new(book, path.clone());
+        ws.disk_stamp = disk_stamp(path);
+        ws.set_locale(locale, tz);
+        Ok(ws)
+    }
+
+    /// Apply the settings from a loaded config file and report any problems
+    /// found while loading it.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.state.settings = config.settings.clone();
+        self.sync_calc_mode();
+        let mut warnings = config.warnings.clone();
+        warnings.extend(self.keymap.apply_overrides(&config.keys));
+        if !warnings.is_empty() {
+            let mut msg = String::from("# Config warnings\n\n");
+            for warning in &warnings {
+                msg.push_str(&format!("* {}\n", warning));
+            }
+            self.enter_dialog_mode(Markdown::from_str(&msg));
+        }
+    }
+
+    /// Whether the workspace refuses changes to the book.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Turn read-only mode on or off.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// In read-only mode report that the change was refused and return true.
+    fn refuse_if_read_only(&mut self) -> bool {
+        if self.read_only {
+            self.state.set_status("read-only");
+        }
+        self.read_only
+    }
+
+    /// The locale used when loading books into this workspace.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// The timezone used when loading books into this workspace.
+    pub fn timezone(&self) -> &str {
+        &self.tz
+    }
+
+    /// Set the locale and timezone used for books loaded after this call.
+    pub fn set_locale(&mut self, locale: &str, tz: &str) {
+        self.locale = locale.to_owned();
+        self.tz = tz.to_owned();
     }
 
     /// Loads a new `Book` into a `Workspace` from a path.
     pub fn load_into<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
         let path: PathBuf = path.into();
-        // FIXME(zaphar): This should be managed better.
-        let book = load_book(&path, "en", "America/New_York")?;
+        let book = load_book(&path, &self.locale, &self.tz)?;
         self.book = book;
+        self.disk_stamp = disk_stamp(&path);
         self.name = path;
+        self.sync_calc_mode();
+        Ok(())
+    }
+
+    /// Put the active book in the calculation mode picked with the `calc`
+    /// setting.
+    fn sync_calc_mode(&mut self) {
+        self.book
+            .set_manual_calc(self.state.settings.calc == CalcMode::Manual);
+    }
+
+    /// Recalculate the book now whatever the calculation mode.
+    fn recalculate(&mut self) {
+        self.book.recalculate();
+        self.state.set_status("Recalculated");
+    }
+
+    /// Open a workbook in a new buffer and make it active.
+    ///
+    /// If the path is already open we switch to that buffer instead. The
+    /// active buffer is reloaded from disk, but only with `force` when it has
+    /// unsaved changes. An untouched empty workbook is replaced rather than
+    /// kept around.
+    pub fn open_buffer<P: Into<PathBuf>>(
+        &mut self,
+        path: P,
+        read_only: bool,
+        force: bool,
+    ) -> Result<()> {
+        let path: PathBuf = path.into();
+        // Switching to a buffer that is already open keeps its own read only
+        // flag so a `view` can't hide its unsaved changes from the quit
+        // prompt. Reloading the active buffer takes the new one.
+        match self.find_buffer(&path) {
+            Some(idx) if idx != self.active => self.switch_buffer(idx),
+            Some(_) if self.book.dirty && !force => {
+                return Err(anyhow!(
+                    "{} has unsaved changes. Use `edit!` to reload it and discard them",
+                    path.display()
+                ));
+            }
+            Some(_) => {
+                self.load_into(path)?;
+                self.read_only = read_only;
+            }
+            None if self.book.get_file_path().is_none() && !self.book.dirty => {
+                self.load_into(path)?;
+                self.state.viewport_state = Default::default();
+                self.read_only = read_only;
+            }
+            None => {
+                let book = load_book(&path, &self.locale, &self.tz)?;
+                self.buffers.push(Some(Buffer {
+                    disk_stamp: disk_stamp(&path),
+                    name: path,
+                    book,
+                    viewport_state: Default::default(),
+                    read_only,
+                }));
+                self.switch_buffer(self.buffers.len() - 1);
+            }
+        }
+        self.handle_movement_change();
         Ok(())
     }
 
+    /// The number of open buffers.
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The 0-based index of the active buffer.
+    pub fn active_buffer(&self) -> usize {
+        self.active
+    }
+
+    fn find_buffer(&self, path: &Path) -> Option<usize> {
+        self.buffers.iter().position(|buffer| match buffer {
+            Some(buffer) => same_file(
+                path,
+                buffer.book.get_file_path().unwrap_or(buffer.name.as_path()),
+            ),
+            None => same_file(path, &self.current_path()),
+        })
+    }
+
+    /// Make the buffer at `idx` the active one.
+    fn switch_buffer(&mut self, idx: usize) {
+        if idx == self.active || idx >= self.buffers.len() {
+            return;
+        }
+        let target = self.buffers[idx]
+            .take()
+            .expect("Inactive buffers are always stored");
+        let previous = Buffer {
+            name: std::mem::replace(&mut self.name, target.name),
+            book: std::mem::replace(&mut self.book, target.book),
+            disk_stamp: std::mem::replace(&mut self.disk_stamp, target.disk_stamp),
+            viewport_state: std::mem::replace(
+                &mut self.state.viewport_state,
+                target.viewport_state,
+            ),
+            read_only: std::mem::replace(&mut self.read_only, target.read_only),
+        };
+        self.buffers[self.active] = Some(previous);
+        self.active = idx;
+        self.sync_calc_mode();
+        self.state.range_select.reset_range_selection();
+        self.handle_movement_change();
+    }
+
+    fn next_buffer(&mut self) {
+        self.switch_buffer((self.active + 1) % self.buffers.len());
+    }
+
+    fn prev_buffer(&mut self) {
+        self.switch_buffer((self.active + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    /// The name, dirty flag and read-only flag of every buffer in order.
+    fn buffer_summaries(&self) -> Vec<(String, bool, bool)> {
+        self.buffers
+            .iter()
+            .map(|buffer| match buffer {
+                Some(b) => (b.name.display().to_string(), b.book.dirty, b.read_only),
+                None => (self.name.display().to_string(), self.book.dirty, self.read_only),
+            })
+            .collect()
+    }
+
+    /// Indexes of the buffers with changes that can be saved.
+    fn unsaved_buffers(&self) -> Vec<usize> {
+        self.buffer_summaries()
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, dirty, read_only))| *dirty && !*read_only)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Names of the buffers with unsaved changes.
+    pub fn unsaved_buffer_names(&self) -> Vec<String> {
+        let summaries = self.buffer_summaries();
+        self.unsaved_buffers()
+            .into_iter()
+            .map(|idx| summaries[idx].0.clone())
+            .collect()
+    }
+
+    fn render_buffers(&self) -> Markdown {
+        let mut msg = String::from("# Buffers\n\n");
+        for (idx, (name, dirty, read_only)) in self.buffer_summaries().into_iter().enumerate() {
+            msg.push_str(&format!(
+                "* {}{} `{}`{}{}\n",
+                idx + 1,
+                if idx == self.active { " (active)" } else { "" },
+                name,
+                if dirty { " [+]" } else { "" },
+                if read_only { " [RO]" } else { "" },
+            ));
+        }
+        Markdown::from_str(&msg)
+    }
+
+    /// Save every buffer with unsaved changes.
+    ///
+    /// Each one is made active while it is saved. Returns false and leaves
+    /// that buffer active if we have to wait on the user.
+    fn save_all(&mut self) -> Result<bool> {
+        for idx in self.unsaved_buffers() {
+            self.switch_buffer(idx);
+            if !self.save_file()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     pub fn selected_range_to_string(&self) -> String {
         let state = &self.state;
         if let Some((start, end)) = state.range_select.get_range() {
@@ -259,6 +879,32 @@ impl<'ws> Workspace<'ws> {
         return String::new();
     }
 
+    /// Jump to a sheet and/or cell. The sheet is a name or a 1-based index and
+    /// the cell is an A1 reference.
+    pub fn jump_to(&mut self, sheet: Option<&str>, cell: Option<&str>) -> Result<()> {
+        // Validate everything before moving so a bad cell doesn't leave us on
+        // a different sheet.
+        let cell = cell.map(book::parse_cell_ref).transpose()?;
+        if let Some(sheet) = sheet {
+            if !self.book.select_sheet_by_name(sheet) {
+                let idx = sheet
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|idx| idx.checked_sub(1))
+                    .ok_or_else(|| anyhow!("No such sheet: {}", sheet))?;
+                self.book
+                    .set_current_sheet_index(idx)
+                    .map_err(|_| anyhow!("No such sheet: {}", sheet))?;
+            }
+        }
+        if let Some(mut addr) = cell {
+            addr.sheet = self.book.location.sheet;
+            self.book.move_to(&addr)?;
+        }
+        self.handle_movement_change();
+        Ok(())
+    }
+
     /// Move a row down in the current sheet.
     pub fn move_down(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
@@ -279,6 +925,17 @@ impl<'ws> Workspace<'ws> {
         Ok(())
     }
 
+    /// Move to `row` in the current column. Rows past the end of the sheet
+    /// go to the last row.
+    pub fn move_to_row(&mut self, row: usize) -> Result<()> {
+        self.book.move_to(&Address {
+            sheet: self.book.location.sheet,
+            row: row.clamp(1, book::LAST_ROW as usize),
+            col: self.book.location.col,
+        })?;
+        Ok(())
+    }
+
     /// Move a row up in the current sheet.
     pub fn move_up(&mut self) -> Result<()> {
         let mut loc = self.book.location.clone();
@@ -311,102 +968,592 @@ impl<'ws> Workspace<'ws> {
 
     /// Handle input in our ui loop.
     pub fn handle_input(&mut self, evt: Event) -> Result<Option<ExitCode>> {
-        if let Event::Key(key) = evt {
-            let result = match self.state.modality() {
-                Modality::Navigate => self.handle_navigation_input(key)?,
-                Modality::CellEdit => self.handle_edit_input(key)?,
-                Modality::Command => self.handle_command_input(key)?,
-                Modality::Dialog => self.handle_dialog_input(key)?,
-                Modality::RangeSelect => self.handle_range_select_input(key)?,
-                Modality::Quit => self.handle_quit_dialog(key)?,
-            };
-            return Ok(result);
+        // Keys fed back by a replay were already recorded as the `@` that
+        // started it.
+        let record = self.state.recording.is_some() && self.state.replay_depth == 0;
+        let result = match evt.clone() {
+            Event::Key(key) => {
+                if key.kind == KeyEventKind::Press {
+                    self.state.status_message = None;
+                }
+                match self.state.modality() {
+                    Modality::Navigate => self.handle_navigation_input(key),
+                    Modality::CellEdit => self.handle_edit_input(key),
+                    Modality::Command => self.handle_command_input(key),
+                    Modality::Dialog => self.handle_dialog_input(key),
+                    Modality::RangeSelect => self.handle_range_select_input(key),
+                    Modality::Quit => self.handle_quit_dialog(key),
+                }
+            }
+            Event::Mouse(mouse) => self.handle_mouse_input(mouse),
+            Event::Paste(text) => self.handle_paste_input(text),
+            _ => return Ok(None),
+        };
+        // The key that stops the recording has taken it by now so it is left
+        // out.
+        if let (true, Event::Key(_), Some((_, keys))) = (record, &evt, &mut self.state.recording) {
+            keys.push(evt);
+        }
+        // NOTE(zaphar): Errors from the handlers are things like an
+        // unavailable clipboard or a failed save. None of them should take
+        // down the app along with any unsaved work so we report them and
+        // keep going.
+        let result = result.and_then(|code| {
+            if code.is_none() {
+                self.maybe_autosave()?;
+            }
+            Ok(code)
+        });
+        match result {
+            Ok(code) => Ok(code),
+            Err(e) => {
+                self.enter_dialog_mode(Markdown::from_str(&format!("# Error\n\n{}", e)));
+                Ok(None)
+            }
         }
-        Ok(None)
     }
 
-    fn render_help_text(&self) -> Markdown {
-        // TODO(zaphar): We should be sourcing these from our actual help documentation.
-        // Ideally we would also render the markdown content properly.
-        // https://github.com/zaphar/sheetsui/issues/22
-        match self.state.modality() {
-            Modality::Navigate => help::to_widget("navigate"),
-            Modality::CellEdit => help::to_widget("edit"),
-            Modality::Command => help::to_widget("command"),
-            Modality::RangeSelect => help::to_widget("visual"),
-            _ => help::to_widget(""),
+    /// Called whenever a tick passes without any input.
+    ///
+    /// This is where anything that happens on a timer belongs, like expiring
+    /// status messages and autosave.
+    pub fn on_tick(&mut self) -> Result<()> {
+        if self.state.status().is_none() {
+            self.state.status_message = None;
+        }
+        // Only previewing once typing pauses keeps big workbooks responsive.
+        self.update_formula_preview();
+        if let Err(e) = self.maybe_autosave() {
+            self.enter_dialog_mode(Markdown::from_str(&format!("# Error\n\n{}", e)));
         }
+        Ok(())
     }
 
-    fn handle_command_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
-        if key.kind == KeyEventKind::Press {
-            match key.code {
-                KeyCode::Esc | KeyCode::Enter => return self.exit_command_mode(),
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.enter_dialog_mode(self.render_help_text());
-                    return Ok(None);
-                }
-                _ => {
-                    // NOOP
-                }
-            }
+    /// Evaluate the formula being edited if it changed since the last
+    /// preview. Text that isn't a formula has no preview.
+    fn update_formula_preview(&mut self) {
+        if self.state.modality() != &Modality::CellEdit {
+            self.state.formula_preview = None;
+            return;
         }
-        self.state.command_state.handle_key_event(key);
-        Ok(None)
+        let text = self.text_area.lines().join("\n");
+        if !text.starts_with('=') {
+            self.state.formula_preview = None;
+            return;
+        }
+        if self
+            .state
+            .formula_preview
+            .as_ref()
+            .is_some_and(|(formula, _)| formula == &text)
+        {
+            return;
+        }
+        let value = self
+            .book
+            .preview_formula(&text)
+            .unwrap_or_else(|_| String::from("#ERROR!"));
+        self.state.formula_preview = Some((text, value));
     }
 
-    fn handle_quit_dialog(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
-        if key.kind == KeyEventKind::Press {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => {
-                    self.exit_quit_mode()?;
-                    return Ok(Some(ExitCode::SUCCESS));
+    /// How long to wait for input before calling [`Workspace::on_tick`].
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_millis(self.state.settings.tick)
+    }
+
+    /// The color theme picked with the `theme` setting with the `cell-fg` and
+    /// `cell-bg` colors in place of its own.
+    pub fn theme(&self) -> Theme {
+        let mut theme = match self.state.settings.theme.as_str() {
+            "auto" if self.state.light_background => Theme::terminal(),
+            name => Theme::by_name(name).unwrap_or_default(),
+        };
+        let settings = &self.state.settings;
+        theme.cell_fg = render::viewport::map_color(settings.cell_fg.as_ref(), theme.cell_fg);
+        theme.cell_bg = render::viewport::map_color(settings.cell_bg.as_ref(), theme.cell_bg);
+        theme
+    }
+
+    /// Tell the `auto` theme whether the terminal has a light background.
+    pub fn set_light_background(&mut self, light: bool) {
+        self.state.light_background = light;
+    }
+
+    /// Handle text pasted into the terminal while bracketed paste is on.
+    ///
+    /// The text is taken as is rather than as keystrokes so nothing in it can
+    /// trigger a keybinding.
+    fn handle_paste_input(&mut self, text: String) -> Result<Option<ExitCode>> {
+        match self.state.modality() {
+            Modality::CellEdit => {
+                if self.text_area.insert_str(text) {
+                    self.state.dirty = true;
                 }
-                KeyCode::Char('y') | KeyCode::Char('Y') => {
-                    // We have been asked to save the file first.
-                    self.save_file()?;
-                    self.exit_quit_mode()?;
-                    return Ok(Some(ExitCode::SUCCESS));
+            }
+            Modality::Command => self.insert_into_command_line(&text),
+            Modality::Navigate => {
+                if self.refuse_if_read_only() {
+                    return Ok(None);
                 }
-                _ => return Ok(None),
+                let trimmed = text.trim_end_matches(['\r', '\n']);
+                if trimmed.contains(['\t', '\n']) {
+                    let rows = rows_from_text(&text)?;
+                    self.paste_rows(&rows)?;
+                } else {
+                    self.book.edit_current_cell(trimmed)?;
+                    self.book.evaluate();
+                }
+                self.handle_movement_change();
             }
+            _ => (),
         }
         Ok(None)
     }
 
-    fn handle_dialog_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
-        if key.kind == KeyEventKind::Press {
-            match key.code {
-                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.exit_dialog_mode()?,
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.exit_dialog_mode()?
+    fn handle_mouse_input(&mut self, mouse: MouseEvent) -> Result<Option<ExitCode>> {
+        let (column, row) = (mouse.column, mouse.row);
+        match (self.state.modality(), mouse.kind) {
+            (Modality::Navigate, MouseEventKind::Down(MouseButton::Left)) => {
+                if let Some(idx) = self.tab_at(column, row) {
+                    self.state.mouse_anchor = None;
+                    self.select_sheet_by_idx(idx);
+                } else if let Some(addr) = self.address_at(column, row) {
+                    self.book.move_to(&addr)?;
+                    self.handle_movement_change();
+                    self.state.mouse_anchor = Some(addr);
                 }
-                KeyCode::Char('j') | KeyCode::Down => {
-                    self.state.dialog_scroll = self.state.dialog_scroll.saturating_add(1);
+            }
+            (Modality::RangeSelect, MouseEventKind::Down(MouseButton::Left)) => {
+                if let Some(addr) = self.address_at(column, row) {
+                    self.book.move_to(&addr)?;
+                    self.maybe_update_range_end();
                 }
-                KeyCode::Char('k') | KeyCode::Up => {
-                    self.state.dialog_scroll = self.state.dialog_scroll.saturating_sub(1);
+            }
+            (Modality::Navigate, MouseEventKind::Drag(MouseButton::Left)) => {
+                let (Some(anchor), Some(addr)) =
+                    (self.state.mouse_anchor.clone(), self.address_at(column, row))
+                else {
+                    return Ok(None);
+                };
+                if anchor != addr {
+                    // Dragging away from the clicked cell starts a selection.
+                    self.enter_range_select_mode(true);
+                    self.book.move_to(&addr)?;
+                    self.maybe_update_range_end();
                 }
-                code => {
-                    if let Some(widget) = &self.state.popup {
-                        widget.handle_input(code);
-                    }
+            }
+            (Modality::RangeSelect, MouseEventKind::Drag(MouseButton::Left)) => {
+                if let Some(addr) = self.address_at(column, row) {
+                    self.book.move_to(&addr)?;
+                    self.maybe_update_range_end();
                 }
             }
+            (_, MouseEventKind::Up(MouseButton::Left)) => {
+                self.state.mouse_anchor = None;
+            }
+            (
+                Modality::Navigate,
+                MouseEventKind::ScrollUp
+                | MouseEventKind::ScrollDown
+                | MouseEventKind::ScrollLeft
+                | MouseEventKind::ScrollRight,
+            ) => {
+                let forward = matches!(
+                    mouse.kind,
+                    MouseEventKind::ScrollDown | MouseEventKind::ScrollRight
+                );
+                // Shift turns the wheel sideways.
+                if mouse.modifiers.contains(KeyModifiers::SHIFT)
+                    || matches!(
+                        mouse.kind,
+                        MouseEventKind::ScrollLeft | MouseEventKind::ScrollRight
+                    )
+                {
+                    self.scroll_viewport(0, if forward { 1 } else { -1 })?;
+                } else {
+                    let rows = WHEEL_SCROLL_ROWS as isize;
+                    self.scroll_viewport(if forward { rows } else { -rows }, 0)?;
+                }
+            }
+            (Modality::Dialog, MouseEventKind::ScrollDown) => {
+                self.scroll_dialog(WHEEL_SCROLL_ROWS as isize);
+            }
+            (Modality::Dialog, MouseEventKind::ScrollUp) => {
+                self.scroll_dialog(-(WHEEL_SCROLL_ROWS as isize));
+            }
+            _ => (),
         }
         Ok(None)
     }
 
-    fn handle_edit_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
-        if key.kind == KeyEventKind::Press {
-            match key.code {
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.enter_dialog_mode(self.render_help_text());
-                    return Ok(None);
-                }
-                KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.enter_range_select_mode(false);
-                    return Ok(None);
+    /// Scroll the viewport leaving the selection alone unless it would
+    /// scroll off the screen.
+    fn scroll_viewport(&mut self, rows: isize, cols: isize) -> Result<()> {
+        let viewport = &mut self.state.viewport_state;
+        let location = self.book.location.clone();
+        viewport.scroll(location.sheet, rows, cols);
+        let (row, col) = viewport.clamp_into_view(location.sheet, location.row, location.col);
+        if (row, col) != (location.row, location.col) {
+            self.book.move_to(&Address {
+                sheet: location.sheet,
+                row,
+                col,
+            })?;
+            self.handle_movement_change();
+        }
+        Ok(())
+    }
+
+    /// The cell rendered at a terminal position if there is one.
+    fn address_at(&self, column: u16, row: u16) -> Option<Address> {
+        let (row, col) = self.state.viewport_state.cell_at(column, row)?;
+        Some(Address {
+            sheet: self.book.location.sheet,
+            row,
+            col,
+        })
+    }
+
+    /// The index of the sheet tab rendered at a terminal position.
+    fn tab_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.state.tabs_area;
+        if row != area.y || column < area.x || column >= area.right() {
+            return None;
+        }
+        // Mirror the layout of the `Tabs` widget: a space of padding on each
+        // side of every title and a one character divider between them.
+        let mut x = area.x;
+        for (idx, title) in tab_titles(&self.book).iter().enumerate() {
+            let end = x + Line::from(title.as_str()).width() as u16 + 2;
+            if column < end {
+                return Some(idx);
+            }
+            x = end + 1;
+        }
+        None
+    }
+
+    fn select_sheet_by_idx(&mut self, idx: usize) {
+        if idx as u32 != self.book.location.sheet {
+            self.push_jump();
+        }
+        if self.book.set_current_sheet_index(idx as u32).is_ok() {
+            self.handle_movement_change();
+        }
+    }
+
+    fn render_help_text(&self) -> Markdown {
+        // TODO(zaphar): We should be sourcing these from our actual help documentation.
+        // Ideally we would also render the markdown content properly.
+        // https://github.com/zaphar/sheetsui/issues/22
+        match self.state.modality() {
+            Modality::Navigate => help::to_widget("navigate"),
+            Modality::CellEdit => help::to_widget("edit"),
+            Modality::Command => help::to_widget("command"),
+            Modality::RangeSelect => help::to_widget("visual"),
+            _ => help::to_widget(""),
+        }
+    }
+
+    fn render_keys(&self) -> Markdown {
+        Markdown::from_str(&self.keymap.cheat_sheet())
+    }
+
+    fn handle_command_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press {
+            match key.code {
+                KeyCode::Enter => return self.exit_command_mode(),
+                KeyCode::Esc => {
+                    self.cancel_command_mode();
+                    return Ok(None);
+                }
+                KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
+                    self.cancel_command_mode();
+                    return Ok(None);
+                }
+                _ if self.handle_command_line_editing(&key) => return Ok(None),
+                KeyCode::Char('v') if key.modifiers == KeyModifiers::CONTROL => {
+                    match self.system_clipboard.get_text() {
+                        Ok(text) => self.insert_into_command_line(&text),
+                        Err(e) => self
+                            .state
+                            .set_status(format!("Couldn't read the clipboard: {}", e)),
+                    }
+                    return Ok(None);
+                }
+                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
+                    self.enter_dialog_mode(self.render_help_text());
+                    return Ok(None);
+                }
+                KeyCode::Char('k') if key.modifiers == KeyModifiers::ALT => {
+                    self.enter_dialog_mode(self.render_keys());
+                    return Ok(None);
+                }
+                KeyCode::Up => {
+                    self.recall_command_history(true);
+                    return Ok(None);
+                }
+                KeyCode::Down => {
+                    self.recall_command_history(false);
+                    return Ok(None);
+                }
+                _ => {
+                    // NOOP
+                }
+            }
+        }
+        self.state.command_state.handle_key_event(key);
+        Ok(None)
+    }
+
+    /// Readline style editing of the command line. Returns false if the key
+    /// isn't one of them.
+    fn handle_command_line_editing(&mut self, key: &event::KeyEvent) -> bool {
+        if key.modifiers != KeyModifiers::CONTROL {
+            return false;
+        }
+        let state = &mut self.state.command_state;
+        let chars: Vec<char> = state.value().chars().collect();
+        let pos = state.position().min(chars.len());
+        let (value, pos): (Vec<char>, usize) = match key.code {
+            KeyCode::Char('a') => (chars, 0),
+            KeyCode::Char('e') => {
+                let end = chars.len();
+                (chars, end)
+            }
+            // Delete back to the start of the line.
+            KeyCode::Char('u') => (chars[pos..].to_vec(), 0),
+            // Delete to the end of the line.
+            KeyCode::Char('k') => (chars[..pos].to_vec(), pos),
+            // Delete the word before the cursor and the spaces after it.
+            KeyCode::Char('w') => {
+                let mut start = pos;
+                while start > 0 && chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                ([&chars[..start], &chars[pos..]].concat(), start)
+            }
+            _ => return false,
+        };
+        *state.value_mut() = value.into_iter().collect();
+        *state.position_mut() = pos;
+        true
+    }
+
+    /// Insert pasted text at the command line's cursor. A command is a single
+    /// line so line breaks become spaces.
+    fn insert_into_command_line(&mut self, text: &str) {
+        let text = text
+            .trim_end_matches(['\r', '\n'])
+            .replace(['\r', '\n'], " ");
+        let state = &mut self.state.command_state;
+        let pos = state.position().min(state.value().chars().count());
+        let offset = state
+            .value()
+            .char_indices()
+            .nth(pos)
+            .map(|(idx, _)| idx)
+            .unwrap_or(state.value().len());
+        state.value_mut().insert_str(offset, &text);
+        *state.position_mut() = pos + text.chars().count();
+    }
+
+    /// Replace the command text with an older or newer entry from the history.
+    /// Moving newer than the latest entry restores what was being typed.
+    fn recall_command_history(&mut self, older: bool) {
+        let len = self.state.command_history.len();
+        if len == 0 {
+            return;
+        }
+        let idx = match (self.state.command_history_idx, older) {
+            (None, true) => Some(len - 1),
+            (None, false) => return,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < len => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        if self.state.command_history_idx.is_none() {
+            self.state.command_draft = self.state.command_state.value().to_string();
+        }
+        self.state.command_history_idx = idx;
+        let value = match idx {
+            Some(i) => self.state.command_history[i].clone(),
+            None => std::mem::take(&mut self.state.command_draft),
+        };
+        *self.state.command_state.value_mut() = value;
+        self.state.command_state.move_end();
+    }
+
+    fn handle_quit_dialog(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('c') | KeyCode::Char('C') => {
+                    // Cancel the quit and go back to what we were doing.
+                    return self.exit_quit_mode();
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') => {
+                    self.exit_quit_mode()?;
+                    return Ok(Some(ExitCode::SUCCESS));
+                }
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    // We have been asked to save the files first.
+                    match self.save_all() {
+                        Err(e) => {
+                            // Stay in the quit flow so the user can save somewhere
+                            // else or explicitly discard their changes.
+                            self.enter_dialog_mode(Markdown::from_str(&format!(
+                                "# Save failed\n\n{}\n\nPress `:` and run `w <path>` to save to a different path or `n` to quit without saving.",
+                                e
+                            )));
+                            return Ok(None);
+                        }
+                        // We are waiting on the user to confirm the save.
+                        Ok(false) => return Ok(None),
+                        Ok(true) => (),
+                    }
+                    self.exit_quit_mode()?;
+                    return Ok(Some(ExitCode::SUCCESS));
+                }
+                KeyCode::Char(':') => {
+                    self.enter_command_mode();
+                    return Ok(None);
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    fn handle_dialog_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press {
+            if let Some(action) = self.state.pending_action.clone() {
+                return self.handle_pending_action(action, key);
+            }
+            if !self.state.popup_targets.is_empty() && self.handle_jump_input(key.code)? {
+                return Ok(None);
+            }
+            match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.exit_dialog_mode()?,
+                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
+                    self.exit_dialog_mode()?
+                }
+                KeyCode::Char('j') | KeyCode::Down => self.scroll_dialog(1),
+                KeyCode::Char('k') | KeyCode::Up => self.scroll_dialog(-1),
+                KeyCode::PageDown => self.scroll_dialog(self.state.dialog_rows as isize),
+                KeyCode::PageUp => self.scroll_dialog(-(self.state.dialog_rows as isize)),
+                KeyCode::Char('g') | KeyCode::Home => self.state.dialog_scroll = 0,
+                KeyCode::Char('G') | KeyCode::End => {
+                    self.state.dialog_scroll = self.state.dialog_max_scroll
+                }
+                code => {
+                    let link = self.state.popup.as_ref().and_then(|w| w.handle_input(code));
+                    if let Some(link) = link {
+                        self.open_link(&link);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Scroll the dialog by `rows` without going past either end.
+    fn scroll_dialog(&mut self, rows: isize) {
+        let scroll = self.state.dialog_scroll as isize + rows;
+        self.state.dialog_scroll = scroll.clamp(0, self.state.dialog_max_scroll as isize) as u16;
+    }
+
+    /// Open a link from a popup in the browser. Links that aren't urls are
+    /// ignored.
+    fn open_link(&mut self, link: &str) {
+        let Some(url) = browser::link_url(link) else {
+            return;
+        };
+        match self.browser.open(url) {
+            Ok(()) => self.state.set_status(format!("Opened {}", url)),
+            Err(e) => self
+                .state
+                .set_status(format!("Couldn't open {}: {}", url, e)),
+        }
+    }
+
+    fn handle_pending_action(
+        &mut self,
+        action: PendingAction,
+        key: event::KeyEvent,
+    ) -> Result<Option<ExitCode>> {
+        match (action, key.code) {
+            (PendingAction::FileChanged, KeyCode::Char('o')) => {
+                self.close_prompt()?;
+                self.write_file()?;
+            }
+            (PendingAction::FileChanged, KeyCode::Char('r')) => {
+                self.close_prompt()?;
+                self.load_into(self.current_path())?;
+                self.handle_movement_change();
+                self.state.set_status("Reloaded from disk");
+            }
+            (PendingAction::Overwrite { path, quit }, KeyCode::Char('y')) => {
+                self.close_prompt()?;
+                self.save_to(path.to_string_lossy())?;
+                if quit {
+                    return self.quit_app();
+                }
+            }
+            (_, KeyCode::Esc) | (_, KeyCode::Char('c')) | (_, KeyCode::Char('n')) => {
+                self.close_prompt()?;
+            }
+            _ => return Ok(None),
+        }
+        Ok(self.maybe_finish_quit(None))
+    }
+
+    fn close_prompt(&mut self) -> Result<()> {
+        self.state.pending_action = None;
+        self.exit_dialog_mode()
+    }
+
+    /// A successful save from inside the quit flow finishes the quit.
+    fn maybe_finish_quit(&self, result: Option<ExitCode>) -> Option<ExitCode> {
+        if result.is_none()
+            && self.state.modality() == &Modality::Quit
+            && self.unsaved_buffers().is_empty()
+        {
+            return Some(ExitCode::SUCCESS);
+        }
+        result
+    }
+
+    fn handle_edit_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
+        if key.kind == KeyEventKind::Press && self.handle_completion_input(&key) {
+            return Ok(None);
+        }
+        if key.kind == KeyEventKind::Press && self.handle_point_input(&key) {
+            return Ok(None);
+        }
+        if key.kind == KeyEventKind::Press {
+            match key.code {
+                KeyCode::Tab if key.modifiers.is_empty() && self.open_completion() => {
+                    return Ok(None);
+                }
+                KeyCode::Char(' ')
+                    if key.modifiers == KeyModifiers::CONTROL && self.open_completion() =>
+                {
+                    return Ok(None);
+                }
+                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
+                    self.enter_dialog_mode(self.render_help_text());
+                    return Ok(None);
+                }
+                KeyCode::Char('k') if key.modifiers == KeyModifiers::ALT => {
+                    self.enter_dialog_mode(self.render_keys());
+                    return Ok(None);
+                }
+                KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                    self.enter_range_select_mode(false);
+                    return Ok(None);
                 }
                 KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
                     self.text_area
@@ -415,7 +1562,31 @@ impl<'ws> Workspace<'ws> {
                     self.state.dirty = true;
                     return Ok(None);
                 }
-                KeyCode::Enter => self.exit_edit_mode(true)?,
+                KeyCode::Enter => {
+                    self.exit_edit_mode(true)?;
+                    if self.state.settings.enter_moves {
+                        self.move_down()?;
+                        self.handle_movement_change();
+                    }
+                    // The key is used up. The editor must not see it.
+                    return Ok(None);
+                }
+                KeyCode::F(4) => {
+                    self.cycle_reference_anchors();
+                    return Ok(None);
+                }
+                KeyCode::Char('4') if key.modifiers == KeyModifiers::CONTROL => {
+                    self.cycle_reference_anchors();
+                    return Ok(None);
+                }
+                KeyCode::Tab if key.modifiers.is_empty() => {
+                    self.commit_and_move(false)?;
+                    return Ok(None);
+                }
+                KeyCode::BackTab => {
+                    self.commit_and_move(true)?;
+                    return Ok(None);
+                }
                 KeyCode::Esc => self.exit_edit_mode(false)?,
                 _ => {
                     // NOOP
@@ -432,43 +1603,328 @@ impl<'ws> Workspace<'ws> {
         Ok(None)
     }
 
-    fn handle_command(&mut self, cmd_text: String) -> Result<Option<ExitCode>> {
+    /// Handle the arrow keys while typing a formula. Right after typing an
+    /// operator or `(` they point at a cell and insert its reference. Moving
+    /// again replaces the reference and any other key keeps it. Returns false
+    /// if the key should go on to the editor.
+    fn handle_point_input(&mut self, key: &event::KeyEvent) -> bool {
+        let step: Option<(isize, isize)> = match key.code {
+            _ if !key.modifiers.is_empty() => None,
+            KeyCode::Up => Some((-1, 0)),
+            KeyCode::Down => Some((1, 0)),
+            KeyCode::Left => Some((0, -1)),
+            KeyCode::Right => Some((0, 1)),
+            _ => None,
+        };
+        let (Some((rows, cols)), Some((from, len))) = (step, self.state.point.take()) else {
+            self.state.point = match key.code {
+                KeyCode::Char(c) if self.starts_pointing(c, key.modifiers) => {
+                    Some((self.book.location.clone(), 0))
+                }
+                _ => None,
+            };
+            return false;
+        };
+        let to = Address {
+            sheet: from.sheet,
+            row: (from.row as isize + rows).clamp(1, book::LAST_ROW as isize) as usize,
+            col: (from.col as isize + cols).clamp(1, book::LAST_COLUMN as isize) as usize,
+        };
+        for _ in 0..len {
+            self.text_area.delete_char();
+        }
+        let reference = to.to_range_part();
+        self.text_area.insert_str(&reference);
+        self.state.point = Some((to, reference.chars().count()));
+        self.state.dirty = true;
+        true
+    }
+
+    /// Whether typing `c` leaves the formula ready for a reference.
+    fn starts_pointing(&self, c: char, modifiers: KeyModifiers) -> bool {
+        if !(modifiers.is_empty() || modifiers == KeyModifiers::SHIFT) {
+            return false;
+        }
+        let in_formula = match self.text_area.lines().first() {
+            Some(line) if line.starts_with('=') => true,
+            Some(line) => c == '=' && line.is_empty() && self.text_area.lines().len() == 1,
+            None => c == '=',
+        };
+        in_formula
+            && matches!(
+                c,
+                '=' | '+' | '-' | '*' | '/' | '^' | '&' | '<' | '>' | ',' | ';' | ':' | '('
+            )
+    }
+
+    /// Cycle the `$` anchors of the formula reference under the cursor. Does
+    /// nothing outside of a reference.
+    fn cycle_reference_anchors(&mut self) {
+        let is_formula = self
+            .text_area
+            .lines()
+            .first()
+            .is_some_and(|line| line.starts_with('='));
+        if !is_formula {
+            return;
+        }
+        let (row, col) = self.text_area.cursor();
+        let line = &self.text_area.lines()[row];
+        let offset = line
+            .char_indices()
+            .nth(col)
+            .map(|(idx, _)| idx)
+            .unwrap_or(line.len());
+        // A cursor just past the reference still counts as on it.
+        let Some(reference) = book::formula::find_references(line)
+            .into_iter()
+            .find(|r| r.span.start <= offset && offset <= r.span.end)
+        else {
+            return;
+        };
+        let text = &line[reference.span.clone()];
+        let replacement = book::formula::cycle_anchors(text);
+        let start = line[..reference.span.start].chars().count();
+        let len = text.chars().count();
+        self.text_area
+            .move_cursor(CursorMove::Jump(row as u16, start as u16));
+        self.text_area.delete_str(len);
+        self.text_area.insert_str(replacement);
+        self.state.dirty = true;
+    }
+
+    /// Commit the edit and carry on editing the cell to the right, or to the
+    /// left when `left` is set.
+    fn commit_and_move(&mut self, left: bool) -> Result<()> {
+        self.exit_edit_mode(true)?;
+        if left {
+            self.move_left()?;
+        } else {
+            self.move_right()?;
+        }
+        self.handle_movement_change();
+        self.enter_edit_mode();
+        Ok(())
+    }
+
+    /// Offer the function names matching the identifier before the cursor
+    /// when editing a formula. Returns false if there is nothing to offer.
+    fn open_completion(&mut self) -> bool {
+        let is_formula = self
+            .text_area
+            .lines()
+            .first()
+            .is_some_and(|line| line.starts_with('='));
+        if !is_formula {
+            return false;
+        }
+        let (row, col) = self.text_area.cursor();
+        let before: Vec<char> = self.text_area.lines()[row].chars().take(col).collect();
+        let prefix: String = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '.' || **c == '_')
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        if !prefix.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return false;
+        }
+        let items = completion::matching_functions(&prefix);
+        if items.is_empty() {
+            return false;
+        }
+        self.state.completion = Some(CompletionState::new(items, prefix.chars().count()));
+        true
+    }
+
+    /// Handle a key while the completion list is open. Returns false if the
+    /// key should go on to the editor. Any key the list doesn't use closes it.
+    fn handle_completion_input(&mut self, key: &event::KeyEvent) -> bool {
+        let Some(state) = self.state.completion.as_mut() else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Down | KeyCode::Tab => state.select_next(),
+            KeyCode::Up | KeyCode::BackTab => state.select_prev(),
+            KeyCode::Esc => self.state.completion = None,
+            KeyCode::Enter => {
+                let name = state.selected_item();
+                for _ in 0..state.prefix_len {
+                    self.text_area.delete_char();
+                }
+                self.text_area.insert_str(format!("{}(", name));
+                self.state.completion = None;
+                self.state.dirty = true;
+            }
+            _ => {
+                self.state.completion = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Parse and run a command typed by the user. `selection` is the range
+    /// selected when the command was typed in range select mode.
+    fn handle_command(
+        &mut self,
+        cmd_text: String,
+        selection: Option<(Address, Address)>,
+    ) -> Result<Option<ExitCode>> {
         if cmd_text.is_empty() {
             return Ok(None);
         }
         match cmd::parse(&cmd_text) {
-            Ok(Some(Cmd::Edit(path))) => {
-                self.load_into(path)?;
+            Ok(Some(cmd)) if cmd.mutates() && self.refuse_if_read_only() => Ok(None),
+            Ok(Some(cmd)) => self.run_command(cmd, selection),
+            Ok(None) => {
+                self.state.set_status(cmd::unrecognized(&cmd_text));
+                Ok(None)
+            }
+            Err(msg) => {
+                self.state.set_status(msg);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Run a single command without a user to answer any questions.
+    ///
+    /// Unknown commands, parse errors and anything that would have prompted
+    /// the user are returned as errors. Dialogs a command opens are closed.
+    pub fn run_batch_command(&mut self, cmd_text: &str) -> Result<Option<ExitCode>> {
+        let cmd = match cmd::parse(cmd_text) {
+            Ok(Some(cmd)) => cmd,
+            Ok(None) => return Err(anyhow!(cmd::unrecognized(cmd_text))),
+            Err(msg) => return Err(anyhow!(msg)),
+        };
+        if cmd.mutates() && self.read_only {
+            return Err(anyhow!("The workbook is read-only"));
+        }
+        let result = self.run_command(cmd, None);
+        let pending = self.state.pending_action.take();
+        let quitting = self.state.modality_stack.contains(&Modality::Quit);
+        self.state.modality_stack.truncate(1);
+        let result = result?;
+        match pending {
+            Some(PendingAction::FileChanged) => {
+                return Err(anyhow!(
+                    "{} changed on disk since it was loaded",
+                    self.current_path().display()
+                ))
+            }
+            Some(PendingAction::Overwrite { path, quit: _ }) => {
+                return Err(anyhow!("{} already exists", path.display()))
+            }
+            None => (),
+        }
+        if quitting && result.is_none() {
+            return Err(anyhow!("There are unsaved changes. Use `wq` or `q!`"));
+        }
+        Ok(result)
+    }
+
+    fn run_command(
+        &mut self,
+        cmd: Cmd,
+        selection: Option<(Address, Address)>,
+    ) -> Result<Option<ExitCode>> {
+        match cmd {
+            Cmd::Edit(path, force) => {
+                self.open_buffer(path, false, force)?;
+                Ok(None)
+            }
+            Cmd::View(path) => {
+                self.open_buffer(path, true, false)?;
+                Ok(None)
+            }
+            Cmd::NextBuffer => {
+                self.next_buffer();
+                Ok(None)
+            }
+            Cmd::PrevBuffer => {
+                self.prev_buffer();
                 Ok(None)
             }
-            Ok(Some(Cmd::Help(maybe_topic))) => {
+            Cmd::Buffers => {
+                self.enter_dialog_mode(self.render_buffers());
+                Ok(None)
+            }
+            Cmd::Help(maybe_topic) => {
                 self.enter_dialog_mode(help::to_widget(maybe_topic.unwrap_or("")));
                 Ok(None)
             }
-            Ok(Some(Cmd::Write(maybe_path))) => {
+            Cmd::Write(maybe_path) => {
                 if let Some(path) = maybe_path {
-                    self.save_to(path)?;
+                    self.save_to_new_path(path, false)?;
                 } else {
                     self.save_file()?;
                 }
                 Ok(None)
             }
-            Ok(Some(Cmd::ExportCsv(path))) => {
-                self.book
-                    .save_sheet_to_csv(self.book.location.sheet, path)?;
+            Cmd::WriteQuit(maybe_path) => {
+                let saved = if let Some(path) = maybe_path {
+                    self.save_to_new_path(path, true)
+                } else {
+                    self.save_file()
+                };
+                match saved {
+                    // Other buffers may still need saving.
+                    Ok(true) => self.quit_app(),
+                    Ok(false) => Ok(None),
+                    // Stay running so the user doesn't lose their changes.
+                    Err(e) => Err(anyhow!("Failed to save: {}", e)),
+                }
+            }
+            Cmd::ExportCsv(path) => {
+                if let Some((start, end)) = selection {
+                    self.book
+                        .save_range_to_csv(&AddressRange { start: &start, end: &end }, path)?;
+                } else {
+                    self.book
+                        .save_sheet_to_csv(self.book.location.sheet, path)?;
+                }
+                self.state.set_status(format!("Exported {}", path));
+                Ok(None)
+            }
+            Cmd::ExportMd(path) => {
+                if let Some((start, end)) = selection {
+                    self.book
+                        .save_range_to_markdown(&AddressRange { start: &start, end: &end }, path)?;
+                } else {
+                    self.book
+                        .save_sheet_to_markdown(self.book.location.sheet, path)?;
+                }
+                self.state.set_status(format!("Exported {}", path));
+                Ok(None)
+            }
+            Cmd::ExportAll(dir) => {
+                let (written, skipped) = self.book.save_all_sheets_to_csv(dir)?;
+                let mut msg = String::from("# Export complete\n\n");
+                for path in written {
+                    msg.push_str(&format!("* wrote `{}`\n", path.display()));
+                }
+                for name in skipped {
+                    msg.push_str(&format!("* skipped empty sheet `{}`\n", name));
+                }
+                self.enter_dialog_mode(Markdown::from_str(&msg));
                 Ok(None)
             }
-            Ok(Some(Cmd::InsertColumns(count))) => {
+            Cmd::InsertColumns(count) => {
                 self.book.insert_columns(self.book.location.col, count)?;
                 self.book.evaluate();
+                self.state.set_status(format!("Inserted {} {}", count, plural(count, "column")));
                 Ok(None)
             }
-            Ok(Some(Cmd::InsertRows(count))) => {
+            Cmd::InsertRows(count) => {
                 self.book.insert_rows(self.book.location.row, count)?;
                 self.book.evaluate();
+                self.state.set_status(format!("Inserted {} {}", count, plural(count, "row")));
                 Ok(None)
             }
-            Ok(Some(Cmd::RenameSheet(idx, name))) => {
+            Cmd::RenameSheet(idx, name) => {
                 match idx {
                     Some(idx) => {
                         self.book.set_sheet_name(idx as u32, name)?;
@@ -477,80 +1933,275 @@ impl<'ws> Workspace<'ws> {
                         self.book.set_sheet_name(self.book.location.sheet, name)?;
                     }
                 }
+                self.state.set_status(format!("Renamed sheet to {}", name));
                 Ok(None)
             }
-            Ok(Some(Cmd::NewSheet(name))) => {
+            Cmd::NewSheet(name) => {
                 self.book.new_sheet(name)?;
+                // A new sheet always starts out at the top left.
+                let idx = self.book.get_sheet_names().len() as u32 - 1;
+                self.state.viewport_state.reset_sheet(idx);
+                self.state.set_status(match name {
+                    Some(name) => format!("Created sheet {}", name),
+                    None => String::from("Created new sheet"),
+                });
                 Ok(None)
             }
-            Ok(Some(Cmd::SelectSheet(name))) => {
+            Cmd::SelectSheet(name) => {
+                self.push_jump();
                 self.book.select_sheet_by_name(name);
                 Ok(None)
             }
-            Ok(Some(Cmd::Quit)) => self.quit_app(),
-            Ok(Some(Cmd::ColorRows(count, color))) => {
-                let row_count = count.unwrap_or(1);
-                let row = self.book.location.row;
-                for r in row..(row + row_count) {
+            Cmd::Goto(cell) => {
+                let reference = book::formula::parse_reference(cell)
+                    .ok_or_else(|| anyhow!("Invalid cell reference: {}", cell))?;
+                let (start, _) = self.book.reference_range(&reference)?;
+                self.push_jump();
+                self.go_to(&start)?;
+                Ok(None)
+            }
+            Cmd::Quit => self.quit_app(),
+            Cmd::ForceQuit => Ok(Some(ExitCode::SUCCESS)),
+            Cmd::ColorRows(extent, color) => {
+                // A selection colors its rows unless given a count.
+                let (row, row_count) = match (extent, &selection) {
+                    (Some(Extent::Between(first, last)), _) => (first, last - first + 1),
+                    (Some(Extent::Count(count)), Some((start, _))) => (start.row, count),
+                    (Some(Extent::Count(count)), None) => (self.book.location.row, count),
+                    (None, Some((start, end))) => (start.row, end.row - start.row + 1),
+                    (None, None) => (self.book.location.row, 1),
+                };
+                // Stop at the last row however big the count.
+                let end = row
+                    .saturating_add(row_count)
+                    .min(book::LAST_ROW as usize + 1);
+                let row_count = end.saturating_sub(row);
+                for r in row..end {
                     self.book.set_row_style(
                         &[("fill.bg_color", &color)],
                         self.book.location.sheet,
                         r,
                     )?;
                 }
+                self.state
+                    .set_status(format!("Colored {} {}", row_count, plural(row_count, "row")));
                 Ok(None)
             }
-            Ok(Some(Cmd::ColorColumns(count, color))) => {
-                let col_count = count.unwrap_or(1);
-                let col = self.book.location.col;
-                for c in col..(col + col_count) {
+            Cmd::ColorColumns(extent, color) => {
+                let (col, col_count) = match (extent, &selection) {
+                    (Some(Extent::Between(first, last)), _) => (first, last - first + 1),
+                    (Some(Extent::Count(count)), Some((start, _))) => (start.col, count),
+                    (Some(Extent::Count(count)), None) => (self.book.location.col, count),
+                    (None, Some((start, end))) => (start.col, end.col - start.col + 1),
+                    (None, None) => (self.book.location.col, 1),
+                };
+                let end = col
+                    .saturating_add(col_count)
+                    .min(book::LAST_COLUMN as usize + 1);
+                let col_count = end.saturating_sub(col);
+                for c in col..end {
                     self.book.set_col_style(
                         &[("fill.bg_color", &color)],
                         self.book.location.sheet,
                         c,
                     )?;
                 }
+                self.state
+                    .set_status(format!("Colored {} {}", col_count, plural(col_count, "column")));
                 Ok(None)
             }
-            Ok(Some(Cmd::ColorCell(color))) => {
+            Cmd::ColorCell(range, color) => {
                 let sheet = self.book.location.sheet;
-                let area = if let Some((start, end)) = self.state.range_select.get_range() {
+                let area = if let Some(range) = range {
+                    let (start, end) = self.book.reference_range(&range)?;
                     Area {
-                        sheet,
+                        sheet: start.sheet,
                         row: start.row as i32,
                         column: start.col as i32,
                         width: (end.col - start.col + 1) as i32,
                         height: (end.row - start.row + 1) as i32,
                     }
                 } else {
-                    let address = self.book.location.clone();
+                    let (start, end) = self.selection_or_cursor(selection);
                     Area {
                         sheet,
-                        row: address.row as i32,
-                        column: address.col as i32,
-                        width: 1,
-                        height: 1,
+                        row: start.row as i32,
+                        column: start.col as i32,
+                        width: (end.col - start.col + 1) as i32,
+                        height: (end.row - start.row + 1) as i32,
                     }
                 };
                 self.book
                     .set_cell_style(&[("fill.bg_color", &color)], &area)?;
+                self.state.set_status("Colored cells");
+                Ok(None)
+            }
+            Cmd::Style(path, value) => {
+                let (start, end) = self.selection_or_cursor(selection);
+                let area = Area {
+                    sheet: self.book.location.sheet,
+                    row: start.row as i32,
+                    column: start.col as i32,
+                    width: (end.col - start.col + 1) as i32,
+                    height: (end.row - start.row + 1) as i32,
+                };
+                self.book.set_cell_style(&[(path, &value)], &area)?;
+                self.state.set_status(format!("Set {} to {}", path, value));
                 Ok(None)
             }
-            Ok(Some(Cmd::SystemPaste)) => {
+            Cmd::SystemPaste => {
                 let rows = self.get_rows_from_system_clipboard()?;
                 self.state.clipboard = Some(ClipboardContents::Range(rows));
                 self.paste_range()?;
                 Ok(None)
             }
-            Ok(None) => {
+            Cmd::Registers => {
+                self.enter_dialog_mode(self.render_registers());
+                Ok(None)
+            }
+            Cmd::Marks => {
+                self.enter_dialog_mode(self.render_marks());
+                Ok(None)
+            }
+            Cmd::Keys => {
+                self.enter_dialog_mode(self.render_keys());
+                Ok(None)
+            }
+            Cmd::Trim(collapse_inner) => {
+                let (start, end) = self.selection_or_cursor(selection);
+                let changed = self.book.trim_range(
+                    &AddressRange {
+                        start: &start,
+                        end: &end,
+                    },
+                    collapse_inner,
+                )?;
+                self.state
+                    .set_status(format!("Trimmed {} {}", changed, plural(changed, "cell")));
+                Ok(None)
+            }
+            Cmd::Case(mode) => {
+                let (start, end) = self.selection_or_cursor(selection);
+                let changed = self.book.change_case_range(
+                    &AddressRange {
+                        start: &start,
+                        end: &end,
+                    },
+                    mode,
+                )?;
+                self.state
+                    .set_status(format!("Changed {} {}", changed, plural(changed, "cell")));
+                Ok(None)
+            }
+            Cmd::SplitColumn(delimiter, insert) => {
+                // Only the cursor's column is split.
+                let (start, end) = self.selection_or_cursor(selection);
+                let col = self.book.location.col;
+                let most = self.book.split_column(
+                    &AddressRange {
+                        start: &Address { col, ..start },
+                        end: &Address { col, ..end },
+                    },
+                    &delimiter,
+                    insert,
+                )?;
+                if most > 1 {
+                    self.state
+                        .set_status(format!("Split into up to {} columns", most));
+                } else {
+                    self.state
+                        .set_status(format!("Nothing to split on {:?}", delimiter));
+                }
+                Ok(None)
+            }
+            Cmd::Fill(start, step) => {
+                let (start_addr, end_addr) = self.selection_or_cursor(selection);
+                // Without a start the series carries on from the first cell.
+                let start = match start {
+                    Some(start) => start,
+                    None => match self.book.get_cell_addr_number(&start_addr) {
+                        Some(n) => SeriesStart::Number(n),
+                        None => {
+                            self.state
+                                .set_status("fill needs a start or a number in the first cell");
+                            return Ok(None);
+                        }
+                    },
+                };
+                let filled = self.book.fill_series(
+                    &AddressRange {
+                        start: &start_addr,
+                        end: &end_addr,
+                    },
+                    start,
+                    step.unwrap_or(1.0),
+                )?;
+                self.state
+                    .set_status(format!("Filled {} {}", filled, plural(filled, "cell")));
+                Ok(None)
+            }
+            Cmd::Dedupe(shift_up) => {
+                let Some((start, end)) = selection else {
+                    self.state.set_status("Select a range to dedupe first");
+                    return Ok(None);
+                };
+                let removed = self.book.dedupe_range(
+                    &AddressRange {
+                        start: &start,
+                        end: &end,
+                    },
+                    shift_up,
+                )?;
                 self.enter_dialog_mode(Markdown::from_str(&format!(
-                    "Unrecognized commmand {}",
-                    cmd_text
+                    "# Dedupe\n\nRemoved {} duplicate {}",
+                    removed,
+                    plural(removed, "row")
                 )));
                 Ok(None)
             }
-            Err(msg) => {
-                self.enter_dialog_mode(Markdown::from_str(msg));
+            Cmd::Calc => {
+                self.recalculate();
+                Ok(None)
+            }
+            Cmd::Errors => {
+                self.enter_errors_dialog()?;
+                Ok(None)
+            }
+            Cmd::Precedents => {
+                self.enter_precedents_dialog()?;
+                Ok(None)
+            }
+            Cmd::Dependents => {
+                self.enter_dependents_dialog()?;
+                Ok(None)
+            }
+            Cmd::NextError => {
+                self.move_to_error(true)?;
+                Ok(None)
+            }
+            Cmd::PrevError => {
+                self.move_to_error(false)?;
+                Ok(None)
+            }
+            Cmd::Set(name, value) => {
+                let result = match name {
+                    "locale" => {
+                        self.locale = value.to_owned();
+                        Ok(())
+                    }
+                    "timezone" => {
+                        self.tz = value.to_owned();
+                        Ok(())
+                    }
+                    _ => self.state.settings.set(name, value),
+                };
+                result?;
+                self.sync_calc_mode();
+                self.state.set_status(format!("{} set to {}", name, value));
+                Ok(None)
+            }
+            Cmd::ShowSettings => {
+                self.enter_dialog_mode(self.render_settings());
                 Ok(None)
             }
         }
@@ -563,110 +2214,172 @@ impl<'ws> Workspace<'ws> {
     fn handle_range_select_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
         if key.kind == KeyEventKind::Press {
             match key.code {
-                KeyCode::Esc => {
-                    if self.state.numeric_prefix.len() > 0 {
-                        self.state.reset_n_prefix();
-                    } else {
-                        self.state.range_select.start = None;
-                        self.state.range_select.end = None;
-                        self.exit_range_select_mode()?;
-                    }
-                }
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.enter_dialog_mode(self.render_help_text());
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'"') => {
+                    self.select_register(c);
                     return Ok(None);
                 }
                 KeyCode::Char(d) if d.is_ascii_digit() => {
                     self.handle_numeric_prefix(d);
+                    return Ok(None);
                 }
-                KeyCode::Char('D') => {
-                    if let Some((start, end)) = self.state.range_select.get_range() {
-                        self.book.clear_cell_range_all(start, end)?;
-                    }
-                }
-                KeyCode::Char('d') => {
-                    if let Some((start, end)) = self.state.range_select.get_range() {
-                        self.book.clear_cell_range(start, end)?;
-                    }
-                }
-                KeyCode::Char('h') => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_left()?;
-                        Ok(())
-                    })?;
-                    self.maybe_update_range_end();
-                }
-                KeyCode::Char('j') => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_down()?;
-                        Ok(())
-                    })?;
-                    self.maybe_update_range_end();
-                }
-                KeyCode::Char('k') => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_up()?;
-                        Ok(())
-                    })?;
-                    self.maybe_update_range_end();
-                }
-                KeyCode::Char('l') => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_right()?;
-                        Ok(())
-                    })?;
-                    self.maybe_update_range_end();
-                }
-                KeyCode::Char(' ') | KeyCode::Enter => {
-                    if self.update_range_selection()? {
-                        self.exit_range_select_mode()?;
-                    }
-                }
-                KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.state.range_select.reset_range_selection();
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_next_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.state.range_select.reset_range_selection();
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_prev_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.copy_range(true)?;
-                    self.exit_range_select_mode()?;
-                }
-                KeyCode::Char('y') => {
-                    self.copy_range(true)?;
+                _ => (),
+            }
+            if let Some(action) = self.lookup_key(&Modality::RangeSelect, &key) {
+                return self.run_range_select_action(action);
+            }
+        }
+        Ok(None)
+    }
+
+    /// The action bound to `key`, or to the sequence it finishes when the
+    /// previous key started one.
+    fn lookup_key(&mut self, modality: &Modality, key: &event::KeyEvent) -> Option<Action> {
+        let this = (key.code, key.modifiers);
+        if let Some((first, register)) = self.state.pending_key.take() {
+            if let Some(action) = self.keymap.lookup_sequence(modality, &[first, this]) {
+                // The sequence takes over from whatever its first key left
+                // pending and gets the register picked before it.
+                self.state.char_queue.clear();
+                self.state.register = register;
+                return Some(action);
+            }
+        }
+        if self.keymap.starts_sequence(modality, key) {
+            self.state.pending_key = Some((this, self.state.register));
+        }
+        self.keymap.lookup(modality, key)
+    }
+
+    fn run_range_select_action(&mut self, action: Action) -> Result<Option<ExitCode>> {
+        if action.mutates() && self.refuse_if_read_only() {
+            return Ok(None);
+        }
+        match action {
+            Action::Cancel => {
+                if self.state.numeric_prefix.len() > 0 {
+                    self.state.reset_n_prefix();
+                } else {
+                    self.state.range_select.start = None;
+                    self.state.range_select.end = None;
+                    self.state.register = None;
                     self.exit_range_select_mode()?;
                 }
-                KeyCode::Char('C') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.copy_range(false)?;
-                    self.exit_range_select_mode()?;
+            }
+            Action::RegisterPrefix => {
+                self.state.char_queue.clear();
+                self.state.char_queue.push('"');
+            }
+            Action::Help => {
+                self.enter_dialog_mode(self.render_help_text());
+            }
+            Action::Keys => {
+                self.enter_dialog_mode(self.render_keys());
+            }
+            Action::ClearCellAll => {
+                if let Some((start, end)) = self.state.range_select.get_range() {
+                    self.book.clear_cell_range_all(start, end)?;
                 }
-                KeyCode::Char('Y') => {
-                    self.copy_range(false)?;
-                    self.exit_range_select_mode()?;
+            }
+            Action::ClearCell => {
+                if let Some((start, end)) = self.state.range_select.get_range() {
+                    self.book.clear_cell_range(start, end)?;
                 }
-                KeyCode::Char('x') => {
-                    if let (Some(from), Some(to)) = (
-                        self.state.range_select.start.as_ref(),
-                        self.state.range_select.end.as_ref(),
-                    ) {
-                        self.book.extend_to(from, to)?;
-                    }
+            }
+            Action::MoveLeft => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_left()?;
+                    Ok(())
+                })?;
+                self.maybe_update_range_end();
+            }
+            Action::MoveDown => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_down()?;
+                    Ok(())
+                })?;
+                self.maybe_update_range_end();
+            }
+            Action::MoveUp => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_up()?;
+                    Ok(())
+                })?;
+                self.maybe_update_range_end();
+            }
+            Action::MoveRight => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_right()?;
+                    Ok(())
+                })?;
+                self.maybe_update_range_end();
+            }
+            Action::Select => {
+                if self.update_range_selection()? {
                     self.exit_range_select_mode()?;
                 }
-                KeyCode::Char(':') => {
-                    self.enter_command_mode();
-                }
-                _ => {
-                    // moop
-                }
+            }
+            Action::NextSheet => {
+                self.state.range_select.reset_range_selection();
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_next_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::PrevSheet => {
+                self.state.range_select.reset_range_selection();
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_prev_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::CopyFormatted => {
+                self.copy_range(true)?;
+                self.exit_range_select_mode()?;
+            }
+            Action::Copy => {
+                self.copy_range(false)?;
+                self.exit_range_select_mode()?;
+            }
+            Action::CopyWithHeader => {
+                self.copy_range_with_header()?;
+                self.exit_range_select_mode()?;
+            }
+            Action::Extend => {
+                if let (Some(from), Some(to)) = (
+                    self.state.range_select.start.as_ref(),
+                    self.state.range_select.end.as_ref(),
+                ) {
+                    self.book.extend_to(from, to)?;
+                }
+                self.exit_range_select_mode()?;
+            }
+            Action::Command => {
+                self.enter_command_mode();
+            }
+            Action::SelectRows => {
+                self.toggle_selection_mode(false)?;
+            }
+            Action::SelectColumns => {
+                self.toggle_selection_mode(true)?;
+            }
+            Action::SwapAnchor => {
+                self.swap_range_anchor(false)?;
+            }
+            Action::Increment | Action::Decrement => {
+                let count = self.state.get_n_prefix() as f64;
+                self.state.reset_n_prefix();
+                let delta = if action == Action::Increment {
+                    count
+                } else {
+                    -count
+                };
+                self.increment_cells(delta)?;
+            }
+            Action::SwapAnchorColumn => {
+                self.swap_range_anchor(true)?;
+            }
+            _ => {
+                // moop
             }
         }
         Ok(None)
@@ -677,24 +2390,7 @@ impl<'ws> Workspace<'ws> {
         self.update_range_selection()?;
         match &self.state.range_select.get_range() {
             Some((start, end)) => {
-                let mut rows = Vec::new();
-                for row in (AddressRange { start, end }).as_rows() {
-                    let mut cols = Vec::new();
-                    for cell in row {
-                        cols.push(if formatted {
-                            self.book.get_cell_addr_rendered(&cell)?
-                        } else {
-                            self.book.get_cell_addr_contents(&cell)?
-                        });
-                    }
-                    rows.push(cols);
-                }
-                let mut cb = Clipboard::new()?;
-                let (html, csv) = self
-                    .book
-                    .range_to_clipboard_content(AddressRange { start, end })?;
-                cb.set_html(html, Some(csv))?;
-                self.state.clipboard = Some(ClipboardContents::Range(rows));
+                self.copy_area(start, end, formatted)?;
             }
             None => {
                 self.copy_cell_to_clipboard(formatted)?;
@@ -703,11 +2399,119 @@ impl<'ws> Workspace<'ws> {
         Ok(())
     }
 
-    fn get_rows_from_system_clipboard(&mut self) -> Result<Vec<Vec<String>>, anyhow::Error> {
+    /// Copy the formatted range along with the row just above it, which
+    /// usually holds the column headers. A range on the first row has no
+    /// header and is copied as is.
+    fn copy_range_with_header(&mut self) -> Result<(), anyhow::Error> {
+        self.update_range_selection()?;
+        match self.state.range_select.get_range() {
+            Some((mut start, end)) => {
+                start.row = std::cmp::max(start.row - 1, 1);
+                self.copy_area(&start, &end, true)?;
+            }
+            None => {
+                self.copy_cell_to_clipboard(true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the cells from `start` to `end` into the selected register.
+    fn copy_area(
+        &mut self,
+        start: &Address,
+        end: &Address,
+        formatted: bool,
+    ) -> Result<(), anyhow::Error> {
         use arboard::Clipboard;
-        let mut cb = Clipboard::new()?;
-        let txt = cb.get_text().map_err(|e| anyhow!(e))?;
-        parse_csv_rows(&txt)
+        let mut rows = Vec::new();
+        for row in (AddressRange { start, end }).as_rows() {
+            let mut cols = Vec::new();
+            for cell in row {
+                cols.push(if formatted {
+                    self.book.get_cell_addr_rendered(&cell)?
+                } else {
+                    self.book.get_cell_addr_contents(&cell)?
+                });
+            }
+            rows.push(cols);
+        }
+        // Named registers are private to sheetui and leave the system
+        // clipboard alone.
+        if self.state.register.is_none() {
+            let mut cb = Clipboard::new()?;
+            let (html, csv) = self
+                .book
+                .range_to_clipboard_content(AddressRange { start, end })?;
+            cb.set_html(html, Some(csv))?;
+        }
+        self.store_in_register(ClipboardContents::Range(rows));
+        Ok(())
+    }
+
+    /// The first and last cell of `count` whole rows, or columns, starting at
+    /// the cursor. Only the used part of the sheet is covered.
+    fn lines_area(&self, columns: bool, count: usize) -> Result<(Address, Address)> {
+        let (row_count, col_count) = self.book.get_size()?;
+        let Address { sheet, row, col } = self.book.location.clone();
+        let start = if columns {
+            Address { sheet, row: 1, col }
+        } else {
+            Address { sheet, row, col: 1 }
+        };
+        let end = if columns {
+            Address {
+                sheet,
+                row: row_count.max(1),
+                col: (col + count - 1).min(book::LAST_COLUMN as usize),
+            }
+        } else {
+            Address {
+                sheet,
+                row: (row + count - 1).min(book::LAST_ROW as usize),
+                col: col_count.max(1),
+            }
+        };
+        Ok((start, end))
+    }
+
+    /// Clear `count` rows or columns from the cursor for `dd`, `dD` and `dc`.
+    fn clear_lines(&mut self, columns: bool, count: usize, all: bool) -> Result<()> {
+        let (start, end) = self.lines_area(columns, count)?;
+        if all {
+            self.book.clear_cell_range_all(start, end)?;
+        } else {
+            self.book.clear_cell_range(start, end)?;
+        }
+        self.handle_movement_change();
+        self.state.last_change = Some(LastChange::ClearLines {
+            columns,
+            count,
+            all,
+        });
+        Ok(())
+    }
+
+    /// Copy `count` rows or columns from the cursor for `yy` and `yc`.
+    fn yank_lines(&mut self, columns: bool, count: usize) -> Result<()> {
+        let (start, end) = self.lines_area(columns, count)?;
+        self.copy_area(&start, &end, false)?;
+        let word = if columns { "column" } else { "row" };
+        self.state
+            .set_status(format!("Yanked {} {}", count, plural(count, word)));
+        Ok(())
+    }
+
+    fn get_rows_from_system_clipboard(&mut self) -> Result<Vec<Vec<String>>, anyhow::Error> {
+        // Prefer html when it is available since it preserves cell boundaries
+        // for content with commas and newlines.
+        if let Ok(html) = self.system_clipboard.get_html() {
+            if let Some(rows) = rows_from_html(&html) {
+                return Ok(rows);
+            }
+        }
+        let txt = self.system_clipboard.get_text()?;
+        rows_from_text(&txt)
     }
 
     fn copy_cell_to_clipboard(&mut self, formatted: bool) -> Result<(), anyhow::Error> {
@@ -717,12 +2521,96 @@ impl<'ws> Workspace<'ws> {
         } else {
             self.book.get_current_cell_contents()?
         };
-        let mut cb = Clipboard::new()?;
-        cb.set_text(&contents)?;
-        self.state.clipboard = Some(ClipboardContents::Cell(contents));
+        if self.state.register.is_none() {
+            let mut cb = Clipboard::new()?;
+            cb.set_text(&contents)?;
+        }
+        self.store_in_register(ClipboardContents::Cell(contents));
         Ok(())
     }
 
+    fn select_register(&mut self, name: char) {
+        self.state.char_queue.clear();
+        self.state.register = Some(name);
+    }
+
+    /// Store contents in the selected register or the unnamed one if no register
+    /// was selected.
+    fn store_in_register(&mut self, contents: ClipboardContents) {
+        match self.state.register.take() {
+            Some(name) => {
+                self.state.registers.insert(name, contents);
+            }
+            None => {
+                self.state.clipboard = Some(contents);
+            }
+        }
+    }
+
+    fn render_settings(&self) -> Markdown {
+        let mut lines = vec!["# Settings".to_string(), String::new()];
+        lines.push(format!("* `locale` {}", self.locale));
+        lines.push(format!("* `timezone` {}", self.tz));
+        for (name, value) in self.state.settings.entries() {
+            lines.push(format!("* `{}` {}", name, value));
+        }
+        Markdown::from_str(&lines.join("\n"))
+    }
+
+    fn render_registers(&self) -> Markdown {
+        let mut lines = vec!["# Registers".to_string(), String::new()];
+        let mut occupied: Vec<(String, &ClipboardContents)> = Vec::new();
+        if let Some(contents) = &self.state.clipboard {
+            occupied.push(("unnamed".to_string(), contents));
+        }
+        let mut names: Vec<&char> = self.state.registers.keys().collect();
+        names.sort();
+        for name in names {
+            occupied.push((format!("\"{}", name), &self.state.registers[name]));
+        }
+        if occupied.is_empty() {
+            lines.push("All registers are empty".to_string());
+        }
+        for (name, contents) in occupied {
+            let (preview, size) = match contents {
+                ClipboardContents::Cell(cell) => (cell.as_str(), "cell".to_string()),
+                ClipboardContents::Range(rows) => (
+                    rows.first()
+                        .and_then(|r| r.first())
+                        .map(|c| c.as_str())
+                        .unwrap_or(""),
+                    format!(
+                        "{}x{}",
+                        rows.len(),
+                        rows.iter().map(|r| r.len()).max().unwrap_or(0)
+                    ),
+                ),
+            };
+            let preview: String = preview.lines().next().unwrap_or("").chars().take(40).collect();
+            lines.push(format!("* `{}` ({}): {}", name, size, preview));
+        }
+        Markdown::from_str(&lines.join("\n"))
+    }
+
+    fn render_marks(&self) -> Markdown {
+        let mut lines = vec!["# Marks".to_string(), String::new()];
+        let sheet_names = self.book.get_sheet_names();
+        let mut names: Vec<&char> = self.state.marks.keys().collect();
+        names.sort();
+        if names.is_empty() {
+            lines.push("No marks are set".to_string());
+        }
+        for name in names {
+            let addr = &self.state.marks[name];
+            let location = match sheet_names.get(addr.sheet as usize) {
+                Some(sheet) => format!("{}!{}", sheet, addr.to_range_part()),
+                None => format!("{} on a deleted sheet", addr.to_range_part()),
+            };
+            lines.push(format!("* `{}` {}", name, location));
+        }
+        Markdown::from_str(&lines.join("\n"))
+    }
+
     fn update_range_selection(&mut self) -> Result<bool, anyhow::Error> {
         Ok(if self.state.range_select.start.is_none() {
             self.state.range_select.start = Some(self.book.location.clone());
@@ -734,6 +2622,41 @@ impl<'ws> Workspace<'ws> {
         })
     }
 
+    /// Add `delta` to every number in the range selection, or to the cell
+    /// under the cursor. Returns false if there weren't any numbers.
+    fn increment_cells(&mut self, delta: f64) -> Result<bool> {
+        let (start, end) = self.selection_or_cursor(self.get_active_range());
+        let mut changed = false;
+        for cell in (AddressRange {
+            start: &start,
+            end: &end,
+        })
+        .as_series()
+        {
+            changed |= self.book.add_to_number(&cell, delta)?;
+        }
+        if changed {
+            self.book.evaluate();
+        } else {
+            self.state.set_status("No numbers to change");
+        }
+        Ok(changed)
+    }
+
+    /// The selected range or just the cell under the cursor.
+    fn selection_or_cursor(&self, selection: Option<(Address, Address)>) -> (Address, Address) {
+        selection.unwrap_or_else(|| (self.book.location.clone(), self.book.location.clone()))
+    }
+
+    /// Get the selected range if we are currently in range select mode.
+    fn get_active_range(&self) -> Option<(Address, Address)> {
+        if self.state.modality() == &Modality::RangeSelect {
+            self.state.range_select.get_range()
+        } else {
+            None
+        }
+    }
+
     fn maybe_update_range_end(&mut self) {
         if self.state.range_select.start.is_some() {
             self.state.range_select.end = Some(self.book.location.clone());
@@ -742,202 +2665,346 @@ impl<'ws> Workspace<'ws> {
 
     fn handle_navigation_input(&mut self, key: event::KeyEvent) -> Result<Option<ExitCode>> {
         if key.kind == KeyEventKind::Press {
+            // Prefixes are handled before the keymap since they consume the
+            // next key.
             match key.code {
-                KeyCode::Esc => {
-                    self.state.reset_n_prefix();
-                    self.state.char_queue.clear();
-                }
-                KeyCode::Char('B') => {
-                    let address = self.book.location.clone();
-                    let style = self.book.get_cell_style(&address).map(|s| s.font.b);
-                    self.toggle_bool_style(style, "font.b", &address)?;
-                }
-                KeyCode::Char('I') => {
-                    let address = self.book.location.clone();
-                    let style = self.book.get_cell_style(&address).map(|s| s.font.i);
-                    self.toggle_bool_style(style, "font.i", &address)?;
-                }
-                KeyCode::Char(d) if d.is_ascii_digit() => {
-                    self.handle_numeric_prefix(d);
-                }
-                KeyCode::Char('e') | KeyCode::Char('i') => {
-                    self.enter_edit_mode();
-                }
-                KeyCode::Char(':') => {
-                    self.enter_command_mode();
-                }
-                KeyCode::Char('s') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.save_file()?;
-                }
-                KeyCode::Char('s') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.book.clear_current_cell()?;
-                    self.text_area = reset_text_area(String::new());
-                    self.enter_edit_mode();
-                }
-                KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.enter_range_select_mode(false);
-                }
-                KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.copy_cell_to_clipboard(false)?;
-                }
-                KeyCode::Char('y') => {
-                    self.copy_cell_to_clipboard(false)?;
-                }
-                KeyCode::Char('Y') => {
-                    self.copy_cell_to_clipboard(true)?;
-                }
-                KeyCode::Char('C') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.copy_cell_to_clipboard(true)?;
-                }
-                KeyCode::Char('v') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.enter_range_select_mode(true)
-                }
-                KeyCode::Char('p') if key.modifiers != KeyModifiers::CONTROL => {
-                    self.paste_range()?;
-                }
-                KeyCode::Char('v') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.paste_range()?;
-                }
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::ALT => {
-                    self.enter_dialog_mode(self.render_help_text());
-                }
-                KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_next_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('d') => {
-                    self.book.clear_current_cell()?;
-                }
-                KeyCode::Char('D') => {
-                    self.book.clear_current_cell_all()?;
-                }
-                KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.book.select_prev_sheet();
-                        Ok(())
-                    })?;
-                }
-                KeyCode::Char('l') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        let Address {
-                            sheet: _,
-                            row: _,
-                            col,
-                        } = &ws.book.location;
-                        ws.book
-                            .set_col_size(*col, ws.book.get_col_size(*col)? + 1)?;
-                        Ok(())
-                    })?;
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'"') => {
+                    self.select_register(c);
+                    return Ok(None);
                 }
-                KeyCode::Char('h') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        let Address {
-                            sheet: _,
-                            row: _,
-                            col,
-                        } = &ws.book.location;
-                        let curr_size = ws.book.get_col_size(*col)?;
-                        if curr_size > 1 {
-                            ws.book.set_col_size(*col, curr_size - 1)?;
-                        }
-                        Ok(())
-                    })?;
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'q') => {
+                    self.state.char_queue.clear();
+                    self.start_recording(c);
+                    return Ok(None);
                 }
-                KeyCode::Char('q') => {
-                    return self.quit_app();
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'@') => {
+                    self.state.char_queue.clear();
+                    return self.play_macro(c);
                 }
-                KeyCode::Char('j') | KeyCode::Down if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_down()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'m') => {
+                    self.state.char_queue.clear();
+                    self.set_mark(c);
+                    return Ok(None);
                 }
-                KeyCode::Enter if key.modifiers != KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_down()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char(c) if self.state.char_queue.first() == Some(&'`') => {
+                    self.state.char_queue.clear();
+                    return self.jump_to_mark(c).map(|_| None);
                 }
-                KeyCode::Enter if key.modifiers == KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_up()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                // Any other key cancels a pending `q`, `@`, `m` or `` ` ``.
+                _ if matches!(self.state.char_queue.first(), Some('q' | '@' | 'm' | '`')) => {
+                    self.state.char_queue.clear();
+                    self.state.reset_n_prefix();
+                    return Ok(None);
                 }
-                KeyCode::Char('k') | KeyCode::Up if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_up()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                KeyCode::Char(d) if d.is_ascii_digit() => {
+                    self.handle_numeric_prefix(d);
+                    return Ok(None);
                 }
-                KeyCode::Char('h') | KeyCode::Left if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_left()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                _ => (),
+            }
+            match self.lookup_key(&Modality::Navigate, &key) {
+                Some(action) => return self.run_navigate_action(action),
+                None => {
+                    // noop
+                    self.state.char_queue.clear();
                 }
-                KeyCode::Char('l') | KeyCode::Right if key.modifiers != KeyModifiers::CONTROL => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_right()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+            }
+        }
+        return Ok(None);
+    }
+
+    fn run_navigate_action(&mut self, action: Action) -> Result<Option<ExitCode>> {
+        if action.mutates() && self.refuse_if_read_only() {
+            return Ok(None);
+        }
+        match action {
+            Action::Cancel => {
+                self.state.reset_n_prefix();
+                self.state.char_queue.clear();
+                self.state.register = None;
+            }
+            Action::RegisterPrefix => {
+                self.state.char_queue.clear();
+                self.state.char_queue.push('"');
+            }
+            Action::Increment | Action::Decrement => {
+                let count = self.state.get_n_prefix() as f64;
+                self.state.reset_n_prefix();
+                let delta = if action == Action::Increment {
+                    count
+                } else {
+                    -count
+                };
+                if self.increment_cells(delta)? {
+                    self.state.last_change = Some(LastChange::Increment(delta));
                 }
-                KeyCode::Tab if key.modifiers != KeyModifiers::SHIFT => {
-                    self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_right()?;
-                        ws.handle_movement_change();
-                        Ok(())
-                    })?;
+                self.handle_movement_change();
+            }
+            Action::ToggleBold => {
+                let address = self.book.location.clone();
+                let style = self.book.get_cell_style(&address).map(|s| s.font.b);
+                self.toggle_bool_style(style, "font.b", &address)?;
+                self.state.last_change = Some(LastChange::ToggleBold);
+            }
+            Action::ToggleItalic => {
+                let address = self.book.location.clone();
+                let style = self.book.get_cell_style(&address).map(|s| s.font.i);
+                self.toggle_bool_style(style, "font.i", &address)?;
+                self.state.last_change = Some(LastChange::ToggleItalic);
+            }
+            Action::Edit => {
+                self.enter_edit_mode();
+            }
+            Action::Command => {
+                self.enter_command_mode();
+            }
+            Action::Save => {
+                self.save_file()?;
+            }
+            Action::Substitute => {
+                self.book.clear_current_cell()?;
+                self.text_area = reset_text_area(String::new());
+                self.enter_edit_mode();
+            }
+            Action::StartFormula => {
+                // Only the editor is replaced so `Esc` leaves the cell as it was.
+                self.text_area = reset_text_area("=".to_string());
+                self.enter_edit_mode();
+                self.state.dirty = true;
+                self.state.point = Some((self.book.location.clone(), 0));
+            }
+            Action::RangeSelect => {
+                self.enter_range_select_mode(false);
+            }
+            Action::Copy => {
+                self.copy_cell_to_clipboard(false)?;
+            }
+            Action::CopyRow | Action::CopyColumn => {
+                let count = self.state.get_n_prefix();
+                self.state.reset_n_prefix();
+                self.yank_lines(action == Action::CopyColumn, count)?;
+            }
+            Action::CopyFormatted => {
+                self.copy_cell_to_clipboard(true)?;
+            }
+            Action::Visual => self.enter_range_select_mode(true),
+            Action::SelectRows => {
+                self.enter_range_select_mode(true);
+                self.toggle_selection_mode(false)?;
+            }
+            Action::Paste => {
+                self.paste_range()?;
+            }
+            Action::Help => {
+                self.enter_dialog_mode(self.render_help_text());
+            }
+            Action::Keys => {
+                self.enter_dialog_mode(self.render_keys());
+            }
+            Action::Peek => {
+                self.enter_peek_mode()?;
+            }
+            Action::Recalc => {
+                self.recalculate();
+            }
+            Action::NextError => {
+                self.move_to_error(true)?;
+            }
+            Action::RecordMacro => {
+                self.state.char_queue.clear();
+                match self.state.recording.take() {
+                    Some((name, keys)) => {
+                        self.state.macros.insert(name, keys);
+                        self.state.set_status(format!("Recorded macro @{}", name));
+                    }
+                    None => self.state.char_queue.push('q'),
                 }
-                KeyCode::Tab if key.modifiers == KeyModifiers::SHIFT => {
+            }
+            Action::PlayMacro => {
+                self.state.char_queue.clear();
+                self.state.char_queue.push('@');
+            }
+            Action::PrevError => {
+                self.move_to_error(false)?;
+            }
+            Action::NextSheet => {
+                self.push_jump();
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_next_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::PrevSheet => {
+                self.push_jump();
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.book.select_prev_sheet();
+                    Ok(())
+                })?;
+            }
+            Action::WriteQuit => {
+                return self.run_command(Cmd::WriteQuit(None), None);
+            }
+            Action::ForceQuit => {
+                return self.run_command(Cmd::ForceQuit, None);
+            }
+            Action::SetMark => {
+                self.state.char_queue.clear();
+                self.state.char_queue.push('m');
+            }
+            Action::JumpToMark => {
+                self.state.char_queue.clear();
+                self.state.char_queue.push('`');
+            }
+            Action::JumpBack => {
+                self.state.reset_n_prefix();
+                self.jump_back()?;
+            }
+            Action::JumpForward => {
+                self.state.reset_n_prefix();
+                self.jump_forward()?;
+            }
+            Action::ClearCell => {
+                self.book.clear_current_cell()?;
+                self.state.last_change = Some(LastChange::ClearCell);
+            }
+            Action::ClearRow | Action::ClearRowAll | Action::ClearColumn => {
+                let count = self.state.get_n_prefix();
+                self.state.reset_n_prefix();
+                let columns = action == Action::ClearColumn;
+                self.clear_lines(columns, count, action == Action::ClearRowAll)?;
+            }
+            Action::ClearCellAll => {
+                self.book.clear_current_cell_all()?;
+                self.state.last_change = Some(LastChange::ClearCellAll);
+            }
+            Action::RepeatChange => {
+                if let Some(change) = self.state.last_change.clone() {
                     self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
-                        ws.move_left()?;
-                        ws.handle_movement_change();
-                        Ok(())
+                        ws.repeat_change(&change)
                     })?;
+                } else {
+                    self.state.reset_n_prefix();
                 }
-                KeyCode::Char('g') => {
-                    // TODO(zaphar): This really needs a better state machine.
-                    if self
-                        .state
-                        .char_queue
-                        .first()
-                        .map(|c| *c == 'g')
-                        .unwrap_or(false)
-                    {
-                        self.state.char_queue.pop();
+            }
+            Action::GrowColumn => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    let Address {
+                        sheet: _,
+                        row: _,
+                        col,
+                    } = &ws.book.location;
+                    ws.book
+                        .set_col_size(*col, ws.book.get_col_size(*col)? + 1)?;
+                    Ok(())
+                })?;
+            }
+            Action::ShrinkColumn => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    let Address {
+                        sheet: _,
+                        row: _,
+                        col,
+                    } = &ws.book.location;
+                    let curr_size = ws.book.get_col_size(*col)?;
+                    if curr_size > 1 {
+                        ws.book.set_col_size(*col, curr_size - 1)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Action::Quit => {
+                return self.quit_app();
+            }
+            Action::MoveDown => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_down()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveUp => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_up()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveLeft => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_left()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::MoveRight => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.move_right()?;
+                    ws.handle_movement_change();
+                    Ok(())
+                })?;
+            }
+            Action::GPrefix => {
+                // TODO(zaphar): This really needs a better state machine.
+                if self
+                    .state
+                    .char_queue
+                    .first()
+                    .map(|c| *c == 'g')
+                    .unwrap_or(false)
+                {
+                    self.state.char_queue.pop();
+                    self.push_jump();
+                    // A count picks the row rather than repeating the move.
+                    if self.state.numeric_prefix.is_empty() {
                         self.move_to_top()?;
                     } else {
-                        self.state.char_queue.push('g');
+                        let row = self.state.get_n_prefix();
+                        self.state.reset_n_prefix();
+                        self.move_to_row(row)?;
                     }
-                }
-                KeyCode::Char('o') => {
-                    self.book.insert_rows(self.book.location.row+1, 1)?;
-                    self.move_down()?;
-                    self.handle_movement_change();
-                    self.enter_edit_mode();
-                },
-                KeyCode::Char('O') => {
-                    self.book.insert_rows(self.book.location.row, 1)?;
-                    self.move_up()?;
                     self.handle_movement_change();
-                    self.enter_edit_mode();
-                },
-                _ => {
-                    // noop
-                    self.state.char_queue.clear();
+                } else {
+                    self.state.char_queue.push('g');
                 }
             }
+            Action::LastRow => {
+                let row = if self.state.numeric_prefix.is_empty() {
+                    self.book.get_last_row_in_column(self.book.location.col)?
+                } else {
+                    self.state.get_n_prefix()
+                };
+                self.state.reset_n_prefix();
+                self.push_jump();
+                self.move_to_row(row)?;
+                self.handle_movement_change();
+            }
+            Action::NextBuffer => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.next_buffer();
+                    Ok(())
+                })?;
+            }
+            Action::PrevBuffer => {
+                self.run_with_prefix(|ws: &mut Workspace<'_>| -> Result<()> {
+                    ws.prev_buffer();
+                    Ok(())
+                })?;
+            }
+            Action::InsertRowBelow => {
+                let row = self.book.location.row + 1;
+                self.insert_rows_and_edit(row)?;
+            }
+            Action::InsertRowAbove => {
+                // Inserting at the cursor pushes it down past the new rows.
+                let row = self.book.location.row;
+                self.insert_rows_and_edit(row)?;
+            }
+            Action::Select | Action::Extend => {
+                // Only meaningful in range select mode.
+                self.state.char_queue.clear();
+            }
         }
-        return Ok(None);
+        Ok(None)
     }
 
     fn toggle_bool_style(
@@ -969,50 +3036,109 @@ impl<'ws> Workspace<'ws> {
     }
 
     fn paste_range(&mut self) -> Result<(), anyhow::Error> {
-        match &self.state.clipboard {
+        if self.refuse_if_read_only() {
+            return Ok(());
+        }
+        if let Some(name) = self.state.register.take() {
+            // Pasting from a named register leaves it intact so it can be
+            // pasted again.
+            if let Some(contents) = self.state.registers.get(&name).cloned() {
+                let rows = match contents {
+                    ClipboardContents::Cell(cell) => vec![vec![cell]],
+                    ClipboardContents::Range(rows) => rows,
+                };
+                self.paste_rows(&rows)?;
+                self.state.last_change = Some(LastChange::Paste(rows));
+            }
+            return Ok(());
+        }
+        let rows = match &self.state.clipboard {
             Some(ClipboardContents::Cell(contents)) => {
                 self.book.edit_current_cell(contents)?;
                 self.book.evaluate();
+                vec![vec![contents.clone()]]
             }
-            Some(ClipboardContents::Range(ref rows)) => {
-                let Address { sheet, row, col } = self.book.location.clone();
-                let row_len = rows.len();
-                for ri in 0..row_len {
-                    let columns = &rows[ri];
-                    let col_len = columns.len();
-                    for ci in 0..col_len {
-                        self.book.update_cell(
-                            &Address {
-                                sheet,
-                                row: ri + row,
-                                col: ci + col,
-                            },
-                            columns[ci].clone(),
-                        )?;
-                    }
-                }
-                self.book.evaluate();
+            Some(ClipboardContents::Range(rows)) => {
+                let rows = rows.clone();
+                self.paste_rows(&rows)?;
+                rows
             }
             None => {
                 let rows = self.get_rows_from_system_clipboard()?;
-                let Address { sheet, row, col } = self.book.location.clone();
-                for ri in 0..rows.len() {
-                    let columns = &rows[ri];
-                    for ci in 0..columns.len() {
-                        self.book.update_cell(
-                            &Address {
-                                sheet,
-                                row: ri + row,
-                                col: ci + col,
-                            },
-                            columns[ci].clone(),
-                        )?;
-                    }
-                }
+                self.paste_rows(&rows)?;
+                rows
+            }
+        };
+        self.state.clipboard = None;
+        self.state.last_change = Some(LastChange::Paste(rows));
+        Ok(())
+    }
+
+    fn paste_rows(&mut self, rows: &[Vec<String>]) -> Result<(), anyhow::Error> {
+        let Address { sheet, row, col } = self.book.location.clone();
+        for (ri, columns) in rows.iter().enumerate() {
+            for (ci, cell) in columns.iter().enumerate() {
+                self.book.update_cell(
+                    &Address {
+                        sheet,
+                        row: ri + row,
+                        col: ci + col,
+                    },
+                    cell.clone(),
+                )?;
+            }
+        }
+        self.book.evaluate();
+        Ok(())
+    }
+
+    /// Insert as many rows as the count prefix says at `row` and start
+    /// editing the first of them.
+    fn insert_rows_and_edit(&mut self, row: usize) -> Result<()> {
+        let count = self.state.get_n_prefix();
+        self.state.reset_n_prefix();
+        self.book.insert_rows(row, count)?;
+        let Address { sheet, col, .. } = self.book.location;
+        self.book.move_to(&Address { sheet, row, col })?;
+        self.handle_movement_change();
+        self.enter_edit_mode();
+        Ok(())
+    }
+
+    /// Make `change` again at the cursor.
+    fn repeat_change(&mut self, change: &LastChange) -> Result<()> {
+        match change {
+            LastChange::ToggleBold => {
+                self.run_navigate_action(Action::ToggleBold)?;
+            }
+            LastChange::ToggleItalic => {
+                self.run_navigate_action(Action::ToggleItalic)?;
+            }
+            LastChange::ClearCell => {
+                self.book.clear_current_cell()?;
+            }
+            LastChange::ClearCellAll => {
+                self.book.clear_current_cell_all()?;
+            }
+            LastChange::ClearLines {
+                columns,
+                count,
+                all,
+            } => {
+                self.clear_lines(*columns, *count, *all)?;
+            }
+            LastChange::Paste(rows) => {
+                self.paste_rows(rows)?;
+            }
+            LastChange::EditCell(contents) => {
+                self.book.edit_current_cell(contents)?;
                 self.book.evaluate();
             }
+            LastChange::Increment(delta) => {
+                self.increment_cells(*delta)?;
+            }
         }
-        self.state.clipboard = None;
+        self.handle_movement_change();
         Ok(())
     }
 
@@ -1028,7 +3154,8 @@ impl<'ws> Workspace<'ws> {
     }
 
     fn enter_quit_mode(&mut self) -> bool {
-        if self.book.dirty {
+        // Read-only buffers can't be saved so they are never asked about.
+        if !self.unsaved_buffers().is_empty() {
             self.state.modality_stack.push(Modality::Quit);
             return true;
         }
@@ -1038,15 +3165,373 @@ impl<'ws> Workspace<'ws> {
     fn enter_command_mode(&mut self) {
         self.state.modality_stack.push(Modality::Command);
         self.state.command_state.truncate();
+        self.state.command_history_idx = None;
         *self.state.command_state.status_mut() = Status::Pending;
         self.state.command_state.focus();
     }
 
     fn enter_dialog_mode(&mut self, msg: Markdown) {
-        self.state.popup = Some(msg);
+        self.state.popup = Some(msg.with_theme(&self.theme()));
+        self.state.popup_title = None;
+        self.state.popup_max_width = None;
+        self.state.popup_targets.clear();
+        self.state.dialog_max_scroll = u16::MAX;
         self.state.modality_stack.push(Modality::Dialog);
     }
 
+    /// List `entries` in a popup numbered from 1. Typing an entry's number
+    /// jumps to its cell.
+    fn enter_jump_dialog(&mut self, title: String, entries: Vec<(Address, String)>) {
+        let width = entries.len().to_string().len();
+        let lines: Vec<Line> = entries
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, label))| Line::raw(format!("{:>width$}. {}", idx + 1, label)))
+            .collect();
+        self.enter_dialog_mode(Markdown::from_text(Text::from(lines)));
+        self.state.popup_title = Some(title);
+        self.state.popup_max_width = Some(PEEK_MAX_WIDTH);
+        self.state.dialog_scroll = 0;
+        self.state.popup_targets = entries.into_iter().map(|(addr, _)| addr).collect();
+    }
+
+    /// Pick an entry of a jump dialog by number. The number is taken as soon
+    /// as another digit couldn't make it a valid entry, otherwise on `Enter`.
+    /// Returns false for keys that aren't part of a number.
+    fn handle_jump_input(&mut self, code: KeyCode) -> Result<bool> {
+        match code {
+            KeyCode::Char(d) if d.is_ascii_digit() => {
+                self.state.numeric_prefix.push(d);
+                let num = self.typed_number();
+                if num.saturating_mul(10) > self.state.popup_targets.len() {
+                    self.jump_to_popup_target(num)?;
+                }
+                Ok(true)
+            }
+            KeyCode::Enter if !self.state.numeric_prefix.is_empty() => {
+                self.jump_to_popup_target(self.typed_number())?;
+                Ok(true)
+            }
+            _ => {
+                self.state.reset_n_prefix();
+                Ok(false)
+            }
+        }
+    }
+
+    fn typed_number(&self) -> usize {
+        self.state
+            .numeric_prefix
+            .iter()
+            .collect::<String>()
+            .parse()
+            .unwrap_or(usize::MAX)
+    }
+
+    fn jump_to_popup_target(&mut self, num: usize) -> Result<()> {
+        self.state.reset_n_prefix();
+        let Some(addr) = num
+            .checked_sub(1)
+            .and_then(|idx| self.state.popup_targets.get(idx))
+            .cloned()
+        else {
+            self.state.set_status(format!("No entry {}", num));
+            return Ok(());
+        };
+        self.exit_dialog_mode()?;
+        self.push_jump();
+        self.go_to(&addr)
+    }
+
+    /// Move to `addr` switching sheets if it is on another one.
+    fn go_to(&mut self, addr: &Address) -> Result<()> {
+        if addr.sheet != self.book.location.sheet {
+            self.book.set_current_sheet_index(addr.sheet)?;
+        }
+        self.book.move_to(addr)?;
+        self.handle_movement_change();
+        Ok(())
+    }
+
+    /// Save the current location as mark `name`.
+    fn set_mark(&mut self, name: char) {
+        self.state.reset_n_prefix();
+        if !name.is_ascii_alphabetic() {
+            self.state.set_status(format!("Invalid mark {}", name));
+            return;
+        }
+        let here = self.book.location.clone();
+        self.state
+            .set_status(format!("Marked {} as {}", here.to_range_part(), name));
+        self.state.marks.insert(name, here);
+    }
+
+    /// Move to mark `name`. A mark on a sheet that no longer exists is
+    /// dropped.
+    fn jump_to_mark(&mut self, name: char) -> Result<()> {
+        self.state.reset_n_prefix();
+        let Some(addr) = self.state.marks.get(&name).cloned() else {
+            self.state.set_status(format!("Mark {} is not set", name));
+            return Ok(());
+        };
+        if addr.sheet as usize >= self.book.get_sheet_names().len() {
+            self.state.marks.remove(&name);
+            self.state
+                .set_status(format!("Mark {} was on a deleted sheet", name));
+            return Ok(());
+        }
+        self.push_jump();
+        self.go_to(&addr)
+    }
+
+    /// Remember the current location before a long move. Jumping from the
+    /// middle of the list forgets the locations after it.
+    fn push_jump(&mut self) {
+        let here = self.book.location.clone();
+        let jumps = &mut self.state.jumps;
+        jumps.truncate(self.state.jump_idx);
+        if jumps.last() != Some(&here) {
+            jumps.push(here);
+        }
+        if jumps.len() > JUMP_LIST_LIMIT {
+            jumps.remove(0);
+        }
+        self.state.jump_idx = jumps.len();
+    }
+
+    /// Go back to where the last long move started.
+    fn jump_back(&mut self) -> Result<()> {
+        if self.state.jump_idx >= self.state.jumps.len() {
+            // Remember where we are so jumping forward comes back here.
+            let here = self.book.location.clone();
+            if self.state.jumps.last() != Some(&here) {
+                self.state.jumps.push(here);
+            }
+            self.state.jump_idx = self.state.jumps.len() - 1;
+        }
+        if self.state.jump_idx == 0 {
+            self.state.set_status("Already at the oldest jump");
+            return Ok(());
+        }
+        self.state.jump_idx -= 1;
+        let addr = self.state.jumps[self.state.jump_idx].clone();
+        self.go_to(&addr)
+    }
+
+    /// Undo a [`Workspace::jump_back`].
+    fn jump_forward(&mut self) -> Result<()> {
+        if self.state.jump_idx + 1 >= self.state.jumps.len() {
+            self.state.set_status("Already at the newest jump");
+            return Ok(());
+        }
+        self.state.jump_idx += 1;
+        let addr = self.state.jumps[self.state.jump_idx].clone();
+        self.go_to(&addr)
+    }
+
+    /// List the error cells of the current sheet.
+    fn enter_errors_dialog(&mut self) -> Result<()> {
+        let errors = self.book.get_error_cells()?;
+        if errors.is_empty() {
+            self.state.set_status("No error cells");
+            return Ok(());
+        }
+        let mut entries = Vec::with_capacity(errors.len());
+        for addr in errors {
+            let label = format!(
+                "{} {}",
+                addr.to_range_part(),
+                self.book.get_cell_addr_rendered(&addr)?
+            );
+            entries.push((addr, label));
+        }
+        let title = format!("Errors ({})", entries.len());
+        self.enter_jump_dialog(title, entries);
+        Ok(())
+    }
+
+    /// An address for a listing. Cells on other sheets than the current one
+    /// get a sheet prefix.
+    fn listing_name(&self, addr: &Address) -> String {
+        if addr.sheet == self.book.location.sheet {
+            return addr.to_range_part();
+        }
+        let names = self.book.get_sheet_names();
+        match names.get(addr.sheet as usize) {
+            Some(name) => format!("{}!{}", name, addr.to_range_part()),
+            None => addr.to_range_part(),
+        }
+    }
+
+    /// List the cells the current cell's formula refers to.
+    fn enter_precedents_dialog(&mut self) -> Result<()> {
+        let here = self.book.location.clone();
+        let entries: Vec<(Address, String)> = self
+            .book
+            .get_precedents(&here)?
+            .into_iter()
+            .map(|(start, end)| {
+                let mut label = self.listing_name(&start);
+                if start != end {
+                    label = format!("{}:{}", label, end.to_range_part());
+                }
+                (start, label)
+            })
+            .collect();
+        if entries.is_empty() {
+            self.state
+                .set_status(format!("{} has no precedents", here.to_range_part()));
+            return Ok(());
+        }
+        self.enter_jump_dialog(format!("Precedents of {}", here.to_range_part()), entries);
+        Ok(())
+    }
+
+    /// List the cells with formulas referring to the current cell.
+    fn enter_dependents_dialog(&mut self) -> Result<()> {
+        let here = self.book.location.clone();
+        let mut entries = Vec::new();
+        for addr in self.book.get_dependents(&here)? {
+            let label = format!(
+                "{} {}",
+                self.listing_name(&addr),
+                self.book.get_cell_addr_contents(&addr)?
+            );
+            entries.push((addr, label));
+        }
+        if entries.is_empty() {
+            self.state
+                .set_status(format!("{} has no dependents", here.to_range_part()));
+            return Ok(());
+        }
+        self.enter_jump_dialog(format!("Dependents of {}", here.to_range_part()), entries);
+        Ok(())
+    }
+
+    /// Start recording keys into the macro register `name`.
+    fn start_recording(&mut self, name: char) {
+        if !name.is_ascii_alphanumeric() {
+            self.state
+                .set_status(format!("Invalid macro register {}", name));
+            return;
+        }
+        self.state.reset_n_prefix();
+        self.state.recording = Some((name, Vec::new()));
+    }
+
+    /// Replay the macro in register `name`, or the last replayed macro for
+    /// `@`, as many times as the count prefix says.
+    fn play_macro(&mut self, name: char) -> Result<Option<ExitCode>> {
+        let name = match (name, self.state.last_macro) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.state.reset_n_prefix();
+                self.state.set_status("No macro replayed yet");
+                return Ok(None);
+            }
+            (name, _) => name,
+        };
+        let keys = match self.state.macros.get(&name) {
+            Some(keys) => keys.clone(),
+            None => {
+                self.state.reset_n_prefix();
+                self.state
+                    .set_status(format!("No macro in register {}", name));
+                return Ok(None);
+            }
+        };
+        if self.state.replay_depth >= MACRO_DEPTH_LIMIT {
+            self.state.replay_aborted = true;
+            return Err(anyhow!(
+                "Stopped replaying macro @{} since it replays macros too deeply",
+                name
+            ));
+        }
+        self.state.last_macro = Some(name);
+        let count = self.state.get_n_prefix();
+        self.state.reset_n_prefix();
+        self.state.replay_depth += 1;
+        let mut result = Ok(None);
+        'replay: for _ in 0..count {
+            for evt in keys.iter() {
+                if self.state.replay_aborted {
+                    break 'replay;
+                }
+                match self.handle_input(evt.clone()) {
+                    Ok(None) => (),
+                    other => {
+                        result = other;
+                        break 'replay;
+                    }
+                }
+            }
+        }
+        self.state.replay_depth -= 1;
+        if self.state.replay_depth == 0 {
+            self.state.replay_aborted = false;
+        }
+        result
+    }
+
+    /// Move to the next cell holding an error, or the previous one when
+    /// `forward` is false, wrapping around the sheet.
+    fn move_to_error(&mut self, forward: bool) -> Result<()> {
+        let errors = self.book.get_error_cells()?;
+        if errors.is_empty() {
+            self.state.set_status("No error cells");
+            return Ok(());
+        }
+        let here = (self.book.location.row, self.book.location.col);
+        let idx = if forward {
+            errors
+                .iter()
+                .position(|addr| (addr.row, addr.col) > here)
+                .unwrap_or(0)
+        } else {
+            errors
+                .iter()
+                .rposition(|addr| (addr.row, addr.col) < here)
+                .unwrap_or(errors.len() - 1)
+        };
+        self.push_jump();
+        self.book.move_to(&errors[idx])?;
+        self.handle_movement_change();
+        self.state
+            .set_status(format!("{} of {} errors", idx + 1, errors.len()));
+        Ok(())
+    }
+
+    /// Show the full value and contents of the current cell in a popup sized
+    /// to fit them.
+    fn enter_peek_mode(&mut self) -> Result<()> {
+        let address = format!(
+            "{}!{}",
+            self.book.get_sheet_name()?,
+            self.book.location.to_range_part()
+        );
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::styled("Value", heading)];
+        lines.extend(
+            self.book
+                .get_current_cell_rendered()?
+                .lines()
+                .map(|l| Line::raw(l.to_string())),
+        );
+        lines.push(Line::default());
+        lines.push(Line::styled("Contents", heading));
+        lines.extend(
+            self.book
+                .get_current_cell_contents()?
+                .lines()
+                .map(|l| Line::raw(l.to_string())),
+        );
+        self.enter_dialog_mode(Markdown::from_text(Text::from(lines)));
+        self.state.popup_title = Some(address);
+        self.state.popup_max_width = Some(PEEK_MAX_WIDTH);
+        self.state.dialog_scroll = 0;
+        Ok(())
+    }
+
     fn enter_range_select_mode(&mut self, init_start: bool) {
         self.state.range_select.original_location = Some(self.book.location.clone());
         if init_start {
@@ -1055,10 +3540,63 @@ impl<'ws> Workspace<'ws> {
             self.state.range_select.start = None;
         }
         self.state.range_select.end = None;
+        self.state.range_select.mode = SelectionMode::Cells;
         self.state.modality_stack.push(Modality::RangeSelect);
     }
 
+    /// Switch a range selection to whole rows or columns, or back to cells
+    /// if it already is.
+    fn toggle_selection_mode(&mut self, columns: bool) -> Result<()> {
+        let range = &mut self.state.range_select;
+        if range.start.is_none() {
+            range.start = Some(self.book.location.clone());
+        }
+        if range.end.is_none() {
+            range.end = Some(self.book.location.clone());
+        }
+        let (row_count, col_count) = self.book.get_size()?;
+        range.mode = match (range.mode, columns) {
+            (SelectionMode::Rows(_), false) | (SelectionMode::Columns(_), true) => {
+                SelectionMode::Cells
+            }
+            (_, false) => SelectionMode::Rows(col_count.max(1)),
+            (_, true) => SelectionMode::Columns(row_count.max(1)),
+        };
+        Ok(())
+    }
+
+    /// Move the cursor to the other end of the range selection so it can be
+    /// grown from there. With `column_only` just the column is swapped which
+    /// moves to the other corner on the same row.
+    fn swap_range_anchor(&mut self, column_only: bool) -> Result<()> {
+        let Some(anchor) = self.state.range_select.start.clone() else {
+            return Ok(());
+        };
+        let cursor = self.book.location.clone();
+        let (new_anchor, new_cursor) = if column_only {
+            (
+                Address {
+                    col: cursor.col,
+                    ..anchor.clone()
+                },
+                Address {
+                    col: anchor.col,
+                    ..cursor
+                },
+            )
+        } else {
+            (cursor, anchor)
+        };
+        self.book.move_to(&new_cursor)?;
+        self.state.range_select.start = Some(new_anchor);
+        self.maybe_update_range_end();
+        Ok(())
+    }
+
     fn enter_edit_mode(&mut self) {
+        if self.refuse_if_read_only() {
+            return;
+        }
         self.state.modality_stack.push(Modality::CellEdit);
         self.text_area
             .set_cursor_line_style(Style::default().add_modifier(Modifier::UNDERLINED));
@@ -1066,6 +3604,18 @@ impl<'ws> Workspace<'ws> {
             .set_cursor_style(Style::default().add_modifier(Modifier::SLOW_BLINK));
         self.text_area.move_cursor(CursorMove::Bottom);
         self.text_area.move_cursor(CursorMove::End);
+        self.text_area
+            .set_block(Block::bordered().title_top(self.edit_title()));
+    }
+
+    /// The address of the cell being edited for the editor's border. Cells
+    /// past the first sheet are prefixed with their sheet, e.g. `Sheet2!C7`.
+    fn edit_title(&self) -> String {
+        let location = &self.book.location;
+        match self.book.get_sheet_name() {
+            Ok(name) if location.sheet != 0 => format!("{}!{}", name, location.to_range_part()),
+            _ => location.to_range_part(),
+        }
     }
 
     fn exit_quit_mode(&mut self) -> Result<Option<ExitCode>> {
@@ -1078,7 +3628,18 @@ impl<'ws> Workspace<'ws> {
         self.state.command_state.blur();
         *self.state.command_state.status_mut() = Status::Done;
         self.state.pop_modality();
-        self.handle_command(cmd)
+        self.state.push_command_history(&cmd);
+        // Commands typed while selecting a range act on the selection.
+        let selection = self.get_active_range();
+        let result = self.handle_command(cmd, selection)?;
+        Ok(self.maybe_finish_quit(result))
+    }
+
+    /// Leave command mode without running the command.
+    fn cancel_command_mode(&mut self) {
+        self.state.command_state.blur();
+        *self.state.command_state.status_mut() = Status::Aborted;
+        self.state.pop_modality();
     }
 
     fn exit_dialog_mode(&mut self) -> Result<()> {
@@ -1109,11 +3670,21 @@ impl<'ws> Workspace<'ws> {
         self.text_area.set_cursor_style(Style::default());
         let contents = self.text_area.lines().join("\n");
         if self.state.dirty && keep {
-            self.book.edit_current_cell(contents)?;
+            self.book.edit_current_cell(&contents)?;
             self.book.evaluate();
+            if contents.starts_with('=') && !book::formula::match_parens(&contents).1.is_empty() {
+                self.state.set_status(format!(
+                    "Unbalanced parentheses in {}",
+                    self.book.location.to_range_part()
+                ));
+            }
+            self.state.last_change = Some(LastChange::EditCell(contents));
         }
         self.text_area = reset_text_area(self.book.get_current_cell_contents()?);
         self.state.dirty = false;
+        self.state.completion = None;
+        self.state.point = None;
+        self.state.formula_preview = None;
         self.state.pop_modality();
         Ok(())
     }
@@ -1124,26 +3695,123 @@ impl<'ws> Workspace<'ws> {
             .get_current_cell_contents()
             .expect("Unexpected failure getting current cell contents");
         self.text_area = reset_text_area(contents);
+        self.text_area
+            .set_block(Block::bordered().title_top(self.edit_title()));
+    }
+
+    /// Save the book to its current file.
+    ///
+    /// Returns false if the file changed on disk since we loaded it. In that
+    /// case nothing is written and the user is asked what to do instead.
+    fn save_file(&mut self) -> Result<bool> {
+        if self.refuse_if_read_only() {
+            return Ok(false);
+        }
+        let path = self.current_path();
+        if self.disk_stamp.is_some() {
+            if let Some(current) = disk_stamp(&path) {
+                if Some(current) != self.disk_stamp {
+                    self.state.pending_action = Some(PendingAction::FileChanged);
+                    self.enter_dialog_mode(Markdown::from_str(&format!(
+                        "# File changed on disk\n\n`{}` was modified after it was loaded.\n\n* `o` overwrite it with your changes\n* `r` reload it from disk and discard your local edits\n* `Esc` cancel",
+                        path.display()
+                    )));
+                    return Ok(false);
+                }
+            }
+        }
+        self.write_file()?;
+        Ok(true)
     }
 
-    fn save_file(&mut self) -> Result<()> {
+    fn write_file(&mut self) -> Result<()> {
         // If a file_path was previously set (i.e. the book was loaded from or
         // saved-as a concrete path), use save() to update it in place.
         // Otherwise fall back to save_as(&self.name) which also sets file_path
         // so that subsequent `:w` calls use save() directly.
+        let path = self.current_path();
+        self.backup_file(&path)?;
         if self.book.get_file_path().is_some() {
             self.book.save()?;
         } else {
             self.book.save_as(&self.name)?;
         }
+        self.report_saved();
         Ok(())
     }
 
     fn save_to<S: Into<String>>(&mut self, path: S) -> Result<()> {
-        self.book.save_as(path.into().as_str())?;
+        let path: String = path.into();
+        self.backup_file(&PathBuf::from(&path))?;
+        self.book.save_as(path.as_str())?;
+        self.report_saved();
+        Ok(())
+    }
+
+    /// Save to `path` asking first if that would overwrite some other existing
+    /// file.
+    ///
+    /// Returns false if we are waiting on the user to confirm the overwrite.
+    fn save_to_new_path(&mut self, path: &str, quit: bool) -> Result<bool> {
+        let target = PathBuf::from(path);
+        if target.exists()
+            && !same_file(&target, &self.current_path())
+            && !same_file(&target, &self.name)
+        {
+            self.enter_dialog_mode(Markdown::from_str(&format!(
+                "# Overwrite file?\n\n`{}` exists, overwrite? y/n",
+                target.display()
+            )));
+            self.state.pending_action = Some(PendingAction::Overwrite { path: target, quit });
+            return Ok(false);
+        }
+        self.save_to(path)?;
+        Ok(true)
+    }
+
+    /// The path the book will be saved to by `save_file`.
+    fn current_path(&self) -> PathBuf {
+        self.book
+            .get_file_path()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| self.name.clone())
+    }
+
+    /// Copy an existing file to `<path>.bak` if backups are turned on.
+    fn backup_file(&self, path: &PathBuf) -> Result<()> {
+        if self.state.settings.backup && path.is_file() {
+            let mut backup = path.clone().into_os_string();
+            backup.push(".bak");
+            std::fs::copy(path, &backup)
+                .map_err(|e| anyhow!("Failed to write backup {:?}: {}", backup, e))?;
+        }
+        Ok(())
+    }
+
+    /// Save the book if autosave is on and the interval has passed since the
+    /// last save.
+    fn maybe_autosave(&mut self) -> Result<()> {
+        let interval = self.state.settings.autosave;
+        if interval == 0
+            || self.read_only
+            || !self.book.dirty
+            || self.book.get_file_path().is_none()
+            || self.state.modality() != &Modality::Navigate
+            || self.last_autosave.elapsed() < Duration::from_secs(interval)
+        {
+            return Ok(());
+        }
+        self.save_file()?;
         Ok(())
     }
 
+    fn report_saved(&mut self) {
+        let path = self.current_path();
+        self.disk_stamp = disk_stamp(&path);
+        self.last_autosave = Instant::now();
+        self.state.set_status(format!("Wrote {}", path.display()));
+    }
+
     fn quit_app(&mut self) -> std::result::Result<Option<ExitCode>, anyhow::Error> {
         if self.enter_quit_mode() {
             return Ok(None);
@@ -1152,11 +3820,32 @@ impl<'ws> Workspace<'ws> {
     }
 }
 
+/// The modification time and size of a file if it exists.
+fn disk_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        word.to_string()
+    } else {
+        format!("{}s", word)
+    }
+}
+
 fn load_book(path: &PathBuf, locale: &str, tz: &str) -> Result<Book, anyhow::Error> {
     let book = if path.exists() {
         Book::load(path, locale, tz)?
     } else {
-        Book::default()
+        Book::new_with_locale(locale, tz)?
     };
     Ok(book)
 }
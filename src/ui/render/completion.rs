@@ -0,0 +1,143 @@
+//! Function name completion while editing a formula.
+use ratatui::{
+    self,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use super::theme::Theme;
+
+/// The spreadsheet functions offered for completion, separated by spaces.
+const FUNCTION_NAMES: &str = "\
+    ABS ACOS ACOSH ACOT ACOTH ADDRESS AND ASIN ASINH ATAN ATAN2 ATANH AVERAGE AVERAGEA \
+    AVERAGEIF AVERAGEIFS BIN2DEC BIN2HEX BIN2OCT CEILING CHAR CHOOSE CLEAN CODE COLUMN \
+    COLUMNS CONCAT CONCATENATE CONVERT COS COSH COT COTH COUNT COUNTA COUNTBLANK COUNTIF \
+    COUNTIFS CSC CSCH CUMIPMT CUMPRINC DATE DATEDIF DATEVALUE DAY DAYS DB DDB DEC2BIN \
+    DEC2HEX DEC2OCT DEGREES DELTA DOLLARDE DOLLARFR EDATE EFFECT EOMONTH ERF ERFC EVEN \
+    EXACT EXP FACT FACTDOUBLE FALSE FIND FLOOR FV GCD GESTEP HEX2BIN HEX2DEC HEX2OCT \
+    HLOOKUP HOUR IF IFERROR IFNA IFS INDEX INDIRECT INT IPMT IRR ISBLANK ISERR ISERROR \
+    ISEVEN ISFORMULA ISLOGICAL ISNA ISNONTEXT ISNUMBER ISODD ISOWEEKNUM ISPMT ISREF ISTEXT \
+    LCM LEFT LEN LN LOG LOG10 LOOKUP LOWER MATCH MAX MAXA MAXIFS MEDIAN MID MIN MINA \
+    MINIFS MINUTE MIRR MOD MONTH MROUND NETWORKDAYS NOMINAL NOT NOW NPER NPV OCT2BIN \
+    OCT2DEC OCT2HEX ODD OFFSET OR PI PMT POWER PPMT PRODUCT PROPER PV QUOTIENT RADIANS \
+    RAND RANDBETWEEN RATE REPLACE REPT RIGHT ROUND ROUNDDOWN ROUNDUP ROW ROWS RRI SEARCH \
+    SEC SECH SECOND SIGN SIN SINH SLN SQRT SQRTPI STDEV SUBSTITUTE SUBTOTAL SUM SUMIF \
+    SUMIFS SUMPRODUCT SUMSQ SWITCH SYD T TAN TANH TBILLEQ TBILLPRICE TBILLYIELD TEXT \
+    TEXTAFTER TEXTBEFORE TEXTJOIN TIME TIMEVALUE TODAY TRIM TRUE TRUNC TYPE UNICODE UPPER \
+    VALUE VLOOKUP WEEKDAY WEEKNUM WORKDAY XLOOKUP XOR YEAR YEARFRAC";
+
+/// The most completions shown at once.
+const MAX_VISIBLE: usize = 8;
+
+/// The function names starting with `prefix` ignoring case. Shorter names come
+/// first so an exact match is always on top.
+pub fn matching_functions(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.to_uppercase();
+    let mut names: Vec<&'static str> = FUNCTION_NAMES
+        .split_whitespace()
+        .filter(|name| name.starts_with(&prefix))
+        .collect();
+    names.sort_by_key(|name| (name.len(), *name));
+    names
+}
+
+/// An open completion list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletionState {
+    pub items: Vec<&'static str>,
+    pub selected: usize,
+    /// How many characters before the cursor the completion replaces.
+    pub prefix_len: usize,
+}
+
+impl CompletionState {
+    pub fn new(items: Vec<&'static str>, prefix_len: usize) -> Self {
+        Self {
+            items,
+            selected: 0,
+            prefix_len,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.items.len();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+    }
+
+    pub fn selected_item(&self) -> &'static str {
+        self.items[self.selected]
+    }
+}
+
+/// A small list of completions drawn just under the cell editor.
+pub struct Completion<'s> {
+    state: &'s CompletionState,
+    style: Style,
+}
+
+impl<'s> Completion<'s> {
+    pub fn new(state: &'s CompletionState) -> Self {
+        Self {
+            state,
+            style: Theme::default().dialog_style(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.style = theme.dialog_style();
+        self
+    }
+
+    /// Where the list goes for an editor drawn in `anchor`. It hangs below the
+    /// editor and is kept inside `bounds`.
+    pub fn area(&self, anchor: Rect, bounds: Rect) -> Rect {
+        let width = self
+            .state
+            .items
+            .iter()
+            .map(|item| item.len())
+            .max()
+            .unwrap_or(0) as u16
+            + 2;
+        let height = self.state.items.len().min(MAX_VISIBLE) as u16 + 2;
+        let x = anchor.x.saturating_add(1);
+        let y = anchor.bottom().saturating_sub(1);
+        Rect::new(x, y, width, height).intersection(bounds)
+    }
+}
+
+impl<'s> Widget for Completion<'s> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        // Keep the selected item in view.
+        let first = (self.state.selected + 1).saturating_sub(MAX_VISIBLE);
+        let lines: Vec<Line> = self
+            .state
+            .items
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(MAX_VISIBLE)
+            .map(|(idx, item)| {
+                let line = Line::from(*item);
+                if idx == self.state.selected {
+                    line.style(Style::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .style(self.style)
+            .block(Block::bordered().style(self.style))
+            .render(area, buf);
+    }
+}
@@ -1,16 +1,30 @@
 use ratatui::{
     self,
-    layout::{Constraint, Layout, Rect},
+    layout::{Margin, Rect},
     style::Style,
     text::Text,
-    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+    widgets::{
+        Block, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget,
+        Widget,
+    },
 };
 
+use super::{markdown::wrap_text, theme::Theme};
+
+/// The narrowest a dialog is drawn if there is room.
+const MIN_WIDTH: u16 = 30;
+
+/// The space always left around a dialog.
+const MARGIN: u16 = 2;
+
 pub struct Dialog<'w> {
     content: Text<'w>,
     title: &'w str,
     bottom_title: &'w str,
     scroll: (u16, u16),
+    style: Style,
+    /// The widest the dialog gets even if the screen has room for more.
+    max_width: Option<u16>,
     // TODO(zaphar): Have a max margin?
 }
 
@@ -19,11 +33,23 @@ impl<'w> Dialog<'w> {
         Self {
             content,
             title,
-            bottom_title: "j,k or up,down to scroll",
+            bottom_title: "j,k or PgUp,PgDn to scroll",
             scroll: (0, 0),
+            style: Theme::default().dialog_style(),
+            max_width: None,
         }
     }
 
+    pub fn with_max_width(mut self, width: u16) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.style = theme.dialog_style();
+        self
+    }
+
     pub fn with_bottom_title(mut self, title: &'w str) -> Self {
         self.bottom_title = title;
         self
@@ -32,6 +58,45 @@ impl<'w> Dialog<'w> {
         self.scroll.0 = line;
         self
     }
+
+    /// How many rows of content show at once when drawn in `area` and the
+    /// furthest the content can be scrolled.
+    pub fn viewport(&self, area: Rect) -> (u16, u16) {
+        let dialog_area = self.dialog_area(area);
+        let rows = dialog_area.height.saturating_sub(2);
+        let content = wrap_text(&self.content, dialog_area.width.saturating_sub(2) as usize);
+        let height = u16::try_from(content.height()).unwrap_or(u16::MAX);
+        (rows, height.saturating_sub(rows))
+    }
+
+    /// Where the dialog goes, centered in `area`.
+    fn dialog_area(&self, area: Rect) -> Rect {
+        // Wide enough for the longest line and the titles but no wider than
+        // the area leaves room for. Long lines wrap onto more rows.
+        let available = area.width.saturating_sub(MARGIN * 2);
+        let max = self.max_width.map_or(available, |max| max.min(available));
+        let widest = self
+            .content
+            .width()
+            .max(self.title.chars().count())
+            .max(self.bottom_title.chars().count());
+        let width = u16::try_from(widest)
+            .unwrap_or(u16::MAX)
+            .saturating_add(2)
+            .max(MIN_WIDTH)
+            .min(max);
+        let height = wrap_text(&self.content, width.saturating_sub(2) as usize).height();
+        let height = u16::try_from(height)
+            .unwrap_or(u16::MAX)
+            .saturating_add(2)
+            .min(area.height.saturating_sub(MARGIN * 2));
+        Rect::new(
+            area.x + area.width.saturating_sub(width) / 2,
+            area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        )
+    }
 }
 
 impl<'w> Widget for Dialog<'w> {
@@ -39,47 +104,32 @@ impl<'w> Widget for Dialog<'w> {
     where
         Self: Sized,
     {
-        // First find the center of the area.
-        let content_width = 120 + 2;
-        let content_height = (self.content.height() + 2) as u16;
-        let vertical_margin = if content_height <= area.height {
-            area.height
-                .saturating_sub(content_height as u16)
-                .saturating_div(2)
-        } else {
-            2
-        };
-        let horizontal_margin = if content_width <= area.width {
-            area
-            .width
-            .saturating_sub(content_width as u16)
-            .saturating_div(2)
-        } else {
-           2
-        };
-        let [_, dialog_vertical, _] = Layout::vertical(vec![
-            Constraint::Length(vertical_margin),
-            Constraint::Fill(1),
-            Constraint::Length(vertical_margin),
-        ])
-        .areas(area);
-        let [_, dialog_area, _] = Layout::horizontal(vec![
-            Constraint::Length(horizontal_margin),
-            Constraint::Fill(1),
-            Constraint::Length(horizontal_margin),
-        ])
-        .areas(dialog_vertical);
-
+        let dialog_area = self.dialog_area(area);
+        let (rows, max_scroll) = self.viewport(area);
+        let scroll = self.scroll.0.min(max_scroll);
         Clear.render(dialog_area, buf);
         let dialog_block = Block::bordered()
             .title_top(self.title)
             .title_bottom(self.bottom_title)
-            .style(Style::default().on_black());
-        let dialog = Paragraph::new(self.content.clone())
-            .wrap(Wrap::default())
-            .scroll(self.scroll.clone())
+            .style(self.style);
+        // Wrapping here rather than in the paragraph keeps the indent of
+        // wrapped list items.
+        let content = wrap_text(&self.content, dialog_area.width.saturating_sub(2) as usize);
+        let dialog = Paragraph::new(content)
+            .scroll((scroll, self.scroll.1))
             .block(dialog_block)
             .style(Style::default());
         dialog.render(dialog_area, buf);
+        if max_scroll > 0 {
+            // Drawn over the right border.
+            let mut state = ScrollbarState::new(max_scroll as usize + 1)
+                .position(scroll as usize)
+                .viewport_content_length(rows as usize);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight).render(
+                dialog_area.inner(Margin::new(0, 1)),
+                buf,
+                &mut state,
+            );
+        }
     }
 }
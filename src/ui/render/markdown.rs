@@ -1,5 +1,3 @@
-use std::collections::BTreeSet;
-
 use crossterm::event::KeyCode;
 use ratatui::{
     style::{Color, Modifier, Style},
@@ -7,13 +5,21 @@ use ratatui::{
     widgets::Widget,
 };
 
-use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use pulldown_cmark::{Alignment, Event, LinkType, Options, Parser, Tag, TagEnd};
+
+use super::theme::Theme;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Markdown {
     input: String,
-    links: BTreeSet<String>,
+    /// Link destinations in the order they first appear. The link numbered
+    /// `[1]` in the text is the first.
+    links: Vec<String>,
     parsed_text: Option<Text<'static>>,
+    /// The color of inline code.
+    code_fg: Color,
+    /// The background behind code blocks.
+    code_block_bg: Color,
 }
 
 /// Define the different states a markdown parser can be in
@@ -23,6 +29,7 @@ enum MarkdownState {
     Heading(pulldown_cmark::HeadingLevel),
     Strong,
     Emphasis,
+    Strikethrough,
     Code,
     List(ListState),
 }
@@ -41,25 +48,152 @@ enum ListType {
     Unordered,
 }
 
+/// How wide a thematic break is drawn.
+const RULE_WIDTH: usize = 40;
+
+/// The widest a table cell gets before it is cut short with `…`.
+const MAX_TABLE_CELL_WIDTH: usize = 40;
+
+/// A table collected until its end so the columns can be sized to fit.
+#[derive(Debug, Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    /// The header row comes first.
+    rows: Vec<Vec<String>>,
+    cell: String,
+}
+
+impl TableState {
+    /// Lay the table out with `│` between the columns and a line under the
+    /// header.
+    fn into_lines(self) -> Vec<Line<'static>> {
+        let columns = self.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|col| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(col))
+                    .map(|cell| cell.chars().count().min(MAX_TABLE_CELL_WIDTH))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut lines = Vec::new();
+        for (idx, row) in self.rows.iter().enumerate() {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(col, width)| {
+                    let cell = truncate(row.get(col).map(|c| c.as_str()).unwrap_or(""), *width);
+                    match self.alignments.get(col) {
+                        Some(Alignment::Right) => format!("{:>width$}", cell, width = width),
+                        Some(Alignment::Center) => format!("{:^width$}", cell, width = width),
+                        _ => format!("{:<width$}", cell, width = width),
+                    }
+                })
+                .collect();
+            let text = cells.join(" │ ");
+            if idx == 0 {
+                lines.push(Line::styled(
+                    text,
+                    Style::default().add_modifier(Modifier::BOLD),
+                ));
+                let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                lines.push(Line::raw(rule.join("─┼─")));
+            } else {
+                lines.push(Line::raw(text));
+            }
+        }
+        lines
+    }
+}
+
+/// The lines of a code block as written. Each is padded to the longest so the
+/// background forms a box.
+fn code_block_lines(code: &str, bg: Color) -> Vec<Line<'static>> {
+    let code = code.replace('\t', "    ");
+    let width = code
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    code.lines()
+        .map(|line| {
+            Line::from(Span::styled(
+                format!("{:<width$}", line, width = width),
+                Style::default().bg(bg),
+            ))
+        })
+        .collect()
+}
+
+/// Cut `text` down to `width` characters ending with `…` if it is longer.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
 impl Markdown {
     pub fn from_str(input: &str) -> Self {
+        let theme = Theme::default();
         let mut me = Self {
             input: input.to_owned(),
             links: Default::default(),
             parsed_text: None,
+            code_fg: theme.code_fg,
+            code_block_bg: theme.code_block_bg,
         };
         me.parse();
         me
     }
 
+    /// Wrap text that is already laid out so it is shown as is.
+    pub fn from_text(text: Text<'static>) -> Self {
+        let theme = Theme::default();
+        Self {
+            input: String::new(),
+            links: Default::default(),
+            parsed_text: Some(text),
+            code_fg: theme.code_fg,
+            code_block_bg: theme.code_block_bg,
+        }
+    }
+
+    /// Draw the code in the theme's colors. Text that was already laid out is
+    /// left alone.
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        self.code_fg = theme.code_fg;
+        self.code_block_bg = theme.code_block_bg;
+        if !self.input.is_empty() {
+            self.parse();
+        }
+        self
+    }
+
     fn parse(&mut self) {
         let input = self.input.clone();
-        
-        let parser = pulldown_cmark::TextMergeStream::new(Parser::new(&input));
+        self.links.clear();
+
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_SUPERSCRIPT
+            | Options::ENABLE_SUBSCRIPT
+            | Options::ENABLE_TASKLISTS;
+        let parser = pulldown_cmark::TextMergeStream::new(Parser::new_ext(&input, options));
 
         let mut current_line = Line::default();
         let mut lines: Vec<Line> = Vec::new();
         let mut state_stack: Vec<MarkdownState> = vec![MarkdownState::Normal];
+        let mut link_numbers: Vec<Option<usize>> = Vec::new();
+        let mut table: Option<TableState> = None;
+        // The text of the code block being read.
+        let mut code_block: Option<String> = None;
+        // Where each open blockquote's lines start.
+        let mut quote_starts: Vec<usize> = Vec::new();
 
         for event in parser {
             match event {
@@ -96,6 +230,11 @@ impl Markdown {
                             state_stack.push(MarkdownState::Emphasis);
                         }
                         Tag::CodeBlock(_) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            code_block = Some(String::new());
                             state_stack.push(MarkdownState::Code);
                         }
                         Tag::List(list_type) => {
@@ -157,12 +296,36 @@ impl Markdown {
                             title: _,
                             id: _,
                         } => {
-                            self.handle_link_tag(&tag);
+                            link_numbers.push(self.handle_link_tag(&tag));
+                        }
+                        Tag::Table(alignments) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            table = Some(TableState {
+                                alignments: alignments.clone(),
+                                ..Default::default()
+                            });
+                        }
+                        Tag::TableHead | Tag::TableRow => {
+                            if let Some(table) = table.as_mut() {
+                                table.rows.push(Vec::new());
+                            }
                         }
-                        Tag::BlockQuote(_) => todo!(),
-                        Tag::Strikethrough => todo!(),
-                        Tag::Superscript => todo!(),
-                        Tag::Subscript => todo!(),
+                        Tag::BlockQuote(_) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            quote_starts.push(lines.len());
+                        }
+                        Tag::Strikethrough => {
+                            state_stack.push(MarkdownState::Strikethrough);
+                        }
+                        // There's no way to raise or lower text in a terminal
+                        // so these are shown inline.
+                        Tag::Superscript | Tag::Subscript => {}
                         _ => {
                             // noop
                         }
@@ -189,6 +352,58 @@ impl Markdown {
                         }
                         TagEnd::CodeBlock => {
                             state_stack.pop();
+                            if let Some(code) = code_block.take() {
+                                lines.extend(code_block_lines(&code, self.code_block_bg));
+                                lines.push(Line::default());
+                            }
+                        }
+                        TagEnd::Strikethrough => {
+                            state_stack.pop();
+                        }
+                        TagEnd::BlockQuote(_) => {
+                            if !current_line.spans.is_empty() {
+                                lines.push(current_line);
+                                current_line = Line::default();
+                            }
+                            let start = quote_starts.pop().unwrap_or(lines.len());
+                            // The blank line after the last paragraph separates
+                            // the quote from what follows.
+                            let end = match lines.last() {
+                                Some(line) if line.spans.is_empty() => lines.len() - 1,
+                                _ => lines.len(),
+                            };
+                            for line in lines[start..end.max(start)].iter_mut() {
+                                line.spans.insert(0, Span::raw("▌ "));
+                                line.style = line.style.add_modifier(Modifier::DIM);
+                            }
+                        }
+                        TagEnd::TableCell => {
+                            if let Some(table) = table.as_mut() {
+                                let cell = std::mem::take(&mut table.cell);
+                                if let Some(row) = table.rows.last_mut() {
+                                    row.push(cell);
+                                }
+                            }
+                        }
+                        TagEnd::Table => {
+                            if let Some(table) = table.take() {
+                                lines.extend(table.into_lines());
+                                lines.push(Line::default());
+                            }
+                        }
+                        TagEnd::Link => {
+                            // Number the link so it can be opened by typing
+                            // the number.
+                            if let Some(number) = link_numbers.pop().flatten() {
+                                if let Some(table) = table.as_mut() {
+                                    table.cell.push_str(&format!("[{}]", number));
+                                } else {
+                                    current_line.spans.push(Span::styled(
+                                        format!("[{}]", number),
+                                        Style::default().add_modifier(Modifier::DIM),
+                                    ));
+                                }
+                            }
                         }
                        TagEnd::Item => {
                             // Push the current line to preserve the list item
@@ -211,12 +426,31 @@ impl Markdown {
                         _ => {}
                     }
                 }
-                Event::InlineMath(text) 
-                | Event::Code(text)
+                Event::Code(text) => {
+                    if let Some(table) = table.as_mut() {
+                        table.cell.push_str(&text);
+                        continue;
+                    }
+                    current_line
+                        .spans
+                        .push(Span::styled(text.to_string(), Style::default().fg(self.code_fg)));
+                }
+                Event::InlineMath(text)
                 | Event::InlineHtml(text)
                 | Event::DisplayMath(text)
                 | Event::Html(text)
                 | Event::Text(text) => {
+                    // Code blocks keep their line breaks and whitespace so
+                    // they are only split into lines at their end.
+                    if let Some(code) = code_block.as_mut() {
+                        code.push_str(&text);
+                        continue;
+                    }
+                    // Table cells are laid out once the whole table is in.
+                    if let Some(table) = table.as_mut() {
+                        table.cell.push_str(&text);
+                        continue;
+                    }
                     let mut style = Style::default();
 
                     // Apply style based on current state
@@ -232,9 +466,9 @@ impl Markdown {
                             MarkdownState::Emphasis => {
                                 style = style.add_modifier(Modifier::ITALIC);
                             }
-                            //MarkdownState::Code => {
-                            //    style = style.fg(Color::Yellow);
-                            //}
+                            MarkdownState::Strikethrough => {
+                                style = style.add_modifier(Modifier::CROSSED_OUT);
+                            }
                             _ => {
                             }
                         }
@@ -253,8 +487,22 @@ impl Markdown {
                     current_line = Line::default();
                 }
                 Event::FootnoteReference(_) => {},
-                Event::Rule => {},
-                Event::TaskListMarker(_) => {},
+                Event::Rule => {
+                    if !current_line.spans.is_empty() {
+                        lines.push(current_line);
+                        current_line = Line::default();
+                    }
+                    lines.push(Line::styled(
+                        "─".repeat(RULE_WIDTH),
+                        Style::default().add_modifier(Modifier::DIM),
+                    ));
+                    lines.push(Line::default());
+                }
+                Event::TaskListMarker(checked) => {
+                    // This comes right after the item's bullet.
+                    let marker = if checked { "[x] " } else { "[ ] " };
+                    current_line.spans.push(Span::raw(marker));
+                }
             }
         }
 
@@ -266,7 +514,8 @@ impl Markdown {
         self.parsed_text = Some(Text::from(lines));
     }
 
-    fn handle_link_tag(&mut self, tag: &Tag<'_>) {
+    /// Remember a link's destination returning its number.
+    fn handle_link_tag(&mut self, tag: &Tag<'_>) -> Option<usize> {
         match tag {
             Tag::Link {
                 link_type,
@@ -287,30 +536,29 @@ impl Markdown {
                     LinkType::CollapsedUnknown => String::from("[collapsed unknown]"),
                     LinkType::ShortcutUnknown => String::from("[shortcut unknown]"),
                     LinkType::Autolink => dest_url.to_string(),
-                    LinkType::Email => dest_url.to_string(),
+                    LinkType::Email => format!("mailto:{}", dest_url),
                     LinkType::WikiLink { has_pothole: _ } => String::from("[wiki]"),
                 };
-                self.links.insert(dest);
+                let idx = match self.links.iter().position(|link| *link == dest) {
+                    Some(idx) => idx,
+                    None => {
+                        self.links.push(dest);
+                        self.links.len() - 1
+                    }
+                };
+                Some(idx + 1)
             }
-            _ => { /* noop */ }
+            _ => None,
         }
     }
 
+    /// The link numbered by the digit typed.
     pub fn handle_input(&self, code: KeyCode) -> Option<String> {
-        let num = match code {
-            KeyCode::Char('0') => 0,
-            KeyCode::Char('1') => 1,
-            KeyCode::Char('2') => 2,
-            KeyCode::Char('3') => 3,
-            KeyCode::Char('4') => 4,
-            KeyCode::Char('5') => 5,
-            KeyCode::Char('6') => 6,
-            KeyCode::Char('7') => 7,
-            KeyCode::Char('8') => 8,
-            KeyCode::Char('9') => 9,
+        let number = match code {
+            KeyCode::Char(c) => c.to_digit(10)? as usize,
             _ => return None,
         };
-        self.links.iter().nth(num).cloned()
+        self.links.get(number.checked_sub(1)?).cloned()
     }
 
     pub fn get_text(&self) -> Text<'_> {
@@ -322,6 +570,116 @@ impl Markdown {
     }
 }
 
+/// Wrap `text` at word boundaries so no line is wider than `width`. Wrapped
+/// list items and blockquotes keep their indent, or their `▌ ` bars, on the
+/// continuation lines. Words longer than `width` are broken.
+pub fn wrap_text(text: &Text<'_>, width: usize) -> Text<'static> {
+    let width = width.max(1);
+    let lines: Vec<Line<'static>> = text
+        .lines
+        .iter()
+        .flat_map(|line| wrap_line(line, width))
+        .collect();
+    Text::from(lines).style(text.style)
+}
+
+fn wrap_line(line: &Line<'_>, width: usize) -> Vec<Line<'static>> {
+    let chars: Vec<(char, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .collect();
+    let to_line = |row: &[(char, Style)]| {
+        let mut spans: Vec<Span<'static>> = Vec::new();
+        for (c, style) in row {
+            match spans.last_mut() {
+                Some(span) if span.style == *style => span.content.to_mut().push(*c),
+                _ => spans.push(Span::styled(c.to_string(), *style)),
+            }
+        }
+        let mut wrapped = Line::from(spans).style(line.style);
+        wrapped.alignment = line.alignment;
+        wrapped
+    };
+    if chars.len() <= width {
+        return vec![to_line(&chars)];
+    }
+    let text: String = chars.iter().map(|(c, _)| c).collect();
+    let hanging = hanging_indent(&text, width);
+    // Continuation lines repeat blockquote bars in their own style.
+    let continuation: Vec<(char, Style)> = chars
+        .iter()
+        .take(hanging.chars().count())
+        .zip(hanging.chars())
+        .map(|((_, style), c)| (c, *style))
+        .collect();
+
+    let mut rows: Vec<Vec<(char, Style)>> = Vec::new();
+    let mut row: Vec<(char, Style)> = Vec::new();
+    let mut space: Vec<(char, Style)> = Vec::new();
+    // The first line's own indent and marker stay together with its first
+    // word.
+    let mut idx = continuation.len();
+    row.extend_from_slice(&chars[..idx]);
+    while idx < chars.len() {
+        let start = idx;
+        let is_space = chars[idx].0 == ' ';
+        while idx < chars.len() && (chars[idx].0 == ' ') == is_space {
+            idx += 1;
+        }
+        let token = &chars[start..idx];
+        if is_space {
+            space = token.to_vec();
+            continue;
+        }
+        let has_words = row.len() > continuation.len();
+        if has_words && row.len() + space.len() + token.len() > width {
+            rows.push(std::mem::replace(&mut row, continuation.clone()));
+            space.clear();
+        }
+        row.append(&mut space);
+        for c in token {
+            if row.len() >= width && row.len() > continuation.len() {
+                rows.push(std::mem::replace(&mut row, continuation.clone()));
+            }
+            row.push(*c);
+        }
+    }
+    rows.push(row);
+    rows.iter().map(|row| to_line(row)).collect()
+}
+
+/// What goes in front of the continuation lines of a wrapped line: any
+/// blockquote bars followed by enough spaces to line up with the text after a
+/// list marker. Nothing if that would leave too little room.
+fn hanging_indent(text: &str, width: usize) -> String {
+    let mut rest = text;
+    while let Some(after) = rest.strip_prefix("▌ ") {
+        rest = after;
+    }
+    let bars = &text[..text.len() - rest.len()];
+    rest = rest.trim_start_matches(' ');
+    if let Some(after) = rest.strip_prefix("* ") {
+        rest = after;
+    } else {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if let Some(after) = rest[digits..].strip_prefix(". ").filter(|_| digits > 0) {
+            rest = after;
+        }
+    }
+    for marker in ["[x] ", "[ ] "] {
+        if let Some(after) = rest.strip_prefix(marker) {
+            rest = after;
+            break;
+        }
+    }
+    let indent = text[..text.len() - rest.len()].chars().count();
+    if indent * 2 > width {
+        return String::new();
+    }
+    format!("{}{}", bars, " ".repeat(indent - bars.chars().count()))
+}
+
 impl Widget for Markdown {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
     where
@@ -492,8 +850,8 @@ mod tests {
         let md = Markdown::from_str("[Link 1](https://example1.com)\n[Link 2](https://example2.com)");
         
         // Test valid key input
-        let link1 = md.handle_input(KeyCode::Char('0'));
-        let link2 = md.handle_input(KeyCode::Char('1'));
+        let link1 = md.handle_input(KeyCode::Char('1'));
+        let link2 = md.handle_input(KeyCode::Char('2'));
         
         assert!(link1.is_some());
         assert!(link2.is_some());
@@ -503,6 +861,259 @@ mod tests {
         assert!(invalid.is_none());
     }
 
+    #[test]
+    fn test_links_are_numbered_in_order() {
+        let md = Markdown::from_str(
+            "[zed](https://z.example) then [alpha](https://a.example) and [zed again](https://z.example)",
+        );
+        let line = md.get_text().lines[0].to_string();
+        assert_eq!("zed[1] then alpha[2] and zed again[1]", line);
+        assert_eq!(
+            Some(String::from("(https://z.example)")),
+            md.handle_input(KeyCode::Char('1'))
+        );
+        assert_eq!(
+            Some(String::from("(https://a.example)")),
+            md.handle_input(KeyCode::Char('2'))
+        );
+        assert_eq!(None, md.handle_input(KeyCode::Char('0')));
+        assert_eq!(None, md.handle_input(KeyCode::Char('3')));
+    }
+
+    #[test]
+    fn test_table() {
+        let md = Markdown::from_str(
+            "Keys:\n\n| Key | Action |\n|-----|--------|\n| j | Move down |\n| k | Up |\n\nAfter",
+        );
+        let text = md.get_text();
+        let shown: Vec<String> = text.lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            vec![
+                "Keys:",
+                "",
+                "Key │ Action   ",
+                "────┼──────────",
+                "j   │ Move down",
+                "k   │ Up       ",
+                "",
+                "After",
+                "",
+            ],
+            shown
+        );
+        assert!(text.lines[2].style.add_modifier.contains(Modifier::BOLD));
+        assert!(!text.lines[4].style.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let md = Markdown::from_str(
+            "| Left | Center | Right |\n|:-----|:------:|------:|\n| a | b | c |\n| dddd | eeeeee | f |",
+        );
+        let shown: Vec<String> = md
+            .get_text()
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!("Left │ Center │ Right", shown[0]);
+        assert_eq!("a    │   b    │     c", shown[2]);
+        assert_eq!("dddd │ eeeeee │     f", shown[3]);
+    }
+
+    #[test]
+    fn test_table_numbers_links_in_cells() {
+        let md = Markdown::from_str("| Doc |\n|-----|\n| [Home](index.md) |\n\nafter");
+        let shown: Vec<String> = md
+            .get_text()
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!("Home[1]", shown[2]);
+        assert_eq!("after", shown[4]);
+    }
+
+    #[test]
+    fn test_table_truncates_long_cells() {
+        let long = "x".repeat(MAX_TABLE_CELL_WIDTH + 10);
+        let md = Markdown::from_str(&format!("| A |\n|---|\n| {} |", long));
+        let text = md.get_text();
+        let cell = text.lines[2].to_string();
+        assert_eq!(MAX_TABLE_CELL_WIDTH, cell.chars().count());
+        assert!(cell.ends_with('…'));
+    }
+
+    #[test]
+    fn test_blockquote_does_not_panic() {
+        let md = Markdown::from_str("> hi");
+        assert_eq!("▌ hi", md.get_text().lines[0].to_string());
+    }
+
+    #[test]
+    fn test_blockquote() {
+        let md = Markdown::from_str("Before\n\n> one\n> two\n>\n> > nested\n\nAfter");
+        let text = md.get_text();
+        let shown: Vec<String> = text.lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            vec![
+                "Before",
+                "",
+                "▌ one two",
+                "▌ ",
+                "▌ ▌ nested",
+                "",
+                "After",
+                "",
+            ],
+            shown
+        );
+        assert!(text.lines[2].style.add_modifier.contains(Modifier::DIM));
+        assert!(!text.lines[6].style.add_modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_strikethrough() {
+        let md = Markdown::from_str("keep ~~gone~~ keep");
+        let text = md.get_text();
+        let spans = &text.lines[0].spans;
+        assert_eq!("gone", spans[1].content);
+        assert!(spans[1].style.add_modifier.contains(Modifier::CROSSED_OUT));
+        assert!(!spans[0].style.add_modifier.contains(Modifier::CROSSED_OUT));
+    }
+
+    #[test]
+    fn test_superscript_and_subscript_render_inline() {
+        let md = Markdown::from_str("x^2^ and H~2~O");
+        assert_eq!("x2 and H2O", md.get_text().lines[0].to_string());
+    }
+
+    #[test]
+    fn test_rule_between_paragraphs() {
+        let md = Markdown::from_str("Above\n\n---\n\nBelow");
+        let text = md.get_text();
+        let shown: Vec<String> = text.lines.iter().map(|line| line.to_string()).collect();
+        let rule = "─".repeat(RULE_WIDTH);
+        assert_eq!(vec!["Above", "", rule.as_str(), "", "Below", ""], shown);
+        assert!(text.lines[2].style.add_modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_task_list() {
+        let md = Markdown::from_str("* [x] Done\n* [ ] Todo\n  * [ ] Nested");
+        let text = md.get_text();
+        assert_eq!(3, text.lines.len());
+        let spans = |idx: usize| -> Vec<String> {
+            text.lines[idx]
+                .spans
+                .iter()
+                .map(|span| span.content.to_string())
+                .collect()
+        };
+        assert_eq!(vec!["* ", "[x] ", "Done"], spans(0));
+        assert_eq!(vec!["* ", "[ ] ", "Todo"], spans(1));
+        assert_eq!(vec!["  * ", "[ ] ", "Nested"], spans(2));
+    }
+
+    #[test]
+    fn test_fenced_code_block() {
+        let md = Markdown::from_str("Before\n\n```\nfn main() {\n    run();\n}\n```\nAfter");
+        let text = md.get_text();
+        let shown: Vec<String> = text.lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            vec![
+                "Before",
+                "",
+                "fn main() {",
+                "    run(); ",
+                "}          ",
+                "",
+                "After",
+                "",
+            ],
+            shown
+        );
+        for line in &text.lines[2..5] {
+            assert_eq!(Some(Theme::default().code_block_bg), line.spans[0].style.bg);
+        }
+        assert_eq!(None, text.lines[6].spans[0].style.bg);
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let md = Markdown::from_str("Run `w out.xlsx` to save");
+        let spans = &md.get_text().lines[0].spans;
+        assert_eq!("w out.xlsx", spans[1].content);
+        assert_eq!(Some(Theme::default().code_fg), spans[1].style.fg);
+        assert_eq!(None, spans[0].style.fg);
+        assert_eq!(" to save", spans[2].content);
+    }
+
+    #[test]
+    fn test_code_uses_the_theme() {
+        let theme = Theme::light();
+        let md =
+            Markdown::from_str("Run `w` to save\n\n```\nw\n```\n\n[docs](https://example.com)")
+                .with_theme(&theme);
+        let text = md.get_text();
+        assert_eq!(Some(theme.code_fg), text.lines[0].spans[1].style.fg);
+        assert_eq!(Some(theme.code_block_bg), text.lines[2].spans[0].style.bg);
+        assert_eq!(
+            Some("(https://example.com)".to_string()),
+            md.handle_input(KeyCode::Char('1'))
+        );
+        assert_eq!(None, md.handle_input(KeyCode::Char('2')));
+    }
+
+    fn wrapped(markdown: &str, width: usize) -> Vec<String> {
+        wrap_text(&Markdown::from_str(markdown).get_text(), width)
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_list_item_hangs_under_its_text() {
+        assert_eq!(
+            vec!["* one two", "  three four", "  * nested", "    item"],
+            wrapped("* one two three four\n  * nested item", 12)
+        );
+        assert_eq!(
+            vec!["1. [x] alpha", "       beta"],
+            wrapped("1. [x] alpha beta", 14)
+        );
+    }
+
+    #[test]
+    fn test_wrap_blockquote_repeats_the_bar() {
+        assert_eq!(
+            vec!["▌ one two", "▌ three", ""],
+            wrapped("> one two three", 10)
+        );
+    }
+
+    #[test]
+    fn test_wrap_heading_between_words() {
+        assert_eq!(
+            vec!["A longer", "heading", ""],
+            wrapped("# A longer heading", 9)
+        );
+        // A word that can't fit anywhere is broken.
+        assert_eq!(vec!["abcd", "efgh", "ij", ""], wrapped("abcdefghij", 4));
+    }
+
+    #[test]
+    fn test_wrap_keeps_styles() {
+        let text = wrap_text(&Markdown::from_str("# Heading words").get_text(), 8);
+        assert_eq!("Heading", text.lines[0].to_string());
+        assert_eq!(text.lines[0].style, text.lines[1].style);
+        let text = wrap_text(&Markdown::from_str("plain **bold words**").get_text(), 8);
+        assert_eq!("bold", text.lines[1].to_string());
+        let bold = &text.lines[1].spans[0];
+        assert!(bold.style.add_modifier.contains(Modifier::BOLD));
+    }
+
     #[test]
     fn test_complex_document() {
         let markdown = r#"
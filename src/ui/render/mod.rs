@@ -10,8 +10,10 @@ use super::*;
 
 pub mod viewport;
 pub use viewport::Viewport;
+pub mod completion;
 pub mod dialog;
 pub mod markdown;
+pub mod theme;
 
 #[cfg(test)]
 mod test;
@@ -29,29 +31,49 @@ impl<'ws> Workspace<'ws> {
         ];
         let mut rs: Vec<Box<dyn Fn(Rect, &mut Buffer, &mut Self)>> = vec![
             Box::new(|rect: Rect, buf: &mut Buffer, ws: &mut Self| {
-                let tabs = Tabs::new(
-                    ws.book
-                        .get_sheet_names()
-                        .iter()
-                        .enumerate()
-                        .map(|(idx, name)| format!("{} {}", name, idx))
-                        .collect::<Vec<String>>(),
-                )
-                .select(Some(ws.book.location.sheet as usize));
+                let tabs = Tabs::new(tab_titles(&ws.book))
+                    .select(Some(ws.book.location.sheet as usize));
                 tabs.render(rect, buf);
+                // Remember where the tabs are so mouse clicks can find them.
+                ws.state.tabs_area = rect;
             }),
             Box::new(|rect: Rect, buf: &mut Buffer, ws: &mut Self| {
                 let [text_rect, info_rect] =
                     Layout::horizontal(vec![Constraint::Fill(1), Constraint::Fill(1)]).areas(rect);
-                ws.text_area.render(text_rect, buf);
-                let hint = Paragraph::new(vec![
-                    Line::from(""),
-                    Line::from("ALT-h to toggle help dialog").centered(),
-                ]);
-                hint.render(info_rect, buf);
+                if ws.state.modality() == &Modality::CellEdit {
+                    ws.text_area.render(text_rect, buf);
+                    ws.highlight_formula(text_rect, buf);
+                    ws.highlight_parens(text_rect, buf);
+                    // Remember where the editor is so completions can hang
+                    // off of it.
+                    ws.state.edit_area = text_rect;
+                } else {
+                    ws.formula_bar(text_rect.width.saturating_sub(2) as usize)
+                        .render(text_rect, buf);
+                }
+                if let Some((_, value)) = &ws.state.formula_preview {
+                    Paragraph::new(value.replace(['\r', '\n'], " "))
+                        .block(
+                            Block::bordered()
+                                .title_top("Result")
+                                .border_style(Style::default().add_modifier(Modifier::DIM)),
+                        )
+                        .render(info_rect, buf);
+                } else {
+                    let hint = Paragraph::new(vec![
+                        Line::from(""),
+                        Line::from("ALT-h to toggle help dialog").centered(),
+                    ]);
+                    hint.render(info_rect, buf);
+                }
             }),
             Box::new(move |rect: Rect, buf: &mut Buffer, ws: &mut Self| {
                 let sheet_name = ws.book.get_sheet_name().unwrap_or("Unknown");
+                // Follow the cell picked while typing a formula once there is one.
+                let point = match &ws.state.point {
+                    Some((addr, len)) if *len > 0 => Some(addr.clone()),
+                    _ => None,
+                };
                 let table_block = Block::bordered().title_top(sheet_name);
                 let viewport = Viewport::new(
                     &ws.book,
@@ -61,7 +83,11 @@ impl<'ws> Workspace<'ws> {
                         None
                     },
                 )
-                .with_selected(ws.book.location.clone())
+                .with_selected(point.clone().unwrap_or_else(|| ws.book.location.clone()))
+                .with_point(point)
+                .with_theme(ws.theme())
+                .with_stripes(ws.state.settings.stripes)
+                .with_gridlines(ws.state.settings.gridlines)
                 .block(table_block);
                 StatefulWidget::render(viewport, rect, buf, &mut ws.state.viewport_state);
             }),
@@ -94,6 +120,155 @@ impl<'ws> Workspace<'ws> {
     }
 }
 
+impl<'ws> Workspace<'ws> {
+    /// The file name of the workbook with a `[+]` when it has unsaved
+    /// changes.
+    fn display_name(&self) -> String {
+        let name = self
+            .name
+            .file_name()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("Unknown"));
+        if self.book.dirty {
+            format!("{} [+]", name)
+        } else {
+            name
+        }
+    }
+
+    /// The title for the terminal window or `None` when the `title` setting
+    /// is off.
+    pub fn terminal_title(&self) -> Option<String> {
+        if self.state.settings.title {
+            Some(format!("{} — sheetsui", self.display_name()))
+        } else {
+            None
+        }
+    }
+
+    /// The size and address of the range being selected, e.g. `4R x 3C A1:C4`.
+    fn range_summary(&self) -> Option<String> {
+        if self.state.modality() != &Modality::RangeSelect {
+            return None;
+        }
+        let range = &self.state.range_select;
+        let (start, end) = match range.get_range() {
+            Some(bounds) => bounds,
+            None => {
+                let start = range.start.clone()?;
+                (start.clone(), start)
+            }
+        };
+        let size = format!(
+            "{}R x {}C",
+            end.row - start.row + 1,
+            end.col - start.col + 1
+        );
+        if start == end {
+            Some(format!("{} {}", size, start.to_range_part()))
+        } else {
+            Some(format!(
+                "{} {}:{}",
+                size,
+                start.to_range_part(),
+                end.to_range_part()
+            ))
+        }
+    }
+
+    /// Color the references, functions, strings and numbers of the formula
+    /// in the editor drawn in `area`.
+    fn highlight_formula(&self, area: Rect, buf: &mut Buffer) {
+        let Some(origin) = self.editor_origin(area, buf) else {
+            return;
+        };
+        let theme = self.theme();
+        for (row, line) in self.text_area.lines().iter().enumerate() {
+            for (span, kind) in book::formula::highlight_tokens(line) {
+                let style = Style::default().fg(theme.formula_color(kind));
+                let start = line[..span.start].chars().count();
+                for col in start..start + line[span].chars().count() {
+                    style_editor_cell(area, origin, (row, col), style, buf);
+                }
+            }
+        }
+    }
+
+    /// Highlight the parenthesis next to the cursor and its match in the
+    /// editor drawn in `area`, and any parenthesis on the line without one.
+    fn highlight_parens(&self, area: Rect, buf: &mut Buffer) {
+        let Some(origin) = self.editor_origin(area, buf) else {
+            return;
+        };
+        let (row, col) = self.text_area.cursor();
+        let line = &self.text_area.lines()[row];
+        let offsets: Vec<usize> = line.char_indices().map(|(idx, _)| idx).collect();
+        let char_at = |offset: usize| offsets.binary_search(&offset).unwrap_or(0);
+        let (pairs, orphans) = book::formula::match_parens(line);
+        let orphan = Style::default()
+            .fg(self.theme().formula_unmatched)
+            .add_modifier(Modifier::BOLD);
+        let mut marks: Vec<(usize, Style)> = orphans
+            .into_iter()
+            .map(|offset| (char_at(offset), orphan))
+            .collect();
+        // The parenthesis under the cursor wins over the one before it.
+        let near = [Some(col), col.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|idx| offsets.get(idx).copied())
+            .find_map(|offset| pairs.iter().find(|(o, c)| *o == offset || *c == offset));
+        if let Some((open, close)) = near {
+            let matched = Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED);
+            marks.push((char_at(*open), matched));
+            marks.push((char_at(*close), matched));
+        }
+        for (idx, style) in marks {
+            style_editor_cell(area, origin, (row, idx), style, buf);
+        }
+    }
+
+    /// Where the editor drawn in `area` put the first character of a formula.
+    /// The editor scrolls long lines so this is worked out from where it drew
+    /// the cursor and may be outside of `area`. `None` if the editor doesn't
+    /// hold a formula.
+    fn editor_origin(&self, area: Rect, buf: &Buffer) -> Option<(isize, isize)> {
+        let lines = self.text_area.lines();
+        if !lines.first().is_some_and(|line| line.starts_with('=')) {
+            return None;
+        }
+        let (x, y) = Block::bordered()
+            .inner(area)
+            .positions()
+            .map(|pos| (pos.x, pos.y))
+            .find(|pos| buf[*pos].modifier.contains(Modifier::SLOW_BLINK))?;
+        let (row, col) = self.text_area.cursor();
+        Some((x as isize - col as isize, y as isize - row as isize))
+    }
+
+    /// A read only view of the current cell's contents labeled with its
+    /// address. Contents wider than `width` are cut off with an ellipsis.
+    fn formula_bar(&self, width: usize) -> Paragraph<'static> {
+        let contents = self
+            .book
+            .get_current_cell_contents()
+            .unwrap_or_default()
+            .replace(['\r', '\n'], " ");
+        let contents = if contents.chars().count() > width {
+            let mut elided: String = contents.chars().take(width.saturating_sub(1)).collect();
+            elided.push('…');
+            elided
+        } else {
+            contents
+        };
+        Paragraph::new(contents).block(
+            Block::bordered()
+                .title_top(self.book.location.to_range_part())
+                .border_style(Style::default().add_modifier(Modifier::DIM)),
+        )
+    }
+}
+
 impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
     where
@@ -106,21 +281,39 @@ impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
                 .as_ref()
                 .map(|md| md.get_text())
                 .unwrap_or_else(|| Text::raw("Popup message here"));
-            let popup = dialog::Dialog::new(lines, "Help").scroll(self.state.dialog_scroll);
-            popup.render(area, buf);
+            let title = self.state.popup_title.as_deref().unwrap_or("Help");
+            let mut popup = dialog::Dialog::new(lines, title).with_theme(&self.theme());
+            if let Some(width) = self.state.popup_max_width {
+                popup = popup
+                    .with_max_width(width)
+                    .with_bottom_title("Esc to close");
+            }
+            let (rows, max_scroll) = popup.viewport(area);
+            self.state.dialog_rows = rows.max(1);
+            self.state.dialog_max_scroll = max_scroll;
+            self.state.dialog_scroll = self.state.dialog_scroll.min(max_scroll);
+            popup.scroll(self.state.dialog_scroll).render(area, buf);
         } else if self.state.modality() == &Modality::Quit {
-            let popup =
-                dialog::Dialog::new(Text::raw("File is not yet saved. Save it first?"), "Quit")
-                    .with_bottom_title("Y/N");
+            let unsaved = self.unsaved_buffer_names();
+            let msg = if self.buffer_count() > 1 {
+                format!("Unsaved changes in {}. Save them first?", unsaved.join(", "))
+            } else {
+                String::from("File is not yet saved. Save it first?")
+            };
+            let popup = dialog::Dialog::new(Text::raw(msg), "Quit")
+                .with_bottom_title("Y/N/Esc to cancel")
+                .with_theme(&self.theme());
             popup.render(area, buf);
         } else {
-            let outer_block = Block::bordered()
-                .title(Line::from(
-                    self.name
-                        .file_name()
-                        .map(|p| p.to_string_lossy().to_string())
-                        .unwrap_or_else(|| String::from("Unknown")),
-                ))
+            let mut outer_block = Block::bordered()
+                .title(Line::from({
+                    let name = self.display_name();
+                    if self.buffer_count() > 1 {
+                        format!("{} [{}/{}]", name, self.active_buffer() + 1, self.buffer_count())
+                    } else {
+                        name
+                    }
+                }))
                 .title_bottom(match self.state.modality() {
                     Modality::Navigate => "navigate",
                     Modality::CellEdit => "edit",
@@ -136,16 +329,64 @@ impl<'widget, 'ws: 'widget> Widget for &'widget mut Workspace<'ws> {
                     ))
                     .right_aligned(),
                 );
+            if let Some(summary) = self.range_summary() {
+                outer_block = outer_block.title_bottom(Line::from(summary).right_aligned());
+            }
+            if self.read_only {
+                outer_block = outer_block.title_bottom("[RO]");
+            }
+            if self.book.needs_recalc {
+                outer_block = outer_block.title_bottom("CALC");
+            }
+            if let Some((name, _)) = &self.state.recording {
+                outer_block = outer_block.title_bottom(format!("recording @{}", name));
+            }
+            if let Some(msg) = self.state.status() {
+                outer_block = outer_block.title_bottom(Line::from(msg.to_string()).centered());
+            }
 
             for (rect, f) in self.get_render_parts(area.clone()) {
                 f(rect, buf, self);
             }
+            if let Some(state) = &self.state.completion {
+                let completion = completion::Completion::new(state).with_theme(&self.theme());
+                let rect = completion.area(self.state.edit_area, area);
+                completion.render(rect, buf);
+            }
 
             outer_block.render(area, buf);
         }
     }
 }
 
+/// The titles shown on the sheet tabs.
+pub(crate) fn tab_titles(book: &Book) -> Vec<String> {
+    book.get_sheet_names()
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| format!("{} {}", name, idx))
+        .collect()
+}
+
 pub fn draw(frame: &mut Frame, ws: &mut Workspace) {
     frame.render_widget(ws, frame.area());
 }
+
+/// Patch `style` onto the character at `(row, col)` of the editor drawn in
+/// `area` with its text starting at `origin`. Characters scrolled out of view
+/// are skipped.
+fn style_editor_cell(
+    area: Rect,
+    (x, y): (isize, isize),
+    (row, col): (usize, usize),
+    style: Style,
+    buf: &mut Buffer,
+) {
+    let inner = Block::bordered().inner(area);
+    let (x, y) = (x + col as isize, y + row as isize);
+    let inside = (inner.left() as isize..inner.right() as isize).contains(&x)
+        && (inner.top() as isize..inner.bottom() as isize).contains(&y);
+    if inside {
+        buf[(x as u16, y as u16)].set_style(style);
+    }
+}
@@ -1,9 +1,14 @@
-use ironcalc::base::Model;
-use ratatui::style::Color;
+use ironcalc::base::{expressions::types::Area, Model};
+use ratatui::style::{Color, Modifier};
 
 use crate::ui::AppState;
 
-use super::{Address, Book, Viewport, ViewportState};
+use super::{
+    dialog::Dialog,
+    theme::{colorfgbg_is_light, Theme},
+    viewport::{num_fmt_color, ROW_LABEL_WIDTH},
+    Address, Book, RangeSelection, Viewport, ViewportState,
+};
 
 #[test]
 fn test_viewport_get_visible_columns() {
@@ -109,9 +114,468 @@ fn test_color_mapping() {
         ("darkgrey", Color::DarkGray),
         ("darkgray", Color::DarkGray),
         ("#35f15b", Color::Rgb(53, 241, 91)),
+        ("hsl(120, 100%, 50%)", Color::Rgb(0, 255, 0)),
     ]
     .map(|(s, c)| (Some(s.to_string()), c))
     {
         assert_eq!(super::viewport::map_color(s.as_ref(), Color::Gray), c);
     }
 }
+
+#[test]
+fn test_viewport_state_cell_at() {
+    let mut state = ViewportState::default();
+    let book = Book::from_model(
+        Model::new_empty("test", "en", "America/New_York", "en").expect("Failed to make model"),
+    );
+    let default_size = book.get_col_size(1).expect("Failed to get column size") as u16;
+    let app_state = AppState::default();
+    let area = ratatui::layout::Rect::new(0, 0, 80, 10);
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    let viewport = Viewport::new(&book, Some(&app_state.range_select))
+        .with_selected(Address::default())
+        .block(ratatui::widgets::Block::bordered());
+    ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+    // The border, the header line and the row labels are not cells.
+    assert_eq!(None, state.cell_at(0, 2));
+    assert_eq!(None, state.cell_at(7, 1));
+    assert_eq!(None, state.cell_at(3, 2));
+    assert_eq!(Some((1, 1)), state.cell_at(6, 2));
+    assert_eq!(Some((3, 2)), state.cell_at(6 + default_size, 4));
+}
+
+#[test]
+fn test_viewport_remembers_corner_per_sheet() {
+    let mut state = ViewportState::default();
+    let mut book = Book::from_model(
+        Model::new_empty("test", "en", "America/New_York", "en").expect("Failed to make model"),
+    );
+    book.new_sheet(Some("Two")).expect("Failed to add sheet");
+    let app_state = AppState::default();
+    let render_at = |book: &Book, state: &mut ViewportState, row: usize| {
+        let viewport = Viewport::new(book, Some(&app_state.range_select)).with_selected(Address {
+            sheet: book.location.sheet,
+            row,
+            col: 1,
+        });
+        let _table = viewport
+            .to_table(80, 10, state)
+            .expect("Failed to make table");
+        state.corner(book.location.sheet).row
+    };
+    // Scroll down on the first sheet.
+    assert_eq!(42, render_at(&book, &mut state, 50));
+    book.select_next_sheet();
+    assert_eq!(1, render_at(&book, &mut state, 1));
+    assert_eq!(42, state.corner(0).row);
+    book.select_prev_sheet();
+    // Coming back shows the same rows as before.
+    assert_eq!(42, render_at(&book, &mut state, 42));
+    state.reset_sheet(0);
+    assert_eq!(1, state.corner(0).row);
+}
+
+#[test]
+fn test_viewport_column_wider_than_viewport() {
+    let mut state = ViewportState::default();
+    let mut book = Book::from_model(
+        Model::new_empty("test", "en", "America/New_York", "en").expect("Failed to make model"),
+    );
+    let width: u16 = 40;
+    book.set_col_size(2, width as usize * 3)
+        .expect("Failed to set column size");
+    let app_state = AppState::default();
+    for col in [1, 2, 3] {
+        let viewport = Viewport::new(&book, Some(&app_state.range_select)).with_selected(Address {
+            sheet: 0,
+            row: 1,
+            col,
+        });
+        let cols = viewport
+            .get_visible_columns(width, &state)
+            .expect("Failed to get visible columns");
+        assert!(cols.iter().any(|c| c.idx == col), "{} is not visible", col);
+        assert!(
+            ROW_LABEL_WIDTH + cols.iter().map(|c| c.length).sum::<u16>() <= width,
+            "{:?} overflows",
+            cols
+        );
+    }
+    let viewport = Viewport::new(&book, Some(&app_state.range_select)).with_selected(Address {
+        sheet: 0,
+        row: 1,
+        col: 2,
+    });
+    let cols = viewport
+        .get_visible_columns(width, &state)
+        .expect("Failed to get visible columns");
+    assert_eq!(1, cols.len());
+    assert_eq!(width - ROW_LABEL_WIDTH, cols[0].length);
+    // Rendering it must not panic.
+    let area = ratatui::layout::Rect::new(0, 0, width + 2, 10);
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    let viewport = viewport.block(ratatui::widgets::Block::bordered());
+    ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+}
+
+#[test]
+fn test_viewport_theme_changes_styles() {
+    let book = Book::from_model(
+        Model::new_empty("test", "en", "America/New_York", "en").expect("Failed to make model"),
+    );
+    let area = ratatui::layout::Rect::new(0, 0, 40, 10);
+    let render = |theme: Theme| {
+        let mut state = ViewportState::default();
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        let viewport = Viewport::new(&book, None)
+            .with_selected(book.location.clone())
+            .with_theme(theme);
+        ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+        buf
+    };
+    let has_bg = |buf: &ratatui::buffer::Buffer, color| buf.content().iter().any(|c| c.bg == color);
+    let dark = Theme::dark();
+    let light = Theme::light();
+    let dark_buf = render(dark.clone());
+    let light_buf = render(light.clone());
+    assert_ne!(dark_buf, light_buf);
+    for (buf, theme, other) in [(&dark_buf, &dark, &light), (&light_buf, &light, &dark)] {
+        assert!(
+            has_bg(buf, theme.cell_bg),
+            "{} cell colors not used",
+            theme.name
+        );
+        assert!(
+            has_bg(buf, theme.selected_bg),
+            "{} selection not used",
+            theme.name
+        );
+        assert!(
+            has_bg(buf, theme.header_odd_bg),
+            "{} headers not used",
+            theme.name
+        );
+        assert!(
+            !has_bg(buf, other.cell_bg),
+            "{} leaked into {}",
+            other.name,
+            theme.name
+        );
+    }
+}
+
+#[test]
+fn test_viewport_terminal_theme_uses_default_colors() {
+    let book = Book::from_model(
+        Model::new_empty("test", "en", "America/New_York", "en").expect("Failed to make model"),
+    );
+    let area = ratatui::layout::Rect::new(0, 0, 40, 10);
+    let mut state = ViewportState::default();
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    let viewport = Viewport::new(&book, None)
+        .with_selected(book.location.clone())
+        .with_theme(Theme::terminal());
+    ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+    // Only the highlighted header and row number of the selection get a
+    // color from the terminal's palette.
+    for y in 1..area.height {
+        for x in ROW_LABEL_WIDTH..area.width {
+            assert_eq!(Color::Reset, buf[(x, y)].bg, "{},{}", x, y);
+            assert_eq!(Color::Reset, buf[(x, y)].fg, "{},{}", x, y);
+        }
+    }
+    let reversed = |x: u16, y: u16| buf[(x, y)].modifier.contains(Modifier::REVERSED);
+    // Every other column header is reversed to keep them apart.
+    assert!((0..area.width).any(|x| reversed(x, 0)));
+    assert!(!(0..area.width).all(|x| reversed(x, 0)));
+    // The selected cell A1 is reversed and its neighbours are not.
+    assert!(reversed(ROW_LABEL_WIDTH + 1, 1));
+    assert!(!reversed(ROW_LABEL_WIDTH + 1, 2));
+}
+
+#[test]
+fn test_colorfgbg_is_light() {
+    assert!(colorfgbg_is_light("0;15"));
+    assert!(colorfgbg_is_light("0;default;7"));
+    assert!(!colorfgbg_is_light("15;0"));
+    assert!(!colorfgbg_is_light("7;8"));
+    assert!(!colorfgbg_is_light("default"));
+    assert!(!colorfgbg_is_light(""));
+}
+
+#[test]
+fn test_viewport_cell_alignment_and_decorations() {
+    let mut book = Book::default();
+    let cells = [
+        (1, 1, "left"),
+        (1, 2, "mid"),
+        (1, 3, "right"),
+        (2, 1, "42"),
+        (2, 2, "7"),
+    ];
+    for (row, col, value) in cells {
+        book.update_cell(&Address { sheet: 0, row, col }, value)
+            .expect("Failed to update cell");
+    }
+    let area = |row, column| Area {
+        sheet: 0,
+        row,
+        column,
+        width: 1,
+        height: 1,
+    };
+    let styles: [(&[(&str, &str)], Area); 4] = [
+        (
+            &[("alignment.horizontal", "center"), ("font.u", "true")],
+            area(1, 2),
+        ),
+        (
+            &[("alignment.horizontal", "right"), ("font.strike", "true")],
+            area(1, 3),
+        ),
+        (&[("alignment.horizontal", "left")], area(2, 2)),
+        (&[("font.b", "true")], area(2, 1)),
+    ];
+    for (style, area) in styles {
+        book.set_cell_style(style, &area)
+            .expect("Failed to set style");
+    }
+    book.evaluate();
+    let size = book.get_col_size(1).expect("Failed to get column size") as u16;
+    let rect = ratatui::layout::Rect::new(0, 0, 80, 10);
+    let mut state = ViewportState::default();
+    let mut buf = ratatui::buffer::Buffer::empty(rect);
+    let viewport = Viewport::new(&book, None).with_selected(book.location.clone());
+    ratatui::widgets::StatefulWidget::render(viewport, rect, &mut buf, &mut state);
+    let start = |col: u16| ROW_LABEL_WIDTH + col * size;
+    let span = |row: u16, col: u16| {
+        (start(col)..start(col + 1))
+            .map(|x| buf[(x, row)].symbol().to_string())
+            .collect::<String>()
+    };
+    assert!(span(1, 0).starts_with("left"), "{:?}", span(1, 0));
+    let mid = span(1, 1);
+    let leading = mid.len() - mid.trim_start().len();
+    let trailing = mid.len() - mid.trim_end().len();
+    assert!(leading > 0 && leading.abs_diff(trailing) <= 1, "{:?}", mid);
+    assert!(span(1, 2).ends_with("right"), "{:?}", span(1, 2));
+    // Numbers default to the right unless the style says otherwise.
+    assert!(span(2, 0).ends_with("42"), "{:?}", span(2, 0));
+    assert!(span(2, 1).starts_with("7"), "{:?}", span(2, 1));
+
+    let at = |row: u16, col: u16| buf[(start(col), row)].modifier;
+    assert!(at(1, 1).contains(Modifier::UNDERLINED));
+    assert!(at(1, 2).contains(Modifier::CROSSED_OUT));
+    assert!(!at(1, 2).contains(Modifier::UNDERLINED));
+}
+
+#[test]
+fn test_num_fmt_color() {
+    let accounting = "#,##0.00;[Red](#,##0.00)";
+    assert_eq!(Some(Color::Red), num_fmt_color(accounting, -1.5));
+    assert_eq!(None, num_fmt_color(accounting, 1.5));
+    assert_eq!(None, num_fmt_color(accounting, 0.0));
+    assert_eq!(None, num_fmt_color("#,##0.00", -1.0));
+    assert_eq!(Some(Color::Blue), num_fmt_color("[Blue]0", -1.0));
+    let three = "[Green]0;[Magenta]-0;[Yellow]\"zero\"";
+    assert_eq!(Some(Color::Green), num_fmt_color(three, 2.0));
+    assert_eq!(Some(Color::Magenta), num_fmt_color(three, -2.0));
+    assert_eq!(Some(Color::Yellow), num_fmt_color(three, 0.0));
+    // Locale tags are not colors and quoted semicolons don't split sections.
+    assert_eq!(
+        Some(Color::Red),
+        num_fmt_color("[$-409]\"a;b\"0;[RED]-0", -3.0)
+    );
+    // Conditions pick sections by value so they are left alone.
+    assert_eq!(None, num_fmt_color("[Red][<0]0;0", -3.0));
+}
+
+#[test]
+fn test_viewport_stripes_setting() {
+    let book = Book::default();
+    let size = book.get_col_size(1).expect("Failed to get column size") as u16;
+    let area = ratatui::layout::Rect::new(0, 0, 60, 10);
+    let theme = Theme::dark();
+    for stripes in [true, false] {
+        let mut state = ViewportState::default();
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        let viewport = Viewport::new(&book, None)
+            .with_selected(book.location.clone())
+            .with_stripes(stripes);
+        ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+        let first = ROW_LABEL_WIDTH;
+        let second = first + size;
+        // Column A is selected so compare B and C.
+        let header_bgs = (buf[(second, 0)].bg, buf[(second + size, 0)].bg);
+        if stripes {
+            assert_ne!(header_bgs.0, header_bgs.1);
+        } else {
+            assert_eq!(header_bgs.0, header_bgs.1);
+        }
+        // The selected cell stands out either way.
+        assert_eq!(theme.selected_bg, buf[(first, 1)].bg);
+        assert_eq!(theme.cell_bg, buf[(second, 1)].bg);
+    }
+}
+
+#[test]
+fn test_viewport_gridlines_setting() {
+    let book = Book::default();
+    let size = book.get_col_size(1).expect("Failed to get column size") as u16;
+    let area = ratatui::layout::Rect::new(0, 0, 60, 10);
+    let theme = Theme::dark();
+    let has_gridline =
+        |buf: &ratatui::buffer::Buffer| buf.content().iter().any(|c| c.symbol() == "│");
+    let mut state = ViewportState::default();
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    let viewport = Viewport::new(&book, None).with_selected(book.location.clone());
+    ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+    assert!(!has_gridline(&buf));
+
+    let mut state = ViewportState::default();
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    let viewport = Viewport::new(&book, None)
+        .with_selected(book.location.clone())
+        .with_gridlines(true);
+    ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+    for col in 0..3 {
+        let x = ROW_LABEL_WIDTH + col * (size + 1);
+        for y in 0..4 {
+            assert_eq!("│", buf[(x, y)].symbol(), "no gridline at {},{}", x, y);
+        }
+        // The gridline is not part of any cell.
+        assert_eq!(None, state.cell_at(x, 1));
+        assert_eq!(Some((1, col as usize + 1)), state.cell_at(x + 1, 1));
+    }
+    // The selected cell stands out with gridlines too.
+    assert_eq!(theme.selected_bg, buf[(ROW_LABEL_WIDTH + 1, 1)].bg);
+    assert_eq!(theme.cell_bg, buf[(ROW_LABEL_WIDTH + 1, 2)].bg);
+}
+
+#[test]
+fn test_viewport_highlights_selected_headers() {
+    let book = Book::default();
+    let size = book.get_col_size(1).expect("Failed to get column size") as u16;
+    let area = ratatui::layout::Rect::new(0, 0, 60, 10);
+    let theme = Theme::dark();
+    let header_x = |col: u16| ROW_LABEL_WIDTH + (col - 1) * size;
+    let render = |location: Address, range: Option<&RangeSelection>| {
+        let mut book = Book::default();
+        book.location = location.clone();
+        let mut state = ViewportState::default();
+        let mut buf = ratatui::buffer::Buffer::empty(area);
+        let viewport = Viewport::new(&book, range).with_selected(location);
+        ratatui::widgets::StatefulWidget::render(viewport, area, &mut buf, &mut state);
+        buf
+    };
+    let highlighted =
+        |buf: &ratatui::buffer::Buffer, x: u16, y: u16| buf[(x, y)].bg == theme.header_selected_bg;
+    let buf = render(Address::new(3, 2), None);
+    assert!(highlighted(&buf, header_x(2), 0));
+    assert!(!highlighted(&buf, header_x(1), 0));
+    assert!(!highlighted(&buf, header_x(3), 0));
+    assert!(highlighted(&buf, 0, 3));
+    assert!(!highlighted(&buf, 0, 2));
+
+    let mut range = RangeSelection::default();
+    range.start = Some(Address::new(2, 1));
+    range.end = Some(Address::new(4, 3));
+    let buf = render(Address::new(4, 3), Some(&range));
+    for col in 1..=3 {
+        assert!(highlighted(&buf, header_x(col), 0), "column {}", col);
+    }
+    assert!(!highlighted(&buf, header_x(4), 0));
+    for row in 2..=4 {
+        assert!(highlighted(&buf, 0, row), "row {}", row);
+    }
+    assert!(!highlighted(&buf, 0, 1));
+    assert!(!highlighted(&buf, 0, 5));
+}
+
+#[test]
+fn test_viewport_text_overflows_into_empty_cells() {
+    let mut book = Book::default();
+    let size = book.get_col_size(1).expect("Failed to get column size") as usize;
+    let long = "x".repeat(size * 4);
+    let cells = [
+        (1, 1, long.as_str()),
+        (1, 3, "stop"),
+        (2, 1, long.as_str()),
+        (3, 1, "123456789012345678901234567890"),
+    ];
+    for (row, col, value) in cells {
+        book.update_cell(&Address { sheet: 0, row, col }, value)
+            .expect("Failed to update cell");
+    }
+    book.evaluate();
+    let theme = Theme::dark();
+    let rect = ratatui::layout::Rect::new(0, 0, ROW_LABEL_WIDTH + size as u16 * 3, 10);
+    let mut state = ViewportState::default();
+    let mut buf = ratatui::buffer::Buffer::empty(rect);
+    let viewport = Viewport::new(&book, None).with_selected(book.location.clone());
+    ratatui::widgets::StatefulWidget::render(viewport, rect, &mut buf, &mut state);
+    let line = |row: u16| {
+        (ROW_LABEL_WIDTH..rect.width)
+            .map(|x| buf[(x, row)].symbol().to_string())
+            .collect::<String>()
+    };
+    // The text runs across the empty B1 and stops at C1.
+    assert_eq!(format!("{}stop", "x".repeat(size * 2)), line(1).trim_end());
+    // It also stops at the edge of the viewport.
+    assert_eq!("x".repeat(size * 3), line(2));
+    // Numbers don't overflow.
+    assert!(!line(3).trim_end().contains(' '), "{:?}", line(3));
+    assert_eq!(size, line(3).trim_end().len(), "{:?}", line(3));
+    // Only the owning cell is highlighted.
+    let x = ROW_LABEL_WIDTH + size as u16;
+    assert_eq!(theme.selected_bg, buf[(x - 1, 1)].bg);
+    assert_eq!(theme.cell_bg, buf[(x, 1)].bg);
+}
+
+/// Where a dialog with `message` is drawn on a `width` by `height` terminal.
+fn dialog_box(message: &str, width: u16, height: u16) -> ratatui::layout::Rect {
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(width, height))
+        .expect("Failed to make terminal");
+    terminal
+        .draw(|frame| {
+            let dialog = Dialog::new(ratatui::text::Text::raw(message), "Error")
+                .with_bottom_title("Esc to close");
+            frame.render_widget(dialog, frame.area());
+        })
+        .expect("Failed to draw");
+    let buf = terminal.backend().buffer();
+    let (x, y) = buf
+        .area
+        .positions()
+        .map(|pos| (pos.x, pos.y))
+        .find(|pos| buf[*pos].symbol() == "┌")
+        .expect("No dialog was drawn");
+    let right = (x..width)
+        .find(|col| buf[(*col, y)].symbol() == "┐")
+        .expect("No top right corner");
+    let bottom = (y..height)
+        .find(|row| buf[(x, *row)].symbol() == "└")
+        .expect("No bottom left corner");
+    ratatui::layout::Rect::new(x, y, right - x + 1, bottom - y + 1)
+}
+
+#[test]
+fn test_dialog_is_sized_to_its_content() {
+    // Short messages get the narrowest box, centered.
+    assert_eq!(
+        ratatui::layout::Rect::new(25, 10, 30, 3),
+        dialog_box("Unrecognized command q3", 80, 24)
+    );
+    // Longer ones are as wide as the line and its borders.
+    let message = "x".repeat(50);
+    assert_eq!(
+        ratatui::layout::Rect::new(14, 10, 52, 3),
+        dialog_box(&message, 80, 24)
+    );
+    // Too long for the screen and it wraps inside the margins.
+    let message = "x".repeat(100);
+    assert_eq!(
+        ratatui::layout::Rect::new(2, 7, 36, 5),
+        dialog_box(&message, 40, 20)
+    );
+}
@@ -0,0 +1,218 @@
+//! Colors used to draw the interface.
+//!
+//! sheetui ships a `dark`, a `light` and a `terminal` theme. The theme is
+//! picked with the `theme` setting either in the config file or with
+//! `:set theme light`. The `terminal` theme leaves the colors to the terminal
+//! and marks the selection with reverse video. The default `auto` setting uses
+//! it when the terminal reports a light background and `dark` otherwise.
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::book::formula::TokenKind;
+
+/// The colors for every part of the interface that isn't styled by the
+/// workbook itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    /// Cells without a fill color.
+    pub cell_fg: Color,
+    pub cell_bg: Color,
+    /// The cell under the cursor.
+    pub selected_fg: Color,
+    pub selected_bg: Color,
+    /// Cells inside a range selection.
+    pub range_fg: Color,
+    pub range_bg: Color,
+    /// Column headers alternate between the even and odd colors.
+    pub header_even_fg: Color,
+    pub header_even_bg: Color,
+    pub header_odd_fg: Color,
+    pub header_odd_bg: Color,
+    /// The headers and row numbers of the selected cell or range.
+    pub header_selected_fg: Color,
+    pub header_selected_bg: Color,
+    /// The help and quit dialogs.
+    pub dialog_fg: Color,
+    pub dialog_bg: Color,
+    /// The lines between columns when gridlines are on.
+    pub gridline: Color,
+    /// Highlighting of the formula being edited.
+    pub formula_reference: Color,
+    pub formula_function: Color,
+    pub formula_string: Color,
+    pub formula_number: Color,
+    /// A parenthesis in the formula without a match.
+    pub formula_unmatched: Color,
+    /// Inline code and the background of code blocks in the dialogs.
+    pub code_fg: Color,
+    pub code_block_bg: Color,
+    /// Mark the selected cell and ranges with reverse video instead of the
+    /// selected and range colors.
+    pub reversed: bool,
+}
+
+/// The names of the built in themes.
+pub const THEME_NAMES: [&str; 3] = ["dark", "light", "terminal"];
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark",
+            cell_fg: Color::White,
+            cell_bg: Color::Rgb(35, 33, 54),
+            selected_fg: Color::White,
+            selected_bg: Color::Rgb(57, 61, 71),
+            range_fg: Color::Black,
+            range_bg: Color::LightBlue,
+            header_even_fg: Color::White,
+            header_even_bg: Color::Rgb(57, 61, 71),
+            header_odd_fg: Color::Black,
+            header_odd_bg: Color::Rgb(165, 169, 160),
+            header_selected_fg: Color::Black,
+            header_selected_bg: Color::Rgb(137, 180, 250),
+            dialog_fg: Color::Reset,
+            dialog_bg: Color::Black,
+            gridline: Color::Rgb(88, 91, 112),
+            formula_reference: Color::Rgb(137, 180, 250),
+            formula_function: Color::Rgb(249, 226, 175),
+            formula_string: Color::Rgb(166, 227, 161),
+            formula_number: Color::Rgb(250, 179, 135),
+            formula_unmatched: Color::Red,
+            code_fg: Color::Yellow,
+            code_block_bg: Color::DarkGray,
+            reversed: false,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light",
+            cell_fg: Color::Black,
+            cell_bg: Color::Rgb(250, 250, 250),
+            selected_fg: Color::Black,
+            selected_bg: Color::Rgb(173, 214, 255),
+            range_fg: Color::Black,
+            range_bg: Color::Rgb(204, 229, 255),
+            header_even_fg: Color::Black,
+            header_even_bg: Color::Rgb(225, 225, 225),
+            header_odd_fg: Color::Black,
+            header_odd_bg: Color::Rgb(195, 195, 195),
+            header_selected_fg: Color::White,
+            header_selected_bg: Color::Rgb(0, 95, 184),
+            dialog_fg: Color::Black,
+            dialog_bg: Color::White,
+            gridline: Color::Rgb(200, 200, 200),
+            formula_reference: Color::Rgb(30, 102, 245),
+            formula_function: Color::Rgb(136, 57, 239),
+            formula_string: Color::Rgb(64, 160, 43),
+            formula_number: Color::Rgb(210, 80, 0),
+            formula_unmatched: Color::Rgb(210, 15, 57),
+            code_fg: Color::Rgb(136, 57, 239),
+            code_block_bg: Color::Rgb(230, 230, 230),
+            reversed: false,
+        }
+    }
+
+    /// Use the terminal's own foreground and background everywhere.
+    pub fn terminal() -> Self {
+        Self {
+            name: "terminal",
+            cell_fg: Color::Reset,
+            cell_bg: Color::Reset,
+            selected_fg: Color::Reset,
+            selected_bg: Color::Reset,
+            range_fg: Color::Reset,
+            range_bg: Color::Reset,
+            header_even_fg: Color::Reset,
+            header_even_bg: Color::Reset,
+            header_odd_fg: Color::Reset,
+            header_odd_bg: Color::Reset,
+            header_selected_fg: Color::Black,
+            header_selected_bg: Color::Yellow,
+            dialog_fg: Color::Reset,
+            dialog_bg: Color::Reset,
+            gridline: Color::Reset,
+            formula_reference: Color::Blue,
+            formula_function: Color::Magenta,
+            formula_string: Color::Green,
+            formula_number: Color::Yellow,
+            formula_unmatched: Color::Red,
+            code_fg: Color::Yellow,
+            code_block_bg: Color::Reset,
+            reversed: true,
+        }
+    }
+
+    /// Look up a built in theme by name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "terminal" => Some(Self::terminal()),
+            _ => None,
+        }
+    }
+
+    /// The style of a column header. Reverse video themes reverse the odd
+    /// headers so neighbouring columns stay apart.
+    pub fn header_style(&self, even: bool) -> Style {
+        if self.reversed && !even {
+            Style::default()
+                .fg(self.header_odd_fg)
+                .bg(self.header_odd_bg)
+                .add_modifier(Modifier::REVERSED)
+        } else if even {
+            Style::default()
+                .fg(self.header_even_fg)
+                .bg(self.header_even_bg)
+        } else {
+            Style::default()
+                .fg(self.header_odd_fg)
+                .bg(self.header_odd_bg)
+        }
+    }
+
+    /// The style of the column header and row number of the selection.
+    pub fn header_selected_style(&self) -> Style {
+        Style::default()
+            .fg(self.header_selected_fg)
+            .bg(self.header_selected_bg)
+    }
+
+    /// The color of a highlighted piece of a formula.
+    pub fn formula_color(&self, kind: TokenKind) -> Color {
+        match kind {
+            TokenKind::Reference => self.formula_reference,
+            TokenKind::Function => self.formula_function,
+            TokenKind::String => self.formula_string,
+            TokenKind::Number => self.formula_number,
+        }
+    }
+
+    pub fn dialog_style(&self) -> Style {
+        Style::default().fg(self.dialog_fg).bg(self.dialog_bg)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// Whether the terminal reports a light background in `COLORFGBG`.
+pub fn light_background() -> bool {
+    std::env::var("COLORFGBG")
+        .map(|value| colorfgbg_is_light(&value))
+        .unwrap_or(false)
+}
+
+/// `COLORFGBG` holds the ANSI color numbers of the terminal's foreground and
+/// background, e.g. `0;15`. The background is the last field. Light grey and
+/// the bright colors other than dark grey count as light.
+pub fn colorfgbg_is_light(value: &str) -> bool {
+    match value.rsplit(';').next().map(|bg| bg.trim().parse::<u8>()) {
+        Some(Ok(bg)) => matches!(bg, 7 | 9..=15),
+        _ => false,
+    }
+}
@@ -1,14 +1,17 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
+use ironcalc::base::types::HorizontalAlignment;
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Flex, Rect},
-    style::{Color, Stylize},
+    layout::{Constraint, Flex, Position, Rect},
+    style::{Color, Style, Stylize},
     text::{Line, Text},
     widgets::{Block, Cell, Row, StatefulWidget, Table, Widget},
 };
 
-use super::{Address, Book, RangeSelection};
-use crate::book;
+use super::{theme::Theme, Address, Book, RangeSelection};
+use crate::{book, ui::color::parse_rgb};
 
 /// A visible column to show in our Viewport.
 #[derive(Clone, Debug)]
@@ -23,9 +26,99 @@ impl<'a> From<&'a VisibleColumn> for Constraint {
     }
 }
 
+/// Width of the first column holding the row numbers.
+pub(crate) const ROW_LABEL_WIDTH: u16 = 5;
+
 #[derive(Debug, Default)]
 pub struct ViewportState {
-    prev_corner: Address,
+    /// The top left cell shown for each sheet by index.
+    corners: HashMap<u32, Address>,
+    /// Where the table was last rendered, inside its block.
+    area: Rect,
+    /// The rows and columns shown by the last render.
+    rows: Vec<usize>,
+    columns: Vec<VisibleColumn>,
+    /// Width of the gridline drawn before each column.
+    spacing: u16,
+}
+
+impl ViewportState {
+    /// The row and column of the cell last rendered at a terminal position.
+    /// Positions on the headers, row numbers or borders have no cell.
+    pub fn cell_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        if !self.area.contains(Position { x, y }) {
+            return None;
+        }
+        // The first line is the column header.
+        let row = *self.rows.get((y - self.area.y).checked_sub(1)? as usize)?;
+        let mut offset = self.area.x + ROW_LABEL_WIDTH;
+        if x < offset {
+            return None;
+        }
+        for col in &self.columns {
+            offset += self.spacing;
+            if x < offset {
+                // This is the gridline in front of the column.
+                return None;
+            }
+            offset += col.length;
+            if x < offset {
+                return Some((row, col.idx));
+            }
+        }
+        None
+    }
+
+    /// The top left cell shown for a sheet.
+    pub fn corner(&self, sheet: u32) -> Address {
+        self.corners.get(&sheet).cloned().unwrap_or_default()
+    }
+
+    fn corner_mut(&mut self, sheet: u32) -> &mut Address {
+        self.corners.entry(sheet).or_default()
+    }
+
+    /// Forget where a sheet was scrolled to so it starts at the top left
+    /// again.
+    pub fn reset_sheet(&mut self, sheet: u32) {
+        self.corners.remove(&sheet);
+    }
+
+    /// Scroll the viewport for a sheet by whole rows and columns independent
+    /// of the selection.
+    pub fn scroll(&mut self, sheet: u32, rows: isize, cols: isize) {
+        let corner = self.corner_mut(sheet);
+        corner.row = corner
+            .row
+            .saturating_add_signed(rows)
+            .clamp(1, book::LAST_ROW as usize);
+        corner.col = corner
+            .col
+            .saturating_add_signed(cols)
+            .clamp(1, book::LAST_COLUMN as usize);
+    }
+
+    /// Clamp a location into the rows and columns shown from the sheet's
+    /// corner. The size of the view is taken from the last render.
+    pub fn clamp_into_view(&self, sheet: u32, row: usize, col: usize) -> (usize, usize) {
+        let corner = self.corner(sheet);
+        let clamp = |idx: usize, start: usize, count: usize, last: usize| {
+            if count == 0 {
+                idx
+            } else {
+                idx.clamp(start, std::cmp::min(start + count - 1, last))
+            }
+        };
+        (
+            clamp(row, corner.row, self.rows.len(), book::LAST_ROW as usize),
+            clamp(
+                col,
+                corner.col,
+                self.columns.len(),
+                book::LAST_COLUMN as usize,
+            ),
+        )
+    }
 }
 
 /// A renderable viewport over a book.
@@ -34,13 +127,15 @@ pub struct Viewport<'ws> {
     book: &'ws Book,
     range_selection: Option<&'ws RangeSelection>,
     block: Option<Block<'ws>>,
+    theme: Theme,
+    /// Alternate the column header colors.
+    stripes: bool,
+    /// Draw a line between columns.
+    gridlines: bool,
+    /// The cell pointed at while typing a formula.
+    point: Option<Address>,
 }
 
-pub(crate) const COLNAMES: [&'static str; 26] = [
-    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
-    "T", "U", "V", "W", "X", "Y", "Z",
-];
-
 impl<'ws> Viewport<'ws> {
     pub fn new(book: &'ws Book, app_state: Option<&'ws RangeSelection>) -> Self {
         Self {
@@ -48,19 +143,55 @@ impl<'ws> Viewport<'ws> {
             range_selection: app_state,
             selected: Default::default(),
             block: None,
+            theme: Theme::default(),
+            stripes: true,
+            gridlines: false,
+            point: None,
         }
     }
 
+    pub fn with_stripes(mut self, stripes: bool) -> Self {
+        self.stripes = stripes;
+        self
+    }
+
+    pub fn with_gridlines(mut self, gridlines: bool) -> Self {
+        self.gridlines = gridlines;
+        self
+    }
+
+    /// Width of the gridline in front of every column.
+    fn spacing(&self) -> u16 {
+        if self.gridlines {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
     pub fn with_selected(mut self, location: Address) -> Self {
         self.selected = location;
         self
     }
 
+    pub fn with_point(mut self, point: Option<Address>) -> Self {
+        self.point = point;
+        self
+    }
+
     pub(crate) fn get_visible_rows(&self, height: u16, state: &ViewportState) -> Vec<usize> {
         // NOTE(jeremy): For now the row default height is 1. We'll have
         // to adjust that if this changes.
         let mut length = 1;
-        let start_row = std::cmp::min(self.selected.row, state.prev_corner.row);
+        let start_row = std::cmp::min(
+            self.selected.row,
+            state.corner(self.book.location.sheet).row,
+        );
         let mut start = start_row;
         let mut end = start_row;
         for row_idx in start_row..=(book::LAST_ROW as usize) {
@@ -84,36 +215,43 @@ impl<'ws> Viewport<'ws> {
         state: &ViewportState,
     ) -> Result<Vec<VisibleColumn>> {
         let mut visible = Vec::new();
-        // TODO(zaphar): This should be a shared constant with our first column.
-        // We start out with a length of 5 already reserved
-        let mut length = 5;
-        let start_idx = std::cmp::min(self.selected.col, state.prev_corner.col);
+        // We start out with the row label column already reserved.
+        let mut length = ROW_LABEL_WIDTH;
+        let start_idx = std::cmp::min(
+            self.selected.col,
+            state.corner(self.book.location.sheet).col,
+        );
+        let sheet = self.book.location.sheet;
+        let spacing = self.spacing();
+        let mut sizes = Vec::new();
         for idx in start_idx..=(book::LAST_COLUMN as usize) {
-            let size = self.book.get_col_size(idx)? as u16;
-            let updated_length = length + size;
+            let offset = idx - start_idx;
+            if offset == sizes.len() {
+                // Fetch the widths a screenful at a time instead of one
+                // column at a time.
+                let count = std::cmp::min(width as usize, book::LAST_COLUMN as usize + 1 - idx);
+                sizes.extend(self.book.get_col_sizes(sheet, idx, count.max(1))?);
+            }
+            let size = sizes[offset] as u16;
+            let updated_length = length.saturating_add(size + spacing);
             let col = VisibleColumn { idx, length: size };
             if updated_length < width {
                 length = updated_length;
                 visible.push(col);
             } else if self.selected.col >= col.idx {
-                // We need a sliding window now
-                if let Some(first) = visible.first() {
-                    // subtract the first columns size.
-                    length = length - first.length;
-                    // remove the first column.
-                    // TODO(jwall): This is a bit inefficient. Can we do better?
-                    visible = visible.into_iter().skip(1).collect();
-                }
-                // Add this col to the visible.
-                length += size;
+                // We need a sliding window now. Drop columns off the front
+                // until this one fits but never drop this one.
+                length = length.saturating_add(size + spacing);
                 visible.push(col);
-                // What if the length is still too long?
+                while length >= width && visible.len() > 1 {
+                    length -= visible.remove(0).length + spacing;
+                }
+                // A column wider than the viewport is cut off at the edge.
                 if length > width {
-                    if let Some(first) = visible.first() {
-                        // subtract the first columns size.
-                        length = length - first.length;
+                    if let Some(only) = visible.first_mut() {
+                        only.length = width.saturating_sub(ROW_LABEL_WIDTH + spacing);
+                        length = ROW_LABEL_WIDTH + spacing + only.length;
                     }
-                    visible = visible.into_iter().skip(1).collect();
                 }
             } else {
                 break;
@@ -135,98 +273,196 @@ impl<'ws> Viewport<'ws> {
     ) -> Result<Table<'widget>> {
         let visible_columns = self.get_visible_columns(width, state)?;
         let visible_rows = self.get_visible_rows(height, state);
+        let corner = state.corner_mut(self.book.location.sheet);
         if let Some(vc) = visible_columns.first() {
-            state.prev_corner.col = vc.idx
+            corner.col = vc.idx
         }
         if let Some(vr) = visible_rows.first() {
-            state.prev_corner.row = *vr;
+            corner.row = *vr;
         }
-        let rows: Vec<Row> =
-            visible_rows
-                .into_iter()
-                .map(|ri| {
-                    let mut cells = vec![Cell::new(Text::from(ri.to_string()))];
-                    cells.extend(visible_columns.iter().map(
-                        |VisibleColumn { idx: ci, length: _ }| {
-                            let content = self
-                                .book
-                                .get_cell_addr_rendered(&Address {
-                                    row: ri,
-                                    col: *ci,
-                                    sheet: self.book.location.sheet,
-                                })
-                                .unwrap();
-                            self.compute_cell_style(ri, *ci, Cell::new(Text::raw(content)))
-                        },
-                    ));
-                    Row::new(cells)
-                })
-                .collect();
+        state.rows = visible_rows.clone();
+        state.columns = visible_columns.clone();
+        state.spacing = self.spacing();
+        let range = self.range_selection.and_then(|r| r.get_range());
+        let row_selected = |ri: usize| match &range {
+            Some((start, end)) => ri >= start.row && ri <= end.row,
+            None => ri == self.book.location.row,
+        };
+        let col_selected = |ci: usize| match &range {
+            Some((start, end)) => ci >= start.col && ci <= end.col,
+            None => ci == self.book.location.col,
+        };
+        let rows: Vec<Row> = visible_rows
+            .into_iter()
+            .map(|ri| {
+                let mut label = Cell::new(Text::from(ri.to_string()));
+                if row_selected(ri) {
+                    label = label.style(self.theme.header_selected_style());
+                }
+                let mut cells = vec![label];
+                // Text that didn't fit in its cell carries on into the
+                // empty cells after it.
+                let mut spill: Option<String> = None;
+                for VisibleColumn { idx: ci, length } in visible_columns.iter() {
+                    let content = self
+                        .book
+                        .get_cell_addr_rendered(&Address {
+                            row: ri,
+                            col: *ci,
+                            sheet: self.book.location.sheet,
+                        })
+                        .unwrap();
+                    let content = if content.is_empty() {
+                        spill.take().unwrap_or_default()
+                    } else {
+                        content
+                    };
+                    let (cell, rest) = self.compute_cell_style(ri, *ci, content, *length);
+                    spill = rest;
+                    cells.push(cell);
+                }
+                Row::new(cells)
+            })
+            .collect();
         let constraints: Vec<Constraint> = visible_columns
             .iter()
             .map(|vc| Constraint::from(vc))
             .collect();
-        let end_idx = visible_columns.last().unwrap().idx;
         let mut header = Vec::with_capacity(constraints.len());
         header.push(Cell::new(""));
-        header.extend((state.prev_corner.col..=end_idx).map(|i| {
-            let count = if i == 26 { 1 } else { (i / 26) + 1 };
-            let even = i % 2 == 0;
-            Cell::new(Line::raw(COLNAMES[(i - 1) % 26].repeat(count)).centered())
-                .bg(if even {
-                    Color::Rgb(57, 61, 71)
-                } else {
-                    Color::Rgb(165, 169, 160)
-                })
-                .fg(if even { Color::White } else { Color::Black })
+        header.extend(visible_columns.iter().map(|vc| {
+            let i = vc.idx;
+            let even = !self.stripes || i % 2 == 0;
+            let style = if col_selected(i) {
+                self.theme.header_selected_style()
+            } else {
+                self.theme.header_style(even)
+            };
+            Cell::new(Line::raw(book::col_to_name(i)).centered())
+                .style(style)
                 .bold()
         }));
-        let mut col_constraints = vec![Constraint::Length(5)];
+        let mut col_constraints = vec![Constraint::Length(ROW_LABEL_WIDTH)];
         col_constraints.extend(constraints.into_iter());
         Ok(Table::new(rows, col_constraints)
             .header(Row::new(header).underlined())
-            .column_spacing(0)
+            .column_spacing(self.spacing())
             .flex(Flex::Start))
     }
 
+    /// Build the cell at `ri`, `ci`. Left aligned text wider than the column
+    /// is cut at `width` and the rest is returned so it can spill into the
+    /// next cell.
     fn compute_cell_style<'widget>(
         &self,
         ri: usize,
         ci: usize,
-        mut cell: Cell<'widget>,
-    ) -> Cell<'widget> {
+        content: String,
+        width: u16,
+    ) -> (Cell<'widget>, Option<String>) {
         // TODO(zaphar): Should probably create somekind of formatter abstraction.
-        if let Some(style) = self.book.get_cell_style(&Address {
+        let addr = Address {
             sheet: self.book.location.sheet,
             row: ri,
             col: ci,
-        }) {
-            cell = self.compute_cell_colors(&style, ri, ci, cell);
+        };
+        let style = self.book.get_cell_style(&addr);
+        let number = self.book.get_cell_addr_number(&addr);
+        // Rows are a single line tall so wrap_text and vertical alignment
+        // have nothing to act on.
+        let alignment = style
+            .as_ref()
+            .and_then(|s| s.alignment.as_ref())
+            .map(|a| &a.horizontal);
+        let spills = number.is_none()
+            && matches!(
+                alignment,
+                None | Some(HorizontalAlignment::General | HorizontalAlignment::Left)
+            );
+        let width = width as usize;
+        let (content, rest) =
+            if spills && !content.contains('\n') && content.chars().count() > width {
+                (
+                    content.chars().take(width).collect(),
+                    Some(content.chars().skip(width).collect()),
+                )
+            } else {
+                (content, None)
+            };
+        let text = Text::raw(content);
+        let text = match alignment {
+            Some(HorizontalAlignment::Left) => text.left_aligned(),
+            Some(HorizontalAlignment::Center | HorizontalAlignment::CenterContinuous) => {
+                text.centered()
+            }
+            Some(HorizontalAlignment::Right) => text.right_aligned(),
+            _ if number.is_some() => text.right_aligned(),
+            _ => text,
+        };
+        let mut cell = Cell::new(text);
+        if let Some(style) = style {
+            cell = self.compute_cell_colors(&style, number, ri, ci, cell);
             cell = if style.font.b { cell.bold() } else { cell };
             cell = if style.font.i { cell.italic() } else { cell };
+            cell = if style.font.u {
+                cell.underlined()
+            } else {
+                cell
+            };
+            cell = if style.font.strike {
+                cell.crossed_out()
+            } else {
+                cell
+            };
         }
-        cell
+        let selected = self.book.location.row == ri && self.book.location.col == ci;
+        if !selected && self.book.is_cell_addr_error(&addr) {
+            cell = cell.fg(Color::Red).bold();
+        }
+        if self
+            .point
+            .as_ref()
+            .is_some_and(|p| p.row == ri && p.col == ci)
+        {
+            cell = if self.theme.reversed {
+                cell.reversed().underlined()
+            } else {
+                cell.fg(self.theme.range_fg)
+                    .bg(self.theme.range_bg)
+                    .underlined()
+            };
+        }
+        (cell, rest)
     }
 
     fn compute_cell_colors<'widget>(
         &self,
         style: &ironcalc::base::types::Style,
+        number: Option<f64>,
         ri: usize,
         ci: usize,
         mut cell: Cell<'widget>,
     ) -> Cell<'widget> {
-        let bg_color = map_color(style.fill.bg_color.as_ref(), Color::Rgb(35, 33, 54));
-        let fg_color = map_color(style.fill.fg_color.as_ref(), Color::White);
-        if let Some((start, end)) = &self.range_selection.map_or(None, |r| r.get_range()) {
-            if ri >= start.row && ri <= end.row && ci >= start.col && ci <= end.col {
+        let theme = &self.theme;
+        let bg_color = map_color(style.fill.bg_color.as_ref(), theme.cell_bg);
+        let fg_color = number
+            .and_then(|n| num_fmt_color(&style.num_fmt, n))
+            .unwrap_or_else(|| map_color(style.fill.fg_color.as_ref(), theme.cell_fg));
+        if let Some(range) = self.range_selection.filter(|r| r.get_range().is_some()) {
+            if range.contains(ri, ci) {
                 // This is a selected range
-                cell = cell.fg(Color::Black).bg(Color::LightBlue)
+                cell = if theme.reversed {
+                    cell.bg(bg_color).fg(fg_color).reversed()
+                } else {
+                    cell.fg(theme.range_fg).bg(theme.range_bg)
+                }
             }
         } else {
             cell = cell.bg(bg_color).fg(fg_color);
         }
         cell = match (self.book.location.row == ri, self.book.location.col == ci) {
-            (true, true) => cell.fg(Color::White).bg(Color::Rgb(57, 61, 71)),
+            (true, true) if theme.reversed => cell.reversed(),
+            (true, true) => cell.fg(theme.selected_fg).bg(theme.selected_bg),
             // TODO(zaphar): Support ironcalc style options
             _ => cell,
         };
@@ -253,33 +489,75 @@ pub(crate) fn map_color(color: Option<&String>, otherwise: Color) -> Color {
             "lightcyan" => Color::LightCyan,
             "lightyellow" => Color::LightYellow,
             "darkgrey" | "darkgray" => Color::DarkGray,
-            candidate => {
-                // TODO(jeremy): Should we support more syntaxes than hex string?
-                // hsl(...) ??
-                if candidate.starts_with("#") {
-                    if let Ok(rgb) = colorsys::Rgb::from_hex_str(candidate) {
-                        // Note that the colorsys rgb model clamps the f64 values to no more
-                        // than 255.0 so the below casts are safe.
-                        Color::Rgb(rgb.red() as u8, rgb.green() as u8, rgb.blue() as u8)
-                    } else {
-                        otherwise
-                    }
-                } else if candidate.starts_with("rgb(") {
-                    if let Ok(rgb) = <colorsys::Rgb as std::str::FromStr>::from_str(candidate) {
-                        // Note that the colorsys rgb model clamps the f64 values to no more
-                        // than 255.0 so the below casts are safe.
-                        Color::Rgb(rgb.red() as u8, rgb.green() as u8, rgb.blue() as u8)
-                    } else {
-                        otherwise
-                    }
-                } else {
-                    otherwise
-                }
-            }
+            // Note that the colorsys rgb model clamps the f64 values to no more
+            // than 255.0 so the below casts are safe.
+            candidate => parse_rgb(candidate)
+                .map(|rgb| Color::Rgb(rgb.red() as u8, rgb.green() as u8, rgb.blue() as u8))
+                .unwrap_or(otherwise),
         })
         .unwrap_or(otherwise)
 }
 
+/// The color a number format like `#,##0.00;[Red](#,##0.00)` gives to
+/// `value`.
+///
+/// A format has up to four `;` separated sections. With one section it
+/// applies to every number. With two the second is for negative numbers and
+/// with three or more the third is for zero. Formats with conditions like
+/// `[>100]` pick their sections differently and get no color.
+pub(crate) fn num_fmt_color(num_fmt: &str, value: f64) -> Option<Color> {
+    let mut sections = vec![String::new()];
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for c in num_fmt.chars() {
+        let section = sections.last_mut().expect("There is always a section");
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == ';' && !in_quotes {
+            sections.push(String::new());
+            continue;
+        }
+        section.push(c);
+    }
+    let brackets = |section: &str| -> Vec<String> {
+        section
+            .split('[')
+            .skip(1)
+            .map(|token| token.split(']').next().unwrap_or_default().to_lowercase())
+            .collect()
+    };
+    if sections
+        .iter()
+        .flat_map(|section| brackets(section))
+        .any(|token| token.starts_with(['<', '>', '=']))
+    {
+        return None;
+    }
+    let idx = match sections.len() {
+        1 => 0,
+        _ if value < 0.0 => 1,
+        n if n > 2 && value == 0.0 => 2,
+        _ => 0,
+    };
+    brackets(&sections[idx])
+        .iter()
+        .find_map(|token| match token.as_str() {
+            "black" => Some(Color::Black),
+            "blue" => Some(Color::Blue),
+            "cyan" => Some(Color::Cyan),
+            "green" => Some(Color::Green),
+            "magenta" => Some(Color::Magenta),
+            "red" => Some(Color::Red),
+            "white" => Some(Color::White),
+            "yellow" => Some(Color::Yellow),
+            _ => None,
+        })
+}
+
 impl<'ws> StatefulWidget for Viewport<'ws> {
     type State = ViewportState;
 
@@ -289,9 +567,32 @@ impl<'ws> StatefulWidget for Viewport<'ws> {
         let mut table = self
             .to_table(area.width - 2, area.height - 2, state)
             .expect("Failed to turn viewport into a table.");
+        state.area = match &self.block {
+            Some(block) => block.inner(area),
+            None => area,
+        };
         if let Some(block) = self.block {
             table = table.block(block);
         }
         Widget::render(table, area, buf);
+        if self.gridlines {
+            let style = Style::default()
+                .fg(self.theme.gridline)
+                .bg(self.theme.cell_bg);
+            let bottom = std::cmp::min(
+                state.area.bottom(),
+                state.area.y + 1 + state.rows.len() as u16,
+            );
+            let mut x = state.area.x + ROW_LABEL_WIDTH;
+            for col in &state.columns {
+                if x >= state.area.right() {
+                    break;
+                }
+                for y in state.area.y..bottom {
+                    buf[(x, y)].set_symbol("│").set_style(style);
+                }
+                x += state.spacing + col.length;
+            }
+        }
     }
 }
@@ -1,14 +1,16 @@
 use std::process::ExitCode;
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use serial_test::serial;
 
-use crate::book;
-use crate::ui::cmd::parse_color;
-use crate::ui::{Address, ClipboardContents, Modality};
+use crate::book::{self, CaseMode, SeriesStart};
+use crate::ui::color::parse_color;
+use crate::ui::{Address, ClipboardContents, Modality, PendingAction, SelectionMode};
 
-use super::cmd::{parse, Cmd};
-use super::{parse_csv_rows, Workspace};
+use super::cmd::{parse, suggest_command, Cmd, Extent, COMMANDS};
+use super::{rows_from_html, rows_from_text, Workspace};
 
 #[derive(Default)]
 pub struct InputScript {
@@ -97,6 +99,68 @@ fn test_short_write_cmd() {
     assert_eq!(cmd, Cmd::Write(Some("foo.xlsx")));
 }
 
+#[test]
+fn test_write_quit_cmd() {
+    let input = "wq";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::WriteQuit(None));
+}
+
+#[test]
+fn test_write_quit_cmd_with_path() {
+    let input = "wq other.xlsx";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::WriteQuit(Some("other.xlsx")));
+}
+
+#[test]
+fn test_write_quit_cmd_short() {
+    let input = "x other.sui";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::WriteQuit(Some("other.sui")));
+}
+
+#[test]
+fn test_write_quit_cmd_invalid() {
+    let result = parse("wqfoo");
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_set_cmd() {
+    let input = "set backup on";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::Set("backup", "on"));
+}
+
+#[test]
+fn test_set_cmd_missing_value() {
+    assert!(parse("set backup").is_err());
+}
+
+#[test]
+fn test_set_cmd_no_args() {
+    let result = parse("set");
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Some(Cmd::ShowSettings));
+}
+
 #[test]
 fn test_insert_rows_cmd() {
     let input = "insert-rows 1";
@@ -149,7 +213,7 @@ fn test_edit_cmd() {
     let output = result.unwrap();
     assert!(output.is_some());
     let cmd = output.unwrap();
-    assert_eq!(cmd, Cmd::Edit("path.txt"));
+    assert_eq!(cmd, Cmd::Edit("path.txt", false));
 }
 
 #[test]
@@ -160,7 +224,7 @@ fn test_edit_cmd_short() {
     let output = result.unwrap();
     assert!(output.is_some());
     let cmd = output.unwrap();
-    assert_eq!(cmd, Cmd::Edit("path.txt"));
+    assert_eq!(cmd, Cmd::Edit("path.txt", false));
 }
 
 #[test]
@@ -207,6 +271,31 @@ fn test_quit_cmd() {
     assert_eq!(cmd, Cmd::Quit);
 }
 
+#[test]
+fn test_force_quit_cmd() {
+    for input in ["q!", "quit!"] {
+        let result = parse(input);
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.is_some());
+        let cmd = output.unwrap();
+        assert_eq!(cmd, Cmd::ForceQuit);
+    }
+}
+
+#[test]
+fn test_quit_cmd_rejects_argument() {
+    assert!(parse("q foo").is_err());
+    assert!(parse("q! foo").is_err());
+}
+
+#[test]
+fn test_write_quit_cmd_bang() {
+    let result = parse("wq!");
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Some(Cmd::WriteQuit(None)));
+}
+
 #[test]
 fn test_cmd_new_sheet_with_name() {
     let input = "new-sheet test";
@@ -229,6 +318,153 @@ fn test_cmd_export() {
     assert_eq!(cmd, Cmd::ExportCsv("test.csv"));
 }
 
+#[test]
+fn test_cmd_export_md() {
+    let input = "export-md test.md";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::ExportMd("test.md"));
+}
+
+#[test]
+fn test_cmd_export_all() {
+    let input = "export-all out/";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::ExportAll("out/"));
+}
+
+#[test]
+fn test_cmd_registers() {
+    let input = "registers";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::Registers);
+}
+
+#[test]
+fn test_cmd_errors() {
+    assert_eq!(Ok(Some(Cmd::Errors)), parse("errors"));
+    assert!(parse("errors A1").is_err());
+}
+
+#[test]
+fn test_cmd_next_and_prev_error() {
+    assert_eq!(Ok(Some(Cmd::NextError)), parse("next-error"));
+    assert_eq!(Ok(Some(Cmd::PrevError)), parse("prev-error"));
+    assert!(parse("next-error 2").is_err());
+}
+
+#[test]
+fn test_cmd_goto() {
+    assert_eq!(Ok(Some(Cmd::Goto("C10"))), parse("goto C10"));
+    assert!(parse("goto").is_err());
+    assert!(parse("gotoC10").is_err());
+}
+
+#[test]
+fn test_cmd_marks() {
+    assert_eq!(Ok(Some(Cmd::Marks)), parse("marks"));
+    assert!(parse("marks a").is_err());
+}
+
+#[test]
+fn test_cmd_dedupe() {
+    assert_eq!(Ok(Some(Cmd::Dedupe(false))), parse("dedupe"));
+    assert_eq!(Ok(Some(Cmd::Dedupe(true))), parse("dedupe shift"));
+    assert!(parse("dedupe up").is_err());
+    assert!(parse("dedupes").is_err());
+}
+
+#[test]
+fn test_cmd_trim() {
+    assert_eq!(Ok(Some(Cmd::Trim(false))), parse("trim"));
+    assert_eq!(Ok(Some(Cmd::Trim(true))), parse("trim!"));
+    assert!(parse("trim all").is_err());
+}
+
+#[test]
+fn test_cmd_case() {
+    assert_eq!(Ok(Some(Cmd::Case(CaseMode::Upper))), parse("case upper"));
+    assert_eq!(Ok(Some(Cmd::Case(CaseMode::Lower))), parse("case lower"));
+    assert_eq!(Ok(Some(Cmd::Case(CaseMode::Title))), parse("case  title "));
+    assert!(parse("case").is_err());
+    assert!(parse("case sentence").is_err());
+    assert!(parse("cases upper").is_err());
+}
+
+#[test]
+fn test_cmd_split_column() {
+    let split = |delimiter: &str, insert| Ok(Some(Cmd::SplitColumn(delimiter.to_string(), insert)));
+    assert_eq!(split(",", false), parse("split-col"));
+    assert_eq!(split(";", false), parse("split-col ;"));
+    assert_eq!(split("\t", true), parse("split-col! \\t"));
+    assert_eq!(split("::", false), parse("split-col  :: "));
+    assert_eq!(split(" ", false), parse("split-col \\s"));
+    assert_eq!(split(" ", false), parse("split-col \" \""));
+    assert_eq!(split(" - ", true), parse("split-col! \" - \""));
+    assert!(parse("split-col \"\"").is_err());
+    assert!(parse("split-col;").is_err());
+}
+
+#[test]
+fn test_cmd_fill() {
+    assert_eq!(Ok(Some(Cmd::Fill(None, None))), parse("fill"));
+    assert_eq!(
+        Ok(Some(Cmd::Fill(Some(SeriesStart::Number(1.0)), Some(1.0)))),
+        parse("fill 1 step 1")
+    );
+    assert_eq!(
+        Ok(Some(Cmd::Fill(Some(SeriesStart::Number(0.5)), None))),
+        parse("fill 0.5")
+    );
+    assert_eq!(Ok(Some(Cmd::Fill(None, Some(-2.0)))), parse("fill step -2"));
+    assert_eq!(
+        Ok(Some(Cmd::Fill(SeriesStart::parse("2024-01-01"), Some(7.0)))),
+        parse("fill 2024-01-01 step 7d")
+    );
+    assert!(parse("fill one").is_err());
+    assert!(parse("fill 1 step").is_err());
+    assert!(parse("fill 1 step x").is_err());
+    assert!(parse("fill 1 2").is_err());
+}
+
+#[test]
+fn test_cmd_precedents_and_dependents() {
+    assert_eq!(Ok(Some(Cmd::Precedents)), parse("precedents"));
+    assert_eq!(Ok(Some(Cmd::Dependents)), parse("dependents"));
+    assert!(parse("precedents A1").is_err());
+    assert!(parse("dependents A1").is_err());
+}
+
+#[test]
+fn test_cmd_table_covers_the_parser() {
+    for info in COMMANDS {
+        for name in std::iter::once(&info.name).chain(info.aliases) {
+            assert_ne!(Ok(None), parse(name), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_cmd_suggest_command() {
+    assert_eq!(Some("color-cell"), suggest_command("colr-cell"));
+    assert_eq!(Some("write"), suggest_command("wrte"));
+    assert_eq!(Some("export-csv"), suggest_command("Export-cvs"));
+    assert_eq!(None, suggest_command("bogus"));
+    assert_eq!(None, suggest_command("z"));
+    assert_eq!(Some("set"), suggest_command("sst"));
+}
+
 #[test]
 fn test_cmd_new_sheet_no_name() {
     let input = "new-sheet";
@@ -292,7 +528,10 @@ fn test_cmd_color_rows_with_idx_and_color() {
     let output = result.unwrap();
     assert!(output.is_some());
     let cmd = output.unwrap();
-    assert_eq!(cmd, Cmd::ColorRows(Some(1), parse_color("red").unwrap()));
+    assert_eq!(
+        cmd,
+        Cmd::ColorRows(Some(Extent::Count(1)), parse_color("red").unwrap())
+    );
 }
 
 #[test]
@@ -314,7 +553,164 @@ fn test_cmd_color_columns_with_idx_and_color() {
     let output = result.unwrap();
     assert!(output.is_some());
     let cmd = output.unwrap();
-    assert_eq!(cmd, Cmd::ColorColumns(Some(1), parse_color("red").unwrap()));
+    assert_eq!(
+        cmd,
+        Cmd::ColorColumns(Some(Extent::Count(1)), parse_color("red").unwrap())
+    );
+}
+
+#[test]
+fn test_cmd_color_rows_and_columns_extents() {
+    let rows = |extent| Cmd::ColorRows(Some(extent), parse_color("red").unwrap());
+    let columns = |extent| Cmd::ColorColumns(Some(extent), parse_color("red").unwrap());
+    let cases = vec![
+        ("color-rows +3 red", rows(Extent::Count(3))),
+        ("color-rows 5-8 red", rows(Extent::Between(5, 8))),
+        ("color-rows 8-5 red", rows(Extent::Between(5, 8))),
+        ("color-rows 10-10 red", rows(Extent::Between(10, 10))),
+        ("color-columns +2 red", columns(Extent::Count(2))),
+        ("color-columns B-D red", columns(Extent::Between(2, 4))),
+        ("color-columns 2-4 red", columns(Extent::Between(2, 4))),
+        ("color-columns c red", columns(Extent::Between(3, 3))),
+        (
+            "color-columns rgb(1, 2, 3)",
+            Cmd::ColorColumns(None, String::from("#010203")),
+        ),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(Ok(Some(expected)), parse(input), "{}", input);
+    }
+    for input in [
+        "color-rows 0-3 red",
+        "color-rows B-D red",
+        "color-rows +x blue",
+        "color-rows 18446744073709551615 red",
+        "color-rows +1048577 red",
+    ] {
+        assert_eq!(
+            Err("Invalid command: Use `color-rows N-M <color>` for rows N to M or `color-rows +count <color>` for rows from the cursor"),
+            parse(input),
+            "{}",
+            input
+        );
+    }
+    assert!(parse("color-columns B-XFE red").is_err());
+    assert!(parse("color-columns +16385 red").is_err());
+}
+
+#[test]
+fn test_color_rows_count_stops_at_the_last_row() {
+    let mut ws = new_workspace();
+    ws.book
+        .move_to(&Address::new(crate::book::LAST_ROW as usize, 1))
+        .expect("Failed to move");
+    ws.run_command(
+        Cmd::ColorRows(Some(Extent::Count(usize::MAX)), String::from("#ff0000")),
+        None,
+    )
+    .expect("Failed to color rows");
+    assert_eq!(Some("Colored 1 row"), ws.state.status());
+}
+
+#[test]
+fn test_parse_color() {
+    for (color, hex) in [
+        ("hsl(0, 100%, 50%)", "#ff0000"),
+        ("hsl(240, 100%, 50%)", "#0000ff"),
+        ("HSL(60,100%,50%)", "#ffff00"),
+        ("hsl(0, 0%, 100%)", "#ffffff"),
+        ("hsl(300, 0, 0)", "#000000"),
+        ("#35F15B", "#35f15b"),
+        ("rgb(1, 2, 3)", "#010203"),
+    ] {
+        assert_eq!(Ok(String::from(hex)), parse_color(color), "{}", color);
+    }
+    assert_eq!(
+        Ok(colorsys::Ansi256::new(33).as_rgb().to_hex_string()),
+        parse_color("33")
+    );
+    assert_eq!(parse_color("red"), parse_color("1"));
+    for bad in [
+        "300",
+        "-1",
+        "hsl(361, 50%, 50%)",
+        "hsl(0, 101%, 50%)",
+        "hsl(0, 50%)",
+        "hsl(0, 50%, 50%",
+        "#nothex",
+    ] {
+        assert_eq!(Err("Invalid color"), parse_color(bad), "{}", bad);
+    }
+}
+
+#[test]
+fn test_cmd_color_cell() {
+    let red = parse_color("red").unwrap();
+    assert_eq!(
+        Ok(Some(Cmd::ColorCell(None, red.clone()))),
+        parse("color-cell red")
+    );
+    let range = book::formula::Reference {
+        sheet: None,
+        start: (2, 2),
+        end: (10, 4),
+        span: 0..6,
+    };
+    assert_eq!(
+        Ok(Some(Cmd::ColorCell(Some(range), red.clone()))),
+        parse("color-cell B2:D10 red")
+    );
+    let range = book::formula::Reference {
+        sheet: Some(String::from("Sheet2")),
+        start: (1, 1),
+        end: (1, 1),
+        span: 0..9,
+    };
+    assert_eq!(
+        Ok(Some(Cmd::ColorCell(Some(range), red.clone()))),
+        parse("cc Sheet2!A1 red")
+    );
+    // Colors with spaces in them aren't mistaken for a range.
+    assert_eq!(
+        Ok(Some(Cmd::ColorCell(None, String::from("#010203")))),
+        parse("color-cell rgb(1, 2, 3)")
+    );
+    assert_eq!(
+        Err("Invalid command: Expected a range like `B2:D10` before the color"),
+        parse("color-cell B2:D0 red")
+    );
+}
+
+#[test]
+fn test_cmd_style() {
+    assert_eq!(
+        Ok(Some(Cmd::Style("font.b", String::from("true")))),
+        parse("style font.b true")
+    );
+    assert_eq!(
+        Ok(Some(Cmd::Style("num_fmt", String::from("0.00 \"kg\"")))),
+        parse("style num_fmt \"0.00 \\\"kg\\\"\"")
+    );
+    assert_eq!(
+        Ok(Some(Cmd::Style("font.color", String::from("#800000")))),
+        parse("style font.color red")
+    );
+    assert_eq!(
+        Err("Invalid command: Quote values with spaces like `style num_fmt \"0 kg\"`"),
+        parse("style num_fmt 0 kg")
+    );
+    assert_eq!(
+        Err("Invalid command: Missing the closing quote"),
+        parse("style num_fmt \"0 kg")
+    );
+    assert_eq!(
+        Err("Invalid command: Unknown style path. `help style` lists them"),
+        parse("style font.bold true")
+    );
+    assert_eq!(
+        Err("Invalid command: Expected a style path and a value like `style font.b true`"),
+        parse("style font.b")
+    );
 }
 
 #[test]
@@ -453,6 +849,66 @@ fn test_command_mode_help_keycode() {
     assert_eq!(Some(edit_help), ws.state.popup);
 }
 
+fn popup_lines(ws: &Workspace) -> Vec<String> {
+    let popup = ws.state.popup.as_ref().expect("No popup was shown");
+    popup
+        .get_text()
+        .lines
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn test_help_for_a_command() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("help write")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let lines = popup_lines(&ws);
+    assert_eq!("write", lines[0]);
+    assert!(lines.contains(&"write [path]".to_string()));
+    assert!(lines.contains(&"w".to_string()), "{:?}", lines);
+}
+
+#[test]
+fn test_help_for_a_command_by_alias() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("? ir")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!("insert-rows", popup_lines(&ws)[0]);
+}
+
+#[test]
+fn test_help_for_an_unknown_topic_lists_the_commands() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("help bogus")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run");
+    let lines = popup_lines(&ws);
+    assert_eq!("Commands", lines[0]);
+    for info in COMMANDS {
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with(&format!("* {}", info.name))),
+            "{} is missing from the index",
+            info.name
+        );
+    }
+}
+
 #[test]
 fn test_edit_mode_esc_keycode() {
     let mut ws = new_workspace();
@@ -1340,6 +1796,85 @@ fn test_color_cells() {
     }
 }
 
+#[test]
+fn test_color_cell_after_copy_colors_the_cursor() {
+    let mut ws = new_workspace();
+    script()
+        .char('v')
+        .chars("jj")
+        .char('y')
+        .chars("5jl")
+        .char(':')
+        .chars("color-cell red")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    let bg_color = |ws: &Workspace, address: &Address| {
+        ws.book
+            .get_cell_style(address)
+            .and_then(|style| style.fill.bg_color)
+    };
+    let cursor = ws.book.location.clone();
+    assert_eq!(Some(String::from("#800000")), bg_color(&ws, &cursor));
+    assert_eq!(None, bg_color(&ws, &Address::new(1, 1)));
+    assert_eq!(None, bg_color(&ws, &Address::new(2, 1)));
+}
+
+#[test]
+fn test_color_cells_in_a_range() {
+    let mut ws = new_workspace();
+    ws.book
+        .new_sheet(Some("Sheet2"))
+        .expect("Failed to create Sheet2");
+    script()
+        .char(':')
+        .chars("color-cell B2:D10 red")
+        .enter()
+        .char(':')
+        .chars("color-cell Sheet2!A1 green")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    let bg_color = |sheet: u32, row: usize, col: usize| {
+        ws.book
+            .get_cell_style(&Address { sheet, row, col })
+            .and_then(|style| style.fill.bg_color)
+    };
+    assert_eq!(Some(String::from("#800000")), bg_color(0, 5, 3));
+    assert_eq!(None, bg_color(0, 1, 1));
+    assert_eq!(None, bg_color(0, 5, 5));
+    assert_eq!(Some(String::from("#008000")), bg_color(1, 1, 1));
+    // The cursor stays put.
+    assert_eq!(Address::new(1, 1), ws.book.location);
+}
+
+#[test]
+fn test_style_cells() {
+    let mut ws = new_workspace();
+    script()
+        .char('v')
+        .chars("jl")
+        .char(':')
+        .chars("style num_fmt \"0.00%\"")
+        .enter()
+        .char(':')
+        .chars("style font.b true")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    let style = |row, col| {
+        ws.book
+            .get_cell_style(&Address::new(row, col))
+            .expect("failed to get style")
+    };
+    assert_eq!("0.00%", style(1, 1).num_fmt);
+    assert_eq!("0.00%", style(2, 2).num_fmt);
+    assert_ne!("0.00%", style(3, 3).num_fmt);
+    assert!(style(2, 2).font.b);
+    assert!(!style(1, 3).font.b);
+}
+
 #[test]
 fn test_color_row() {
     let mut ws = new_workspace();
@@ -1370,14 +1905,50 @@ fn test_color_row() {
 }
 
 #[test]
-fn test_color_col() {
+fn test_color_rows_and_columns_by_index() {
     let mut ws = new_workspace();
     script()
+        .chars("jj")
         .char(':')
-        .chars("color-columns red")
+        .chars("color-rows 5-6 red")
         .enter()
-        .run(&mut ws)
-        .expect("Unable to run script");
+        .char(':')
+        .chars("color-columns B-C green")
+        .enter()
+        .char(':')
+        .chars("color-rows +2 blue")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    let bg_color = |row: usize, col: usize| {
+        ws.book
+            .get_cell_style(&Address::new(row, col))
+            .and_then(|style| style.fill.bg_color)
+    };
+    let red = parse_color("red").ok();
+    let green = parse_color("green").ok();
+    let blue = parse_color("blue").ok();
+    assert_eq!(red, bg_color(5, 4));
+    assert_eq!(red, bg_color(6, 1));
+    assert_eq!(green, bg_color(1, 2));
+    assert_eq!(green, bg_color(1, 3));
+    assert_eq!(None, bg_color(1, 4));
+    // Counts still go down from the cursor.
+    assert_eq!(blue, bg_color(3, 5));
+    assert_eq!(blue, bg_color(4, 5));
+    assert_eq!(None, bg_color(2, 5));
+    assert_eq!(None, bg_color(7, 5));
+}
+
+#[test]
+fn test_color_col() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("color-columns red")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
     for ri in [1, book::LAST_ROW] {
         let style = ws
             .book
@@ -1478,7 +2049,9 @@ fn test_quit_dialog() {
     let mut ws = new_workspace();
     assert!(!ws.book.dirty);
     let mut result = script()
+        .char(':')
         .char('q')
+        .enter()
         .run(&mut ws)
         .expect("Failed to run input script");
     assert!(result.is_some());
@@ -1490,7 +2063,9 @@ fn test_quit_dialog() {
         .expect("Failed to modify book");
     assert!(ws.book.dirty);
     result = script()
+        .char(':')
         .char('q')
+        .enter()
         .run(&mut ws)
         .expect("Failed to run input script");
     assert!(!result.is_some());
@@ -1503,7 +2078,9 @@ fn test_quit_dialog() {
     assert_eq!(ws.state.modality(), &Modality::default());
     assert!(ws.book.dirty);
     script()
+        .char(':')
         .char('q')
+        .enter()
         .esc()
         .run(&mut ws)
         .expect("Failed to run input script");
@@ -1518,32 +2095,126 @@ fn test_quit_dialog() {
     //assert!(!ws.book.dirty);
 }
 
-// parse_csv_rows unit tests
+// rows_from_text unit tests
 
 #[test]
-fn test_parse_csv_rows_preserves_first_row() {
+fn test_rows_from_text_preserves_first_row() {
     let csv_text = "A1,B1\nA2,B2\n";
-    let rows = parse_csv_rows(csv_text).expect("Failed to parse csv");
+    let rows = rows_from_text(csv_text).expect("Failed to parse csv");
     assert_eq!(rows.len(), 2);
     assert_eq!(rows[0], vec!["A1", "B1"]);
     assert_eq!(rows[1], vec!["A2", "B2"]);
 }
 
 #[test]
-fn test_parse_csv_rows_single_cell() {
+fn test_rows_from_text_single_cell() {
     let csv_text = "hello\n";
-    let rows = parse_csv_rows(csv_text).expect("Failed to parse csv");
+    let rows = rows_from_text(csv_text).expect("Failed to parse csv");
     assert_eq!(rows.len(), 1);
     assert_eq!(rows[0], vec!["hello"]);
 }
 
 #[test]
-fn test_parse_csv_rows_empty() {
+fn test_rows_from_text_empty() {
     let csv_text = "";
-    let rows = parse_csv_rows(csv_text).expect("Failed to parse csv");
+    let rows = rows_from_text(csv_text).expect("Failed to parse csv");
     assert_eq!(rows.len(), 0);
 }
 
+#[test]
+fn test_rows_from_text_tsv() {
+    let tsv_text = "A1\tB1, with comma\nA2\tB2\n";
+    let rows = rows_from_text(tsv_text).expect("Failed to parse tsv");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], vec!["A1", "B1, with comma"]);
+    assert_eq!(rows[1], vec!["A2", "B2"]);
+}
+
+#[test]
+fn test_rows_from_text_csv() {
+    let csv_text = "A1,\"B1\tB1\"\nA2,B2\n";
+    let rows = rows_from_text(csv_text).expect("Failed to parse csv");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], vec!["A1", "B1\tB1"]);
+    assert_eq!(rows[1], vec!["A2", "B2"]);
+}
+
+#[test]
+fn test_rows_from_text_plain_single_cell() {
+    let text = "a \"quoted\" value";
+    let rows = rows_from_text(text).expect("Failed to parse text");
+    assert_eq!(rows, vec![vec!["a \"quoted\" value"]]);
+}
+
+#[test]
+fn test_rows_from_text_two_rows() {
+    let rows = rows_from_text("a,b\nc,d").expect("Failed to parse csv");
+    assert_eq!(rows, vec![vec!["a", "b"], vec!["c", "d"]]);
+}
+
+#[test]
+fn test_rows_from_text_ragged_rows() {
+    let csv_text = "A1,B1,C1\nA2\nA3,B3\n";
+    let rows = rows_from_text(csv_text).expect("Failed to parse ragged csv");
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0], vec!["A1", "B1", "C1"]);
+    assert_eq!(rows[1], vec!["A2"]);
+    assert_eq!(rows[2], vec!["A3", "B3"]);
+}
+
+#[test]
+fn test_rows_from_text_quoted_newline() {
+    let csv_text = "A1,\"multi\nline\"\nA2,B2\n";
+    let rows = rows_from_text(csv_text).expect("Failed to parse csv");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], vec!["A1", "multi\nline"]);
+    assert_eq!(rows[1], vec!["A2", "B2"]);
+}
+
+#[test]
+fn test_rows_from_html_libreoffice_fragment() {
+    let html = r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.0 Transitional//EN">
+<html><head><meta http-equiv="content-type" content="text/html; charset=utf-8"/><title></title>
+<style type="text/css">
+	body,div,table,thead,tbody,tfoot,tr,th,td,p { font-family:"Liberation Sans"; font-size:x-small }
+</style>
+</head>
+<body>
+<table cellspacing="0" border="0">
+	<colgroup width="85"></colgroup>
+	<colgroup width="117"></colgroup>
+	<tr>
+		<td height="17" align="left">Name</td>
+		<td align="left">Notes</td>
+	</tr>
+	<tr>
+		<td height="17" align="left">Smith &amp; Co</td>
+		<td align="left">a, b<br>c&nbsp;d</td>
+	</tr>
+	<tr>
+		<td height="17" align="right" sdval="42" sdnum="1033;">42</td>
+		<td align="left"><br></td>
+	</tr>
+</table>
+<!-- ************************************************************************** -->
+</body>
+</html>"#;
+    let rows = rows_from_html(html).expect("Failed to parse html");
+    assert_eq!(
+        rows,
+        vec![
+            vec!["Name", "Notes"],
+            vec!["Smith & Co", "a, b\nc d"],
+            vec!["42", ""],
+        ]
+    );
+}
+
+#[test]
+fn test_rows_from_html_no_table() {
+    assert!(rows_from_html("<p>just some text</p>").is_none());
+}
+
 // System clipboard integration tests
 
 #[test]
@@ -1642,178 +2313,3199 @@ fn test_paste_from_internal_range_clipboard() {
     assert_eq!(ws.book.get_current_cell_contents().unwrap(), "B1");
 }
 
-fn new_workspace<'a>() -> Workspace<'a> {
-    Workspace::new_empty("en", "America/New_York").expect("Failed to get empty workbook")
-}
-
-// ---------------------------------------------------------------------------
-// Phase 3: UI/Command Integration (REQ-005..REQ-009)
-// ---------------------------------------------------------------------------
-
-fn ui_tmp_path(name: &str) -> std::path::PathBuf {
-    std::env::temp_dir().join(format!("sheetui_ui_p3_{name}"))
+#[test]
+fn test_named_register_yank_and_paste() {
+    let mut ws = new_workspace();
+    ws.book.edit_current_cell("first").expect("Failed to edit cell");
+    script().char('"').char('a').char('y').run(&mut ws).unwrap();
+    script().char('j').run(&mut ws).unwrap();
+    ws.book.edit_current_cell("second").expect("Failed to edit cell");
+    script().char('"').char('b').char('y').run(&mut ws).unwrap();
+    assert!(ws.state.clipboard.is_none());
+    assert!(ws.state.register.is_none());
+    script().char('l').char('"').char('a').char('p').run(&mut ws).unwrap();
+    assert_eq!(ws.book.get_current_cell_contents().unwrap(), "first");
+    script().char('l').char('"').char('a').char('p').run(&mut ws).unwrap();
+    assert_eq!(ws.book.get_current_cell_contents().unwrap(), "first");
+    script().char('l').char('"').char('b').char('p').run(&mut ws).unwrap();
+    assert_eq!(ws.book.get_current_cell_contents().unwrap(), "second");
 }
 
 #[test]
-fn test_new_workspace_default_format_is_sui() {
-    // REQ-008: new empty workspace defaults to SUI format.
-    let ws = new_workspace();
+fn test_named_register_range_yank_and_paste() {
+    let mut ws = new_workspace();
+    ws.book.edit_current_cell("A1").expect("Failed to edit cell");
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 2 }, "B1")
+        .expect("Failed to update cell");
+    script()
+        .char('v')
+        .char('l')
+        .char('"')
+        .char('1')
+        .char('y')
+        .run(&mut ws)
+        .unwrap();
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(ws.state.clipboard.is_none());
+    match ws.state.registers.get(&'1') {
+        Some(ClipboardContents::Range(rows)) => {
+            assert_eq!(&vec![vec!["A1".to_string(), "B1".to_string()]], rows);
+        }
+        other => assert!(false, "Unexpected register contents {:?}", other),
+    }
+    script().char('j').char('"').char('1').char('p').run(&mut ws).unwrap();
     assert_eq!(
-        ws.book.get_format(),
-        &book::FileFormat::Sui,
-        "new workspace book must default to FileFormat::Sui"
+        ws.book
+            .get_cell_addr_contents(&Address { sheet: 0, row: 2, col: 1 })
+            .unwrap(),
+        "A1"
+    );
+    assert_eq!(
+        ws.book
+            .get_cell_addr_contents(&Address { sheet: 0, row: 2, col: 2 })
+            .unwrap(),
+        "B1"
     );
 }
 
 #[test]
-fn test_new_workspace_name_ends_with_sui() {
-    // REQ-008: new empty workspace name should default to .sui extension.
-    let ws = new_workspace();
-    assert!(
-        ws.name.to_string_lossy().ends_with(".sui"),
-        "new workspace path must end with .sui, got: {}",
-        ws.name.display()
-    );
+fn test_registers_command_opens_dialog() {
+    let mut ws = new_workspace();
+    ws.state
+        .registers
+        .insert('a', ClipboardContents::Cell("hello".to_string()));
+    script()
+        .char(':')
+        .chars("registers")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!(Some(ws.render_registers()), ws.state.popup);
 }
 
 #[test]
-fn test_write_cmd_saves_as_sui_and_updates_format() {
-    // REQ-007: :w path.sui saves as SUI and updates the stored format.
-    let path = ui_tmp_path("write_sui.sui");
+fn test_command_history_recall() {
+    use tui_prompts::State;
     let mut ws = new_workspace();
-    ws.book
-        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "write-sui-test")
-        .expect("set cell");
-    ws.book.evaluate();
     script()
         .char(':')
-        .chars(&format!("w {}", path.display()))
+        .chars("new-sheet first")
         .enter()
+        .char(':')
+        .chars("new-sheet second")
+        .enter()
+        .char(':')
+        .event(construct_key_event(KeyCode::Up))
         .run(&mut ws)
-        .expect("write command failed");
-    assert_eq!(
-        ws.book.get_format(),
-        &book::FileFormat::Sui,
-        "book format must be Sui after :w .sui"
-    );
-    let text = std::fs::read_to_string(&path).expect("read saved file");
-    assert!(
-        text.contains("[sheet"),
-        "saved .sui file must contain a sheet block, got: {text}"
-    );
-    std::fs::remove_file(&path).ok();
+        .expect("Failed to run");
+    assert_eq!(Some(&Modality::Command), ws.state.modality_stack.last());
+    assert_eq!("new-sheet second", ws.state.command_state.value());
+    script()
+        .event(construct_key_event(KeyCode::Up))
+        .event(construct_key_event(KeyCode::Up))
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!("new-sheet first", ws.state.command_state.value());
+    script()
+        .event(construct_key_event(KeyCode::Down))
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!("new-sheet second", ws.state.command_state.value());
+    script()
+        .event(construct_key_event(KeyCode::Down))
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!("", ws.state.command_state.value());
+
+    // What was typed before browsing comes back past the newest entry.
+    script()
+        .chars("new-sh")
+        .event(construct_key_event(KeyCode::Up))
+        .event(construct_key_event(KeyCode::Up))
+        .event(construct_key_event(KeyCode::Down))
+        .event(construct_key_event(KeyCode::Down))
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!("new-sh", ws.state.command_state.value());
 }
 
 #[test]
-fn test_write_cmd_saves_as_xlsx_and_updates_format() {
-    // REQ-007: :w path.xlsx saves as xlsx and updates the stored format.
-    let path = ui_tmp_path("write_xlsx.xlsx");
+fn test_command_history_dedupes_and_caps() {
+    let mut ws = new_workspace();
+    ws.state.push_command_history("new-sheet a");
+    ws.state.push_command_history("new-sheet a");
+    ws.state.push_command_history("  ");
+    assert_eq!(vec!["new-sheet a".to_string()], ws.state.command_history);
+    for i in 0..300 {
+        ws.state.push_command_history(&format!("new-sheet {}", i));
+    }
+    assert_eq!(200, ws.state.command_history.len());
+    assert_eq!("new-sheet 299", ws.state.command_history.last().unwrap());
+}
+
+#[test]
+fn test_status_message_for_command() {
     let mut ws = new_workspace();
-    ws.book
-        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "xlsx-write-test")
-        .expect("set cell");
-    ws.book.evaluate();
     script()
         .char(':')
-        .chars(&format!("w {}", path.display()))
+        .chars("insert-rows 3")
         .enter()
         .run(&mut ws)
-        .expect("write command failed");
-    assert_eq!(
-        ws.book.get_format(),
-        &book::FileFormat::Xlsx,
-        "book format must be Xlsx after :w .xlsx"
-    );
-    let bytes = std::fs::read(&path).expect("read saved xlsx");
-    assert!(
-        bytes.starts_with(b"PK"),
-        "saved .xlsx must be a valid zip/xlsx (starts with PK magic bytes)"
-    );
-    std::fs::remove_file(&path).ok();
+        .expect("Failed to run");
+    assert_eq!(Some("Inserted 3 rows"), ws.state.status());
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    script().char('j').run(&mut ws).expect("Failed to run");
+    assert_eq!(None, ws.state.status());
 }
 
 #[test]
-fn test_edit_cmd_loads_sui_file() {
-    // REQ-005: :e path.sui loads SUI content using the SUI parser.
-    let path = ui_tmp_path("edit_sui.sui");
-    let content = "[sheet \"Sheet1\"]\nA1 = \"loaded-via-edit\"\n[/sheet]\n";
-    std::fs::write(&path, content).expect("write temp .sui");
+fn test_status_message_for_unknown_command() {
     let mut ws = new_workspace();
     script()
         .char(':')
-        .chars(&format!("e {}", path.display()))
+        .chars("bogus")
         .enter()
         .run(&mut ws)
-        .expect("edit command failed");
-    assert_eq!(
-        ws.book.get_format(),
-        &book::FileFormat::Sui,
-        "book format must be Sui after :e .sui"
-    );
-    let val = ws
-        .book
-        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
-        .expect("get A1 after :e");
-    assert_eq!(val, "loaded-via-edit", "cell A1 must be loaded from .sui file");
-    std::fs::remove_file(&path).ok();
+        .expect("Failed to run");
+    assert_eq!(Some("Unrecognized command bogus"), ws.state.status());
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
 }
 
 #[test]
-fn test_write_then_edit_round_trip_sui() {
-    // REQ-006 + REQ-010: :w saves, :e reloads, cell values survive.
-    let path = ui_tmp_path("round_trip.sui");
+fn test_status_suggests_a_close_command() {
     let mut ws = new_workspace();
-    ws.book
-        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "round-trip-val")
-        .expect("set cell");
-    ws.book.evaluate();
     script()
         .char(':')
-        .chars(&format!("w {}", path.display()))
+        .chars("colr-cell red")
         .enter()
         .run(&mut ws)
-        .expect(":w command");
-    ws.book
-        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "")
-        .expect("clear cell locally");
+        .expect("Failed to run");
+    assert_eq!(
+        Some("Unrecognized command colr-cell. Did you mean color-cell?"),
+        ws.state.status()
+    );
+}
+
+fn dirty_workspace_in_quit_dialog<'a>() -> Workspace<'a> {
+    let mut ws = new_workspace();
     script()
+        .chars("efoo")
+        .enter()
         .char(':')
-        .chars(&format!("e {}", path.display()))
+        .char('q')
         .enter()
         .run(&mut ws)
-        .expect(":e command");
-    let val = ws
-        .book
-        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
-        .expect("get A1 after :e");
-    assert_eq!(val, "round-trip-val", "cell must survive :w/:e round-trip");
-    std::fs::remove_file(&path).ok();
+        .expect("Failed to run input script");
+    assert!(ws.book.dirty);
+    assert_eq!(Some(&Modality::Quit), ws.state.modality_stack.last());
+    ws
 }
 
 #[test]
-fn test_parse_warnings_stored_after_loading_malformed_sui() {
-    // REQ-009: loading a malformed .sui file stores warnings on the Book.
-    let path = ui_tmp_path("malformed.sui");
-    let content = "[sheet \"Sheet1\"]\nTHIS IS NOT VALID\nA1 = \"valid-cell\"\n[/sheet]\n";
-    std::fs::write(&path, content).expect("write malformed .sui");
-    let mut ws = new_workspace();
-    script()
+fn test_quit_dialog_cancel_keeps_running() {
+    for key in [construct_key_event(KeyCode::Esc), construct_key_event(KeyCode::Char('c'))] {
+        let mut ws = dirty_workspace_in_quit_dialog();
+        let result = script().event(key).run(&mut ws).expect("Failed to cancel");
+        assert_eq!(None, result);
+        assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+        assert!(ws.book.dirty);
+    }
+}
+
+#[test]
+fn test_quit_dialog_no_quits_without_saving() {
+    let mut ws = dirty_workspace_in_quit_dialog();
+    let result = script().char('n').run(&mut ws).expect("Failed to quit");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(ws.book.dirty);
+}
+
+#[test]
+fn test_quit_dialog_yes_saves_and_quits() {
+    let path = ui_tmp_path("quit_dialog_yes.sui");
+    let _ = std::fs::remove_file(&path);
+    let mut ws = dirty_workspace_in_quit_dialog();
+    ws.name = path.clone();
+    let result = script().char('y').run(&mut ws).expect("Failed to save and quit");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(!ws.book.dirty);
+    assert!(path.exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_quit_dialog_save_failure_keeps_running() {
+    let mut ws = dirty_workspace_in_quit_dialog();
+    // A directory can't be written as a file so the save will fail.
+    ws.name = std::env::temp_dir();
+    let result = script().char('y').run(&mut ws).expect("Failed to answer quit dialog");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert!(ws.book.dirty);
+    script().esc().run(&mut ws).expect("Failed to close dialog");
+    assert_eq!(Some(&Modality::Quit), ws.state.modality_stack.last());
+
+    let path = ui_tmp_path("quit_dialog_retry.sui");
+    let _ = std::fs::remove_file(&path);
+    let result = script()
         .char(':')
-        .chars(&format!("e {}", path.display()))
+        .chars(&format!("w {}", path.display()))
         .enter()
         .run(&mut ws)
-        .expect("edit command");
-    assert!(
-        !ws.book.parse_warnings.is_empty(),
-        "loading a malformed .sui file must store parse warnings on Book"
-    );
-    let val = ws
-        .book
-        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
-        .expect("get A1");
+        .expect("Failed to save to a new path");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(path.exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+fn new_workspace<'a>() -> Workspace<'a> {
+    Workspace::new_empty("en", "America/New_York").expect("Failed to get empty workbook")
+}
+
+// ---------------------------------------------------------------------------
+// Phase 3: UI/Command Integration (REQ-005..REQ-009)
+// ---------------------------------------------------------------------------
+
+fn ui_tmp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("sheetui_ui_p3_{name}"))
+}
+
+#[test]
+fn test_write_quit_command_saves_and_exits() {
+    let path = ui_tmp_path("write_quit.sui");
+    let _ = std::fs::remove_file(&path);
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "write-quit")
+        .expect("set cell");
+    let result = script()
+        .char(':')
+        .chars(&format!("wq {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("wq command failed");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(path.exists(), "wq should have written the file");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_zz_saves_and_exits() {
+    let path = ui_tmp_path("zz.sui");
+    let _ = std::fs::remove_file(&path);
+    let mut ws = new_workspace();
+    ws.name = path.clone();
+    script()
+        .chars("ezz-edit")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    assert!(ws.book.dirty);
+    let result = script().chars("ZZ").run(&mut ws).expect("ZZ failed");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    let saved = std::fs::read_to_string(&path).expect("ZZ should have written the file");
+    assert!(saved.contains("zz-edit"), "{}", saved);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_zq_quits_without_saving() {
+    let mut ws = new_workspace();
+    script()
+        .chars("eunsaved")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    // Esc and any other key drop a pending `Z`.
+    let result = script()
+        .char('Z')
+        .esc()
+        .char('Q')
+        .char('Z')
+        .char('j')
+        .char('Q')
+        .run(&mut ws)
+        .expect("Failed to cancel Z");
+    assert_eq!(None, result);
+    assert_eq!(Address::new(2, 1), ws.book.location);
+    let result = script().chars("ZQ").run(&mut ws).expect("ZQ failed");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(ws.book.dirty);
+}
+
+#[test]
+fn test_write_quit_command_stays_running_on_failure() {
+    let blocker = ui_tmp_path("write_quit_blocker");
+    std::fs::write(&blocker, "not a directory").expect("write blocker file");
+    let path = blocker.join("out.sui");
+    let mut ws = new_workspace();
+    let result = script()
+        .char(':')
+        .chars(&format!("wq {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("wq command errored");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let _ = std::fs::remove_file(&blocker);
+}
+
+#[test]
+fn test_force_quit_skips_dirty_check() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "unsaved")
+        .expect("set cell");
+    ws.book.dirty = true;
+    let result = script()
+        .char(':')
+        .chars("q!")
+        .enter()
+        .run(&mut ws)
+        .expect("q! command failed");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+
+    let result = script()
+        .char(':')
+        .chars("q")
+        .enter()
+        .run(&mut ws)
+        .expect("q command failed");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Quit), ws.state.modality_stack.last());
+}
+
+#[test]
+fn test_failed_operation_shows_error_dialog() {
+    let blocker = ui_tmp_path("write_error_blocker");
+    std::fs::write(&blocker, "not a directory").expect("write blocker file");
+    let path = blocker.join("out.sui");
+    let mut ws = new_workspace();
+    let result = script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws);
+    assert!(matches!(result, Ok(None)), "expected the app to keep running");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    script().esc().run(&mut ws).expect("Failed to close dialog");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    let _ = std::fs::remove_file(&blocker);
+}
+
+#[test]
+fn test_save_with_backup_keeps_previous_version() {
+    let path = ui_tmp_path("backup.sui");
+    let backup = ui_tmp_path("backup.sui.bak");
+    let _ = std::fs::remove_file(&backup);
+    let original = "[sheet \"Sheet1\"]\nA1 = \"yesterday\"\n[/sheet]\n";
+    std::fs::write(&path, original).expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "today")
+        .expect("set cell");
+    script()
+        .char(':')
+        .chars("set backup on")
+        .enter()
+        .run(&mut ws)
+        .expect("set command failed");
+    assert!(ws.state.settings.backup);
+    script()
+        .char(':')
+        .chars("w")
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
     assert_eq!(
-        val, "valid-cell",
-        "valid cell must be loaded despite malformed lines"
+        original,
+        std::fs::read_to_string(&backup).expect("backup should exist")
+    );
+    assert!(std::fs::read_to_string(&path).unwrap().contains("today"));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&backup);
+}
+
+#[test]
+fn test_save_without_backup_by_default() {
+    let path = ui_tmp_path("no_backup.sui");
+    let backup = ui_tmp_path("no_backup.sui.bak");
+    let _ = std::fs::remove_file(&backup);
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    script()
+        .char(':')
+        .chars("w")
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert!(!backup.exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+fn workspace_with_changed_file<'a>(name: &str) -> (Workspace<'a>, std::path::PathBuf) {
+    let path = ui_tmp_path(name);
+    std::fs::write(&path, "[sheet \"Sheet1\"]\nA1 = \"original\"\n[/sheet]\n")
+        .expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "ours")
+        .expect("set cell");
+    std::fs::write(
+        &path,
+        "[sheet \"Sheet1\"]\nA1 = \"changed by someone else\"\n[/sheet]\n",
+    )
+    .expect("modify temp .sui");
+    script()
+        .char(':')
+        .chars("w")
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!(Some(PendingAction::FileChanged), ws.state.pending_action);
+    (ws, path)
+}
+
+#[test]
+fn test_save_changed_file_cancel() {
+    let (mut ws, path) = workspace_with_changed_file("changed_cancel.sui");
+    script().esc().run(&mut ws).expect("Failed to cancel");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(None, ws.state.pending_action);
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains("changed by someone else"));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_save_changed_file_overwrite() {
+    let (mut ws, path) = workspace_with_changed_file("changed_overwrite.sui");
+    script().char('o').run(&mut ws).expect("Failed to overwrite");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(std::fs::read_to_string(&path).unwrap().contains("ours"));
+    // Our own save shouldn't trigger the prompt again.
+    script()
+        .char(':')
+        .chars("w")
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_save_changed_file_reload() {
+    let (mut ws, path) = workspace_with_changed_file("changed_reload.sui");
+    script().char('r').run(&mut ws).expect("Failed to reload");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(
+        "changed by someone else",
+        ws.book
+            .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
+            .unwrap()
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_write_to_existing_other_file_prompts() {
+    let path = ui_tmp_path("overwrite_prompt.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\nA1 = \"keep me\"\n[/sheet]\n")
+        .expect("write temp .sui");
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "replacement")
+        .expect("set cell");
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    script().char('n').run(&mut ws).expect("Failed to decline");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(std::fs::read_to_string(&path).unwrap().contains("keep me"));
+
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .char('y')
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(std::fs::read_to_string(&path).unwrap().contains("replacement"));
+
+    // Saving to the file we are now editing doesn't prompt.
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_write_quit_to_existing_other_file_prompts() {
+    let path = ui_tmp_path("overwrite_prompt_quit.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = new_workspace();
+    let result = script()
+        .char(':')
+        .chars(&format!("wq {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("wq command failed");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let result = script().char('y').run(&mut ws).expect("Failed to confirm");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_load_into_keeps_workspace_locale() {
+    let path = ui_tmp_path("load_into_locale.xlsx");
+    std::fs::remove_file(&path).ok();
+    let mut book = book::Book::default();
+    book.update_cell(&Address { sheet: 0, row: 1, col: 1 }, "1234.5")
+        .expect("set cell");
+    book.evaluate();
+    book.save_as(&path).expect("save temp xlsx");
+
+    let mut ws = Workspace::new_empty("de", "Europe/Berlin").expect("new workspace");
+    assert_eq!("de", ws.locale());
+    assert_eq!("Europe/Berlin", ws.timezone());
+    ws.load_into(&path).expect("load_into");
+    assert_eq!(
+        "1234,5",
+        ws.book
+            .get_cell_addr_rendered(&Address { sheet: 0, row: 1, col: 1 })
+            .unwrap()
+    );
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_set_updates_settings_and_shows_them() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("set autosave 30")
+        .enter()
+        .char(':')
+        .chars("set locale de")
+        .enter()
+        .run(&mut ws)
+        .expect("set command failed");
+    assert_eq!(30, ws.state.settings.autosave);
+    assert_eq!("de", ws.locale());
+    script()
+        .char(':')
+        .chars("set")
+        .enter()
+        .run(&mut ws)
+        .expect("set command failed");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!(Some(ws.render_settings()), ws.state.popup);
+}
+
+#[test]
+fn test_set_default_cell_colors() {
+    let mut ws = new_workspace();
+    let theme = ws.theme();
+    script()
+        .char(':')
+        .chars("set cell-bg #102030")
+        .enter()
+        .run(&mut ws)
+        .expect("set command failed");
+    assert_eq!(ratatui::style::Color::Rgb(16, 32, 48), ws.theme().cell_bg);
+    assert_eq!(theme.cell_fg, ws.theme().cell_fg);
+}
+
+#[test]
+fn test_apply_config_reports_warnings() {
+    let mut config = crate::config::parse_config("backup = true\n");
+    let mut ws = new_workspace();
+    ws.apply_config(&config);
+    assert!(ws.state.settings.backup);
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    config.warnings.push("config.toml line 2: Unknown option bogus".to_string());
+    ws.apply_config(&config);
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+}
+
+#[test]
+fn test_autosave_after_interval() {
+    let path = ui_tmp_path("autosave.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    ws.state.settings.autosave = 1;
+    ws.last_autosave = std::time::Instant::now() - std::time::Duration::from_secs(2);
+    script()
+        .chars("eautosaved")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    assert!(!ws.book.dirty);
+    assert!(std::fs::read_to_string(&path).unwrap().contains("autosaved"));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_new_workspace_default_format_is_sui() {
+    // REQ-008: new empty workspace defaults to SUI format.
+    let ws = new_workspace();
+    assert_eq!(
+        ws.book.get_format(),
+        &book::FileFormat::Sui,
+        "new workspace book must default to FileFormat::Sui"
+    );
+}
+
+#[test]
+fn test_new_workspace_name_ends_with_sui() {
+    // REQ-008: new empty workspace name should default to .sui extension.
+    let ws = new_workspace();
+    assert!(
+        ws.name.to_string_lossy().ends_with(".sui"),
+        "new workspace path must end with .sui, got: {}",
+        ws.name.display()
     );
+}
+
+#[test]
+fn test_write_cmd_saves_as_sui_and_updates_format() {
+    // REQ-007: :w path.sui saves as SUI and updates the stored format.
+    let path = ui_tmp_path("write_sui.sui");
     std::fs::remove_file(&path).ok();
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "write-sui-test")
+        .expect("set cell");
+    ws.book.evaluate();
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(
+        ws.book.get_format(),
+        &book::FileFormat::Sui,
+        "book format must be Sui after :w .sui"
+    );
+    let text = std::fs::read_to_string(&path).expect("read saved file");
+    assert!(
+        text.contains("[sheet"),
+        "saved .sui file must contain a sheet block, got: {text}"
+    );
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_write_cmd_saves_as_xlsx_and_updates_format() {
+    // REQ-007: :w path.xlsx saves as xlsx and updates the stored format.
+    let path = ui_tmp_path("write_xlsx.xlsx");
+    std::fs::remove_file(&path).ok();
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "xlsx-write-test")
+        .expect("set cell");
+    ws.book.evaluate();
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert_eq!(
+        ws.book.get_format(),
+        &book::FileFormat::Xlsx,
+        "book format must be Xlsx after :w .xlsx"
+    );
+    let bytes = std::fs::read(&path).expect("read saved xlsx");
+    assert!(
+        bytes.starts_with(b"PK"),
+        "saved .xlsx must be a valid zip/xlsx (starts with PK magic bytes)"
+    );
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_edit_cmd_loads_sui_file() {
+    // REQ-005: :e path.sui loads SUI content using the SUI parser.
+    let path = ui_tmp_path("edit_sui.sui");
+    let content = "[sheet \"Sheet1\"]\nA1 = \"loaded-via-edit\"\n[/sheet]\n";
+    std::fs::write(&path, content).expect("write temp .sui");
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars(&format!("e {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("edit command failed");
+    assert_eq!(
+        ws.book.get_format(),
+        &book::FileFormat::Sui,
+        "book format must be Sui after :e .sui"
+    );
+    let val = ws
+        .book
+        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
+        .expect("get A1 after :e");
+    assert_eq!(val, "loaded-via-edit", "cell A1 must be loaded from .sui file");
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_write_then_edit_round_trip_sui() {
+    // REQ-006 + REQ-010: :w saves, :e reloads, cell values survive.
+    let path = ui_tmp_path("round_trip.sui");
+    std::fs::remove_file(&path).ok();
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "round-trip-val")
+        .expect("set cell");
+    ws.book.evaluate();
+    script()
+        .char(':')
+        .chars(&format!("w {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect(":w command");
+    ws.book
+        .update_cell(&Address { sheet: 0, row: 1, col: 1 }, "")
+        .expect("clear cell locally");
+    script()
+        .char(':')
+        .chars(&format!("e {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect(":e command");
+    let val = ws
+        .book
+        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
+        .expect("get A1 after :e");
+    assert_eq!(val, "round-trip-val", "cell must survive :w/:e round-trip");
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_parse_warnings_stored_after_loading_malformed_sui() {
+    // REQ-009: loading a malformed .sui file stores warnings on the Book.
+    let path = ui_tmp_path("malformed.sui");
+    let content = "[sheet \"Sheet1\"]\nTHIS IS NOT VALID\nA1 = \"valid-cell\"\n[/sheet]\n";
+    std::fs::write(&path, content).expect("write malformed .sui");
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars(&format!("e {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("edit command");
+    assert!(
+        !ws.book.parse_warnings.is_empty(),
+        "loading a malformed .sui file must store parse warnings on Book"
+    );
+    let val = ws
+        .book
+        .get_cell_addr_contents(&Address { sheet: 0, row: 1, col: 1 })
+        .expect("get A1");
+    assert_eq!(
+        val, "valid-cell",
+        "valid cell must be loaded despite malformed lines"
+    );
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_parse_key() {
+    use crate::ui::keymap::parse_key;
+    assert_eq!(
+        Some((KeyCode::Char('d'), KeyModifiers::CONTROL)),
+        parse_key("ctrl-d")
+    );
+    assert_eq!(
+        Some((KeyCode::Char('D'), KeyModifiers::SHIFT)),
+        parse_key("shift-d")
+    );
+    assert_eq!(Some((KeyCode::Char('-'), KeyModifiers::NONE)), parse_key("-"));
+    assert_eq!(Some((KeyCode::PageDown, KeyModifiers::NONE)), parse_key("pagedown"));
+    assert_eq!(
+        Some((KeyCode::Enter, KeyModifiers::ALT | KeyModifiers::SHIFT)),
+        parse_key("alt-shift-enter")
+    );
+    assert_eq!(Some((KeyCode::F(5), KeyModifiers::NONE)), parse_key("f5"));
+    assert_eq!(None, parse_key("bogus"));
+}
+
+#[test]
+fn test_config_key_override() {
+    let config = crate::config::parse_config(
+        "[keys]\nnavigate.\"ctrl-d\" = \"move_down\"\nnavigate.j = \"move_up\"\n",
+    );
+    let mut ws = new_workspace();
+    ws.apply_config(&config);
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    script()
+        .ctrl('d')
+        .ctrl('d')
+        .char('j')
+        .run(&mut ws)
+        .expect("Failed to run script");
+    assert_eq!(2, ws.book.location.row);
+}
+
+#[test]
+fn test_config_key_override_warnings() {
+    let config = crate::config::parse_config(
+        "[keys]\nnavigate.x = \"explode\"\nedit.x = \"cancel\"\nvisual.\"ctrl-nope\" = \"cancel\"\n",
+    );
+    let warnings = crate::ui::keymap::Keymap::default().apply_overrides(&config.keys);
+    assert_eq!(
+        vec![
+            "line 2: Unknown action explode".to_string(),
+            "line 3: Unknown keybinding mode edit".to_string(),
+            "line 4: Unknown key ctrl-nope".to_string(),
+        ],
+        warnings
+    );
+    let mut ws = new_workspace();
+    ws.apply_config(&config);
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    // The default bindings are left alone.
+    script().esc().char('j').run(&mut ws).expect("Failed to run script");
+    assert_eq!(2, ws.book.location.row);
+}
+
+#[test]
+fn test_keys_cheat_sheet_lists_every_binding() {
+    use crate::ui::keymap::{parse_key, parse_keys, sequence_name, Keymap, FIXED_KEYS};
+    let keymap = Keymap::default();
+    let sheet = keymap.cheat_sheet();
+    for modality in [Modality::Navigate, Modality::RangeSelect] {
+        for (action, sequences) in keymap.bindings(&modality) {
+            for keys in sequences {
+                let name = sequence_name(&keys);
+                assert_eq!(Some(keys.clone()), parse_keys(&name), "{}", name);
+                assert_eq!(
+                    Some(action),
+                    keymap.lookup_sequence(&modality, &keys),
+                    "{}",
+                    name
+                );
+                assert!(sheet.contains(&name), "{} is missing", name);
+            }
+        }
+    }
+    for (modality, keys) in FIXED_KEYS {
+        for (sequence, description) in keys.iter() {
+            assert!(sheet.contains(description));
+            // Put the workspace where the key does something.
+            let mut ws = new_workspace();
+            ws.system_clipboard = Box::new(FakeClipboard(Some("pasted".to_string())));
+            ws.state.command_history = vec!["w first.csv".to_string(), "w second.csv".to_string()];
+            let setup = match (modality, *sequence) {
+                (Modality::Navigate, _) => script(),
+                (Modality::RangeSelect, _) => script().char('v'),
+                (Modality::CellEdit, "ctrl-space") => script().char('e').chars("=SU"),
+                (Modality::CellEdit, "ctrl-p") => script().char('e').ctrl('r').chars(" j "),
+                (Modality::CellEdit, _) => script().char('e').chars("=A1"),
+                (Modality::Command, "down") => script()
+                    .char(':')
+                    .chars("foo bar")
+                    .event(construct_key_event(KeyCode::Up)),
+                (Modality::Command, _) => script()
+                    .char(':')
+                    .chars("foo bar")
+                    .event(construct_key_event(KeyCode::Left)),
+                _ => panic!("No setup for {:?}", modality),
+            };
+            setup.run(&mut ws).expect("Failed to set up");
+            assert_eq!(
+                Some(modality),
+                ws.state.modality_stack.last(),
+                "{}",
+                sequence
+            );
+            let before = observable_state(&ws);
+            let replay = if *sequence == "<digit>" {
+                script().char('3')
+            } else {
+                let (code, mods) = parse_key(sequence).expect(sequence);
+                script().event(construct_modified_key_event(code, mods))
+            };
+            replay.run(&mut ws).expect("Failed to replay");
+            assert_ne!(before, observable_state(&ws), "{} did nothing", sequence);
+        }
+    }
+}
+
+/// Everything a key could visibly change.
+fn observable_state(ws: &Workspace) -> String {
+    use tui_prompts::State;
+    format!(
+        "{:?}",
+        (
+            &ws.state.modality_stack,
+            &ws.book.location,
+            &ws.state.numeric_prefix,
+            ws.text_area.lines(),
+            ws.text_area.cursor(),
+            ws.state.completion.is_some(),
+            ws.state.command_state.value(),
+            ws.state.command_state.position(),
+            ws.state.command_history_idx,
+            ws.state.popup.is_some(),
+            ws.state.status(),
+            &ws.state.pending_action,
+        )
+    )
+}
+
+#[test]
+fn test_keys_dialog_shows_rebound_keys() {
+    let config = crate::config::parse_config("[keys]\nnavigate.\"ctrl-d\" = \"move_down\"\n");
+    let mut ws = new_workspace();
+    ws.apply_config(&config);
+    script().alt('k').run(&mut ws).expect("Failed to run");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let lines = popup_lines(&ws);
+    assert_eq!("Keys", lines[0]);
+    assert!(lines.contains(&"* down, enter, j, ctrl-d Move down".to_string()));
+    let shown = lines.clone();
+    script()
+        .esc()
+        .char(':')
+        .chars("keys")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!(shown, popup_lines(&ws));
+    script()
+        .esc()
+        .char('e')
+        .alt('k')
+        .run(&mut ws)
+        .expect("Failed to run");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!(shown, popup_lines(&ws));
+}
+
+#[test]
+fn test_jump_to_sheet_and_cell() {
+    let mut ws = new_workspace();
+    ws.book.new_sheet(Some("Summary")).expect("Failed to add sheet");
+    ws.jump_to(Some("Summary"), Some("B12")).expect("Failed to jump");
+    assert_eq!(1, ws.book.location.sheet);
+    assert_eq!((12, 2), (ws.book.location.row, ws.book.location.col));
+    ws.jump_to(Some("1"), None).expect("Failed to jump by index");
+    assert_eq!(0, ws.book.location.sheet);
+    assert!(ws.jump_to(Some("Nope"), None).is_err());
+    assert!(ws.jump_to(Some("3"), None).is_err());
+    // A bad cell leaves the sheet alone.
+    assert!(ws.jump_to(Some("Summary"), Some("B0")).is_err());
+    assert_eq!(0, ws.book.location.sheet);
+}
+
+#[test]
+fn test_cmd_view_with_path() {
+    let input = "view test.xlsx";
+    let result = parse(input);
+    assert!(result.is_ok());
+    let output = result.unwrap();
+    assert!(output.is_some());
+    let cmd = output.unwrap();
+    assert_eq!(cmd, Cmd::View("test.xlsx"));
+    assert!(parse("view").is_err());
+}
+
+#[test]
+fn test_read_only_refuses_edits() {
+    let mut ws = new_workspace();
+    ws.book.edit_current_cell("keep").expect("Failed to edit cell");
+    ws.book.dirty = false;
+    ws.set_read_only(true);
+    script()
+        .char('e')
+        .char('s')
+        .char('d')
+        .char('D')
+        .char('B')
+        .char('o')
+        .run(&mut ws)
+        .expect("Failed to run script");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!("keep", ws.book.get_current_cell_contents().unwrap());
+    assert_eq!(Some("read-only"), ws.state.status());
+    script()
+        .char(':')
+        .chars("insert-rows 2")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run script");
+    assert_eq!(Some("read-only"), ws.state.status());
+    // Navigation still works and quitting doesn't ask to save.
+    script().char('j').run(&mut ws).expect("Failed to run script");
+    assert_eq!(2, ws.book.location.row);
+    assert_eq!(
+        Some(ExitCode::SUCCESS),
+        script().char('q').run(&mut ws).expect("Failed to run script")
+    );
+}
+
+#[test]
+fn test_view_cmd_sets_read_only() {
+    let path = ui_tmp_path("view.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars(&format!("view {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run script");
+    assert!(ws.read_only());
+    script()
+        .char(':')
+        .chars(&format!("edit {}", path.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run script");
+    assert!(!ws.read_only());
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_cmd_buffers() {
+    for (input, expected) in [
+        ("bnext", Cmd::NextBuffer),
+        ("bn", Cmd::NextBuffer),
+        ("bprev", Cmd::PrevBuffer),
+        ("bp", Cmd::PrevBuffer),
+        ("buffers", Cmd::Buffers),
+        ("ls", Cmd::Buffers),
+    ] {
+        let result = parse(input);
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.is_some());
+        let cmd = output.unwrap();
+        assert_eq!(cmd, expected);
+    }
+    assert!(parse("bnext 2").is_err());
+}
+
+fn workspace_with_two_buffers(prefix: &str) -> (Workspace<'static>, std::path::PathBuf, std::path::PathBuf) {
+    let first = ui_tmp_path(&format!("{}_first.sui", prefix));
+    let second = ui_tmp_path(&format!("{}_second.sui", prefix));
+    std::fs::write(&first, "[sheet \"Sheet1\"]\nA1 = \"first\"\n[/sheet]\n").expect("write temp .sui");
+    std::fs::write(&second, "[sheet \"Sheet1\"]\nA1 = \"second\"\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars(&format!("e {}", first.display()))
+        .enter()
+        .char(':')
+        .chars(&format!("e {}", second.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to open buffers");
+    (ws, first, second)
+}
+
+#[test]
+fn test_switch_buffers_keeps_location() {
+    let (mut ws, first, second) = workspace_with_two_buffers("switch");
+    // The untouched empty workbook was replaced by the first file.
+    assert_eq!(2, ws.buffer_count());
+    assert_eq!(1, ws.active_buffer());
+    assert_eq!("second", ws.book.get_current_cell_contents().unwrap());
+    script().char('j').char('j').char('g').char('T').run(&mut ws).expect("Failed to switch");
+    assert_eq!(0, ws.active_buffer());
+    assert_eq!(1, ws.book.location.row);
+    assert_eq!("first", ws.book.get_current_cell_contents().unwrap());
+    script().char('g').char('t').run(&mut ws).expect("Failed to switch");
+    assert_eq!(1, ws.active_buffer());
+    assert_eq!(3, ws.book.location.row);
+    script()
+        .char(':')
+        .chars("bnext")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to switch");
+    assert_eq!(0, ws.active_buffer());
+    // Editing an already open file switches to it.
+    script()
+        .char(':')
+        .chars(&format!("e {}", second.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to switch");
+    assert_eq!(2, ws.buffer_count());
+    assert_eq!(1, ws.active_buffer());
+    script().char(':').chars("buffers").enter().run(&mut ws).expect("Failed to list");
+    assert_eq!(Some(ws.render_buffers()), ws.state.popup);
+    std::fs::remove_file(&first).ok();
+    std::fs::remove_file(&second).ok();
+}
+
+#[test]
+fn test_config_binds_buffer_keys() {
+    let (mut ws, first, second) = workspace_with_two_buffers("rebind");
+    let config = crate::config::parse_config("[keys]\nnavigate.\"g b\" = \"prev_buffer\"\n");
+    ws.apply_config(&config);
+    script().char('g').char('b').run(&mut ws).expect("Failed to switch");
+    assert_eq!(0, ws.active_buffer());
+    // `g` still works on its own.
+    script().char('j').char('g').char('g').run(&mut ws).expect("Failed to move");
+    assert_eq!(1, ws.book.location.row);
+    std::fs::remove_file(&first).ok();
+    std::fs::remove_file(&second).ok();
+}
+
+#[test]
+fn test_edit_active_buffer_keeps_unsaved_changes() {
+    let (mut ws, first, second) = workspace_with_two_buffers("reload");
+    ws.book.edit_current_cell("changed").expect("Failed to edit");
+    script()
+        .char(':')
+        .chars(&format!("e {}", second.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run edit");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!("changed", ws.book.get_current_cell_contents().unwrap());
+    script()
+        .esc()
+        .char(':')
+        .chars(&format!("e! {}", second.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run edit!");
+    assert_eq!("second", ws.book.get_current_cell_contents().unwrap());
+    assert!(!ws.book.dirty);
+    std::fs::remove_file(&first).ok();
+    std::fs::remove_file(&second).ok();
+}
+
+#[test]
+fn test_view_keeps_unsaved_buffer_writable() {
+    let (mut ws, first, second) = workspace_with_two_buffers("view_dirty");
+    script().char('g').char('T').run(&mut ws).expect("Failed to switch");
+    ws.book.edit_current_cell("changed").expect("Failed to edit");
+    script()
+        .char('g')
+        .char('t')
+        .char(':')
+        .chars(&format!("view {}", first.display()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run view");
+    assert_eq!(0, ws.active_buffer());
+    assert!(!ws.read_only());
+    let result = script()
+        .char(':')
+        .char('q')
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to quit");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Quit), ws.state.modality_stack.last());
+    assert_eq!(vec![first.display().to_string()], ws.unsaved_buffer_names());
+    std::fs::remove_file(&first).ok();
+    std::fs::remove_file(&second).ok();
+}
+
+#[test]
+fn test_quit_checks_every_buffer() {
+    let (mut ws, first, second) = workspace_with_two_buffers("quit_all");
+    ws.book.edit_current_cell("changed").expect("Failed to edit");
+    script().char('g').char('T').run(&mut ws).expect("Failed to switch");
+    ws.book.dirty = false;
+    let result = script().char('q').run(&mut ws).expect("Failed to quit");
+    assert_eq!(None, result);
+    assert_eq!(Some(&Modality::Quit), ws.state.modality_stack.last());
+    assert_eq!(vec![second.display().to_string()], ws.unsaved_buffer_names());
+    let result = script().char('y').run(&mut ws).expect("Failed to save");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert!(std::fs::read_to_string(&second).unwrap().contains("changed"));
+    std::fs::remove_file(&first).ok();
+    std::fs::remove_file(&second).ok();
+}
+
+#[test]
+fn test_replay_log_against_workbook() {
+    let book_path = ui_tmp_path("replay.sui");
+    let log_path = ui_tmp_path("replay_log.json");
+    std::fs::write(&book_path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut events = vec![
+        Event::Key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE)),
+        Event::Key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE)),
+    ];
+    for c in "replayed".chars() {
+        events.push(Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)));
+    }
+    events.push(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)));
+    let log = events
+        .iter()
+        .map(|e| serde_json::to_string(e).expect("Failed to serialize event"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    std::fs::write(&log_path, log).expect("write temp log");
+
+    let mut ws = Workspace::load(&book_path, "en", "America/New_York").expect("load workspace");
+    for evt in crate::replay::load_events(&log_path).expect("Failed to load log") {
+        ws.handle_input(evt).expect("Failed to handle event");
+    }
+    assert_eq!(
+        "replayed",
+        ws.book
+            .get_cell_addr_contents(&Address { sheet: 0, row: 2, col: 1 })
+            .expect("Failed to get cell")
+    );
+    std::fs::remove_file(&book_path).ok();
+    std::fs::remove_file(&log_path).ok();
+}
+
+#[test]
+fn test_run_batch_commands() {
+    let path = ui_tmp_path("batch.sui");
+    let existing = ui_tmp_path("batch_existing.sui");
+    std::fs::remove_file(&path).ok();
+    std::fs::write(&existing, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = new_workspace();
+    ws.book.edit_current_cell("stamped").expect("Failed to edit");
+    assert!(ws.run_batch_command("bogus").is_err());
+    assert!(ws.run_batch_command("insert-rows x").is_err());
+    assert_eq!(None, ws.run_batch_command("insert-rows 2").expect("insert-rows"));
+    assert_eq!(None, ws.run_batch_command("help").expect("help"));
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(ws.run_batch_command("q").is_err());
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    let err = ws
+        .run_batch_command(&format!("w {}", existing.display()))
+        .expect_err("Overwriting another file needs confirmation");
+    assert!(err.to_string().contains("already exists"), "{}", err);
+    assert_eq!(None, ws.state.pending_action);
+    assert_eq!(
+        None,
+        ws.run_batch_command(&format!("w {}", path.display())).expect("write")
+    );
+    assert!(std::fs::read_to_string(&path).unwrap().contains("stamped"));
+    assert_eq!(Some(ExitCode::SUCCESS), ws.run_batch_command("q").expect("quit"));
+    std::fs::remove_file(&path).ok();
+    std::fs::remove_file(&existing).ok();
+}
+
+fn render_workspace(ws: &mut Workspace) -> ratatui::buffer::Buffer {
+    let area = ratatui::layout::Rect::new(0, 0, 120, 40);
+    let mut buf = ratatui::buffer::Buffer::empty(area);
+    ratatui::widgets::Widget::render(&mut *ws, area, &mut buf);
+    buf
+}
+
+/// The text of one line of a rendered buffer.
+fn screen_line(buf: &ratatui::buffer::Buffer, y: u16) -> String {
+    (0..buf.area.width).map(|x| buf[(x, y)].symbol()).collect()
+}
+
+fn mouse_event(kind: MouseEventKind, (column, row): (u16, u16)) -> Event {
+    Event::Mouse(MouseEvent {
+        kind,
+        column,
+        row,
+        modifiers: KeyModifiers::NONE,
+    })
+}
+
+/// Find where a cell was rendered on screen.
+fn find_on_screen(ws: &Workspace, row: usize, col: usize) -> Option<(u16, u16)> {
+    for y in 0..40 {
+        for x in 0..120 {
+            if ws.state.viewport_state.cell_at(x, y) == Some((row, col)) {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+fn screen_pos(ws: &Workspace, row: usize, col: usize) -> (u16, u16) {
+    find_on_screen(ws, row, col).expect("Cell was not rendered")
+}
+
+#[test]
+fn test_mouse_click_selects_cell() {
+    let mut ws = new_workspace();
+    render_workspace(&mut ws);
+    let (x, y) = screen_pos(&ws, 2, 2);
+    script()
+        .event(mouse_event(MouseEventKind::Down(MouseButton::Left), (x, y)))
+        .event(mouse_event(MouseEventKind::Up(MouseButton::Left), (x, y)))
+        .run(&mut ws)
+        .expect("Failed to click");
+    assert_eq!((2, 2), (ws.book.location.row, ws.book.location.col));
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    // Clicks on the column header or the row numbers are ignored.
+    let (x, y) = screen_pos(&ws, 1, 1);
+    script()
+        .event(mouse_event(
+            MouseEventKind::Down(MouseButton::Left),
+            (x, y - 1),
+        ))
+        .event(mouse_event(
+            MouseEventKind::Down(MouseButton::Left),
+            (x - 1, y),
+        ))
+        .event(mouse_event(MouseEventKind::Down(MouseButton::Left), (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to click");
+    assert_eq!((2, 2), (ws.book.location.row, ws.book.location.col));
+}
+
+#[test]
+fn test_mouse_drag_selects_range() {
+    let mut ws = new_workspace();
+    render_workspace(&mut ws);
+    script()
+        .event(mouse_event(
+            MouseEventKind::Down(MouseButton::Left),
+            screen_pos(&ws, 2, 2),
+        ))
+        .event(mouse_event(
+            MouseEventKind::Drag(MouseButton::Left),
+            screen_pos(&ws, 3, 2),
+        ))
+        .event(mouse_event(
+            MouseEventKind::Drag(MouseButton::Left),
+            screen_pos(&ws, 4, 3),
+        ))
+        .event(mouse_event(
+            MouseEventKind::Up(MouseButton::Left),
+            screen_pos(&ws, 4, 3),
+        ))
+        .run(&mut ws)
+        .expect("Failed to drag");
+    assert_eq!(Some(&Modality::RangeSelect), ws.state.modality_stack.last());
+    assert_eq!(
+        Some((Address::new(2, 2), Address::new(4, 3))),
+        ws.state.range_select.get_range()
+    );
+}
+
+#[test]
+fn test_mouse_click_selects_sheet_tab() {
+    let mut ws = new_workspace();
+    ws.book.new_sheet(Some("Two")).expect("Failed to add sheet");
+    render_workspace(&mut ws);
+    let area = ws.state.tabs_area;
+    // " Sheet1 0 " is followed by a divider so the second tab starts 11
+    // columns in.
+    script()
+        .event(mouse_event(
+            MouseEventKind::Down(MouseButton::Left),
+            (area.x + 12, area.y),
+        ))
+        .run(&mut ws)
+        .expect("Failed to click");
+    assert_eq!(1, ws.book.location.sheet);
+    script()
+        .event(mouse_event(
+            MouseEventKind::Down(MouseButton::Left),
+            (area.x + 1, area.y),
+        ))
+        .run(&mut ws)
+        .expect("Failed to click");
+    assert_eq!(0, ws.book.location.sheet);
+}
+
+#[test]
+fn test_mouse_wheel_scrolls_viewport() {
+    let mut ws = new_workspace();
+    render_workspace(&mut ws);
+    script()
+        .char('5')
+        .char('j')
+        .event(mouse_event(MouseEventKind::ScrollDown, (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    render_workspace(&mut ws);
+    // The selection stays put while it is still on screen.
+    assert_eq!(6, ws.book.location.row);
+    assert!(find_on_screen(&ws, 3, 1).is_none());
+    assert!(find_on_screen(&ws, 4, 1).is_some());
+    script()
+        .event(mouse_event(MouseEventKind::ScrollDown, (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    // Scrolling the selection off the top drags it along.
+    assert_eq!(7, ws.book.location.row);
+    render_workspace(&mut ws);
+    script()
+        .event(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::ScrollDown,
+            column: 0,
+            row: 0,
+            modifiers: KeyModifiers::SHIFT,
+        }))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!((7, 2), (ws.book.location.row, ws.book.location.col));
+    render_workspace(&mut ws);
+    assert!(find_on_screen(&ws, 7, 1).is_none());
+    script()
+        .event(mouse_event(MouseEventKind::ScrollUp, (0, 0)))
+        .event(mouse_event(MouseEventKind::ScrollUp, (0, 0)))
+        .event(mouse_event(MouseEventKind::ScrollUp, (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    render_workspace(&mut ws);
+    assert!(find_on_screen(&ws, 1, 2).is_some());
+    assert_eq!(7, ws.book.location.row);
+}
+
+#[test]
+fn test_mouse_wheel_scrolls_dialog() {
+    let mut ws = new_workspace();
+    script().alt('h').run(&mut ws).expect("Failed to open help");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    script()
+        .event(mouse_event(MouseEventKind::ScrollDown, (0, 0)))
+        .event(mouse_event(MouseEventKind::ScrollDown, (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!(6, ws.state.dialog_scroll);
+    script()
+        .event(mouse_event(MouseEventKind::ScrollUp, (0, 0)))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!(3, ws.state.dialog_scroll);
+}
+
+#[test]
+fn test_dialog_scroll_stops_at_the_end() {
+    let mut ws = new_workspace();
+    ws.enter_dialog_mode(super::render::markdown::Markdown::from_str("Short"));
+    render_workspace(&mut ws);
+    script()
+        .chars(&"j".repeat(20))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!(0, ws.state.dialog_scroll);
+    let buf = render_workspace(&mut ws);
+    assert!(!(0..buf.area.height).any(|y| screen_line(&buf, y).contains('▲')));
+
+    let lines: Vec<ratatui::text::Line> = (1..=100)
+        .map(|n| ratatui::text::Line::raw(format!("line {}", n)))
+        .collect();
+    ws.exit_dialog_mode().expect("Failed to close dialog");
+    ws.enter_dialog_mode(super::render::markdown::Markdown::from_text(
+        ratatui::text::Text::from(lines),
+    ));
+    // 34 of the 100 lines fit in the 40 row terminal.
+    let buf = render_workspace(&mut ws);
+    assert!(screen_line(&buf, 3).trim_end().ends_with('▲'));
+    script().char('G').run(&mut ws).expect("Failed to scroll");
+    assert_eq!(66, ws.state.dialog_scroll);
+    script()
+        .chars("jjj")
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    render_workspace(&mut ws);
+    assert_eq!(66, ws.state.dialog_scroll);
+    script()
+        .event(construct_key_event(KeyCode::PageUp))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!(32, ws.state.dialog_scroll);
+    script().char('g').run(&mut ws).expect("Failed to scroll");
+    assert_eq!(0, ws.state.dialog_scroll);
+    script()
+        .event(construct_key_event(KeyCode::PageDown))
+        .event(construct_key_event(KeyCode::PageDown))
+        .event(construct_key_event(KeyCode::PageDown))
+        .run(&mut ws)
+        .expect("Failed to scroll");
+    assert_eq!(66, ws.state.dialog_scroll);
+}
+
+#[test]
+fn test_bracketed_paste_in_edit_mode() {
+    let mut ws = new_workspace();
+    script()
+        .char('e')
+        .event(Event::Paste("=SUM(A2:A4)q".to_string()))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to paste");
+    // A pasted q is text and not a keybinding.
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!("=SUM(A2:A4)q", ws.book.get_current_cell_contents().unwrap());
+}
+
+#[test]
+fn test_bracketed_paste_in_command_mode() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("rename-sheet ")
+        .event(Event::Paste("Pasted\n".to_string()))
+        .run(&mut ws)
+        .expect("Failed to paste");
+    assert_eq!("rename-sheet Pasted", ws.state.command_state.value());
+    script().enter().run(&mut ws).expect("Failed to run");
+    assert_eq!(vec!["Pasted".to_string()], ws.book.get_sheet_names());
+}
+
+#[test]
+fn test_bracketed_paste_in_navigate_mode() {
+    let mut ws = new_workspace();
+    script()
+        .event(Event::Paste("quit".to_string()))
+        .run(&mut ws)
+        .expect("Failed to paste");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!("quit", ws.book.get_current_cell_contents().unwrap());
+    script()
+        .char('j')
+        .event(Event::Paste("X1\tY1\nX2\tY2\n".to_string()))
+        .run(&mut ws)
+        .expect("Failed to paste");
+    assert_eq!("X1", ws.book.get_current_cell_contents().unwrap());
+    assert_eq!(
+        "Y2",
+        ws.book.get_cell_addr_contents(&Address::new(3, 2)).unwrap()
+    );
+}
+
+#[test]
+fn test_on_tick_expires_status_and_autosaves() {
+    let path = ui_tmp_path("tick_autosave.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    ws.state.status_message = Some((
+        "old news".to_string(),
+        std::time::Instant::now() - std::time::Duration::from_secs(60),
+    ));
+    ws.on_tick().expect("Failed to tick");
+    assert!(ws.state.status_message.is_none());
+    script()
+        .chars("eticked")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    assert!(ws.book.dirty);
+    ws.state.settings.autosave = 1;
+    ws.last_autosave = std::time::Instant::now() - std::time::Duration::from_secs(2);
+    ws.on_tick().expect("Failed to tick");
+    assert!(!ws.book.dirty);
+    assert!(std::fs::read_to_string(&path).unwrap().contains("ticked"));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_navigation_does_not_dirty_book() {
+    let path = ui_tmp_path("navigate_clean.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n[sheet \"Sheet2\"]\n[/sheet]\n")
+        .expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    let result = script()
+        .chars("jjlllk")
+        .ctrl('n')
+        .chars("gg")
+        .ctrl('p')
+        .char('v')
+        .char('j')
+        .esc()
+        .char('q')
+        .run(&mut ws)
+        .expect("Failed to navigate");
+    assert!(!ws.book.dirty);
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_formula_bar_follows_selection() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(2, 2), "=1+2")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    let bar = |ws: &mut Workspace| {
+        let buf = render_workspace(ws);
+        (0..7)
+            .map(|y| screen_line(&buf, y))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let screen = bar(&mut ws);
+    assert!(screen.contains("A1"), "{}", screen);
+    script().chars("jl").run(&mut ws).expect("Failed to move");
+    let screen = bar(&mut ws);
+    assert!(screen.contains("B2"), "{}", screen);
+    assert!(!screen.contains("A1"), "{}", screen);
+    assert!(screen.contains("=1+2"), "{}", screen);
+    // Long contents are cut off with an ellipsis.
+    ws.book
+        .update_cell(&Address::new(2, 2), "x".repeat(200))
+        .expect("Failed to update cell");
+    let screen = bar(&mut ws);
+    assert!(screen.contains("x…"), "{}", screen);
+    // Editing switches to the text area.
+    script().char('e').run(&mut ws).expect("Failed to edit");
+    let screen = bar(&mut ws);
+    assert!(!screen.contains("B2"), "{}", screen);
+}
+
+#[test]
+fn test_range_select_shows_dimensions() {
+    let mut ws = new_workspace();
+    let bottom = |ws: &mut Workspace| screen_line(&render_workspace(ws), 39);
+    assert!(!bottom(&mut ws).contains("R x "));
+    script().char('v').run(&mut ws).expect("Failed to select");
+    let line = bottom(&mut ws);
+    assert!(line.contains("1R x 1C A1"), "{}", line);
+    script()
+        .chars("jjjll")
+        .run(&mut ws)
+        .expect("Failed to select");
+    let line = bottom(&mut ws);
+    assert!(line.contains("4R x 3C A1:C4"), "{}", line);
+    script().esc().run(&mut ws).expect("Failed to cancel");
+    assert!(!bottom(&mut ws).contains("R x "));
+}
+
+#[test]
+fn test_title_marks_unsaved_changes() {
+    let path = ui_tmp_path("title_dirty.sui");
+    std::fs::write(&path, "[sheet \"Sheet1\"]\n[/sheet]\n").expect("write temp .sui");
+    let mut ws = Workspace::load(&path, "en", "America/New_York").expect("load workspace");
+    let title = |ws: &mut Workspace| screen_line(&render_workspace(ws), 0);
+    assert!(title(&mut ws).contains("title_dirty.sui"));
+    assert!(!title(&mut ws).contains("[+]"));
+    assert_eq!(
+        Some(String::from("title_dirty.sui — sheetsui")),
+        ws.terminal_title()
+    );
+    script()
+        .char('e')
+        .chars("changed")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    assert!(title(&mut ws).contains("title_dirty.sui [+]"));
+    assert_eq!(
+        Some(String::from("title_dirty.sui [+] — sheetsui")),
+        ws.terminal_title()
+    );
+    script()
+        .char(':')
+        .chars("w")
+        .enter()
+        .run(&mut ws)
+        .expect("write command failed");
+    assert!(!title(&mut ws).contains("[+]"));
+    script()
+        .char(':')
+        .chars("set title off")
+        .enter()
+        .run(&mut ws)
+        .expect("set command failed");
+    assert_eq!(None, ws.terminal_title());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_peek_shows_cell_value_and_contents() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(2, 2), "=1+2")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    script().chars("jlK").run(&mut ws).expect("Failed to peek");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let buf = render_workspace(&mut ws);
+    let screen: Vec<String> = (0..40).map(|y| screen_line(&buf, y)).collect();
+    let top = screen
+        .iter()
+        .find(|line| line.contains("Sheet1!B2"))
+        .expect("No popup title");
+    // The popup is sized to its content rather than the whole screen.
+    assert!(top.trim().chars().count() < 40, "{:?}", top);
+    let text = screen.join("\n");
+    assert!(text.contains("Value"), "{}", text);
+    assert!(text.contains("=1+2"), "{}", text);
+    assert!(screen
+        .iter()
+        .any(|line| line.trim_matches([' ', '│']) == "3"));
+    script().esc().run(&mut ws).expect("Failed to close");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    // Other dialogs are still titled help.
+    script().alt('h').run(&mut ws).expect("Failed to open help");
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("Help")));
+}
+
+#[test]
+fn test_error_cells_are_styled_and_found() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(3, 3), "=1/0")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    render_workspace(&mut ws);
+    let (x, y) = screen_pos(&ws, 3, 3);
+    let buf = render_workspace(&mut ws);
+    assert_eq!(ratatui::style::Color::Red, buf[(x, y)].fg);
+    assert!(buf[(x, y)]
+        .modifier
+        .contains(ratatui::style::Modifier::BOLD));
+    script()
+        .char(':')
+        .chars("errors")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run errors");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    script()
+        .char('1')
+        .run(&mut ws)
+        .expect("Failed to pick error");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(Address::new(3, 3), ws.book.location);
+    // The selection wins over the error style.
+    let buf = render_workspace(&mut ws);
+    assert_ne!(ratatui::style::Color::Red, buf[(x, y)].fg);
+}
+
+#[test]
+fn test_precedents_and_dependents_dialogs() {
+    let mut ws = new_workspace();
+    ws.book
+        .new_sheet(Some("Sheet2"))
+        .expect("Failed to add sheet");
+    ws.book
+        .update_cell(&Address::new(5, 2), "=SUM(A1:A3)+Sheet2!B1")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(6, 2), "=B5*2")
+        .expect("Failed to update cell");
+    ws.book
+        .move_to(&Address::new(5, 2))
+        .expect("Failed to move");
+    script()
+        .char(':')
+        .chars("precedents")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run precedents");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("1 A1:A3")));
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("2 Sheet2!B1")));
+    script()
+        .char('2')
+        .run(&mut ws)
+        .expect("Failed to pick precedent");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(1, ws.book.location.sheet);
+    assert_eq!((1, 2), (ws.book.location.row, ws.book.location.col));
+
+    // B5 depends on Sheet2!B1 from the other sheet.
+    script()
+        .char(':')
+        .chars("dependents")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run dependents");
+    let buf = render_workspace(&mut ws);
+    let expected = "1 Sheet1!B5 =SUM(A1:A3)+Sheet2!B1";
+    assert!((0..40).any(|y| screen_line(&buf, y).contains(expected)));
+    script()
+        .char('1')
+        .run(&mut ws)
+        .expect("Failed to pick dependent");
+    assert_eq!(Address::new(5, 2), ws.book.location);
+
+    script()
+        .char(':')
+        .chars("dependents")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run dependents");
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("1 B6 =B5*2")));
+    script().esc().run(&mut ws).expect("Failed to close dialog");
+
+    ws.book
+        .move_to(&Address::new(6, 2))
+        .expect("Failed to move");
+    script()
+        .char(':')
+        .chars("dependents")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run dependents");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(Some("B6 has no dependents"), ws.state.status());
+}
+
+#[test]
+fn test_edit_completes_function_names() {
+    let mut ws = new_workspace();
+    script()
+        .char('e')
+        .chars("=SU")
+        .tab()
+        .run(&mut ws)
+        .expect("Failed to open completion");
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("SUMPRODUCT")));
+    script().enter().run(&mut ws).expect("Failed to complete");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!("=SUM(", ws.text_area.lines().join("\n"));
+
+    // Esc closes the list without leaving edit mode.
+    script()
+        .chars("vlo")
+        .tab()
+        .esc()
+        .run(&mut ws)
+        .expect("Failed to dismiss completion");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!(None, ws.state.completion);
+    script()
+        .modified_char(' ', KeyModifiers::CONTROL)
+        .event(construct_key_event(KeyCode::Down))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to complete");
+    assert_eq!("=SUM(VLOOKUP(", ws.text_area.lines().join("\n"));
+}
+
+#[test]
+fn test_edit_previews_formula_result() {
+    let mut ws = new_workspace();
+    script()
+        .char('e')
+        .chars("=1+2")
+        .run(&mut ws)
+        .expect("Failed to edit");
+    // Nothing is evaluated until typing pauses.
+    assert_eq!(None, ws.state.formula_preview);
+    ws.on_tick().expect("Failed to tick");
+    assert_eq!(
+        Some(("=1+2".to_string(), "3".to_string())),
+        ws.state.formula_preview
+    );
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("Result")));
+    // The book is untouched until the edit is committed.
+    assert_eq!(
+        "",
+        ws.book
+            .get_cell_addr_contents(&Address::new(1, 1))
+            .expect("Failed to get contents")
+    );
+
+    script().chars("/0").run(&mut ws).expect("Failed to edit");
+    ws.on_tick().expect("Failed to tick");
+    assert_eq!(
+        Some("#DIV/0!"),
+        ws.state.formula_preview.as_ref().map(|(_, v)| v.as_str())
+    );
+
+    script()
+        .event(construct_key_event(KeyCode::Home))
+        .event(construct_key_event(KeyCode::Delete))
+        .run(&mut ws)
+        .expect("Failed to edit");
+    ws.on_tick().expect("Failed to tick");
+    assert_eq!(None, ws.state.formula_preview);
+    script().esc().run(&mut ws).expect("Failed to leave edit");
+    assert_eq!(None, ws.state.formula_preview);
+}
+
+#[test]
+fn test_manual_calc_waits_for_recalc() {
+    let mut ws = new_workspace();
+    script()
+        .char(':')
+        .chars("set calc manual")
+        .enter()
+        .chars("e=1+2")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    let a1 = Address::new(1, 1);
+    assert_eq!("=1+2", ws.book.get_cell_addr_contents(&a1).unwrap());
+    assert_ne!("3", ws.book.get_cell_addr_rendered(&a1).unwrap());
+    assert!(ws.book.needs_recalc);
+    let buf = render_workspace(&mut ws);
+    assert!(screen_line(&buf, 39).contains("CALC"));
+
+    script()
+        .event(construct_key_event(KeyCode::F(9)))
+        .run(&mut ws)
+        .expect("Failed to recalc");
+    assert_eq!("3", ws.book.get_cell_addr_rendered(&a1).unwrap());
+    assert!(!ws.book.needs_recalc);
+    let buf = render_workspace(&mut ws);
+    assert!(!screen_line(&buf, 39).contains("CALC"));
+
+    // Going back to auto catches up straight away.
+    script()
+        .chars("s=2+2")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit");
+    assert!(ws.book.needs_recalc);
+    script()
+        .char(':')
+        .chars("set calc auto")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to set calc");
+    assert!(!ws.book.needs_recalc);
+    assert_eq!("4", ws.book.get_cell_addr_rendered(&a1).unwrap());
+}
+
+#[test]
+fn test_next_error_wraps_and_counts() {
+    let mut ws = new_workspace();
+    for (row, col) in [(2, 3), (5, 1), (9, 2)] {
+        ws.book
+            .update_cell(&Address::new(row, col), "=1/0")
+            .expect("Failed to update cell");
+    }
+    ws.book
+        .update_cell(&Address::new(4, 1), "=1+1")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    script().chars("]e").run(&mut ws).expect("Failed to move");
+    assert_eq!(Address::new(2, 3), ws.book.location);
+    assert_eq!(Some("1 of 3 errors"), ws.state.status());
+    script().chars("]e]e").run(&mut ws).expect("Failed to move");
+    assert_eq!(Address::new(9, 2), ws.book.location);
+    script().chars("]e").run(&mut ws).expect("Failed to move");
+    assert_eq!(Address::new(2, 3), ws.book.location);
+    assert_eq!(Some("1 of 3 errors"), ws.state.status());
+    script().chars("[e").run(&mut ws).expect("Failed to move");
+    assert_eq!(Address::new(9, 2), ws.book.location);
+    assert_eq!(Some("3 of 3 errors"), ws.state.status());
+    // A bracket followed by anything else is dropped.
+    script().chars("]j").run(&mut ws).expect("Failed to move");
+    assert_eq!(Address::new(10, 2), ws.book.location);
+    assert!(ws.state.char_queue.is_empty());
+
+    script()
+        .char(':')
+        .chars("prev-error")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run prev-error");
+    assert_eq!(Address::new(9, 2), ws.book.location);
+
+    script()
+        .char(':')
+        .chars("errors")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to list errors");
+    let buf = render_workspace(&mut ws);
+    let text: Vec<String> = (0..40).map(|y| screen_line(&buf, y)).collect();
+    let text = text.join("\n");
+    assert!(text.contains("Errors (3)"), "{}", text);
+    assert!(text.contains("2. A5 #DIV/0!"), "{}", text);
+    script()
+        .char('2')
+        .run(&mut ws)
+        .expect("Failed to pick error");
+    assert_eq!(Address::new(5, 1), ws.book.location);
+}
+
+#[test]
+fn test_config_binds_error_keys() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(3, 1), "=1/0")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    let config = crate::config::parse_config("[keys]\nnavigate.\"space e\" = \"next_error\"\n");
+    ws.apply_config(&config);
+    script()
+        .char(' ')
+        .char('e')
+        .run(&mut ws)
+        .expect("Failed to move");
+    assert_eq!(Address::new(3, 1), ws.book.location);
+}
+
+#[test]
+fn test_macro_record_and_replay() {
+    let mut ws = new_workspace();
+    script()
+        .chars("qa")
+        .run(&mut ws)
+        .expect("Failed to start recording");
+    let buf = render_workspace(&mut ws);
+    assert!(screen_line(&buf, 39).contains("recording @a"));
+    script()
+        .chars("sx")
+        .enter()
+        .char('j')
+        .char('q')
+        .run(&mut ws)
+        .expect("Failed to record macro");
+    assert_eq!(Address::new(2, 1), ws.book.location);
+    let buf = render_workspace(&mut ws);
+    assert!(!screen_line(&buf, 39).contains("recording"));
+
+    script().chars("@a").run(&mut ws).expect("Failed to replay");
+    assert_eq!(Address::new(3, 1), ws.book.location);
+    script()
+        .chars("2@a")
+        .run(&mut ws)
+        .expect("Failed to replay");
+    assert_eq!(Address::new(5, 1), ws.book.location);
+    script().chars("@@").run(&mut ws).expect("Failed to replay");
+    assert_eq!(Address::new(6, 1), ws.book.location);
+    for row in 1..=5 {
+        assert_eq!(
+            "x",
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, 1))
+                .expect("Failed to get contents")
+        );
+    }
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+}
+
+#[test]
+fn test_macro_replaying_itself_stops() {
+    let mut ws = new_workspace();
+    script()
+        .chars("qbj@bq")
+        .run(&mut ws)
+        .expect("Failed to record macro");
+    script().chars("@b").run(&mut ws).expect("Failed to replay");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    assert_eq!(0, ws.state.replay_depth);
+    assert!(!ws.state.replay_aborted);
+    script().esc().run(&mut ws).expect("Failed to close dialog");
+    script().char('j').run(&mut ws).expect("Failed to move");
+    assert!(ws.book.location.row > 2);
+}
+
+#[test]
+fn test_repeat_last_change() {
+    let mut ws = new_workspace();
+    script()
+        .chars("efoo")
+        .enter()
+        .chars("jjl")
+        .char('.')
+        .run(&mut ws)
+        .expect("Failed to repeat edit");
+    assert_eq!(Address::new(3, 2), ws.book.location);
+    assert_eq!("foo", ws.book.get_current_cell_contents().unwrap());
+
+    // Moving around doesn't forget the change and a count repeats it.
+    script()
+        .char('B')
+        .chars("jj")
+        .char('.')
+        .run(&mut ws)
+        .expect("Failed to repeat toggle");
+    let style = ws.book.get_cell_style(&Address::new(5, 2)).unwrap();
+    assert!(style.font.b);
+    script()
+        .char('j')
+        .chars("2.")
+        .run(&mut ws)
+        .expect("Failed to repeat toggle twice");
+    let style = ws.book.get_cell_style(&Address::new(6, 2)).unwrap();
+    assert!(!style.font.b);
+
+    script()
+        .chars("kkkd")
+        .chars("hkk.")
+        .run(&mut ws)
+        .expect("Failed to repeat clear");
+    assert_eq!(Address::new(1, 1), ws.book.location);
+    assert_eq!("", ws.book.get_current_cell_contents().unwrap());
+    let contents = ws.book.get_cell_addr_contents(&Address::new(3, 2));
+    assert_eq!("", contents.unwrap());
+}
+
+#[test]
+fn test_jump_list() {
+    let mut ws = new_workspace();
+    let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+    script()
+        .char(':')
+        .chars("goto C10")
+        .enter()
+        .chars("gg")
+        .run(&mut ws)
+        .expect("Failed to jump");
+    assert_eq!(Address::new(1, 3), ws.book.location);
+    script()
+        .ctrl('o')
+        .run(&mut ws)
+        .expect("Failed to jump back");
+    assert_eq!(Address::new(10, 3), ws.book.location);
+    script()
+        .ctrl('o')
+        .run(&mut ws)
+        .expect("Failed to jump back");
+    assert_eq!(Address::new(1, 1), ws.book.location);
+    script()
+        .ctrl('o')
+        .run(&mut ws)
+        .expect("Failed to jump back");
+    assert_eq!(Address::new(1, 1), ws.book.location);
+    script()
+        .modified_char('O', ctrl_shift)
+        .run(&mut ws)
+        .expect("Failed to jump forward");
+    assert_eq!(Address::new(10, 3), ws.book.location);
+
+    // Stepping around isn't a jump but switching sheets is.
+    ws.book
+        .new_sheet(Some("Sheet2"))
+        .expect("Failed to add sheet");
+    script()
+        .chars("jl")
+        .ctrl('n')
+        .run(&mut ws)
+        .expect("Failed to switch sheet");
+    assert_eq!(1, ws.book.location.sheet);
+    script()
+        .ctrl('o')
+        .run(&mut ws)
+        .expect("Failed to jump back");
+    assert_eq!(Address::new(11, 4), ws.book.location);
+    script()
+        .ctrl('o')
+        .run(&mut ws)
+        .expect("Failed to jump back");
+    assert_eq!(Address::new(1, 1), ws.book.location);
+}
+
+#[test]
+fn test_marks() {
+    let mut ws = new_workspace();
+    ws.book
+        .new_sheet(Some("Sheet2"))
+        .expect("Failed to add sheet");
+    script()
+        .ctrl('n')
+        .char(':')
+        .chars("goto B5")
+        .enter()
+        .chars("ma")
+        .ctrl('p')
+        .chars("jjj")
+        .run(&mut ws)
+        .expect("Failed to set mark");
+    assert_eq!(0, ws.book.location.sheet);
+    script().chars("`a").run(&mut ws).expect("Failed to jump");
+    assert_eq!(1, ws.book.location.sheet);
+    assert_eq!((5, 2), (ws.book.location.row, ws.book.location.col));
+
+    script()
+        .ctrl('p')
+        .chars("'a")
+        .run(&mut ws)
+        .expect("Failed to jump");
+    assert_eq!(1, ws.book.location.sheet);
+    script().chars("`b").run(&mut ws).expect("Failed to jump");
+    assert_eq!(Some("Mark b is not set"), ws.state.status());
+
+    script()
+        .char(':')
+        .chars("marks")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to list marks");
+    let buf = render_workspace(&mut ws);
+    assert!((0..40).any(|y| screen_line(&buf, y).contains("Sheet2!B5")));
+}
+
+#[test]
+fn test_dd_and_yy_whole_rows() {
+    let mut ws = new_workspace();
+    for row in 1..=3 {
+        for col in 1..=3 {
+            ws.book
+                .update_cell(&Address::new(row, col), format!("{}-{}", row, col))
+                .expect("Failed to update cell");
+        }
+    }
+    ws.book.evaluate();
+    script()
+        .chars("l2dd")
+        .run(&mut ws)
+        .expect("Failed to clear rows");
+    for row in 1..=2 {
+        for col in 1..=3 {
+            let contents = ws.book.get_cell_addr_contents(&Address::new(row, col));
+            assert_eq!("", contents.unwrap());
+        }
+    }
+    let contents = ws.book.get_cell_addr_contents(&Address::new(3, 1));
+    assert_eq!("3-1", contents.unwrap());
+
+    script()
+        .chars("jj\"ayy")
+        .run(&mut ws)
+        .expect("Failed to yank a row");
+    assert_eq!(Some("Yanked 1 row"), ws.state.status());
+    script()
+        .chars("jjh\"ap")
+        .run(&mut ws)
+        .expect("Failed to paste a row");
+    let contents = ws.book.get_cell_addr_contents(&Address::new(5, 1));
+    assert_eq!("3-1", contents.unwrap());
+    let contents = ws.book.get_cell_addr_contents(&Address::new(5, 3));
+    assert_eq!("3-3", contents.unwrap());
+
+    // `dc` clears the column and `d` followed by anything else only clears
+    // the cell.
+    script()
+        .chars("dcdj")
+        .run(&mut ws)
+        .expect("Failed to clear column");
+    assert_eq!(Address::new(6, 1), ws.book.location);
+    for row in [3, 5] {
+        let contents = ws.book.get_cell_addr_contents(&Address::new(row, 1));
+        assert_eq!("", contents.unwrap());
+        let contents = ws.book.get_cell_addr_contents(&Address::new(row, 2));
+        assert_eq!("3-2", contents.unwrap());
+    }
+}
+
+#[test]
+fn test_config_binds_row_operations() {
+    let mut ws = new_workspace();
+    for row in 1..=2 {
+        ws.book
+            .update_cell(&Address::new(row, 2), "text")
+            .expect("Failed to update cell");
+    }
+    ws.book.evaluate();
+    let config = crate::config::parse_config("[keys]\nnavigate.\"x x\" = \"clear_row\"\n");
+    ws.apply_config(&config);
+    script()
+        .chars("xx")
+        .run(&mut ws)
+        .expect("Failed to clear the row");
+    let contents = ws.book.get_cell_addr_contents(&Address::new(1, 2));
+    assert_eq!("", contents.unwrap());
+    let contents = ws.book.get_cell_addr_contents(&Address::new(2, 2));
+    assert_eq!("text", contents.unwrap());
+}
+
+#[test]
+fn test_config_binds_quit_keys() {
+    let mut ws = new_workspace();
+    let config = crate::config::parse_config("[keys]\nnavigate.\"space q\" = \"force_quit\"\n");
+    ws.apply_config(&config);
+    let result = script().chars(" q").run(&mut ws).expect("Failed to quit");
+    assert_eq!(Some(ExitCode::SUCCESS), result);
+}
+
+#[test]
+fn test_insert_rows_below_and_above_then_edit() {
+    let mut ws = new_workspace();
+    for row in 1..=3 {
+        ws.book
+            .update_cell(&Address::new(row, 1), row.to_string())
+            .expect("Failed to update cell");
+    }
+    script()
+        .char('j')
+        .chars("2o")
+        .run(&mut ws)
+        .expect("Failed to insert rows below");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!(Address::new(3, 1), ws.book.location);
+    assert_eq!("", ws.text_area.lines().join("\n"));
+    let column: Vec<String> = (1..=5)
+        .map(|row| {
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, 1))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(vec!["1", "2", "", "", "3"], column);
+
+    script()
+        .chars("new")
+        .enter()
+        .chars("jjO")
+        .run(&mut ws)
+        .expect("Failed to insert a row above");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!(Address::new(5, 1), ws.book.location);
+    let column: Vec<String> = (1..=6)
+        .map(|row| {
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, 1))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(vec!["1", "2", "new", "", "", "3"], column);
+}
+
+#[test]
+fn test_gg_and_g_go_to_rows() {
+    let mut ws = new_workspace();
+    // An empty sheet has nowhere to go.
+    script().char('G').run(&mut ws).expect("Failed to run G");
+    assert_eq!(Address::new(1, 1), ws.book.location);
+
+    ws.book
+        .update_cell(&Address::new(42, 2), "last")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(50, 3), "elsewhere")
+        .expect("Failed to update cell");
+    script()
+        .chars("5gg")
+        .run(&mut ws)
+        .expect("Failed to run 5gg");
+    assert_eq!(Address::new(5, 1), ws.book.location);
+    script().chars("lG").run(&mut ws).expect("Failed to run G");
+    assert_eq!(Address::new(42, 2), ws.book.location);
+    assert_eq!("last", ws.text_area.lines().join("\n"));
+    script().chars("gg").run(&mut ws).expect("Failed to run gg");
+    assert_eq!(Address::new(1, 2), ws.book.location);
+    // A column without data goes to the last used row of the sheet.
+    script().chars("hG").run(&mut ws).expect("Failed to run G");
+    assert_eq!(Address::new(50, 1), ws.book.location);
+    script()
+        .chars("100G")
+        .run(&mut ws)
+        .expect("Failed to run 100G");
+    assert_eq!(Address::new(100, 1), ws.book.location);
+    script()
+        .chars("99999999gg")
+        .run(&mut ws)
+        .expect("Failed to run gg past the end");
+    assert_eq!(book::LAST_ROW as usize, ws.book.location.row);
+}
+
+#[test]
+fn test_select_whole_rows_and_columns() {
+    let mut ws = new_workspace();
+    for (row, col, value) in [
+        (1, 1, "a1"),
+        (2, 1, "a2"),
+        (3, 1, "a3"),
+        (1, 26, "z1"),
+        (2, 26, "z2"),
+    ] {
+        ws.book
+            .update_cell(&Address::new(row, col), value)
+            .expect("Failed to update cell");
+    }
+    script()
+        .chars("Vj")
+        .run(&mut ws)
+        .expect("Failed to select rows");
+    let (start, end) = ws.state.range_select.get_range().expect("No range");
+    assert_eq!((1, 1), (start.row, start.col));
+    assert_eq!((2, 26), (end.row, end.col));
+    let buf = render_workspace(&mut ws);
+    assert!(screen_line(&buf, 39).contains("2R x 26C A1:Z2"));
+    script()
+        .char('d')
+        .run(&mut ws)
+        .expect("Failed to clear rows");
+    for (row, col) in [(1, 1), (2, 1), (1, 26), (2, 26)] {
+        assert_eq!(
+            "",
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, col))
+                .expect("Failed to get cell")
+        );
+    }
+    assert_eq!(
+        "a3",
+        ws.book
+            .get_cell_addr_contents(&Address::new(3, 1))
+            .expect("Failed to get cell")
+    );
+
+    // C selects whole columns and pressing it again goes back to cells.
+    script()
+        .esc()
+        .char('v')
+        .char('C')
+        .run(&mut ws)
+        .expect("Failed to select columns");
+    let (start, end) = ws.state.range_select.get_range().expect("No range");
+    assert_eq!((1, 1), (start.row, start.col));
+    assert_eq!((3, 1), (end.row, end.col));
+    script()
+        .char('C')
+        .run(&mut ws)
+        .expect("Failed to toggle columns");
+    assert_eq!(SelectionMode::Cells, ws.state.range_select.mode);
+}
+
+#[test]
+fn test_swap_range_anchor() {
+    let mut ws = new_workspace();
+    ws.book
+        .move_to(&Address::new(2, 2))
+        .expect("Failed to move to B2");
+    script()
+        .chars("vjjll")
+        .run(&mut ws)
+        .expect("Failed to select B2:D4");
+    script()
+        .char('o')
+        .run(&mut ws)
+        .expect("Failed to swap anchor");
+    assert_eq!(Address::new(2, 2), ws.book.location);
+    assert_eq!(Some(Address::new(4, 4)), ws.state.range_select.start);
+    // Moving now grows the range the other way.
+    script()
+        .char('h')
+        .run(&mut ws)
+        .expect("Failed to move left");
+    assert_eq!(
+        Some((Address::new(2, 1), Address::new(4, 4))),
+        ws.state.range_select.get_range()
+    );
+
+    // O only swaps the columns so the cursor goes to the other corner of the
+    // row it is on.
+    script()
+        .char('O')
+        .run(&mut ws)
+        .expect("Failed to swap anchor column");
+    assert_eq!(Address::new(2, 4), ws.book.location);
+    assert_eq!(Some(Address::new(4, 1)), ws.state.range_select.start);
+    script()
+        .char('l')
+        .run(&mut ws)
+        .expect("Failed to move right");
+    assert_eq!(
+        Some((Address::new(2, 1), Address::new(4, 5))),
+        ws.state.range_select.get_range()
+    );
+}
+
+#[test]
+fn test_color_rows_over_selection() {
+    let mut ws = new_workspace();
+    ws.book
+        .move_to(&Address::new(2, 3))
+        .expect("Failed to move to C2");
+    script()
+        .chars("vjjh")
+        .char(':')
+        .chars("color-rows red")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    assert_eq!(Some("Colored 3 rows"), ws.state.status());
+    let bg_color = |ws: &Workspace, row: usize| {
+        ws.book
+            .get_cell_style(&Address::new(row, 1))
+            .expect("failed to get style")
+            .fill
+            .bg_color
+    };
+    for row in 2..=4 {
+        assert_eq!(
+            Some("#800000".to_string()),
+            bg_color(&ws, row),
+            "row {}",
+            row
+        );
+    }
+    assert_eq!(None, bg_color(&ws, 1));
+    assert_eq!(None, bg_color(&ws, 5));
+
+    // The selection sets where the columns start and a count overrides how
+    // many there are.
+    script()
+        .char(':')
+        .chars("color-columns 1 red")
+        .enter()
+        .run(&mut ws)
+        .expect("Unable to run script");
+    assert_eq!(Some("Colored 1 column"), ws.state.status());
+}
+
+#[test]
+fn test_copy_range_with_header() {
+    let mut ws = new_workspace();
+    for (row, values) in [
+        (1, ["Name", "Qty"]),
+        (2, ["apple", "3"]),
+        (3, ["pear", "5"]),
+    ] {
+        for (col, value) in values.iter().enumerate() {
+            ws.book
+                .update_cell(&Address::new(row, col + 1), value)
+                .expect("Failed to update cell");
+        }
+    }
+    ws.book
+        .move_to(&Address::new(2, 1))
+        .expect("Failed to move to A2");
+    script()
+        .chars("vlj\"aH")
+        .run(&mut ws)
+        .expect("Failed to copy with header");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    match ws.state.registers.get(&'a') {
+        Some(ClipboardContents::Range(rows)) => {
+            assert_eq!(
+                &vec![
+                    vec!["Name".to_string(), "Qty".to_string()],
+                    vec!["apple".to_string(), "3".to_string()],
+                    vec!["pear".to_string(), "5".to_string()],
+                ],
+                rows
+            );
+        }
+        other => panic!("Unexpected register contents {:?}", other),
+    }
+
+    // A range on the first row has no header above it.
+    script()
+        .chars("kvl\"bH")
+        .run(&mut ws)
+        .expect("Failed to copy with header");
+    match ws.state.registers.get(&'b') {
+        Some(ClipboardContents::Range(rows)) => {
+            assert_eq!(&vec![vec!["Name".to_string(), "Qty".to_string()]], rows);
+        }
+        other => panic!("Unexpected register contents {:?}", other),
+    }
+}
+
+#[test]
+fn test_dedupe_command_over_selection() {
+    let mut ws = new_workspace();
+    for (row, value) in [(1, "a"), (2, "b"), (3, "a"), (4, "c")] {
+        ws.book
+            .update_cell(&Address::new(row, 1), value)
+            .expect("Failed to update cell");
+    }
+    script()
+        .char(':')
+        .chars("dedupe")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run dedupe");
+    assert_eq!(Some("Select a range to dedupe first"), ws.state.status());
+
+    script()
+        .chars("v3j:dedupe shift")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run dedupe");
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+    let buf = render_workspace(&mut ws);
+    let screen = (0..40).map(|y| screen_line(&buf, y)).collect::<Vec<_>>();
+    assert!(
+        screen
+            .iter()
+            .any(|line| line.contains("Removed 1 duplicate row")),
+        "{:#?}",
+        screen
+    );
+    let column = (1..=4)
+        .map(|row| {
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, 1))
+                .expect("Failed to get cell")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["a", "b", "c", ""], column);
+}
+
+#[test]
+fn test_case_command_reports_changed_cells() {
+    let mut ws = new_workspace();
+    for (row, value) in [(1, "ada"), (2, "42"), (3, "GRACE")] {
+        ws.book
+            .update_cell(&Address::new(row, 1), value)
+            .expect("Failed to update cell");
+    }
+    script()
+        .chars("vjj:case upper")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run case");
+    assert_eq!(Some("Changed 1 cell"), ws.state.status());
+    assert_eq!(
+        "ADA",
+        ws.book
+            .get_cell_addr_contents(&Address::new(1, 1))
+            .expect("Failed to get cell")
+    );
+}
+
+#[test]
+fn test_fill_command_continues_from_first_cell() {
+    let mut ws = new_workspace();
+    script()
+        .chars("vjj:fill")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run fill");
+    assert_eq!(
+        Some("fill needs a start or a number in the first cell"),
+        ws.state.status()
+    );
+    ws.book
+        .update_cell(&Address::new(1, 1), "5")
+        .expect("Failed to update cell");
+    script()
+        .chars(":fill step 5")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run fill");
+    assert_eq!(Some("Filled 3 cells"), ws.state.status());
+    let column = (1..=3)
+        .map(|row| {
+            ws.book
+                .get_cell_addr_contents(&Address::new(row, 1))
+                .expect("Failed to get cell")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["5", "10", "15"], column);
+}
+
+#[test]
+fn test_increment_and_decrement_numbers() {
+    let mut ws = new_workspace();
+    let contents = |ws: &Workspace, row: usize, col: usize| {
+        ws.book
+            .get_cell_addr_contents(&Address::new(row, col))
+            .expect("Failed to get cell")
+    };
+    ws.book
+        .update_cell(&Address::new(1, 1), "5")
+        .expect("Failed to update cell");
+    script().chars("3+").run(&mut ws).expect("Failed to run 3+");
+    assert_eq!("8", contents(&ws, 1, 1));
+    assert_eq!("8", ws.text_area.lines().join("\n"));
+    script()
+        .ctrl('x')
+        .char('.')
+        .run(&mut ws)
+        .expect("Failed to decrement");
+    assert_eq!("6", contents(&ws, 1, 1));
+    script()
+        .ctrl('a')
+        .run(&mut ws)
+        .expect("Failed to increment");
+    assert_eq!("7", contents(&ws, 1, 1));
+
+    // Formulas and text are left alone.
+    ws.book
+        .update_cell(&Address::new(2, 1), "=A1")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(3, 1), "1.5")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(4, 1), "four")
+        .expect("Failed to update cell");
+    script().chars("j+").run(&mut ws).expect("Failed to run +");
+    assert_eq!("=A1", contents(&ws, 2, 1));
+    assert_eq!(Some("No numbers to change"), ws.state.status());
+
+    // Every number in a selection changes.
+    script()
+        .chars("vjj10-")
+        .run(&mut ws)
+        .expect("Failed to run -");
+    assert_eq!(
+        vec!["=A1", "-8.5", "four"],
+        (2..=4).map(|row| contents(&ws, row, 1)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_equals_starts_a_formula_over_the_cell() {
+    let mut ws = new_workspace();
+    ws.book
+        .update_cell(&Address::new(1, 1), "old")
+        .expect("Failed to update cell");
+    ws.book
+        .update_cell(&Address::new(2, 1), "4")
+        .expect("Failed to update cell");
+    ws.book.evaluate();
+    script().char('=').run(&mut ws).expect("Failed to run =");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!("=", ws.text_area.lines().join("\n"));
+    // The cell isn't touched until the edit is committed.
+    assert_eq!(
+        "old",
+        ws.book
+            .get_current_cell_contents()
+            .expect("Failed to get cell")
+    );
+    script().esc().run(&mut ws).expect("Failed to run Esc");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(
+        "old",
+        ws.book
+            .get_current_cell_contents()
+            .expect("Failed to get cell")
+    );
+    assert_eq!("old", ws.text_area.lines().join("\n"));
+
+    script()
+        .chars("=A2*2")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit the cell");
+    assert_eq!(
+        "=A2*2",
+        ws.book
+            .get_cell_addr_contents(&Address::new(1, 1))
+            .expect("Failed to get cell")
+    );
+    assert_eq!(
+        "8",
+        ws.book
+            .get_cell_addr_rendered(&Address::new(1, 1))
+            .expect("Failed to get cell")
+    );
+}
+
+#[test]
+fn test_edit_border_shows_the_cell() {
+    let mut ws = new_workspace();
+    script()
+        .chars("2l6je")
+        .run(&mut ws)
+        .expect("Failed to edit C7");
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    let buf = render_workspace(&mut ws);
+    assert!(
+        screen_line(&buf, 4).contains("┌C7"),
+        "{}",
+        screen_line(&buf, 4)
+    );
+
+    // Other sheets than the first are named.
+    script()
+        .esc()
+        .run(&mut ws)
+        .expect("Failed to leave edit mode");
+    ws.book
+        .new_sheet(Some("Sheet2"))
+        .expect("Failed to create Sheet2");
+    script()
+        .ctrl('n')
+        .chars("2l6je")
+        .run(&mut ws)
+        .expect("Failed to edit Sheet2!C7");
+    let buf = render_workspace(&mut ws);
+    assert!(
+        screen_line(&buf, 4).contains("┌Sheet2!C7"),
+        "{}",
+        screen_line(&buf, 4)
+    );
+}
+
+#[test]
+fn test_tab_commits_and_moves() {
+    let mut ws = new_workspace();
+    let contents = |ws: &Workspace, row: usize, col: usize| {
+        ws.book
+            .get_cell_addr_contents(&Address::new(row, col))
+            .expect("Failed to get cell")
+    };
+    script()
+        .char('e')
+        .char('x')
+        .tab()
+        .char('y')
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit with tab");
+    assert_eq!("x", contents(&ws, 1, 1));
+    assert_eq!("y", contents(&ws, 1, 2));
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert_eq!(Address::new(1, 2), ws.book.location);
+
+    script()
+        .chars("ez")
+        .event(construct_key_event(KeyCode::BackTab))
+        .run(&mut ws)
+        .expect("Failed to edit with shift-tab");
+    assert_eq!("yz", contents(&ws, 1, 2));
+    assert_eq!(Some(&Modality::CellEdit), ws.state.modality_stack.last());
+    assert_eq!(Address::new(1, 1), ws.book.location);
+    assert_eq!("x", ws.text_area.lines().join("\n"));
+    script()
+        .esc()
+        .run(&mut ws)
+        .expect("Failed to leave edit mode");
+
+    // Enter only moves down when asked to.
+    script()
+        .char(':')
+        .chars("set enter-moves on")
+        .enter()
+        .chars("e1")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit with enter-moves on");
+    assert_eq!("x1", contents(&ws, 1, 1));
+    assert_eq!(Address::new(2, 1), ws.book.location);
+}
+
+#[test]
+fn test_f4_cycles_reference_anchors() {
+    let mut ws = new_workspace();
+    let left = || construct_key_event(KeyCode::Left);
+    script()
+        .char('e')
+        .chars("=A1+B2")
+        .event(left())
+        .event(left())
+        .event(left())
+        .event(construct_key_event(KeyCode::F(4)))
+        .run(&mut ws)
+        .expect("Failed to toggle the reference");
+    assert_eq!("=$A$1+B2", ws.text_area.lines().join("\n"));
+    script()
+        .event(construct_key_event(KeyCode::F(4)))
+        .run(&mut ws)
+        .expect("Failed to toggle the reference");
+    assert_eq!("=A$1+B2", ws.text_area.lines().join("\n"));
+
+    // Outside of a reference nothing happens.
+    script()
+        .event(construct_key_event(KeyCode::Home))
+        .event(construct_key_event(KeyCode::F(4)))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to toggle the reference");
+    assert_eq!(
+        "=A$1+B2",
+        ws.book
+            .get_cell_addr_contents(&Address::new(1, 1))
+            .expect("Failed to get cell")
+    );
+}
+
+#[test]
+fn test_arrow_keys_point_at_cells_in_a_formula() {
+    let mut ws = new_workspace();
+    let key = |code| construct_key_event(code);
+    script()
+        .chars("2jl=")
+        .event(key(KeyCode::Down))
+        .event(key(KeyCode::Down))
+        .run(&mut ws)
+        .expect("Failed to point at a cell");
+    assert_eq!("=B5", ws.text_area.lines().join("\n"));
+    assert_eq!(Some((Address::new(5, 2), 2)), ws.state.point);
+    // The edited cell stays put while pointing.
+    assert_eq!(Address::new(3, 2), ws.book.location);
+
+    // Typing locks the reference in and an operator starts a new one.
+    script()
+        .char('+')
+        .event(key(KeyCode::Left))
+        .event(key(KeyCode::Up))
+        .chars("*2")
+        .event(key(KeyCode::Left))
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to point at a second cell");
+    assert_eq!(
+        "=B5+A2*2",
+        ws.book
+            .get_cell_addr_contents(&Address::new(3, 2))
+            .expect("Failed to get cell")
+    );
+    assert_eq!(None, ws.state.point);
+
+    // Arrow keys anywhere else move the editor's cursor.
+    script()
+        .char('e')
+        .event(key(KeyCode::Left))
+        .char('0')
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to edit the formula");
+    assert_eq!(
+        "=B5+A2*02",
+        ws.book
+            .get_cell_addr_contents(&Address::new(3, 2))
+            .expect("Failed to get cell")
+    );
+}
+
+#[test]
+fn test_edit_highlights_matching_parens() {
+    let mut ws = new_workspace();
+    script()
+        .char('e')
+        .chars("=(1+2)*3")
+        .event(construct_key_event(KeyCode::Left))
+        .event(construct_key_event(KeyCode::Left))
+        .run(&mut ws)
+        .expect("Failed to edit the formula");
+    // The editor's text is on the line under its border.
+    let buf = render_workspace(&mut ws);
+    let marked = |buf: &ratatui::buffer::Buffer, symbol: &str, modifier| {
+        (0..buf.area.width).any(|x| {
+            let cell = &buf[(x, 5)];
+            cell.symbol() == symbol && cell.modifier.contains(modifier)
+        })
+    };
+    // The cursor is right after the `)`.
+    assert!(marked(&buf, "(", ratatui::style::Modifier::REVERSED));
+    assert!(marked(&buf, ")", ratatui::style::Modifier::REVERSED));
+
+    script()
+        .esc()
+        .char('e')
+        .chars("=SUM((A1")
+        .run(&mut ws)
+        .expect("Failed to edit the formula");
+    let buf = render_workspace(&mut ws);
+    let orphan = (0..buf.area.width)
+        .map(|x| &buf[(x, 5)])
+        .find(|cell| cell.symbol() == "(")
+        .expect("Missing parenthesis");
+    assert_eq!(ws.theme().formula_unmatched, orphan.fg);
+
+    script().enter().run(&mut ws).expect("Failed to commit");
+    assert_eq!(Some("Unbalanced parentheses in A1"), ws.state.status());
+}
+
+#[test]
+fn test_edit_highlights_formula_syntax() {
+    let mut ws = new_workspace();
+    let theme = ws.theme();
+    script()
+        .char('e')
+        .chars("=SUM(A1)&\"x\"")
+        .run(&mut ws)
+        .expect("Failed to edit the formula");
+    let buf = render_workspace(&mut ws);
+    let fg_of = |buf: &ratatui::buffer::Buffer, symbol: &str| {
+        (0..buf.area.width)
+            .map(|x| &buf[(x, 5)])
+            .find(|cell| cell.symbol() == symbol)
+            .map(|cell| cell.fg)
+            .expect("Missing symbol")
+    };
+    assert_eq!(theme.formula_function, fg_of(&buf, "S"));
+    assert_eq!(theme.formula_reference, fg_of(&buf, "A"));
+    assert_eq!(theme.formula_string, fg_of(&buf, "x"));
+
+    // Plain text isn't highlighted.
+    script()
+        .esc()
+        .char('e')
+        .chars("SUM(A1)")
+        .run(&mut ws)
+        .expect("Failed to edit the cell");
+    let buf = render_workspace(&mut ws);
+    assert_ne!(theme.formula_function, fg_of(&buf, "S"));
+    assert_ne!(theme.formula_reference, fg_of(&buf, "A"));
+}
+
+#[test]
+fn test_command_line_editing_keys() {
+    use tui_prompts::State;
+    let mut ws = new_workspace();
+    let value = |ws: &Workspace| ws.state.command_state.value().to_string();
+    script()
+        .char(':')
+        .chars("set stripes off")
+        .ctrl('w')
+        .run(&mut ws)
+        .expect("Failed to delete a word");
+    assert_eq!("set stripes ", value(&ws));
+    script()
+        .ctrl('w')
+        .ctrl('a')
+        .event(construct_key_event(KeyCode::Right))
+        .ctrl('k')
+        .run(&mut ws)
+        .expect("Failed to kill the line");
+    assert_eq!("s", value(&ws));
+    script()
+        .chars("yz")
+        .ctrl('u')
+        .run(&mut ws)
+        .expect("Failed to clear the line");
+    assert_eq!("", value(&ws));
+
+    // Esc cancels the command instead of running it.
+    script()
+        .chars("set stripes off")
+        .esc()
+        .run(&mut ws)
+        .expect("Failed to cancel the command");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(ws.state.settings.stripes);
+    script()
+        .char(':')
+        .chars("set stripes off")
+        .ctrl('c')
+        .run(&mut ws)
+        .expect("Failed to cancel the command");
+    assert_eq!(Some(&Modality::Navigate), ws.state.modality_stack.last());
+    assert!(ws.state.settings.stripes);
+    script()
+        .char(':')
+        .chars("set stripes off")
+        .enter()
+        .run(&mut ws)
+        .expect("Failed to run the command");
+    assert!(!ws.state.settings.stripes);
+}
+
+/// A clipboard holding fixed text, or failing when there is none.
+struct FakeClipboard(Option<String>);
+
+impl super::clipboard::SystemClipboard for FakeClipboard {
+    fn get_text(&mut self) -> anyhow::Result<String> {
+        self.0
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("no clipboard"))
+    }
+
+    fn get_html(&mut self) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!("no html"))
+    }
+}
+
+#[test]
+fn test_command_line_paste() {
+    use tui_prompts::State;
+    let mut ws = new_workspace();
+    ws.system_clipboard = Box::new(FakeClipboard(Some("#ff0000\n".to_string())));
+    script()
+        .char(':')
+        .chars("color-cell ")
+        .ctrl('v')
+        .run(&mut ws)
+        .expect("Failed to paste");
+    assert_eq!("color-cell #ff0000", ws.state.command_state.value());
+
+    // Pasting goes in at the cursor.
+    script()
+        .ctrl('a')
+        .ctrl('v')
+        .run(&mut ws)
+        .expect("Failed to paste");
+    assert_eq!("#ff0000color-cell #ff0000", ws.state.command_state.value());
+
+    // A clipboard that can't be read only leaves a status message.
+    ws.system_clipboard = Box::new(FakeClipboard(None));
+    script().ctrl('v').run(&mut ws).expect("Failed to paste");
+    assert_eq!(Some(&Modality::Command), ws.state.modality_stack.last());
+    assert_eq!(
+        Some("Couldn't read the clipboard: no clipboard"),
+        ws.state.status()
+    );
+}
+
+struct FakeBrowser(bool);
+
+impl super::browser::Browser for FakeBrowser {
+    fn open(&mut self, _url: &str) -> anyhow::Result<()> {
+        if self.0 {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("no browser"))
+        }
+    }
+}
+
+#[test]
+fn test_dialog_opens_links_by_number() {
+    let mut ws = new_workspace();
+    ws.browser = Box::new(FakeBrowser(true));
+    ws.enter_dialog_mode(super::render::markdown::Markdown::from_str(
+        "See [the docs](https://example.com/docs) or [navigation](./navigation.md).",
+    ));
+    assert_eq!("See the docs[1] or navigation[2].", popup_lines(&ws)[0]);
+    script().char('1').run(&mut ws).expect("Failed to run");
+    assert_eq!(Some("Opened https://example.com/docs"), ws.state.status());
+    assert_eq!(Some(&Modality::Dialog), ws.state.modality_stack.last());
+
+    // Links that aren't urls are ignored.
+    script().char('2').run(&mut ws).expect("Failed to run");
+    assert_eq!(None, ws.state.status());
+
+    ws.browser = Box::new(FakeBrowser(false));
+    script().char('1').run(&mut ws).expect("Failed to run");
+    assert_eq!(
+        Some("Couldn't open https://example.com/docs: no browser"),
+        ws.state.status()
+    );
+}
+
+#[test]
+fn test_link_url() {
+    use super::browser::link_url;
+    assert_eq!(
+        Some("https://example.com"),
+        link_url("(https://example.com)")
+    );
+    assert_eq!(Some("https://example.com"), link_url("https://example.com"));
+    assert_eq!(
+        Some("mailto:me@example.com"),
+        link_url("mailto:me@example.com")
+    );
+    assert_eq!(None, link_url("(./navigation.md)"));
+    assert_eq!(None, link_url("[reference]"));
+    assert_eq!(None, link_url("(://nothing)"));
+}
+
+#[test]
+fn test_export_csv_uses_the_selection() {
+    let path = ui_tmp_path("export_selection.csv");
+    let _ = std::fs::remove_file(&path);
+    let mut ws = new_workspace();
+    for (row, col, value) in [(1, 1, "a"), (1, 2, "b"), (2, 1, "c"), (2, 2, "d")] {
+        ws.book
+            .update_cell(&Address::new(row, col), value)
+            .expect("Failed to update cell");
+    }
+    ws.book.evaluate();
+    ws.handle_command(
+        format!("export-csv {}", path.display()),
+        Some((Address::new(2, 1), Address::new(2, 2))),
+    )
+    .expect("Failed to export");
+    let exported = std::fs::read_to_string(&path).expect("Failed to read export");
+    assert_eq!("c,d", exported.trim_end());
 }